@@ -0,0 +1,270 @@
+use axum::{
+    body::{Body, HttpBody},
+    extract::{Request, State},
+    http::{header, HeaderMap},
+    middleware::Next,
+    response::Response,
+};
+use chrono::Utc;
+use std::collections::HashMap;
+use std::env;
+use std::mem;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::time::interval;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+use crate::filesystem::{
+    bucket_exists, create_storage_dir, read_bucket_logging, read_bucket_owner, write_storage_file,
+};
+use crate::list_index::ListIndex;
+use crate::models::{AppState, ObjectMetadata};
+use crate::quota::QuotaManager;
+use crate::request_id::RequestId;
+use crate::utils::parse_sigv4_authorization;
+use crate::wal::WALWriter;
+
+const DEFAULT_FLUSH_INTERVAL_SECS: u64 = 300;
+
+// Bucket subresources that name their own operation in the log, e.g. REST.GET.ACL
+const LOGGED_SUBRESOURCES: [&str; 12] = [
+    "acl", "cors", "encryption", "lifecycle", "logging", "policy", "quota", "stats", "tagging", "uploads",
+    "versioning", "website",
+];
+
+// Buffers server access log records per source bucket until they are delivered to the target bucket
+pub struct AccessLogger {
+    storage_path: PathBuf,
+    records: Mutex<HashMap<String, Vec<String>>>,
+    flush_interval: Duration,
+    quota_manager: Arc<QuotaManager>,
+    wal_writer: Arc<WALWriter>,
+    list_index: Arc<ListIndex>,
+}
+
+impl AccessLogger {
+    pub fn new(
+        storage_path: PathBuf,
+        quota_manager: Arc<QuotaManager>,
+        wal_writer: Arc<WALWriter>,
+        list_index: Arc<ListIndex>,
+    ) -> Self {
+        let flush_interval_secs = env::var("ACCESS_LOG_FLUSH_INTERVAL_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .filter(|secs| *secs > 0)
+            .unwrap_or(DEFAULT_FLUSH_INTERVAL_SECS);
+
+        AccessLogger {
+            storage_path,
+            records: Mutex::new(HashMap::new()),
+            flush_interval: Duration::from_secs(flush_interval_secs),
+            quota_manager,
+            wal_writer,
+            list_index,
+        }
+    }
+
+    fn record(&self, bucket: &str, line: String) {
+        self.records.lock().unwrap()
+            .entry(bucket.to_string())
+            .or_default()
+            .push(line);
+    }
+
+    // Write each bucket's buffered records as one log object into its target bucket
+    pub async fn flush_all(&self) {
+        let pending = mem::take(&mut *self.records.lock().unwrap());
+
+        for (bucket, lines) in pending {
+            // Logging may have been switched off or the target removed since the records were taken
+            let config = match read_bucket_logging(&self.storage_path, &bucket) {
+                Some(config) => config,
+                None => continue,
+            };
+            if !bucket_exists(&self.storage_path, &config.target_bucket) {
+                warn!("Dropping {} access log records for {}: target bucket {} does not exist",
+                      lines.len(), bucket, config.target_bucket);
+                continue;
+            }
+
+            let now = Utc::now();
+            let key = format!(
+                "{}{}-{}",
+                config.target_prefix,
+                now.format("%Y-%m-%d-%H-%M-%S"),
+                Uuid::new_v4().simple().to_string()[..16].to_uppercase()
+            );
+            let data = lines.join("\n") + "\n";
+            let etag = format!("{:x}", md5::compute(data.as_bytes()));
+
+            let target_path = self.storage_path.join(&config.target_bucket);
+            let object_path = target_path.join(&key);
+            if let Some(parent) = object_path.parent() {
+                if let Err(e) = create_storage_dir(parent) {
+                    warn!("Failed to create access log directory {:?}: {}", parent, e);
+                    continue;
+                }
+            }
+            if let Err(e) = write_storage_file(&object_path, &data) {
+                warn!("Failed to deliver access logs for {} to {}/{}: {}", bucket, config.target_bucket, key, e);
+                continue;
+            }
+
+            let metadata = ObjectMetadata {
+                key: key.clone(),
+                size: data.len() as u64,
+                etag: etag.clone(),
+                last_modified: now,
+                created: Some(now),
+                content_type: "text/plain".to_string(),
+                storage_class: "STANDARD".to_string(),
+                metadata: HashMap::new(),
+                version_id: None,
+                encryption: None,
+                tags: None,
+                restore_required: false,
+                website_redirect_location: None,
+                acl: None,
+            };
+            if let Ok(metadata_json) = serde_json::to_string(&metadata) {
+                if let Err(e) = write_storage_file(target_path.join(format!("{}.metadata", key)), metadata_json) {
+                    warn!("Failed to write access log metadata for {}/{}: {}", config.target_bucket, key, e);
+                }
+            }
+
+            self.wal_writer.log_put(&config.target_bucket, &key, data.len() as u64, Some(etag));
+            self.list_index.record_write(&config.target_bucket, &key);
+            if let Err(e) = self.quota_manager.update_quota_add(&config.target_bucket, &key, data.len() as u64).await {
+                warn!("Failed to update quota for bucket {}: {}", config.target_bucket, e);
+            }
+
+            info!("Delivered {} access log records for {} to {}/{}", lines.len(), bucket, config.target_bucket, key);
+        }
+    }
+
+    // Background task to periodically deliver buffered access logs
+    pub async fn start_flush_task(self: Arc<Self>) {
+        info!("Delivering server access logs every {} seconds", self.flush_interval.as_secs());
+        let mut interval = interval(self.flush_interval);
+
+        loop {
+            interval.tick().await;
+            self.flush_all().await;
+        }
+    }
+}
+
+// Record an access log line for requests on buckets that have logging enabled
+pub async fn access_log_middleware(
+    State(state): State<AppState>,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    let path = request.uri().path().trim_start_matches('/').to_string();
+    let (bucket, key) = match path.split_once('/') {
+        Some((bucket, key)) => (bucket.to_string(), key.to_string()),
+        None => (path.clone(), String::new()),
+    };
+
+    // Bucket names cannot start with '_', which keeps /_ironbucket endpoints out of the logs
+    if bucket.is_empty() || bucket.starts_with('_') || read_bucket_logging(&state.storage_path, &bucket).is_none() {
+        return next.run(request).await;
+    }
+
+    let start = Instant::now();
+    let time = Utc::now();
+    let method = request.method().clone();
+    let uri = request.uri().to_string();
+    let headers = request.headers().clone();
+    let operation = operation_name(method.as_str(), &key, request.uri().query());
+    let request_id = request.extensions().get::<RequestId>()
+        .map(|id| id.0.clone())
+        .unwrap_or_else(|| Uuid::new_v4().simple().to_string()[..16].to_uppercase());
+
+    let response = next.run(request).await;
+
+    let owner = read_bucket_owner(&state.storage_path, &bucket).unwrap_or_else(|| "-".to_string());
+    let bytes_sent = response.body()
+        .size_hint()
+        .exact()
+        .filter(|size| *size > 0)
+        .map(|size| size.to_string())
+        .unwrap_or_else(|| "-".to_string());
+
+    // Fields follow the S3 server access log format; values IronBucket does not track are "-"
+    let line = format!(
+        "{} {} [{}] {} {} {} {} {} \"{} {} HTTP/1.1\" {} - {} - {} - \"{}\" \"{}\" -",
+        owner,
+        bucket,
+        time.format("%d/%b/%Y:%H:%M:%S +0000"),
+        client_ip(&headers),
+        requester(&headers, &uri),
+        request_id,
+        operation,
+        if key.is_empty() { "-".to_string() } else { key },
+        method,
+        uri,
+        response.status().as_u16(),
+        bytes_sent,
+        start.elapsed().as_millis(),
+        header_or_dash(&headers, header::REFERER.as_str()),
+        header_or_dash(&headers, header::USER_AGENT.as_str()),
+    );
+    debug!("Access log for {}: {}", bucket, line);
+    state.access_logger.record(&bucket, line);
+
+    response
+}
+
+// REST.<METHOD>.<RESOURCE>, where the resource is OBJECT, BUCKET or the bucket subresource
+fn operation_name(method: &str, key: &str, query: Option<&str>) -> String {
+    let resource = if !key.is_empty() {
+        "OBJECT".to_string()
+    } else {
+        query.unwrap_or("")
+            .split('&')
+            .map(|param| param.split('=').next().unwrap_or(""))
+            .find(|name| LOGGED_SUBRESOURCES.contains(name))
+            .map(|name| name.to_uppercase())
+            .unwrap_or_else(|| "BUCKET".to_string())
+    };
+    format!("REST.{}.{}", method, resource)
+}
+
+fn client_ip(headers: &HeaderMap) -> String {
+    headers.get("x-real-ip")
+        .or_else(|| headers.get("x-forwarded-for"))
+        .and_then(|v| v.to_str().ok())
+        .map(|s| s.split(',').next().unwrap_or(s).trim().to_string())
+        .unwrap_or_else(|| "-".to_string())
+}
+
+// Access key from the Authorization header or a presigned URL's X-Amz-Credential
+fn requester(headers: &HeaderMap, uri: &str) -> String {
+    let from_header = headers.get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(parse_sigv4_authorization)
+        .map(|authorization| authorization.access_key().to_string());
+
+    let from_query = || {
+        uri.split_once('?')
+            .map(|(_, query)| query)
+            .unwrap_or("")
+            .split('&')
+            .find_map(|param| param.strip_prefix("X-Amz-Credential="))
+            .and_then(|credential| urlencoding::decode(credential).ok())
+            .and_then(|credential| credential.split('/').next().map(String::from))
+    };
+
+    from_header.or_else(from_query).unwrap_or_else(|| "-".to_string())
+}
+
+fn header_or_dash(headers: &HeaderMap, name: &str) -> String {
+    headers.get(name)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("-")
+        .to_string()
+}
@@ -1,4 +1,4 @@
-use crate::{config::AuthConfig, error::{Error, Result}};
+use crate::{config::AuthConfig, error::{Error, Result}, utils::parse_sigv4_authorization};
 use axum::{
     extract::Request,
     http::{header, StatusCode},
@@ -47,16 +47,14 @@ impl<S> AuthMiddleware<S> {
         req: &Request,
         auth_header: &str,
     ) -> Result<()> {
-        // Parse Authorization header
-        let parts: Vec<&str> = auth_header.split_whitespace().collect();
-        if parts.len() != 4 || !parts[0].starts_with("AWS4-HMAC-SHA256") {
-            return Err(Error::InvalidRequest("Invalid authorization header".to_string()));
-        }
+        // Parse Authorization header, tolerating any whitespace between its components
+        let authorization = parse_sigv4_authorization(auth_header)
+            .ok_or_else(|| Error::InvalidRequest("Invalid authorization header".to_string()))?;
 
         // Extract credential, signed headers, and signature
-        let credential_part = parts[1].trim_start_matches("Credential=").trim_end_matches(',');
-        let signed_headers_part = parts[2].trim_start_matches("SignedHeaders=").trim_end_matches(',');
-        let signature = parts[3].trim_start_matches("Signature=");
+        let credential_part = authorization.credential;
+        let signed_headers_part = authorization.signed_headers;
+        let signature = authorization.signature;
 
         // Parse credential
         let cred_parts: Vec<&str> = credential_part.split('/').collect();
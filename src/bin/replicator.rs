@@ -185,7 +185,7 @@ impl Replicator {
                 };
                 (key, size, etag)
             }
-            "DELETE" => {
+            "DELETE" | "UPDATE_OBJECT_METADATA" => {
                 if parts.len() < 6 {
                     return None;
                 }
@@ -324,9 +324,16 @@ impl Replicator {
                 continue;
             }
 
-            // Take only the last operation for this key
-            if let Some(last_op) = ops.into_iter().last() {
-                optimized.push(last_op);
+            // Take only the last operation for this key. A metadata update after a PUT in the same batch is
+            // covered by the PUT, which copies the current .metadata sidecar along with the object.
+            let last_put = ops.iter().rposition(|e| e.operation == "PUT");
+            let last_index = ops.len() - 1;
+            let index = match last_put {
+                Some(put) if ops[last_index].operation == "UPDATE_OBJECT_METADATA" => put,
+                _ => last_index,
+            };
+            if let Some(op) = ops.into_iter().nth(index) {
+                optimized.push(op);
             }
         }
 
@@ -401,6 +408,19 @@ impl Replicator {
                         info!("Deleted {}/{} on {}", entry.bucket, entry.key, target_node);
                     }
                 }
+                "UPDATE_OBJECT_METADATA" => {
+                    // Tags or ACL changed; only the .metadata sidecar is copied
+                    let source_metadata = PathBuf::from(format!("{}.metadata",
+                        self.config.storage_path.join(&entry.bucket).join(&entry.key).display()));
+                    let target_path = target_storage.join(&entry.bucket).join(&entry.key);
+                    let target_metadata = PathBuf::from(format!("{}.metadata", target_path.display()));
+
+                    // Only objects the target already has; a missing object arrives with its own PUT
+                    if source_metadata.exists() && target_path.exists() {
+                        fs::copy(&source_metadata, &target_metadata)?;
+                        info!("Replicated metadata of {}/{} to {}", entry.bucket, entry.key, target_node);
+                    }
+                }
                 "CREATE_BUCKET" => {
                     let target_path = target_storage.join(&entry.bucket);
                     if !target_path.exists() {
@@ -0,0 +1,2684 @@
+use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use reqwest::{Client, Method, StatusCode};
+use sha2::{Digest, Sha256};
+use std::time::Duration;
+
+type HmacSha256 = Hmac<Sha256>;
+
+#[derive(Debug, Clone)]
+struct SelfTestConfig {
+    endpoint: String,
+    access_key: String,
+    secret_key: String,
+    region: String,
+    bucket: String,
+}
+
+impl SelfTestConfig {
+    fn from_env() -> Self {
+        let endpoint = std::env::var("ENDPOINT")
+            .unwrap_or_else(|_| "http://localhost:9000".to_string())
+            .trim_end_matches('/')
+            .to_string();
+
+        let access_key = std::env::var("ACCESS_KEY")
+            .expect("ACCESS_KEY environment variable must be set");
+        let secret_key = std::env::var("SECRET_KEY")
+            .expect("SECRET_KEY environment variable must be set");
+
+        let region = std::env::var("REGION")
+            .unwrap_or_else(|_| "us-east-1".to_string());
+
+        // Use a unique bucket so repeated runs never collide
+        let bucket = std::env::var("SELFTEST_BUCKET")
+            .unwrap_or_else(|_| format!("selftest-{}", Utc::now().timestamp_millis()));
+
+        SelfTestConfig {
+            endpoint,
+            access_key,
+            secret_key,
+            region,
+            bucket,
+        }
+    }
+}
+
+struct S3Response {
+    status: StatusCode,
+    headers: reqwest::header::HeaderMap,
+    body: Vec<u8>,
+}
+
+impl S3Response {
+    fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).to_string()
+    }
+
+    fn header(&self, name: &str) -> Option<String> {
+        self.headers.get(name).and_then(|v| v.to_str().ok()).map(|v| v.to_string())
+    }
+}
+
+// Minimal S3 client that signs requests with AWS Signature V4
+struct S3Client {
+    config: SelfTestConfig,
+    http_client: Client,
+}
+
+impl S3Client {
+    fn new(config: SelfTestConfig) -> Self {
+        let http_client = Client::builder()
+            .timeout(Duration::from_secs(30))
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .expect("Failed to create HTTP client");
+
+        S3Client { config, http_client }
+    }
+
+    async fn send(
+        &self,
+        method: Method,
+        path: &str,
+        query: &[(&str, &str)],
+        headers: &[(&str, &str)],
+        body: Vec<u8>,
+    ) -> Result<S3Response, String> {
+        self.send_signed_at(Utc::now(), method, path, query, headers, body).await
+    }
+
+    // Sign the request as of the given time, which lets tests send requests from a skewed clock
+    async fn send_signed_at(
+        &self,
+        now: DateTime<Utc>,
+        method: Method,
+        path: &str,
+        query: &[(&str, &str)],
+        headers: &[(&str, &str)],
+        body: Vec<u8>,
+    ) -> Result<S3Response, String> {
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+        let payload_hash = hex(&Sha256::digest(&body));
+
+        let host = self.config.endpoint
+            .trim_start_matches("http://")
+            .trim_start_matches("https://")
+            .to_string();
+
+        let canonical_uri = path.split('/')
+            .map(|segment| urlencoding::encode(segment).into_owned())
+            .collect::<Vec<_>>()
+            .join("/");
+
+        let mut query_pairs: Vec<(String, String)> = query.iter()
+            .map(|(k, v)| (urlencoding::encode(k).into_owned(), urlencoding::encode(v).into_owned()))
+            .collect();
+        query_pairs.sort();
+        let canonical_query = query_pairs.iter()
+            .map(|(k, v)| format!("{}={}", k, v))
+            .collect::<Vec<_>>()
+            .join("&");
+
+        let canonical_request = format!(
+            "{}\n{}\n{}\nhost:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n\nhost;x-amz-content-sha256;x-amz-date\n{}",
+            method, canonical_uri, canonical_query, host, payload_hash, amz_date, payload_hash
+        );
+
+        let scope = format!("{}/{}/s3/aws4_request", date, self.config.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date, scope, hex(&Sha256::digest(canonical_request.as_bytes()))
+        );
+
+        let mut key = hmac(format!("AWS4{}", self.config.secret_key).as_bytes(), date.as_bytes());
+        for part in [self.config.region.as_str(), "s3", "aws4_request"] {
+            key = hmac(&key, part.as_bytes());
+        }
+        let signature = hex(&hmac(&key, string_to_sign.as_bytes()));
+
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders=host;x-amz-content-sha256;x-amz-date, Signature={}",
+            self.config.access_key, scope, signature
+        );
+
+        let mut url = format!("{}{}", self.config.endpoint, canonical_uri);
+        if !canonical_query.is_empty() {
+            url = format!("{}?{}", url, canonical_query);
+        }
+
+        let mut request = self.http_client.request(method, &url)
+            .header("x-amz-date", &amz_date)
+            .header("x-amz-content-sha256", &payload_hash)
+            .header("authorization", authorization)
+            .body(body);
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+
+        let response = request.send().await.map_err(|e| format!("request to {} failed: {}", url, e))?;
+        let status = response.status();
+        let headers = response.headers().clone();
+        let body = response.bytes().await.map_err(|e| format!("failed to read body: {}", e))?.to_vec();
+
+        Ok(S3Response { status, headers, body })
+    }
+
+    // Send a browser form upload to a bucket: the signing fields for a policy with the given conditions (which
+    // cover the signing fields themselves), then `fields`, which may override them, then the file
+    async fn send_form(&self, path: &str, conditions: &[serde_json::Value], fields: &[(&str, &str)], file: &[u8]) -> Result<S3Response, String> {
+        let now = Utc::now();
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+        let credential = format!("{}/{}/{}/s3/aws4_request", self.config.access_key, date, self.config.region);
+
+        let mut conditions = conditions.to_vec();
+        conditions.push(serde_json::json!({"x-amz-algorithm": "AWS4-HMAC-SHA256"}));
+        conditions.push(serde_json::json!({"x-amz-credential": credential}));
+        conditions.push(serde_json::json!({"x-amz-date": amz_date}));
+        let policy = serde_json::json!({
+            "expiration": (now + chrono::Duration::minutes(5)).format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+            "conditions": conditions,
+        });
+        let policy = BASE64.encode(policy.to_string());
+
+        let mut key = hmac(format!("AWS4{}", self.config.secret_key).as_bytes(), date.as_bytes());
+        for part in [self.config.region.as_str(), "s3", "aws4_request"] {
+            key = hmac(&key, part.as_bytes());
+        }
+        let signature = hex(&hmac(&key, policy.as_bytes()));
+
+        let boundary = format!("selftest-{}", now.timestamp_nanos_opt().unwrap_or_default());
+        let mut body = Vec::new();
+        let signing_fields = [
+            ("policy", policy.as_str()),
+            ("x-amz-algorithm", "AWS4-HMAC-SHA256"),
+            ("x-amz-credential", credential.as_str()),
+            ("x-amz-date", amz_date.as_str()),
+            ("x-amz-signature", signature.as_str()),
+        ];
+        for (name, value) in signing_fields.iter().chain(fields) {
+            body.extend_from_slice(format!("--{}\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n{}\r\n", boundary, name, value).as_bytes());
+        }
+        body.extend_from_slice(format!("--{}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"upload.txt\"\r\n\r\n", boundary).as_bytes());
+        body.extend_from_slice(file);
+        body.extend_from_slice(format!("\r\n--{}--\r\n", boundary).as_bytes());
+
+        let url = format!("{}{}", self.config.endpoint, path);
+        let response = self.http_client.post(&url)
+            .header("content-type", format!("multipart/form-data; boundary={}", boundary))
+            .body(body)
+            .send().await
+            .map_err(|e| format!("request to {} failed: {}", url, e))?;
+        let status = response.status();
+        let headers = response.headers().clone();
+        let body = response.bytes().await.map_err(|e| format!("failed to read body: {}", e))?.to_vec();
+
+        Ok(S3Response { status, headers, body })
+    }
+
+    // Send an unsigned request, as an anonymous client would; the path may carry a query string
+    async fn send_anonymous(&self, method: Method, path: &str) -> Result<S3Response, String> {
+        let url = format!("{}{}", self.config.endpoint, path);
+        let response = self.http_client.request(method, &url).send().await
+            .map_err(|e| format!("request to {} failed: {}", url, e))?;
+        let status = response.status();
+        let headers = response.headers().clone();
+        let body = response.bytes().await.map_err(|e| format!("failed to read body: {}", e))?.to_vec();
+
+        Ok(S3Response { status, headers, body })
+    }
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// Fail the current check unless the status matches
+fn expect_status(response: &S3Response, expected: StatusCode) -> Result<(), String> {
+    if response.status == expected {
+        Ok(())
+    } else {
+        Err(format!("expected {}, got {}: {}", expected, response.status, response.text()))
+    }
+}
+
+// An ETag as sent on the wire: exactly one pair of quotes around the hex digest
+fn expect_quoted_etag(etag: Option<String>, source: &str) -> Result<String, String> {
+    let etag = etag.ok_or_else(|| format!("missing ETag in {}", source))?;
+    let digest = etag.strip_prefix('"').and_then(|e| e.strip_suffix('"'));
+    match digest {
+        Some(digest) if !digest.is_empty() && digest.chars().all(|c| c.is_ascii_hexdigit() || c == '-') => Ok(etag),
+        _ => Err(format!("ETag in {} is not quoted exactly once: {}", source, etag)),
+    }
+}
+
+// Pull the text of the first <tag>...</tag> out of an XML body
+fn xml_value(xml: &str, tag: &str) -> Option<String> {
+    let open = format!("<{}>", tag);
+    let close = format!("</{}>", tag);
+    let start = xml.find(&open)? + open.len();
+    let end = xml[start..].find(&close)? + start;
+    Some(xml[start..end].to_string())
+}
+
+// Text of every <tag>...</tag> in an XML body, in document order
+fn xml_values(xml: &str, tag: &str) -> Vec<String> {
+    let close = format!("</{}>", tag);
+    xml.split(&format!("<{}>", tag))
+        .skip(1)
+        .filter_map(|rest| rest.split(&close).next().map(String::from))
+        .collect()
+}
+
+struct SelfTest {
+    client: S3Client,
+    bucket: String,
+    passed: usize,
+    failed: usize,
+}
+
+impl SelfTest {
+    fn new(config: SelfTestConfig) -> Self {
+        let bucket = config.bucket.clone();
+        SelfTest {
+            client: S3Client::new(config),
+            bucket,
+            passed: 0,
+            failed: 0,
+        }
+    }
+
+    fn record(&mut self, name: &str, result: Result<(), String>) {
+        match result {
+            Ok(()) => {
+                self.passed += 1;
+                println!("PASS  {}", name);
+            }
+            Err(e) => {
+                self.failed += 1;
+                println!("FAIL  {}: {}", name, e);
+            }
+        }
+    }
+
+    async fn run(&mut self) {
+        let result = self.create_bucket().await;
+        self.record("create bucket", result);
+        let result = self.put_and_get_object().await;
+        self.record("put and get object", result);
+        let result = self.checksum_trailer().await;
+        self.record("checksum trailer", result);
+        let result = self.root_options().await;
+        self.record("OPTIONS on the root", result);
+        let result = self.clock_skew().await;
+        self.record("request time too skewed", result);
+        let result = self.head_object().await;
+        self.record("head object", result);
+        let result = self.method_not_allowed().await;
+        self.record("method not allowed", result);
+        let result = self.config_body_limit().await;
+        self.record("configuration body limit", result);
+        let result = self.trailing_slash_subresources().await;
+        self.record("trailing-slash bucket subresources", result);
+        let result = self.cors_actual_requests().await;
+        self.record("CORS headers on object requests", result);
+        let result = self.object_ttl().await;
+        self.record("object TTL configuration", result);
+        let result = self.lifecycle_preview().await;
+        self.record("lifecycle dry run", result);
+        let result = self.default_storage_class().await;
+        self.record("bucket default storage class", result);
+        let result = self.restore_object().await;
+        self.record("restore archived object", result);
+        let result = self.bucket_logging().await;
+        self.record("bucket logging configuration", result);
+        let result = self.website_hosting().await;
+        self.record("static website hosting", result);
+        let result = self.subresource_status_codes().await;
+        self.record("subresource status codes", result);
+        let result = self.reserved_keys().await;
+        self.record("reserved keys", result);
+        let result = self.list_objects().await;
+        self.record("list objects", result);
+        let result = self.list_pagination_with_writes().await;
+        self.record("pagination across writes", result);
+        let result = self.delimiter_listing().await;
+        self.record("top-level folders with a delimiter", result);
+        let result = self.folder_marker().await;
+        self.record("folder marker listed as a key", result);
+        let result = self.gzip_listing().await;
+        self.record("gzip-compressed listing", result);
+        let result = self.etag_quoting().await;
+        self.record("ETag quoting", result);
+        let result = self.multipart_upload().await;
+        self.record("multipart upload", result);
+        let result = self.multipart_part_reupload().await;
+        self.record("multipart part re-upload", result);
+        let result = self.unknown_multipart_upload().await;
+        self.record("unknown multipart upload", result);
+        let result = self.versioning_states().await;
+        self.record("versioning status XML", result);
+        let result = self.versioning().await;
+        self.record("versioning", result);
+        let result = self.delete_markers().await;
+        self.record("delete markers", result);
+        let result = self.null_version_delete().await;
+        self.record("delete the null version", result);
+        let result = self.tagging().await;
+        self.record("object tagging", result);
+        let result = self.copy_tags().await;
+        self.record("copies keep tags", result);
+        let result = self.tagging_keeps_metadata().await;
+        self.record("tag changes keep object metadata", result);
+        let result = self.public_object_acl().await;
+        self.record("public-read object in a private bucket", result);
+        let result = self.default_object_acl().await;
+        self.record("bucket default object ACL", result);
+        let result = self.secure_transport_policy().await;
+        self.record("aws:SecureTransport policy condition", result);
+        let result = self.negated_policy_elements().await;
+        self.record("NotAction and NotResource policy", result);
+        let result = self.deny_after_allow_policy().await;
+        self.record("policy Deny after a matching Allow", result);
+        let result = self.account_policy_principals().await;
+        self.record("account and AWS wildcard principals", result);
+        let result = self.list_with_metadata().await;
+        self.record("list objects with metadata", result);
+        let result = self.creation_time().await;
+        self.record("creation time kept across metadata updates", result);
+        let result = self.intermediate_folder_markers().await;
+        self.record("intermediate folder markers", result);
+        let result = self.delimiter_prefix_pages().await;
+        self.record("delimiter prefix pages", result);
+        let result = self.exact_prefix_listing().await;
+        self.record("key equal to the listing prefix", result);
+        let result = self.list_modified_since().await;
+        self.record("list objects by modification time", result);
+        let result = self.copy_across_encryption().await;
+        self.record("copy across encryption", result);
+        let result = self.copy_replace_metadata().await;
+        self.record("copy with REPLACE clears metadata", result);
+        let result = self.key_rotation().await;
+        self.record("encryption key rotation", result);
+        let result = self.sse_customer_key().await;
+        self.record("SSE-C customer key", result);
+        let result = self.encrypted_ranges().await;
+        self.record("ranged reads of encrypted objects", result);
+        let result = self.quota_object_count().await;
+        self.record("quota object count", result);
+        let result = self.multipart_upload_cap().await;
+        self.record("multipart uploads per bucket", result);
+        let result = self.key_naming_policy().await;
+        self.record("key naming policy", result);
+        let result = self.post_form_upload().await;
+        self.record("POST form upload policy", result);
+        let result = self.delete_objects().await;
+        self.record("delete objects", result);
+        let result = self.force_delete_bucket().await;
+        self.record("force-delete a bucket with versions", result);
+        let result = self.delete_bucket().await;
+        self.record("delete bucket", result);
+    }
+
+    fn bucket_path(&self) -> String {
+        format!("/{}", self.bucket)
+    }
+
+    fn object_path(&self, key: &str) -> String {
+        format!("/{}/{}", self.bucket, key)
+    }
+
+    async fn create_bucket(&self) -> Result<(), String> {
+        let response = self.client.send(Method::PUT, &self.bucket_path(), &[], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::OK)?;
+
+        let response = self.client.send(Method::HEAD, &self.bucket_path(), &[], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::OK)?;
+
+        // The '_' prefix is reserved for the /_ironbucket endpoints
+        let response = self.client.send(Method::PUT, &format!("/_{}", self.bucket), &[], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::BAD_REQUEST)?;
+        if xml_value(&response.text(), "Code").as_deref() != Some("InvalidBucketName") {
+            return Err(format!("bucket name starting with '_' accepted: {}", response.text()));
+        }
+        Ok(())
+    }
+
+    async fn put_and_get_object(&self) -> Result<(), String> {
+        let body = b"hello from the ironbucket self-test".to_vec();
+        let response = self.client.send(
+            Method::PUT,
+            &self.object_path("hello.txt"),
+            &[],
+            &[("content-type", "text/plain"), ("x-amz-meta-origin", "selftest")],
+            body.clone(),
+        ).await?;
+        expect_status(&response, StatusCode::OK)?;
+
+        let response = self.client.send(Method::GET, &self.object_path("hello.txt"), &[], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::OK)?;
+        if response.body != body {
+            return Err(format!("content mismatch: got {:?}", response.text()));
+        }
+        if response.header("x-amz-request-id").is_none_or(|id| id.is_empty()) {
+            return Err("response has no x-amz-request-id".to_string());
+        }
+
+        let response = self.client.send(Method::GET, &self.object_path("hello.txt"), &[], &[("range", "bytes=6-9")], Vec::new()).await?;
+        expect_status(&response, StatusCode::PARTIAL_CONTENT)?;
+        if response.body != body[6..=9] {
+            return Err(format!("range mismatch: got {:?}", response.text()));
+        }
+        Ok(())
+    }
+
+    async fn checksum_trailer(&self) -> Result<(), String> {
+        let payload = b"payload with a crc32c trailer".to_vec();
+        let checksum = BASE64.encode(crc32c::crc32c(&payload).to_be_bytes());
+
+        // Unsigned aws-chunked body as sent by SDKs that default to trailing checksums
+        let chunked = |trailer: &str| {
+            let mut body = format!("{:x}\r\n", payload.len()).into_bytes();
+            body.extend_from_slice(&payload);
+            body.extend_from_slice(format!("\r\n0\r\nx-amz-checksum-crc32c:{}\r\n\r\n", trailer).as_bytes());
+            body
+        };
+        let headers = [("content-encoding", "aws-chunked"), ("x-amz-trailer", "x-amz-checksum-crc32c")];
+
+        let response = self.client.send(Method::PUT, &self.object_path("trailer.txt"), &[], &headers, chunked(&checksum)).await?;
+        expect_status(&response, StatusCode::OK)?;
+        let response = self.client.send(Method::GET, &self.object_path("trailer.txt"), &[], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::OK)?;
+        if response.body != payload {
+            return Err(format!("decoded payload mismatch: got {:?}", response.text()));
+        }
+
+        let response = self.client.send(Method::PUT, &self.object_path("trailer.txt"), &[], &headers, chunked("AAAAAA==")).await?;
+        expect_status(&response, StatusCode::BAD_REQUEST)?;
+        if xml_value(&response.text(), "Code").as_deref() != Some("BadDigest") {
+            return Err(format!("expected BadDigest: {}", response.text()));
+        }
+
+        // A body cut off before the final zero-length chunk is not stored
+        let mut truncated = chunked(&checksum);
+        truncated.truncate(payload.len() / 2);
+        let response = self.client.send(Method::PUT, &self.object_path("trailer.txt"), &[], &[("content-encoding", "aws-chunked")], truncated).await?;
+        expect_status(&response, StatusCode::BAD_REQUEST)?;
+        if xml_value(&response.text(), "Code").as_deref() != Some("IncompleteBody") {
+            return Err(format!("expected IncompleteBody: {}", response.text()));
+        }
+        let response = self.client.send(Method::GET, &self.object_path("trailer.txt"), &[], &[], Vec::new()).await?;
+        if response.body != payload {
+            return Err("truncated upload replaced the object".to_string());
+        }
+
+        let response = self.client.send(Method::DELETE, &self.object_path("trailer.txt"), &[], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::NO_CONTENT)
+    }
+
+    async fn root_options(&self) -> Result<(), String> {
+        let response = self.client.send(Method::OPTIONS, "/", &[], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::OK)?;
+        if response.header("allow").is_none() {
+            return Err("OPTIONS / did not return an Allow header".to_string());
+        }
+
+        let response = self.client.send(
+            Method::OPTIONS,
+            "/",
+            &[],
+            &[("origin", "https://app.example"), ("access-control-request-method", "GET")],
+            Vec::new(),
+        ).await?;
+        expect_status(&response, StatusCode::OK)?;
+        match response.header("access-control-allow-methods") {
+            Some(methods) if methods.contains("GET") => Ok(()),
+            other => Err(format!("root preflight did not allow GET: {:?}", other)),
+        }
+    }
+
+    // Requests signed more than MAX_CLOCK_SKEW_SECS (15 minutes by default) ago are refused
+    async fn clock_skew(&self) -> Result<(), String> {
+        let signed_at = Utc::now() - chrono::Duration::hours(1);
+        let response = self.client.send_signed_at(signed_at, Method::GET, &self.bucket_path(), &[], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::FORBIDDEN)?;
+        if xml_value(&response.text(), "Code").as_deref() != Some("RequestTimeTooSkewed") {
+            return Err(format!("expected RequestTimeTooSkewed: {}", response.text()));
+        }
+
+        let signed_at = Utc::now() - chrono::Duration::minutes(5);
+        let response = self.client.send_signed_at(signed_at, Method::GET, &self.bucket_path(), &[], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::OK)
+    }
+
+    async fn head_object(&self) -> Result<(), String> {
+        let response = self.client.send(Method::HEAD, &self.object_path("hello.txt"), &[], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::OK)?;
+
+        match response.header("content-length").as_deref() {
+            Some("35") => {}
+            other => return Err(format!("unexpected Content-Length {:?}", other)),
+        }
+        if response.header("etag").is_none() {
+            return Err("missing ETag header".to_string());
+        }
+
+        let response = self.client.send(Method::HEAD, &self.object_path("missing.txt"), &[], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::NOT_FOUND)
+    }
+
+    async fn method_not_allowed(&self) -> Result<(), String> {
+        let response = self.client.send(Method::PATCH, &self.object_path("hello.txt"), &[], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::METHOD_NOT_ALLOWED)?;
+        if xml_value(&response.text(), "Code").as_deref() != Some("MethodNotAllowed") {
+            return Err(format!("expected MethodNotAllowed: {}", response.text()));
+        }
+        let allow = response.header("allow").unwrap_or_default();
+        let methods: Vec<&str> = allow.split(',').map(str::trim).collect();
+        for method in ["GET", "HEAD", "PUT", "DELETE"] {
+            if methods.iter().filter(|m| **m == method).count() != 1 {
+                return Err(format!("Allow header should list {} once: {:?}", method, allow));
+            }
+        }
+
+        // POST on an object without ?uploads or ?uploadId is not served either
+        let response = self.client.send(Method::POST, &self.object_path("hello.txt"), &[], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::METHOD_NOT_ALLOWED)?;
+        match response.header("allow") {
+            Some(allow) if !allow.contains("POST") => Ok(()),
+            other => Err(format!("unexpected Allow header {:?}", other)),
+        }
+    }
+
+    // Assumes the default CONFIG_BODY_MAX_BYTES of 2 MiB
+    async fn config_body_limit(&self) -> Result<(), String> {
+        let oversized = vec![b' '; 3 * 1024 * 1024];
+        // The server stops reading the body and drops the connection, which must not go back to the pool
+        let headers = [("connection", "close")];
+        let response = self.client.send(Method::PUT, &self.bucket_path(), &[("policy", "")], &headers, oversized.clone()).await?;
+        expect_status(&response, StatusCode::PAYLOAD_TOO_LARGE)?;
+        let response = self.client.send(Method::PUT, &self.object_path("hello.txt"), &[("tagging", "")], &headers, oversized).await?;
+        expect_status(&response, StatusCode::PAYLOAD_TOO_LARGE)
+    }
+
+    async fn list_objects(&self) -> Result<(), String> {
+        let response = self.client.send(Method::GET, &self.bucket_path(), &[("list-type", "2")], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::OK)?;
+
+        let xml = response.text();
+        if !xml.contains("<Key>hello.txt</Key>") {
+            return Err(format!("hello.txt missing from listing: {}", xml));
+        }
+
+        // max-keys=0 probes the bucket without returning any keys; hello.txt lies beyond the empty page
+        let response = self.client.send(Method::GET, &self.bucket_path(), &[("list-type", "2"), ("max-keys", "0")], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::OK)?;
+        let xml = response.text();
+        if xml.contains("<Contents>")
+            || xml_value(&xml, "KeyCount").as_deref() != Some("0")
+            || xml_value(&xml, "IsTruncated").as_deref() != Some("true")
+        {
+            return Err(format!("unexpected max-keys=0 listing: {}", xml));
+        }
+
+        // Its token resumes at the start of the listing
+        let token = xml_value(&xml, "NextContinuationToken").ok_or_else(|| format!("max-keys=0 listing without a token: {}", xml))?;
+        let response = self.client.send(Method::GET, &self.bucket_path(), &[("list-type", "2"), ("continuation-token", &token)], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::OK)?;
+        if !response.text().contains("<Key>hello.txt</Key>") {
+            return Err(format!("hello.txt missing after the max-keys=0 token: {}", response.text()));
+        }
+        Ok(())
+    }
+
+    // Keys stay at the top level, as an emptied folder would keep the bucket from being deleted
+    async fn list_pagination_with_writes(&self) -> Result<(), String> {
+        let initial = ["paging-a.txt", "paging-c.txt", "paging-e.txt"];
+        for key in initial {
+            let response = self.client.send(Method::PUT, &self.object_path(key), &[], &[], b"x".to_vec()).await?;
+            expect_status(&response, StatusCode::OK)?;
+        }
+
+        let result = async {
+            let query = [("list-type", "2"), ("prefix", "paging-"), ("max-keys", "2")];
+            let response = self.client.send(Method::GET, &self.bucket_path(), &query, &[], Vec::new()).await?;
+            expect_status(&response, StatusCode::OK)?;
+            let xml = response.text();
+            let first_page = xml_values(&xml, "Key");
+            let token = xml_value(&xml, "NextContinuationToken").ok_or_else(|| format!("first page not truncated: {}", xml))?;
+            if first_page != ["paging-a.txt", "paging-c.txt"] {
+                return Err(format!("unexpected first page {:?}", first_page));
+            }
+
+            // b sorts before the token and must not show up again; d sorts after it and must not be skipped
+            for key in ["paging-b.txt", "paging-d.txt"] {
+                let response = self.client.send(Method::PUT, &self.object_path(key), &[], &[], b"x".to_vec()).await?;
+                expect_status(&response, StatusCode::OK)?;
+            }
+            let response = self.client.send(Method::DELETE, &self.object_path("paging-a.txt"), &[], &[], Vec::new()).await?;
+            expect_status(&response, StatusCode::NO_CONTENT)?;
+
+            let response = self.client.send(Method::GET, &self.bucket_path(), &[("list-type", "2"), ("prefix", "paging-"), ("continuation-token", &token)], &[], Vec::new()).await?;
+            expect_status(&response, StatusCode::OK)?;
+            let second_page = xml_values(&response.text(), "Key");
+            if second_page != ["paging-d.txt", "paging-e.txt"] {
+                return Err(format!("expected d and e after the token, got {:?}", second_page));
+            }
+
+            let response = self.client.send(Method::GET, &self.bucket_path(), &[("list-type", "2"), ("continuation-token", "not a token")], &[], Vec::new()).await?;
+            expect_status(&response, StatusCode::BAD_REQUEST)
+        }.await;
+
+        for key in ["paging-a.txt", "paging-b.txt", "paging-c.txt", "paging-d.txt", "paging-e.txt"] {
+            self.client.send(Method::DELETE, &self.object_path(key), &[], &[], Vec::new()).await?;
+        }
+        result
+    }
+
+    async fn delimiter_listing(&self) -> Result<(), String> {
+        let tree_path = format!("/{}-tree", self.bucket);
+        let response = self.client.send(Method::PUT, &tree_path, &[], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::OK)?;
+        let keys = ["top.txt", "docs/c.md", "photos/a.jpg", "photos/2024/b.jpg"];
+        for key in keys {
+            let response = self.client.send(Method::PUT, &format!("{}/{}", tree_path, key), &[], &[], b"x".to_vec()).await?;
+            expect_status(&response, StatusCode::OK)?;
+        }
+
+        // Without a prefix only the first level shows: files as Contents, folders as CommonPrefixes
+        let response = self.client.send(Method::GET, &tree_path, &[("list-type", "2"), ("delimiter", "/")], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::OK)?;
+        let xml = response.text();
+        let listed = (xml_values(&xml, "Key"), xml_values(&xml, "Prefix"));
+        let expected = (vec!["top.txt".to_string()], vec!["".to_string(), "docs/".to_string(), "photos/".to_string()]);
+        let result = if listed == expected {
+            Ok(())
+        } else {
+            Err(format!("expected keys and prefixes {:?}, got {:?}", expected, listed))
+        };
+
+        // The folders are left as empty directories once their keys are gone
+        for key in keys.into_iter().chain(["photos/2024/", "photos/", "docs/"]) {
+            let response = self.client.send(Method::DELETE, &format!("{}/{}", tree_path, key), &[], &[], Vec::new()).await?;
+            expect_status(&response, StatusCode::NO_CONTENT)?;
+        }
+        let response = self.client.send(Method::DELETE, &tree_path, &[], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::NO_CONTENT)?;
+        result
+    }
+
+    async fn folder_marker(&self) -> Result<(), String> {
+        let folder_path = format!("/{}/empty-folder/", self.bucket);
+        let response = self.client.send(Method::PUT, &folder_path, &[], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::OK)?;
+
+        let result = async {
+            let response = self.client.send(Method::GET, &format!("/{}", self.bucket), &[("list-type", "2"), ("prefix", "empty-folder/")], &[], Vec::new()).await?;
+            expect_status(&response, StatusCode::OK)?;
+            let xml = response.text();
+            let listed = (xml_values(&xml, "Key"), xml_value(&xml, "Size"));
+            if listed != (vec!["empty-folder/".to_string()], Some("0".to_string())) {
+                return Err(format!("expected the zero-byte key empty-folder/, got {:?}", listed));
+            }
+
+            let response = self.client.send(Method::HEAD, &folder_path, &[], &[], Vec::new()).await?;
+            expect_status(&response, StatusCode::OK)?;
+            let response = self.client.send(Method::GET, &folder_path, &[], &[], Vec::new()).await?;
+            expect_status(&response, StatusCode::OK)?;
+            if !response.body.is_empty() || response.header("content-length").as_deref() != Some("0") {
+                return Err(format!("GET on the folder marker returned {} bytes", response.body.len()));
+            }
+
+            // A folder that only exists because of the keys beneath it is not an object
+            let response = self.client.send(Method::PUT, &format!("/{}/implied-folder/file.txt", self.bucket), &[], &[], b"x".to_vec()).await?;
+            expect_status(&response, StatusCode::OK)?;
+            for method in [Method::GET, Method::HEAD] {
+                let response = self.client.send(method, &format!("/{}/implied-folder/", self.bucket), &[], &[], Vec::new()).await?;
+                expect_status(&response, StatusCode::NOT_FOUND)?;
+            }
+            Ok(())
+        }.await;
+
+        // Deleting the folder key last also removes the directory the file left behind
+        let cleanup = [folder_path, format!("/{}/implied-folder/file.txt", self.bucket), format!("/{}/implied-folder/", self.bucket)];
+        for path in cleanup {
+            let response = self.client.send(Method::DELETE, &path, &[], &[], Vec::new()).await?;
+            expect_status(&response, StatusCode::NO_CONTENT)?;
+        }
+        result
+    }
+
+    async fn gzip_listing(&self) -> Result<(), String> {
+        let gzip_path = format!("/{}-gzip", self.bucket);
+        let response = self.client.send(Method::PUT, &gzip_path, &[], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::OK)?;
+        let keys: Vec<String> = (0..20).map(|i| format!("reports/2024/quarterly-summary-{:02}.csv", i)).collect();
+        for key in &keys {
+            let response = self.client.send(Method::PUT, &format!("{}/{}", gzip_path, key), &[], &[], b"x".to_vec()).await?;
+            expect_status(&response, StatusCode::OK)?;
+        }
+
+        let result = async {
+            let response = self.client.send(Method::GET, &gzip_path, &[("list-type", "2")], &[("accept-encoding", "gzip")], Vec::new()).await?;
+            expect_status(&response, StatusCode::OK)?;
+            if response.header("content-encoding").as_deref() != Some("gzip") || response.header("content-length").is_some() {
+                return Err(format!("listing was not gzipped: {:?}", response.headers));
+            }
+
+            // Clients that do not ask for gzip get the plain XML with its length
+            let response = self.client.send(Method::GET, &gzip_path, &[("list-type", "2")], &[], Vec::new()).await?;
+            expect_status(&response, StatusCode::OK)?;
+            if response.header("content-encoding").is_some()
+                || response.header("content-length") != Some(response.body.len().to_string())
+            {
+                return Err(format!("plain listing has wrong headers: {:?}", response.headers));
+            }
+
+            // Object bodies are never compressed
+            let response = self.client.send(Method::GET, &format!("{}/{}", gzip_path, keys[0]), &[], &[("accept-encoding", "gzip")], Vec::new()).await?;
+            expect_status(&response, StatusCode::OK)?;
+            match response.header("content-encoding") {
+                None => Ok(()),
+                Some(encoding) => Err(format!("object body was encoded as {}", encoding)),
+            }
+        }.await;
+
+        for key in keys.iter().map(String::as_str).chain(["reports/2024/", "reports/"]) {
+            let response = self.client.send(Method::DELETE, &format!("{}/{}", gzip_path, key), &[], &[], Vec::new()).await?;
+            expect_status(&response, StatusCode::NO_CONTENT)?;
+        }
+        let response = self.client.send(Method::DELETE, &gzip_path, &[], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::NO_CONTENT)?;
+        result
+    }
+
+    async fn etag_quoting(&self) -> Result<(), String> {
+        let response = self.client.send(Method::GET, &self.object_path("hello.txt"), &[], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::OK)?;
+        let etag = expect_quoted_etag(response.header("etag"), "GET")?;
+
+        let response = self.client.send(Method::HEAD, &self.object_path("hello.txt"), &[], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::OK)?;
+        let mut found = vec![("HEAD", expect_quoted_etag(response.header("etag"), "HEAD")?)];
+
+        let response = self.client.send(Method::GET, &self.bucket_path(), &[("list-type", "2"), ("prefix", "hello.txt")], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::OK)?;
+        // With LIST_FETCH_METADATA=false listings carry a synthetic ETag, so only its form is checked
+        expect_quoted_etag(xml_value(&response.text(), "ETag"), "listing")?;
+
+        let source = format!("{}/hello.txt", self.bucket);
+        let copy_path = self.object_path("etag-copy.txt");
+        let response = self.client.send(Method::PUT, &copy_path, &[], &[("x-amz-copy-source", &source)], Vec::new()).await?;
+        expect_status(&response, StatusCode::OK)?;
+        found.push(("copy header", expect_quoted_etag(response.header("etag"), "copy header")?));
+        found.push(("copy result", expect_quoted_etag(xml_value(&response.text(), "ETag"), "copy result")?));
+        let response = self.client.send(Method::DELETE, &copy_path, &[], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::NO_CONTENT)?;
+
+        match found.into_iter().find(|(_, other)| *other != etag) {
+            Some((source, other)) => Err(format!("{} ETag {} differs from GET ETag {}", source, other, etag)),
+            None => Ok(()),
+        }
+    }
+
+    async fn trailing_slash_subresources(&self) -> Result<(), String> {
+        let slash_path = format!("{}/", self.bucket_path());
+        let cors = "<CORSConfiguration><CORSRule><AllowedOrigin>*</AllowedOrigin><AllowedMethod>GET</AllowedMethod></CORSRule></CORSConfiguration>";
+        let response = self.client.send(Method::PUT, &slash_path, &[("cors", "")], &[], cors.as_bytes().to_vec()).await?;
+        expect_status(&response, StatusCode::OK)?;
+
+        // Quota and stats carry timestamps, so only their status is compared
+        let subresources = [
+            "policy", "cors", "encryption", "lifecycle", "versioning", "location", "acl", "versions", "uploads",
+            "logging", "quota", "stats",
+        ];
+        for subresource in subresources {
+            let plain = self.client.send(Method::GET, &self.bucket_path(), &[(subresource, "")], &[], Vec::new()).await?;
+            let slashed = self.client.send(Method::GET, &slash_path, &[(subresource, "")], &[], Vec::new()).await?;
+            if plain.status != slashed.status {
+                return Err(format!("?{}: {} without slash, {} with slash", subresource, plain.status, slashed.status));
+            }
+            if subresource != "quota" && subresource != "stats" && plain.body != slashed.body {
+                return Err(format!("?{}: bodies differ: {:?} vs {:?}", subresource, plain.text(), slashed.text()));
+            }
+        }
+
+        let response = self.client.send(Method::DELETE, &self.bucket_path(), &[("cors", "")], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::NO_CONTENT)?;
+        let response = self.client.send(Method::GET, &slash_path, &[("cors", "")], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::NOT_FOUND)
+    }
+
+    // Object responses carry the headers of the matching CORS rule, and none for origins no rule allows
+    async fn cors_actual_requests(&self) -> Result<(), String> {
+        let path = self.object_path("cors.txt");
+        let cors = "<CORSConfiguration><CORSRule><AllowedOrigin>https://app.example</AllowedOrigin><AllowedMethod>GET</AllowedMethod>\
+            <AllowedMethod>PUT</AllowedMethod><ExposeHeader>ETag</ExposeHeader></CORSRule></CORSConfiguration>";
+        let response = self.client.send(Method::PUT, &self.bucket_path(), &[("cors", "")], &[], cors.as_bytes().to_vec()).await?;
+        expect_status(&response, StatusCode::OK)?;
+
+        let result = async {
+            let response = self.client.send(Method::PUT, &path, &[], &[("origin", "https://app.example")], b"cors".to_vec()).await?;
+            expect_status(&response, StatusCode::OK)?;
+            if response.header("access-control-allow-origin").as_deref() != Some("https://app.example") {
+                return Err(format!("PUT Access-Control-Allow-Origin is {:?}", response.header("access-control-allow-origin")));
+            }
+            if response.header("access-control-expose-headers").as_deref() != Some("ETag") {
+                return Err(format!("PUT Access-Control-Expose-Headers is {:?}", response.header("access-control-expose-headers")));
+            }
+
+            let response = self.client.send(Method::GET, &path, &[], &[("origin", "https://other.example")], Vec::new()).await?;
+            expect_status(&response, StatusCode::OK)?;
+            match response.header("access-control-allow-origin") {
+                None => Ok(()),
+                Some(origin) => Err(format!("GET from a disallowed origin got Access-Control-Allow-Origin {}", origin)),
+            }
+        }.await;
+
+        self.client.send(Method::DELETE, &self.bucket_path(), &[("cors", "")], &[], Vec::new()).await?;
+        let response = self.client.send(Method::DELETE, &path, &[], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::NO_CONTENT)?;
+        result
+    }
+
+    async fn object_ttl(&self) -> Result<(), String> {
+        let response = self.client.send(Method::PUT, &self.bucket_path(), &[("ttl", "")], &[], br#"{"seconds": 0}"#.to_vec()).await?;
+        expect_status(&response, StatusCode::BAD_REQUEST)?;
+
+        let response = self.client.send(Method::PUT, &self.bucket_path(), &[("ttl", "")], &[], br#"{"seconds": 3600}"#.to_vec()).await?;
+        expect_status(&response, StatusCode::OK)?;
+        let response = self.client.send(Method::GET, &self.bucket_path(), &[("ttl", "")], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::OK)?;
+        let ttl: serde_json::Value = serde_json::from_slice(&response.body).map_err(|e| format!("invalid TTL JSON: {}", e))?;
+        if ttl["seconds"] != 3600 {
+            return Err(format!("unexpected TTL: {}", response.text()));
+        }
+
+        let response = self.client.send(Method::DELETE, &self.bucket_path(), &[("ttl", "")], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::NO_CONTENT)?;
+        let response = self.client.send(Method::GET, &self.bucket_path(), &[("ttl", "")], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::NOT_FOUND)
+    }
+
+    // The preview lists due transitions with their rule, then TTL expiries instead, and changes nothing
+    async fn lifecycle_preview(&self) -> Result<(), String> {
+        let lifecycle_bucket = format!("{}-lifecycle", self.bucket);
+        let lifecycle_path = format!("/{}", lifecycle_bucket);
+        let response = self.client.send(Method::PUT, &lifecycle_path, &[], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::OK)?;
+
+        let result = async {
+            for key in ["cold/a.txt", "warm/b.txt"] {
+                let response = self.client.send(Method::PUT, &format!("{}/{}", lifecycle_path, key), &[], &[], b"aging".to_vec()).await?;
+                expect_status(&response, StatusCode::OK)?;
+            }
+            let lifecycle = "<LifecycleConfiguration><Rule><ID>to-glacier</ID><Status>Enabled</Status><Filter><Prefix>cold/</Prefix></Filter>\
+                <Transition><Days>0</Days><StorageClass>GLACIER</StorageClass></Transition></Rule></LifecycleConfiguration>";
+            let response = self.client.send(Method::PUT, &lifecycle_path, &[("lifecycle", "")], &[], lifecycle.as_bytes().to_vec()).await?;
+            expect_status(&response, StatusCode::OK)?;
+
+            let preview_path = format!("/_ironbucket/lifecycle-preview/{}", lifecycle_bucket);
+            let preview = || async {
+                let response = self.client.send(Method::GET, &preview_path, &[], &[], Vec::new()).await?;
+                expect_status(&response, StatusCode::OK)?;
+                serde_json::from_slice::<serde_json::Value>(&response.body).map_err(|e| format!("invalid preview JSON: {}", e))
+            };
+
+            let first = preview().await?;
+            let expected = serde_json::json!([{"key": "cold/a.txt", "rule_id": "to-glacier", "from": "STANDARD", "to": "GLACIER"}]);
+            if first["transitions"] != expected || first["expirations"] != serde_json::json!([]) {
+                return Err(format!("unexpected preview: {}", first));
+            }
+            // A GLACIER object could not be read without a restore
+            let response = self.client.send(Method::GET, &format!("{}/cold/a.txt", lifecycle_path), &[], &[], Vec::new()).await?;
+            expect_status(&response, StatusCode::OK)?;
+
+            // Objects past the TTL are expired by the sweep before any transition
+            let response = self.client.send(Method::PUT, &lifecycle_path, &[("ttl", "")], &[], br#"{"seconds": 1}"#.to_vec()).await?;
+            expect_status(&response, StatusCode::OK)?;
+            tokio::time::sleep(Duration::from_millis(1100)).await;
+            let second = preview().await?;
+            let mut expired: Vec<&str> = second["expirations"].as_array().into_iter().flatten()
+                .filter(|expiration| expiration["reason"] == "ttl")
+                .filter_map(|expiration| expiration["key"].as_str())
+                .collect();
+            expired.sort();
+            if expired != ["cold/a.txt", "warm/b.txt"] || second["transitions"] != serde_json::json!([]) {
+                return Err(format!("unexpected preview after the TTL: {}", second));
+            }
+            let response = self.client.send(Method::HEAD, &format!("{}/warm/b.txt", lifecycle_path), &[], &[], Vec::new()).await?;
+            expect_status(&response, StatusCode::OK)
+        }.await;
+
+        let response = self.client.send(Method::DELETE, &lifecycle_path, &[("force", "")], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::NO_CONTENT)?;
+        result
+    }
+
+    // Uploads without x-amz-storage-class take the bucket's default; an explicit header still wins
+    async fn default_storage_class(&self) -> Result<(), String> {
+        let path = self.object_path("infrequent.txt");
+        let response = self.client.send(Method::PUT, &self.bucket_path(), &[("storage-class", "")], &[], br#"{"storage_class": "COLD"}"#.to_vec()).await?;
+        expect_status(&response, StatusCode::BAD_REQUEST)?;
+        let response = self.client.send(Method::PUT, &self.bucket_path(), &[("storage-class", "")], &[], br#"{"storage_class": "STANDARD_IA"}"#.to_vec()).await?;
+        expect_status(&response, StatusCode::OK)?;
+
+        let result = async {
+            let response = self.client.send(Method::PUT, &path, &[], &[], b"rarely read".to_vec()).await?;
+            expect_status(&response, StatusCode::OK)?;
+            let response = self.client.send(Method::HEAD, &path, &[], &[], Vec::new()).await?;
+            expect_status(&response, StatusCode::OK)?;
+            if response.header("x-amz-storage-class").as_deref() != Some("STANDARD_IA") {
+                return Err(format!("default storage class not applied: {:?}", response.header("x-amz-storage-class")));
+            }
+
+            let response = self.client.send(Method::PUT, &path, &[], &[("x-amz-storage-class", "STANDARD")], b"read often".to_vec()).await?;
+            expect_status(&response, StatusCode::OK)?;
+            let response = self.client.send(Method::HEAD, &path, &[], &[], Vec::new()).await?;
+            expect_status(&response, StatusCode::OK)?;
+            if let Some(storage_class) = response.header("x-amz-storage-class") {
+                return Err(format!("explicit STANDARD stored as {}", storage_class));
+            }
+
+            let response = self.client.send(Method::PUT, &path, &[], &[("x-amz-storage-class", "COLD")], b"invalid".to_vec()).await?;
+            expect_status(&response, StatusCode::BAD_REQUEST)
+        }.await;
+
+        let response = self.client.send(Method::DELETE, &self.bucket_path(), &[("storage-class", "")], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::NO_CONTENT)?;
+        let response = self.client.send(Method::DELETE, &path, &[], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::NO_CONTENT)?;
+        let response = self.client.send(Method::GET, &self.bucket_path(), &[("storage-class", "")], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::NOT_FOUND)?;
+        result
+    }
+
+    // Only archive classes can be restored; objects uploaded to one are readable without a restore
+    async fn restore_object(&self) -> Result<(), String> {
+        let standard_path = self.object_path("restore-standard.txt");
+        let archived_path = self.object_path("restore-glacier.txt");
+        let restore_request = b"<RestoreRequest><Days>1</Days></RestoreRequest>".to_vec();
+
+        let result = async {
+            let response = self.client.send(Method::POST, &archived_path, &[("restore", "")], &[], restore_request.clone()).await?;
+            expect_status(&response, StatusCode::NOT_FOUND)?;
+
+            let response = self.client.send(Method::PUT, &standard_path, &[], &[], b"warm".to_vec()).await?;
+            expect_status(&response, StatusCode::OK)?;
+            let response = self.client.send(Method::POST, &standard_path, &[("restore", "")], &[], restore_request.clone()).await?;
+            expect_status(&response, StatusCode::FORBIDDEN)?;
+            if xml_value(&response.text(), "Code").as_deref() != Some("InvalidObjectState") {
+                return Err(format!("STANDARD object restored: {}", response.text()));
+            }
+
+            let response = self.client.send(Method::PUT, &archived_path, &[], &[("x-amz-storage-class", "GLACIER")], b"cold".to_vec()).await?;
+            expect_status(&response, StatusCode::OK)?;
+            let response = self.client.send(Method::GET, &archived_path, &[], &[], Vec::new()).await?;
+            expect_status(&response, StatusCode::OK)?;
+            if response.body != b"cold" {
+                return Err(format!("unexpected GLACIER data: {}", response.text()));
+            }
+            let response = self.client.send(Method::POST, &archived_path, &[("restore", "")], &[], restore_request.clone()).await?;
+            expect_status(&response, StatusCode::OK)
+        }.await;
+
+        for path in [&standard_path, &archived_path] {
+            let response = self.client.send(Method::DELETE, path, &[], &[], Vec::new()).await?;
+            expect_status(&response, StatusCode::NO_CONTENT)?;
+        }
+        result
+    }
+
+    async fn bucket_logging(&self) -> Result<(), String> {
+        let target_bucket = format!("{}-logs", self.bucket);
+        let config = |target: &str| format!(
+            "<BucketLoggingStatus><LoggingEnabled><TargetBucket>{}</TargetBucket><TargetPrefix>access/</TargetPrefix></LoggingEnabled></BucketLoggingStatus>",
+            target
+        );
+
+        let response = self.client.send(Method::PUT, &self.bucket_path(), &[("logging", "")], &[], config(&target_bucket).into_bytes()).await?;
+        expect_status(&response, StatusCode::BAD_REQUEST)?;
+        if xml_value(&response.text(), "Code").as_deref() != Some("InvalidTargetBucketForLogging") {
+            return Err(format!("missing target bucket was accepted: {}", response.text()));
+        }
+
+        let target_path = format!("/{}", target_bucket);
+        let response = self.client.send(Method::PUT, &target_path, &[], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::OK)?;
+        let response = self.client.send(Method::PUT, &self.bucket_path(), &[("logging", "")], &[], config(&target_bucket).into_bytes()).await?;
+        expect_status(&response, StatusCode::OK)?;
+
+        let response = self.client.send(Method::GET, &self.bucket_path(), &[("logging", "")], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::OK)?;
+        let xml = response.text();
+        if xml_value(&xml, "TargetBucket").as_deref() != Some(target_bucket.as_str())
+            || xml_value(&xml, "TargetPrefix").as_deref() != Some("access/")
+        {
+            return Err(format!("logging configuration not returned: {}", xml));
+        }
+
+        // An empty BucketLoggingStatus turns logging off
+        let response = self.client.send(Method::PUT, &self.bucket_path(), &[("logging", "")], &[], b"<BucketLoggingStatus/>".to_vec()).await?;
+        expect_status(&response, StatusCode::OK)?;
+        let response = self.client.send(Method::GET, &self.bucket_path(), &[("logging", "")], &[], Vec::new()).await?;
+        if response.text().contains("<LoggingEnabled>") {
+            return Err(format!("logging still enabled: {}", response.text()));
+        }
+
+        let response = self.client.send(Method::DELETE, &target_path, &[], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::NO_CONTENT)
+    }
+
+    async fn website_hosting(&self) -> Result<(), String> {
+        let site_bucket = format!("{}-site", self.bucket);
+        let site_path = format!("/{}", site_bucket);
+        let website_path = format!("/_ironbucket/website/{}", site_bucket);
+        let response = self.client.send(Method::PUT, &site_path, &[], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::OK)?;
+
+        // Bucket policies also govern signed requests, so the grant is s3:* rather than just s3:GetObject
+        let policy = format!(
+            r#"{{"Version":"2012-10-17","Statement":[{{"Effect":"Allow","Principal":"*","Action":"s3:*","Resource":"arn:aws:s3:::{}/*"}}]}}"#,
+            site_bucket
+        );
+        let response = self.client.send(Method::PUT, &site_path, &[("policy", "")], &[], policy.into_bytes()).await?;
+        expect_status(&response, StatusCode::NO_CONTENT)?;
+        let pages = [
+            ("index.html", "text/html", "home"),
+            ("docs/index.html", "text/html", "docs"),
+            ("blob/index.html", "application/octet-stream", "binary"),
+            ("error.html", "text/html; charset=utf-8", "oops"),
+        ];
+        for (key, content_type, body) in pages {
+            let response = self.client.send(Method::PUT, &format!("{}/{}", site_path, key), &[], &[("content-type", content_type)], body.as_bytes().to_vec()).await?;
+            expect_status(&response, StatusCode::OK)?;
+        }
+
+        let response = self.client.send(Method::PUT, &format!("{}/old.html", site_path), &[], &[("x-amz-website-redirect-location", "/docs/")], Vec::new()).await?;
+        expect_status(&response, StatusCode::OK)?;
+        let response = self.client.send(Method::PUT, &format!("{}/bad.html", site_path), &[], &[("x-amz-website-redirect-location", "docs/")], Vec::new()).await?;
+        expect_status(&response, StatusCode::BAD_REQUEST)?;
+
+        let config = "<WebsiteConfiguration><IndexDocument><Suffix>index.html</Suffix></IndexDocument><ErrorDocument><Key>error.html</Key></ErrorDocument></WebsiteConfiguration>";
+        let response = self.client.send(Method::PUT, &site_path, &[("website", "")], &[], config.as_bytes().to_vec()).await?;
+        expect_status(&response, StatusCode::OK)?;
+
+        let result = async {
+            let response = self.client.send(Method::GET, &format!("{}/", website_path), &[], &[], Vec::new()).await?;
+            expect_status(&response, StatusCode::OK)?;
+            if response.text() != "home" {
+                return Err(format!("root did not serve the index document: {}", response.text()));
+            }
+
+            let response = self.client.send(Method::GET, &format!("{}/docs", website_path), &[], &[], Vec::new()).await?;
+            expect_status(&response, StatusCode::FOUND)?;
+            if response.header("location").as_deref() != Some(format!("{}/docs/", website_path).as_str()) {
+                return Err(format!("directory redirect went to {:?}", response.header("location")));
+            }
+
+            // Redirect objects answer with a 301, and GET shows where they point
+            let response = self.client.send(Method::GET, &format!("{}/old.html", website_path), &[], &[], Vec::new()).await?;
+            expect_status(&response, StatusCode::MOVED_PERMANENTLY)?;
+            if response.header("location").as_deref() != Some(format!("{}/docs/", website_path).as_str()) {
+                return Err(format!("redirect object pointed to {:?}", response.header("location")));
+            }
+            let response = self.client.send(Method::GET, &format!("{}/old.html", site_path), &[], &[], Vec::new()).await?;
+            expect_status(&response, StatusCode::OK)?;
+            if response.header("x-amz-website-redirect-location").as_deref() != Some("/docs/") {
+                return Err("GET did not return x-amz-website-redirect-location".to_string());
+            }
+
+            // A binary index document is not served as a page; the error document is
+            for path in ["missing.html", "blob/"] {
+                let response = self.client.send(Method::GET, &format!("{}/{}", website_path, path), &[], &[], Vec::new()).await?;
+                expect_status(&response, StatusCode::NOT_FOUND)?;
+                if response.text() != "oops" {
+                    return Err(format!("{} did not serve the error document: {}", path, response.text()));
+                }
+            }
+
+            // With the error document gone the 404 is plain
+            let response = self.client.send(Method::DELETE, &format!("{}/error.html", site_path), &[], &[], Vec::new()).await?;
+            expect_status(&response, StatusCode::NO_CONTENT)?;
+            let response = self.client.send(Method::GET, &format!("{}/missing.html", website_path), &[], &[], Vec::new()).await?;
+            expect_status(&response, StatusCode::NOT_FOUND)
+        }.await;
+
+        // error.html may already be gone, so only the bucket delete below checks the cleanup
+        for key in pages.iter().map(|(key, _, _)| *key).chain(["old.html"]) {
+            self.client.send(Method::DELETE, &format!("{}/{}", site_path, key), &[], &[], Vec::new()).await?;
+        }
+        for key in ["docs/", "blob/"] {
+            let response = self.client.send(Method::DELETE, &format!("{}/{}", site_path, key), &[], &[], Vec::new()).await?;
+            expect_status(&response, StatusCode::NO_CONTENT)?;
+        }
+        let response = self.client.send(Method::DELETE, &site_path, &[], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::NO_CONTENT)?;
+        result
+    }
+
+    async fn subresource_status_codes(&self) -> Result<(), String> {
+        let bucket_path = format!("/{}-status", self.bucket);
+        let response = self.client.send(Method::PUT, &bucket_path, &[], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::OK)?;
+
+        let policy = format!(
+            r#"{{"Version":"2012-10-17","Statement":[{{"Effect":"Allow","Principal":"*","Action":"s3:*","Resource":"arn:aws:s3:::{}-status/*"}}]}}"#,
+            self.bucket
+        );
+        let configs = [
+            ("versioning", "<VersioningConfiguration><Status>Suspended</Status></VersioningConfiguration>".to_string(), StatusCode::OK),
+            ("policy", policy, StatusCode::NO_CONTENT),
+            ("encryption", "<ServerSideEncryptionConfiguration><Rule><ApplyServerSideEncryptionByDefault><SSEAlgorithm>AES256</SSEAlgorithm></ApplyServerSideEncryptionByDefault></Rule></ServerSideEncryptionConfiguration>".to_string(), StatusCode::OK),
+            ("cors", "<CORSConfiguration><CORSRule><AllowedOrigin>*</AllowedOrigin><AllowedMethod>GET</AllowedMethod></CORSRule></CORSConfiguration>".to_string(), StatusCode::OK),
+            ("lifecycle", "<LifecycleConfiguration><Rule><ID>archive</ID><Status>Enabled</Status><Filter><Prefix>logs/</Prefix></Filter><Transition><Days>30</Days><StorageClass>GLACIER</StorageClass></Transition></Rule></LifecycleConfiguration>".to_string(), StatusCode::OK),
+        ];
+
+        for (subresource, body, expected) in configs {
+            let response = self.client.send(Method::PUT, &bucket_path, &[(subresource, "")], &[], body.into_bytes()).await?;
+            expect_status(&response, expected).map_err(|e| format!("PUT ?{}: {}", subresource, e))?;
+
+            // Versioning cannot be deleted, only suspended
+            if subresource == "versioning" {
+                continue;
+            }
+            // Deleting twice must succeed both times
+            for _ in 0..2 {
+                let response = self.client.send(Method::DELETE, &bucket_path, &[(subresource, "")], &[], Vec::new()).await?;
+                expect_status(&response, StatusCode::NO_CONTENT).map_err(|e| format!("DELETE ?{}: {}", subresource, e))?;
+            }
+        }
+
+        let response = self.client.send(Method::DELETE, &bucket_path, &[], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::NO_CONTENT)
+    }
+
+    async fn reserved_keys(&self) -> Result<(), String> {
+        for key in [".policy", ".versions/hello.txt", "hello.txt.metadata"] {
+            let response = self.client.send(Method::PUT, &self.object_path(key), &[], &[], b"x".to_vec()).await?;
+            expect_status(&response, StatusCode::BAD_REQUEST)?;
+        }
+        Ok(())
+    }
+
+    async fn multipart_upload(&self) -> Result<(), String> {
+        let key = "multipart.bin";
+        // Content type and user metadata given at initiation end up on the completed object
+        let initiate_headers = [("content-type", "application/x-selftest"), ("x-amz-meta-origin", "multipart")];
+        let response = self.client.send(Method::POST, &self.object_path(key), &[("uploads", "")], &initiate_headers, Vec::new()).await?;
+        expect_status(&response, StatusCode::OK)?;
+        let upload_id = xml_value(&response.text(), "UploadId")
+            .ok_or_else(|| format!("no UploadId in response: {}", response.text()))?;
+
+        // Listed exactly once, although it is both in memory and on disk
+        let response = self.client.send(Method::GET, &self.bucket_path(), &[("uploads", "")], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::OK)?;
+        let listed = xml_values(&response.text(), "UploadId").iter().filter(|id| **id == upload_id).count();
+        if listed != 1 {
+            return Err(format!("upload listed {} times: {}", listed, response.text()));
+        }
+
+        // Two parts: a full 5MB part followed by a short final part
+        let parts = [vec![b'a'; 5 * 1024 * 1024], vec![b'b'; 1024]];
+        let mut complete_xml = String::from("<CompleteMultipartUpload>");
+        for (index, data) in parts.iter().enumerate() {
+            let part_number = (index + 1).to_string();
+            let response = self.client.send(
+                Method::PUT,
+                &self.object_path(key),
+                &[("partNumber", &part_number), ("uploadId", &upload_id)],
+                &[],
+                data.clone(),
+            ).await?;
+            expect_status(&response, StatusCode::OK)?;
+            let etag = response.header("etag").ok_or("missing ETag on part upload")?;
+            complete_xml.push_str(&format!("<Part><PartNumber>{}</PartNumber><ETag>{}</ETag></Part>", part_number, etag));
+        }
+        complete_xml.push_str("</CompleteMultipartUpload>");
+
+        // A stale ETag is rejected and leaves the upload open for a retry
+        let stale_xml = r#"<CompleteMultipartUpload><Part><PartNumber>1</PartNumber><ETag>"0"</ETag></Part></CompleteMultipartUpload>"#;
+        let response = self.client.send(
+            Method::POST,
+            &self.object_path(key),
+            &[("uploadId", &upload_id)],
+            &[],
+            stale_xml.as_bytes().to_vec(),
+        ).await?;
+        expect_status(&response, StatusCode::BAD_REQUEST)?;
+        if xml_value(&response.text(), "Code").as_deref() != Some("InvalidPart") {
+            return Err(format!("expected InvalidPart, got: {}", response.text()));
+        }
+
+        let response = self.client.send(
+            Method::POST,
+            &self.object_path(key),
+            &[("uploadId", &upload_id)],
+            &[],
+            complete_xml.clone().into_bytes(),
+        ).await?;
+        expect_status(&response, StatusCode::OK)?;
+        let etag = expect_quoted_etag(xml_value(&response.text(), "ETag"), "CompleteMultipartUploadResult")?;
+
+        // A client retrying the completion after losing the response gets the same result, not NoSuchUpload
+        let response = self.client.send(
+            Method::POST,
+            &self.object_path(key),
+            &[("uploadId", &upload_id)],
+            &[],
+            complete_xml.into_bytes(),
+        ).await?;
+        expect_status(&response, StatusCode::OK)?;
+        if expect_quoted_etag(xml_value(&response.text(), "ETag"), "retried CompleteMultipartUploadResult")? != etag {
+            return Err(format!("retried completion returned a different ETag: {}", response.text()));
+        }
+
+        let response = self.client.send(Method::HEAD, &self.object_path(key), &[], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::OK)?;
+        let expected_size = (parts[0].len() + parts[1].len()).to_string();
+        match response.header("content-length") {
+            Some(size) if size == expected_size => {}
+            other => return Err(format!("expected Content-Length {}, got {:?}", expected_size, other)),
+        }
+        for response in [response, self.client.send(Method::GET, &self.object_path(key), &[], &[], Vec::new()).await?] {
+            if response.header("x-amz-meta-origin").as_deref() != Some("multipart")
+                || response.header("content-type").as_deref() != Some("application/x-selftest")
+            {
+                return Err(format!("initiate-time metadata missing: {:?}", response.headers));
+            }
+        }
+        Ok(())
+    }
+
+    async fn multipart_part_reupload(&self) -> Result<(), String> {
+        let key = "reupload.bin";
+        let response = self.client.send(Method::POST, &self.object_path(key), &[("uploads", "")], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::OK)?;
+        let upload_id = xml_value(&response.text(), "UploadId")
+            .ok_or_else(|| format!("no UploadId in response: {}", response.text()))?;
+
+        // Upload part 1 twice with different bytes; only the second may end up in the object
+        let mut etag = String::new();
+        for data in [b"first attempt".to_vec(), b"second attempt".to_vec()] {
+            let response = self.client.send(
+                Method::PUT,
+                &self.object_path(key),
+                &[("partNumber", "1"), ("uploadId", &upload_id)],
+                &[],
+                data,
+            ).await?;
+            expect_status(&response, StatusCode::OK)?;
+            etag = response.header("etag").ok_or("missing ETag on part upload")?;
+        }
+
+        // Part numbers outside 1..=10000 are refused
+        for part_number in ["0", "10001"] {
+            let response = self.client.send(
+                Method::PUT,
+                &self.object_path(key),
+                &[("partNumber", part_number), ("uploadId", &upload_id)],
+                &[],
+                b"out of range".to_vec(),
+            ).await?;
+            expect_status(&response, StatusCode::BAD_REQUEST)?;
+            if xml_value(&response.text(), "Code").as_deref() != Some("InvalidArgument") {
+                return Err(format!("part {} did not return InvalidArgument: {}", part_number, response.text()));
+            }
+        }
+
+        let complete_xml = format!("<CompleteMultipartUpload><Part><PartNumber>1</PartNumber><ETag>{}</ETag></Part></CompleteMultipartUpload>", etag);
+        let response = self.client.send(
+            Method::POST,
+            &self.object_path(key),
+            &[("uploadId", &upload_id)],
+            &[],
+            complete_xml.into_bytes(),
+        ).await?;
+        expect_status(&response, StatusCode::OK)?;
+
+        let response = self.client.send(Method::GET, &self.object_path(key), &[], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::OK)?;
+        if response.body != b"second attempt" {
+            return Err(format!("expected the re-uploaded part, got {:?}", response.text()));
+        }
+        Ok(())
+    }
+
+    async fn unknown_multipart_upload(&self) -> Result<(), String> {
+        let path = self.object_path("missing-upload.bin");
+        let upload_id = "00000000-0000-0000-0000-000000000000";
+        let complete = "<CompleteMultipartUpload><Part><PartNumber>1</PartNumber><ETag>\"x\"</ETag></Part></CompleteMultipartUpload>";
+        let requests = [
+            (Method::GET, vec![("uploadId", upload_id)], Vec::new()),
+            (Method::PUT, vec![("partNumber", "1"), ("uploadId", upload_id)], b"part".to_vec()),
+            (Method::POST, vec![("uploadId", upload_id)], complete.as_bytes().to_vec()),
+            (Method::DELETE, vec![("uploadId", upload_id)], Vec::new()),
+        ];
+        for (method, query, body) in requests {
+            let response = self.client.send(method.clone(), &path, &query, &[], body).await?;
+            expect_status(&response, StatusCode::NOT_FOUND)?;
+            if xml_value(&response.text(), "Code").as_deref() != Some("NoSuchUpload") {
+                return Err(format!("{} did not return NoSuchUpload: {}", method, response.text()));
+            }
+        }
+        Ok(())
+    }
+
+    // Versioning tests use their own bucket, as versions keep a bucket from being deleted
+    fn versioned_bucket_path(&self) -> String {
+        format!("/{}-versioned", self.bucket)
+    }
+
+    // GetBucketVersioning answers exactly as S3 does for never configured, enabled and suspended buckets
+    async fn versioning_states(&self) -> Result<(), String> {
+        let path = format!("/{}-versioning-states", self.bucket);
+        let response = self.client.send(Method::PUT, &path, &[], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::OK)?;
+
+        let result = async {
+            let expected_never = r#"<?xml version="1.0" encoding="UTF-8"?>
+<VersioningConfiguration xmlns="http://s3.amazonaws.com/doc/2006-03-01/"/>"#;
+            let response = self.client.send(Method::GET, &path, &[("versioning", "")], &[], Vec::new()).await?;
+            expect_status(&response, StatusCode::OK)?;
+            if response.text() != expected_never {
+                return Err(format!("never configured versioning returned {:?}", response.text()));
+            }
+
+            for status in ["Enabled", "Suspended"] {
+                let config = format!("<VersioningConfiguration><Status>{}</Status></VersioningConfiguration>", status);
+                let response = self.client.send(Method::PUT, &path, &[("versioning", "")], &[], config.into_bytes()).await?;
+                expect_status(&response, StatusCode::OK)?;
+
+                let expected = format!(r#"<?xml version="1.0" encoding="UTF-8"?>
+<VersioningConfiguration xmlns="http://s3.amazonaws.com/doc/2006-03-01/">
+    <Status>{}</Status>
+</VersioningConfiguration>"#, status);
+                let response = self.client.send(Method::GET, &path, &[("versioning", "")], &[], Vec::new()).await?;
+                expect_status(&response, StatusCode::OK)?;
+                if response.text() != expected {
+                    return Err(format!("{} versioning returned {:?}", status, response.text()));
+                }
+            }
+            Ok(())
+        }.await;
+
+        let response = self.client.send(Method::DELETE, &path, &[], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::NO_CONTENT)?;
+        result
+    }
+
+    async fn versioning(&self) -> Result<(), String> {
+        let response = self.client.send(Method::PUT, &self.versioned_bucket_path(), &[], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::OK)?;
+
+        let config = r#"<VersioningConfiguration><Status>Enabled</Status></VersioningConfiguration>"#;
+        let response = self.client.send(
+            Method::PUT,
+            &self.versioned_bucket_path(),
+            &[("versioning", "")],
+            &[],
+            config.as_bytes().to_vec(),
+        ).await?;
+        expect_status(&response, StatusCode::OK)?;
+
+        let response = self.client.send(Method::GET, &self.versioned_bucket_path(), &[("versioning", "")], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::OK)?;
+        if xml_value(&response.text(), "Status").as_deref() != Some("Enabled") {
+            return Err(format!("versioning not reported as Enabled: {}", response.text()));
+        }
+
+        let path = format!("{}/versioned.txt", self.versioned_bucket_path());
+        let response = self.client.send(Method::PUT, &path, &[], &[], b"version one".to_vec()).await?;
+        expect_status(&response, StatusCode::OK)?;
+        let version_id = response.header("x-amz-version-id").ok_or("missing x-amz-version-id on versioned PUT")?;
+        let first_etag = response.header("etag").ok_or("missing ETag on versioned PUT")?;
+        let response = self.client.send(Method::PUT, &path, &[], &[], b"version two".to_vec()).await?;
+        expect_status(&response, StatusCode::OK)?;
+        let second_version_id = response.header("x-amz-version-id").ok_or("missing x-amz-version-id on versioned PUT")?;
+        let second_etag = response.header("etag").ok_or("missing ETag on versioned PUT")?;
+
+        // The versions listing reports the ETags the PUTs returned
+        let response = self.client.send(Method::GET, &path, &[("versions", "")], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::OK)?;
+        let etags = xml_values(&response.text(), "ETag");
+        if !etags.contains(&first_etag) || !etags.contains(&second_etag) {
+            return Err(format!("versions listing ETags {:?} do not match {} and {}", etags, first_etag, second_etag));
+        }
+
+        for version_id in [&second_version_id, &version_id] {
+            let response = self.client.send(Method::DELETE, &path, &[("versionId", version_id)], &[], Vec::new()).await?;
+            expect_status(&response, StatusCode::NO_CONTENT)?;
+        }
+        let response = self.client.send(Method::HEAD, &path, &[], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::NOT_FOUND)
+    }
+
+    // versionId=null names the object written before versioning was enabled, never a versioned object
+    async fn null_version_delete(&self) -> Result<(), String> {
+        let null_path = format!("/{}-null", self.bucket);
+        let response = self.client.send(Method::PUT, &null_path, &[], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::OK)?;
+
+        let result = async {
+            for key in ["replaced.txt", "current.txt"] {
+                let response = self.client.send(Method::PUT, &format!("{}/{}", null_path, key), &[], &[], b"null version".to_vec()).await?;
+                expect_status(&response, StatusCode::OK)?;
+            }
+            let config = "<VersioningConfiguration><Status>Enabled</Status></VersioningConfiguration>";
+            let response = self.client.send(Method::PUT, &null_path, &[("versioning", "")], &[], config.as_bytes().to_vec()).await?;
+            expect_status(&response, StatusCode::OK)?;
+            for key in ["replaced.txt", "versioned.txt"] {
+                let response = self.client.send(Method::PUT, &format!("{}/{}", null_path, key), &[], &[], b"versioned".to_vec()).await?;
+                expect_status(&response, StatusCode::OK)?;
+            }
+
+            // The preserved null version goes; the versioned object over it stays current
+            let path = format!("{}/replaced.txt", null_path);
+            let response = self.client.send(Method::DELETE, &path, &[("versionId", "null")], &[], Vec::new()).await?;
+            expect_status(&response, StatusCode::NO_CONTENT)?;
+            let response = self.client.send(Method::GET, &path, &[], &[], Vec::new()).await?;
+            expect_status(&response, StatusCode::OK)?;
+            if response.body != b"versioned" {
+                return Err(format!("deleting the null version changed the current object to {:?}", response.text()));
+            }
+            let response = self.client.send(Method::GET, &path, &[("versionId", "null")], &[], Vec::new()).await?;
+            expect_status(&response, StatusCode::NOT_FOUND)?;
+
+            // A key with only versioned objects has no null version to delete
+            let path = format!("{}/versioned.txt", null_path);
+            let response = self.client.send(Method::DELETE, &path, &[("versionId", "null")], &[], Vec::new()).await?;
+            expect_status(&response, StatusCode::NOT_FOUND)?;
+            let response = self.client.send(Method::GET, &path, &[], &[], Vec::new()).await?;
+            expect_status(&response, StatusCode::OK)?;
+
+            // A null version that is still current is removed without leaving a delete marker
+            let path = format!("{}/current.txt", null_path);
+            let response = self.client.send(Method::DELETE, &path, &[("versionId", "null")], &[], Vec::new()).await?;
+            expect_status(&response, StatusCode::NO_CONTENT)?;
+            let response = self.client.send(Method::HEAD, &path, &[], &[], Vec::new()).await?;
+            expect_status(&response, StatusCode::NOT_FOUND)?;
+            if response.header("x-amz-delete-marker").is_some() {
+                return Err("deleting the current null version left a delete marker".to_string());
+            }
+            Ok(())
+        }.await;
+
+        let response = self.client.send(Method::DELETE, &null_path, &[("force", "")], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::NO_CONTENT)?;
+        result
+    }
+
+    async fn delete_markers(&self) -> Result<(), String> {
+        let path = format!("{}/marked.txt", self.versioned_bucket_path());
+        let response = self.client.send(Method::PUT, &path, &[], &[], b"still here".to_vec()).await?;
+        expect_status(&response, StatusCode::OK)?;
+        let version_id = response.header("x-amz-version-id").ok_or("missing x-amz-version-id on versioned PUT")?;
+
+        let response = self.client.send(Method::DELETE, &path, &[], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::NO_CONTENT)?;
+        if response.header("x-amz-delete-marker").as_deref() != Some("true") {
+            return Err("versioned DELETE did not create a delete marker".to_string());
+        }
+        let marker_id = response.header("x-amz-version-id").ok_or("missing delete marker version id")?;
+
+        // The marker hides the key, but the older version stays readable by id
+        let response = self.client.send(Method::HEAD, &path, &[], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::NOT_FOUND)?;
+        if response.header("x-amz-delete-marker").as_deref() != Some("true")
+            || response.header("x-amz-version-id").as_deref() != Some(marker_id.as_str())
+        {
+            return Err("HEAD on a deleted key did not report its delete marker".to_string());
+        }
+        let response = self.client.send(Method::HEAD, &path, &[("versionId", &version_id)], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::OK)?;
+        if response.header("x-amz-version-id").as_deref() != Some(version_id.as_str()) {
+            return Err("HEAD with versionId returned the wrong version".to_string());
+        }
+        let response = self.client.send(Method::GET, &path, &[("versionId", &marker_id)], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::METHOD_NOT_ALLOWED)?;
+
+        // Copies can name the older version, but not the marker or a version that never existed
+        let copy_path = self.object_path("copied-version.txt");
+        let source = format!("{}?versionId={}", path, version_id);
+        let response = self.client.send(Method::PUT, &copy_path, &[], &[("x-amz-copy-source", &source)], Vec::new()).await?;
+        expect_status(&response, StatusCode::OK)?;
+        if response.header("x-amz-copy-source-version-id").as_deref() != Some(version_id.as_str()) {
+            return Err("copy did not report the source version id".to_string());
+        }
+        let source = format!("{}?versionId={}", path, marker_id);
+        let response = self.client.send(Method::PUT, &copy_path, &[], &[("x-amz-copy-source", &source)], Vec::new()).await?;
+        expect_status(&response, StatusCode::BAD_REQUEST)?;
+        let response = self.client.send(Method::PUT, &copy_path, &[], &[("x-amz-copy-source", &path)], Vec::new()).await?;
+        expect_status(&response, StatusCode::NOT_FOUND)?;
+        let source = format!("{}?versionId=missing", path);
+        let response = self.client.send(Method::PUT, &copy_path, &[], &[("x-amz-copy-source", &source)], Vec::new()).await?;
+        expect_status(&response, StatusCode::NOT_FOUND)?;
+        if xml_value(&response.text(), "Code").as_deref() != Some("NoSuchVersion") {
+            return Err(format!("copy from a missing version did not return NoSuchVersion: {}", response.text()));
+        }
+        let response = self.client.send(Method::DELETE, &copy_path, &[], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::NO_CONTENT)?;
+
+        // Removing the marker brings the object back
+        let response = self.client.send(Method::DELETE, &path, &[("versionId", &marker_id)], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::NO_CONTENT)?;
+        let response = self.client.send(Method::GET, &path, &[], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::OK)?;
+        if response.body != b"still here" {
+            return Err(format!("restored object has wrong content: {:?}", response.text()));
+        }
+
+        // A bucket holding only a noncurrent version and a delete marker is still not empty
+        let response = self.client.send(Method::DELETE, &path, &[], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::NO_CONTENT)?;
+        let marker_id = response.header("x-amz-version-id").ok_or("missing delete marker version id")?;
+        let response = self.client.send(Method::DELETE, &self.versioned_bucket_path(), &[], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::CONFLICT)?;
+
+        for id in [&marker_id, &version_id] {
+            let response = self.client.send(Method::DELETE, &path, &[("versionId", id)], &[], Vec::new()).await?;
+            expect_status(&response, StatusCode::NO_CONTENT)?;
+        }
+        let response = self.client.send(Method::DELETE, &self.versioned_bucket_path(), &[], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::NO_CONTENT)
+    }
+
+    async fn tagging(&self) -> Result<(), String> {
+        let tags = "<Tagging><TagSet><Tag><Key>env</Key><Value>selftest</Value></Tag></TagSet></Tagging>";
+        let response = self.client.send(
+            Method::PUT,
+            &self.object_path("hello.txt"),
+            &[("tagging", "")],
+            &[],
+            tags.as_bytes().to_vec(),
+        ).await?;
+        expect_status(&response, StatusCode::OK)?;
+
+        let response = self.client.send(Method::GET, &self.object_path("hello.txt"), &[("tagging", "")], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::OK)?;
+        let xml = response.text();
+        if xml_value(&xml, "Key").as_deref() != Some("env") || xml_value(&xml, "Value").as_deref() != Some("selftest") {
+            return Err(format!("tag not returned: {}", xml));
+        }
+        Ok(())
+    }
+
+    // Setting, reading and removing tags rewrites the metadata sidecar; everything but the tags must survive
+    async fn tagging_keeps_metadata(&self) -> Result<(), String> {
+        let tagged_bucket = format!("/{}-tagmeta", self.bucket);
+        let response = self.client.send(Method::PUT, &tagged_bucket, &[], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::OK)?;
+
+        let result = async {
+            let versioning = "<VersioningConfiguration><Status>Enabled</Status></VersioningConfiguration>";
+            let response = self.client.send(Method::PUT, &tagged_bucket, &[("versioning", "")], &[], versioning.as_bytes().to_vec()).await?;
+            expect_status(&response, StatusCode::OK)?;
+            let encryption = r#"<ServerSideEncryptionConfiguration><Rule><ApplyServerSideEncryptionByDefault><SSEAlgorithm>AES256</SSEAlgorithm></ApplyServerSideEncryptionByDefault></Rule></ServerSideEncryptionConfiguration>"#;
+            let response = self.client.send(Method::PUT, &tagged_bucket, &[("encryption", "")], &[], encryption.as_bytes().to_vec()).await?;
+            expect_status(&response, StatusCode::OK)?;
+
+            let path = format!("{}/described.txt", tagged_bucket);
+            let headers = [("content-type", "text/markdown"), ("x-amz-meta-owner", "selftest")];
+            let response = self.client.send(Method::PUT, &path, &[], &headers, b"described".to_vec()).await?;
+            expect_status(&response, StatusCode::OK)?;
+
+            // Lost encryption metadata would show as a body that no longer decrypts
+            let described = ["content-type", "x-amz-meta-owner", "x-amz-version-id", "etag"];
+            let response = self.client.send(Method::HEAD, &path, &[], &[], Vec::new()).await?;
+            expect_status(&response, StatusCode::OK)?;
+            let before: Vec<Option<String>> = described.iter().map(|name| response.header(name)).collect();
+            if before.iter().any(|value| value.is_none()) {
+                return Err(format!("HEAD is missing some of {:?}: {:?}", described, before));
+            }
+
+            let tags = "<Tagging><TagSet><Tag><Key>env</Key><Value>selftest</Value></Tag></TagSet></Tagging>";
+            let changes = [
+                (Method::PUT, tags.as_bytes().to_vec(), StatusCode::OK),
+                (Method::GET, Vec::new(), StatusCode::OK),
+                (Method::DELETE, Vec::new(), StatusCode::NO_CONTENT),
+            ];
+            for (method, body, status) in changes {
+                let step = method.to_string();
+                let response = self.client.send(method, &path, &[("tagging", "")], &[], body).await?;
+                expect_status(&response, status)?;
+
+                let response = self.client.send(Method::HEAD, &path, &[], &[], Vec::new()).await?;
+                expect_status(&response, StatusCode::OK)?;
+                let after: Vec<Option<String>> = described.iter().map(|name| response.header(name)).collect();
+                if after != before {
+                    return Err(format!("{} ?tagging changed the object: {:?} became {:?}", step, before, after));
+                }
+                let response = self.client.send(Method::GET, &path, &[], &[], Vec::new()).await?;
+                expect_status(&response, StatusCode::OK)?;
+                if response.body != b"described" {
+                    return Err(format!("{} ?tagging left a body of {:?}", step, response.text()));
+                }
+            }
+            Ok(())
+        }.await;
+
+        let response = self.client.send(Method::DELETE, &tagged_bucket, &[("force", "")], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::NO_CONTENT)?;
+        result
+    }
+
+    // Copies keep the source's tags, including those of a version that is no longer current, unless
+    // x-amz-tagging-directive: REPLACE gives new ones
+    async fn copy_tags(&self) -> Result<(), String> {
+        let tags = "<Tagging><TagSet><Tag><Key>env</Key><Value>selftest</Value></Tag></TagSet></Tagging>";
+        let versioned_bucket = format!("/{}-tagged", self.bucket);
+        let versioned_path = format!("{}/tagged.txt", versioned_bucket);
+        let response = self.client.send(Method::PUT, &versioned_bucket, &[], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::OK)?;
+        let config = r#"<VersioningConfiguration><Status>Enabled</Status></VersioningConfiguration>"#;
+        let response = self.client.send(Method::PUT, &versioned_bucket, &[("versioning", "")], &[], config.as_bytes().to_vec()).await?;
+        expect_status(&response, StatusCode::OK)?;
+        let response = self.client.send(Method::PUT, &versioned_path, &[], &[], b"first".to_vec()).await?;
+        expect_status(&response, StatusCode::OK)?;
+        let version_id = response.header("x-amz-version-id").ok_or("missing x-amz-version-id on versioned PUT")?;
+        let response = self.client.send(Method::PUT, &versioned_path, &[("tagging", "")], &[], tags.as_bytes().to_vec()).await?;
+        expect_status(&response, StatusCode::OK)?;
+        let response = self.client.send(Method::PUT, &versioned_path, &[], &[], b"second".to_vec()).await?;
+        expect_status(&response, StatusCode::OK)?;
+
+        let copy_path = self.object_path("tagged-copy.txt");
+        let result = async {
+            let sources = [format!("{}/hello.txt", self.bucket), format!("{}?versionId={}", versioned_path, version_id)];
+            for source in &sources {
+                let response = self.client.send(Method::PUT, &copy_path, &[], &[("x-amz-copy-source", source)], Vec::new()).await?;
+                expect_status(&response, StatusCode::OK)?;
+                let response = self.client.send(Method::GET, &copy_path, &[("tagging", "")], &[], Vec::new()).await?;
+                if xml_value(&response.text(), "Key").as_deref() != Some("env") {
+                    return Err(format!("copy of {} lost its tags: {}", source, response.text()));
+                }
+            }
+
+            let response = self.client.send(
+                Method::PUT,
+                &copy_path,
+                &[],
+                &[("x-amz-copy-source", &sources[0]), ("x-amz-tagging-directive", "REPLACE"), ("x-amz-tagging", "owner=self%20test")],
+                Vec::new(),
+            ).await?;
+            expect_status(&response, StatusCode::OK)?;
+            let response = self.client.send(Method::GET, &copy_path, &[("tagging", "")], &[], Vec::new()).await?;
+            let xml = response.text();
+            if xml_values(&xml, "Key") != ["owner"] || xml_value(&xml, "Value").as_deref() != Some("self test") {
+                return Err(format!("REPLACE did not set the requested tags: {}", xml));
+            }
+            Ok(())
+        }.await;
+
+        let response = self.client.send(Method::DELETE, &copy_path, &[], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::NO_CONTENT)?;
+        let response = self.client.send(Method::DELETE, &versioned_bucket, &[("force", "")], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::NO_CONTENT)?;
+        result
+    }
+
+    async fn public_object_acl(&self) -> Result<(), String> {
+        let acl_bucket = format!("{}-acl", self.bucket);
+        let acl_path = format!("/{}", acl_bucket);
+        let public_path = format!("{}/public.txt", acl_path);
+        let private_path = format!("{}/private.txt", acl_path);
+        let response = self.client.send(Method::PUT, &acl_path, &[], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::OK)?;
+        let response = self.client.send(Method::PUT, &public_path, &[], &[("x-amz-acl", "public-read")], b"shared".to_vec()).await?;
+        expect_status(&response, StatusCode::OK)?;
+        let response = self.client.send(Method::PUT, &private_path, &[], &[], b"secret".to_vec()).await?;
+        expect_status(&response, StatusCode::OK)?;
+
+        let result = async {
+            let response = self.client.send_anonymous(Method::GET, &public_path).await?;
+            expect_status(&response, StatusCode::OK)?;
+            if response.text() != "shared" {
+                return Err(format!("anonymous GET returned {:?}", response.text()));
+            }
+            let response = self.client.send_anonymous(Method::HEAD, &public_path).await?;
+            expect_status(&response, StatusCode::OK)?;
+
+            // The grant covers the object only, not its subresources or other objects
+            let response = self.client.send_anonymous(Method::GET, &format!("{}?tagging", public_path)).await?;
+            expect_status(&response, StatusCode::FORBIDDEN)?;
+            let response = self.client.send_anonymous(Method::GET, &private_path).await?;
+            expect_status(&response, StatusCode::FORBIDDEN)?;
+
+            let response = self.client.send(Method::GET, &public_path, &[("acl", "")], &[], Vec::new()).await?;
+            expect_status(&response, StatusCode::OK)?;
+            if !response.text().contains("http://acs.amazonaws.com/groups/global/AllUsers") {
+                return Err(format!("ACL does not grant AllUsers: {}", response.text()));
+            }
+
+            // An explicit deny in the bucket policy overrides the ACL
+            let policy = format!(
+                r#"{{"Version":"2012-10-17","Statement":[{{"Effect":"Allow","Principal":"*","Action":"s3:*","Resource":"arn:aws:s3:::{0}/*"}},{{"Effect":"Deny","Principal":"*","Action":"s3:GetObject","Resource":"arn:aws:s3:::{0}/public.txt"}}]}}"#,
+                acl_bucket
+            );
+            let response = self.client.send(Method::PUT, &acl_path, &[("policy", "")], &[], policy.into_bytes()).await?;
+            expect_status(&response, StatusCode::NO_CONTENT)?;
+            let response = self.client.send_anonymous(Method::GET, &public_path).await?;
+            expect_status(&response, StatusCode::FORBIDDEN)?;
+            let response = self.client.send(Method::DELETE, &acl_path, &[("policy", "")], &[], Vec::new()).await?;
+            expect_status(&response, StatusCode::NO_CONTENT)?;
+
+            // Setting the ACL back to private closes the object again
+            let response = self.client.send(Method::PUT, &public_path, &[("acl", "")], &[("x-amz-acl", "private")], Vec::new()).await?;
+            expect_status(&response, StatusCode::OK)?;
+            let response = self.client.send_anonymous(Method::GET, &public_path).await?;
+            expect_status(&response, StatusCode::FORBIDDEN)
+        }.await;
+
+        self.client.send(Method::DELETE, &acl_path, &[("policy", "")], &[], Vec::new()).await?;
+        for path in [&public_path, &private_path] {
+            let response = self.client.send(Method::DELETE, path, &[], &[], Vec::new()).await?;
+            expect_status(&response, StatusCode::NO_CONTENT)?;
+        }
+        let response = self.client.send(Method::DELETE, &acl_path, &[], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::NO_CONTENT)?;
+        result
+    }
+
+    async fn list_with_metadata(&self) -> Result<(), String> {
+        let response = self.client.send(
+            Method::GET,
+            &self.bucket_path(),
+            &[("list-type", "2"), ("prefix", "hello.txt"), ("metadata", "true")],
+            &[],
+            Vec::new(),
+        ).await?;
+        expect_status(&response, StatusCode::OK)?;
+        let xml = response.text();
+        if !xml.contains("<Entry><Key>x-amz-meta-origin</Key><Value>selftest</Value></Entry>")
+            || !xml.contains("<Tag><Key>env</Key><Value>selftest</Value></Tag>")
+        {
+            return Err(format!("metadata or tags missing from listing: {}", xml));
+        }
+
+        // Without the flag the listing stays standard S3 XML
+        let response = self.client.send(Method::GET, &self.bucket_path(), &[("list-type", "2")], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::OK)?;
+        if response.text().contains("<UserMetadata>") {
+            return Err("user metadata returned without metadata=true".to_string());
+        }
+        Ok(())
+    }
+
+    // Uploads without x-amz-acl, single and multipart, get the bucket's default ACL; an explicit one still wins
+    async fn default_object_acl(&self) -> Result<(), String> {
+        let acl_path = format!("/{}-defacl", self.bucket);
+        let response = self.client.send(Method::PUT, &acl_path, &[], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::OK)?;
+
+        let result = async {
+            let response = self.client.send(Method::PUT, &acl_path, &[("default-acl", "")], &[], br#"{"acl": "world-readable"}"#.to_vec()).await?;
+            expect_status(&response, StatusCode::BAD_REQUEST)?;
+            let response = self.client.send(Method::PUT, &acl_path, &[("default-acl", "")], &[], br#"{"acl": "public-read"}"#.to_vec()).await?;
+            expect_status(&response, StatusCode::OK)?;
+            let response = self.client.send(Method::GET, &acl_path, &[("default-acl", "")], &[], Vec::new()).await?;
+            expect_status(&response, StatusCode::OK)?;
+            if !response.text().contains("public-read") {
+                return Err(format!("default ACL reads back as {}", response.text()));
+            }
+
+            let defaulted_path = format!("{}/defaulted.txt", acl_path);
+            let response = self.client.send(Method::PUT, &defaulted_path, &[], &[], b"shared".to_vec()).await?;
+            expect_status(&response, StatusCode::OK)?;
+            let explicit_path = format!("{}/explicit.txt", acl_path);
+            let response = self.client.send(Method::PUT, &explicit_path, &[], &[("x-amz-acl", "private")], b"secret".to_vec()).await?;
+            expect_status(&response, StatusCode::OK)?;
+
+            let multipart_path = format!("{}/multipart.txt", acl_path);
+            let response = self.client.send(Method::POST, &multipart_path, &[("uploads", "")], &[], Vec::new()).await?;
+            expect_status(&response, StatusCode::OK)?;
+            let upload_id = xml_value(&response.text(), "UploadId")
+                .ok_or_else(|| format!("no UploadId in response: {}", response.text()))?;
+            let response = self.client.send(Method::PUT, &multipart_path, &[("partNumber", "1"), ("uploadId", &upload_id)], &[], b"parts".to_vec()).await?;
+            expect_status(&response, StatusCode::OK)?;
+            let etag = response.header("etag").ok_or("missing ETag on part upload")?;
+            let complete_xml = format!("<CompleteMultipartUpload><Part><PartNumber>1</PartNumber><ETag>{}</ETag></Part></CompleteMultipartUpload>", etag);
+            let response = self.client.send(Method::POST, &multipart_path, &[("uploadId", &upload_id)], &[], complete_xml.into_bytes()).await?;
+            expect_status(&response, StatusCode::OK)?;
+
+            for (path, status) in [(&defaulted_path, StatusCode::OK), (&multipart_path, StatusCode::OK), (&explicit_path, StatusCode::FORBIDDEN)] {
+                let response = self.client.send_anonymous(Method::GET, path).await?;
+                if response.status != status {
+                    return Err(format!("anonymous GET of {} returned {}, expected {}", path, response.status, status));
+                }
+            }
+
+            // Changing the default only affects later uploads; a private default also overrides DEFAULT_OBJECT_ACL
+            let response = self.client.send(Method::PUT, &acl_path, &[("default-acl", "")], &[], br#"{"acl": "private"}"#.to_vec()).await?;
+            expect_status(&response, StatusCode::OK)?;
+            let later_path = format!("{}/later.txt", acl_path);
+            let response = self.client.send(Method::PUT, &later_path, &[], &[], b"later".to_vec()).await?;
+            expect_status(&response, StatusCode::OK)?;
+            let response = self.client.send_anonymous(Method::GET, &later_path).await?;
+            expect_status(&response, StatusCode::FORBIDDEN)?;
+            let response = self.client.send_anonymous(Method::GET, &defaulted_path).await?;
+            expect_status(&response, StatusCode::OK)?;
+
+            let response = self.client.send(Method::DELETE, &acl_path, &[("default-acl", "")], &[], Vec::new()).await?;
+            expect_status(&response, StatusCode::NO_CONTENT)?;
+            let response = self.client.send(Method::GET, &acl_path, &[("default-acl", "")], &[], Vec::new()).await?;
+            expect_status(&response, StatusCode::NOT_FOUND)
+        }.await;
+
+        let response = self.client.send(Method::DELETE, &acl_path, &[("force", "")], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::NO_CONTENT)?;
+        result
+    }
+
+    async fn secure_transport_policy(&self) -> Result<(), String> {
+        let path = self.object_path("tls-only.txt");
+        let response = self.client.send(Method::PUT, &path, &[], &[], b"secure".to_vec()).await?;
+        expect_status(&response, StatusCode::OK)?;
+
+        let policy = format!(
+            r#"{{"Version":"2012-10-17","Statement":[{{"Effect":"Allow","Principal":"*","Action":"s3:*","Resource":["arn:aws:s3:::{0}","arn:aws:s3:::{0}/*"]}},{{"Effect":"Deny","Principal":"*","Action":"s3:GetObject","Resource":"arn:aws:s3:::{0}/tls-*","Condition":{{"Bool":{{"aws:SecureTransport":"false"}}}}}}]}}"#,
+            self.bucket
+        );
+        let response = self.client.send(Method::PUT, &self.bucket_path(), &[("policy", "")], &[], policy.into_bytes()).await?;
+        expect_status(&response, StatusCode::NO_CONTENT)?;
+
+        let result = async {
+            // Even the signed owner request is refused over plain HTTP
+            let response = self.client.send(Method::GET, &path, &[], &[], Vec::new()).await?;
+            expect_status(&response, StatusCode::FORBIDDEN)?;
+            let response = self.client.send(Method::GET, &path, &[], &[("x-forwarded-proto", "https")], Vec::new()).await?;
+            expect_status(&response, StatusCode::OK)?;
+            if response.text() != "secure" {
+                return Err(format!("GET over HTTPS returned {:?}", response.text()));
+            }
+            Ok(())
+        }.await;
+
+        self.client.send(Method::DELETE, &self.bucket_path(), &[("policy", "")], &[], Vec::new()).await?;
+        let response = self.client.send(Method::DELETE, &path, &[], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::NO_CONTENT)?;
+        result
+    }
+
+    // A Deny wins over an Allow that matches the same request, even when the Allow is listed first
+    async fn deny_after_allow_policy(&self) -> Result<(), String> {
+        let allowed_path = self.object_path("deny-order-open.txt");
+        let denied_path = self.object_path("deny-order-secret.txt");
+        for path in [&allowed_path, &denied_path] {
+            let response = self.client.send(Method::PUT, path, &[], &[], b"ordered".to_vec()).await?;
+            expect_status(&response, StatusCode::OK)?;
+        }
+
+        let policy = format!(
+            r#"{{"Version":"2012-10-17","Statement":[{{"Effect":"Allow","Principal":"*","Action":"s3:*","Resource":["arn:aws:s3:::{0}","arn:aws:s3:::{0}/*"]}},{{"Effect":"Deny","Principal":"*","Action":"s3:GetObject","Resource":"arn:aws:s3:::{0}/deny-order-secret*"}}]}}"#,
+            self.bucket
+        );
+        let response = self.client.send(Method::PUT, &self.bucket_path(), &[("policy", "")], &[], policy.into_bytes()).await?;
+        expect_status(&response, StatusCode::NO_CONTENT)?;
+
+        let result = async {
+            let response = self.client.send(Method::GET, &allowed_path, &[], &[], Vec::new()).await?;
+            expect_status(&response, StatusCode::OK)?;
+            let response = self.client.send(Method::GET, &denied_path, &[], &[], Vec::new()).await?;
+            expect_status(&response, StatusCode::FORBIDDEN)
+        }.await;
+
+        self.client.send(Method::DELETE, &self.bucket_path(), &[("policy", "")], &[], Vec::new()).await?;
+        for path in [&allowed_path, &denied_path] {
+            let response = self.client.send(Method::DELETE, path, &[], &[], Vec::new()).await?;
+            expect_status(&response, StatusCode::NO_CONTENT)?;
+        }
+        result
+    }
+
+    async fn negated_policy_elements(&self) -> Result<(), String> {
+        let path = self.object_path("notaction-kept.txt");
+        let other_path = self.object_path("other.txt");
+        for path in [&path, &other_path] {
+            let response = self.client.send(Method::PUT, path, &[], &[], b"kept".to_vec()).await?;
+            expect_status(&response, StatusCode::OK)?;
+        }
+
+        // Everything but deletes is allowed, and reads are refused outside notaction-*
+        let policy = format!(
+            r#"{{"Version":"2012-10-17","Statement":[{{"Effect":"Allow","Principal":"*","NotAction":"s3:DeleteObject","Resource":["arn:aws:s3:::{0}","arn:aws:s3:::{0}/*"]}},{{"Effect":"Deny","Principal":"*","Action":"s3:GetObject","NotResource":["arn:aws:s3:::{0}/notaction-*"]}}]}}"#,
+            self.bucket
+        );
+        let response = self.client.send(Method::PUT, &self.bucket_path(), &[("policy", "")], &[], policy.into_bytes()).await?;
+        expect_status(&response, StatusCode::NO_CONTENT)?;
+
+        let result = async {
+            let response = self.client.send(Method::GET, &path, &[], &[], Vec::new()).await?;
+            expect_status(&response, StatusCode::OK)?;
+            let response = self.client.send(Method::GET, &other_path, &[], &[], Vec::new()).await?;
+            expect_status(&response, StatusCode::FORBIDDEN)?;
+            let response = self.client.send(Method::DELETE, &path, &[], &[], Vec::new()).await?;
+            expect_status(&response, StatusCode::FORBIDDEN)
+        }.await;
+
+        self.client.send(Method::DELETE, &self.bucket_path(), &[("policy", "")], &[], Vec::new()).await?;
+        for path in [&path, &other_path] {
+            let response = self.client.send(Method::DELETE, path, &[], &[], Vec::new()).await?;
+            expect_status(&response, StatusCode::NO_CONTENT)?;
+        }
+        result
+    }
+
+    async fn account_policy_principals(&self) -> Result<(), String> {
+        let account_id = std::env::var("ACCOUNT_ID").unwrap_or_else(|_| "000000000000".to_string());
+        let allowed_path = self.object_path("account-allowed.txt");
+        let denied_path = self.object_path("aws-deny-me.txt");
+        for path in [&allowed_path, &denied_path] {
+            let response = self.client.send(Method::PUT, path, &[], &[], b"principal".to_vec()).await?;
+            expect_status(&response, StatusCode::OK)?;
+        }
+
+        // The signed requests are allowed as the account root, and the {"AWS":"*"} deny applies to them too
+        let policy = format!(
+            r#"{{"Version":"2012-10-17","Statement":[{{"Effect":"Allow","Principal":{{"AWS":"arn:aws:iam::{1}:root"}},"Action":"s3:*","Resource":["arn:aws:s3:::{0}","arn:aws:s3:::{0}/*"]}},{{"Effect":"Deny","Principal":{{"AWS":"*"}},"Action":"s3:GetObject","Resource":"arn:aws:s3:::{0}/aws-deny-*"}}]}}"#,
+            self.bucket, account_id
+        );
+        let response = self.client.send(Method::PUT, &self.bucket_path(), &[("policy", "")], &[], policy.into_bytes()).await?;
+        expect_status(&response, StatusCode::NO_CONTENT)?;
+
+        let result = async {
+            let response = self.client.send(Method::GET, &allowed_path, &[], &[], Vec::new()).await?;
+            expect_status(&response, StatusCode::OK)?;
+            let response = self.client.send(Method::GET, &denied_path, &[], &[], Vec::new()).await?;
+            expect_status(&response, StatusCode::FORBIDDEN)
+        }.await;
+
+        self.client.send(Method::DELETE, &self.bucket_path(), &[("policy", "")], &[], Vec::new()).await?;
+        for path in [&allowed_path, &denied_path] {
+            let response = self.client.send(Method::DELETE, path, &[], &[], Vec::new()).await?;
+            expect_status(&response, StatusCode::NO_CONTENT)?;
+        }
+        result
+    }
+
+    // With CREATE_FOLDER_MARKERS every folder above an upload is listed as its own key; without it only the
+    // object is. Either way the listing must match one of the two exactly.
+    async fn intermediate_folder_markers(&self) -> Result<(), String> {
+        let folders_path = format!("/{}-folders", self.bucket);
+        let response = self.client.send(Method::PUT, &folders_path, &[], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::OK)?;
+
+        let result = async {
+            let response = self.client.send(Method::PUT, &format!("{}/tree/a/b/c.txt", folders_path), &[], &[], b"leaf".to_vec()).await?;
+            expect_status(&response, StatusCode::OK)?;
+
+            let response = self.client.send(Method::GET, &folders_path, &[("list-type", "2")], &[], Vec::new()).await?;
+            expect_status(&response, StatusCode::OK)?;
+            let keys = xml_values(&response.text(), "Key");
+            let without_markers = ["tree/a/b/c.txt"];
+            let with_markers = ["tree/", "tree/a/", "tree/a/b/", "tree/a/b/c.txt"];
+            if keys == without_markers || keys == with_markers {
+                Ok(())
+            } else {
+                Err(format!("unexpected keys: {:?}", keys))
+            }
+        }.await;
+
+        // Empty directories left by the upload would keep a plain DELETE from removing the bucket
+        let response = self.client.send(Method::DELETE, &folders_path, &[("force", "")], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::NO_CONTENT)?;
+        result
+    }
+
+    async fn delimiter_prefix_pages(&self) -> Result<(), String> {
+        let pages_path = format!("/{}-pages", self.bucket);
+        let response = self.client.send(Method::PUT, &pages_path, &[], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::OK)?;
+
+        let result = async {
+            let mut expected = Vec::new();
+            for i in 0..5 {
+                let folder = format!("dir-{}/", i);
+                for j in 0..2 {
+                    let path = format!("{}/{}file-{}.txt", pages_path, folder, j);
+                    let response = self.client.send(Method::PUT, &path, &[], &[], b"page".to_vec()).await?;
+                    expect_status(&response, StatusCode::OK)?;
+                }
+                expected.push(folder);
+            }
+            let response = self.client.send(Method::PUT, &format!("{}/top.txt", pages_path), &[], &[], b"top".to_vec()).await?;
+            expect_status(&response, StatusCode::OK)?;
+            expected.push("top.txt".to_string());
+
+            // Common prefixes count against max-keys, so five folders and a key take three pages of two
+            for v1 in [false, true] {
+                let mut seen = Vec::new();
+                let mut resume: Option<String> = None;
+                for _ in 0..10 {
+                    let mut query = vec![("delimiter", "/"), ("max-keys", "2")];
+                    if !v1 {
+                        query.push(("list-type", "2"));
+                    }
+                    if let Some(resume) = resume.as_deref() {
+                        query.push((if v1 { "marker" } else { "continuation-token" }, resume));
+                    }
+                    let response = self.client.send(Method::GET, &pages_path, &query, &[], Vec::new()).await?;
+                    expect_status(&response, StatusCode::OK)?;
+                    let xml = response.text();
+                    // SDKs read back the request's delimiter and paging position
+                    let (echoed, expected) = if v1 {
+                        (xml_value(&xml, "Marker"), Some(resume.clone().unwrap_or_default()))
+                    } else {
+                        (xml_value(&xml, "ContinuationToken"), resume.clone())
+                    };
+                    if xml_value(&xml, "Delimiter").as_deref() != Some("/") || echoed != expected {
+                        return Err(format!("request parameters not echoed: {}", xml));
+                    }
+                    let page: Vec<String> = xml_values(&xml, "Prefix").into_iter()
+                        .filter(|prefix| !prefix.is_empty())
+                        .chain(xml_values(&xml, "Key"))
+                        .collect();
+                    if page.len() > 2 {
+                        return Err(format!("page exceeds max-keys: {:?}", page));
+                    }
+                    seen.extend(page);
+                    resume = xml_value(&xml, if v1 { "NextMarker" } else { "NextContinuationToken" });
+                    if xml_value(&xml, "IsTruncated").as_deref() != Some("true") {
+                        break;
+                    }
+                    if resume.is_none() {
+                        return Err(format!("truncated page without a resume point: {}", xml));
+                    }
+                }
+                seen.sort();
+                if seen != expected {
+                    return Err(format!("{} pages listed {:?}", if v1 { "V1" } else { "V2" }, seen));
+                }
+            }
+            Ok(())
+        }.await;
+
+        let response = self.client.send(Method::DELETE, &pages_path, &[("force", "")], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::NO_CONTENT)?;
+        result
+    }
+
+    // A key equal to the prefix is listed as an object, both for a file next to keys sharing its name and for
+    // a folder marker that has keys beneath it
+    async fn exact_prefix_listing(&self) -> Result<(), String> {
+        let exact_path = format!("/{}-exact", self.bucket);
+        let response = self.client.send(Method::PUT, &exact_path, &[], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::OK)?;
+
+        let result = async {
+            for key in ["foo/bar", "foo/barn", "foo/bar2/x", "foo/dir/", "foo/dir/baz", "foo/dir/sub/y"] {
+                let response = self.client.send(Method::PUT, &format!("{}/{}", exact_path, key), &[], &[], b"exact".to_vec()).await?;
+                expect_status(&response, StatusCode::OK)?;
+            }
+
+            let expectations: [(&str, &[&str], &[&str]); 3] = [
+                ("foo/bar", &["foo/bar", "foo/barn"], &["foo/bar2/"]),
+                ("foo/dir/", &["foo/dir/", "foo/dir/baz"], &["foo/dir/sub/"]),
+                ("foo/dir/baz", &["foo/dir/baz"], &[]),
+            ];
+            for (prefix, keys, prefixes) in expectations {
+                let response = self.client.send(
+                    Method::GET,
+                    &exact_path,
+                    &[("list-type", "2"), ("prefix", prefix), ("delimiter", "/")],
+                    &[],
+                    Vec::new(),
+                ).await?;
+                expect_status(&response, StatusCode::OK)?;
+                let xml = response.text();
+                let listed_keys = xml_values(&xml, "Key");
+                // The first Prefix element echoes the request
+                let listed_prefixes: Vec<String> = xml_values(&xml, "Prefix").into_iter().skip(1).collect();
+                if listed_keys != keys || listed_prefixes != prefixes {
+                    return Err(format!("prefix {} listed keys {:?} and prefixes {:?}", prefix, listed_keys, listed_prefixes));
+                }
+            }
+            Ok(())
+        }.await;
+
+        let response = self.client.send(Method::DELETE, &exact_path, &[("force", "")], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::NO_CONTENT)?;
+        result
+    }
+
+    async fn creation_time(&self) -> Result<(), String> {
+        let key = "created.txt";
+        let result = async {
+            let times = || async {
+                let response = self.client.send(
+                    Method::GET,
+                    &self.bucket_path(),
+                    &[("list-type", "2"), ("prefix", key), ("metadata", "true")],
+                    &[],
+                    Vec::new(),
+                ).await?;
+                expect_status(&response, StatusCode::OK)?;
+                let xml = response.text();
+                match (xml_value(&xml, "Created"), xml_value(&xml, "LastModified")) {
+                    (Some(created), Some(last_modified)) => Ok::<_, String>((created, last_modified)),
+                    _ => Err(format!("Created or LastModified missing from listing: {}", xml)),
+                }
+            };
+
+            let response = self.client.send(Method::PUT, &self.object_path(key), &[], &[], b"first".to_vec()).await?;
+            expect_status(&response, StatusCode::OK)?;
+            let (created, last_modified) = times().await?;
+
+            // Timestamps have second precision
+            tokio::time::sleep(Duration::from_millis(1100)).await;
+            let tags = "<Tagging><TagSet><Tag><Key>env</Key><Value>selftest</Value></Tag></TagSet></Tagging>";
+            let response = self.client.send(Method::PUT, &self.object_path(key), &[("tagging", "")], &[], tags.as_bytes().to_vec()).await?;
+            expect_status(&response, StatusCode::OK)?;
+            let (created_after, _) = times().await?;
+            if created_after != created {
+                return Err(format!("creation time changed by tagging from {} to {}", created, created_after));
+            }
+
+            // An overwrite is a new object, so both times move
+            let response = self.client.send(Method::PUT, &self.object_path(key), &[], &[], b"second".to_vec()).await?;
+            expect_status(&response, StatusCode::OK)?;
+            let (created_after, last_modified_after) = times().await?;
+            if created_after == created || last_modified_after == last_modified {
+                return Err(format!("overwrite kept Created {} or LastModified {}", created_after, last_modified_after));
+            }
+            Ok(())
+        }.await;
+
+        let _ = self.client.send(Method::DELETE, &self.object_path(key), &[], &[], Vec::new()).await;
+        result
+    }
+
+    async fn list_modified_since(&self) -> Result<(), String> {
+        for (name, value, expect_listed) in [
+            ("modified-since", "2000-01-01T00:00:00Z", true),
+            ("modified-since", "2999-01-01T00:00:00Z", false),
+            ("modified-before", "2000-01-01T00:00:00Z", false),
+        ] {
+            let response = self.client.send(
+                Method::GET,
+                &self.bucket_path(),
+                &[("list-type", "2"), ("prefix", "hello.txt"), (name, value)],
+                &[],
+                Vec::new(),
+            ).await?;
+            expect_status(&response, StatusCode::OK)?;
+            if response.text().contains("<Key>hello.txt</Key>") != expect_listed {
+                return Err(format!("{}={} listed the wrong objects: {}", name, value, response.text()));
+            }
+        }
+
+        let response = self.client.send(
+            Method::GET,
+            &self.bucket_path(),
+            &[("list-type", "2"), ("modified-since", "yesterday")],
+            &[],
+            Vec::new(),
+        ).await?;
+        expect_status(&response, StatusCode::BAD_REQUEST)
+    }
+
+    async fn copy_replace_metadata(&self) -> Result<(), String> {
+        let copy_path = self.object_path("hello-replaced.txt");
+        let source = format!("/{}/hello.txt", self.bucket);
+        let response = self.client.send(
+            Method::PUT,
+            &copy_path,
+            &[],
+            &[("x-amz-copy-source", &source), ("x-amz-metadata-directive", "REPLACE")],
+            Vec::new(),
+        ).await?;
+        expect_status(&response, StatusCode::OK)?;
+
+        // Neither the source's x-amz-meta-origin nor its text/plain content type carries over
+        let result = async {
+            let response = self.client.send(Method::HEAD, &copy_path, &[], &[], Vec::new()).await?;
+            expect_status(&response, StatusCode::OK)?;
+            if response.header("x-amz-meta-origin").is_some() {
+                return Err("REPLACE kept the source's user metadata".to_string());
+            }
+            match response.header("content-type") {
+                Some(content_type) if content_type == "binary/octet-stream" => Ok(()),
+                other => Err(format!("expected content type binary/octet-stream, got {:?}", other)),
+            }
+        }.await;
+
+        let response = self.client.send(Method::DELETE, &copy_path, &[], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::NO_CONTENT)?;
+        result
+    }
+
+    async fn copy_across_encryption(&self) -> Result<(), String> {
+        let encrypted_bucket = format!("{}-enc", self.bucket);
+        let encrypted_path = format!("/{}", encrypted_bucket);
+        let response = self.client.send(Method::PUT, &encrypted_path, &[], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::OK)?;
+
+        let config = r#"<ServerSideEncryptionConfiguration><Rule><ApplyServerSideEncryptionByDefault><SSEAlgorithm>AES256</SSEAlgorithm></ApplyServerSideEncryptionByDefault></Rule></ServerSideEncryptionConfiguration>"#;
+        let response = self.client.send(Method::PUT, &encrypted_path, &[("encryption", "")], &[], config.as_bytes().to_vec()).await?;
+        expect_status(&response, StatusCode::OK)?;
+
+        let body = b"copied between encrypted and plain buckets".to_vec();
+        let encrypted_object = format!("{}/secret.txt", encrypted_path);
+        let response = self.client.send(Method::PUT, &encrypted_object, &[], &[], body.clone()).await?;
+        expect_status(&response, StatusCode::OK)?;
+
+        // Encrypted -> plain, then plain -> encrypted; both copies must read back intact
+        let plain_source = format!("{}/secret.txt", encrypted_bucket);
+        let response = self.client.send(Method::PUT, &self.object_path("copied.txt"), &[], &[("x-amz-copy-source", &plain_source)], Vec::new()).await?;
+        expect_status(&response, StatusCode::OK)?;
+        let copy_source = format!("{}/copied.txt", self.bucket);
+        let response = self.client.send(Method::PUT, &format!("{}/copied-back.txt", encrypted_path), &[], &[("x-amz-copy-source", &copy_source)], Vec::new()).await?;
+        expect_status(&response, StatusCode::OK)?;
+        if response.header("x-amz-server-side-encryption").as_deref() != Some("AES256") {
+            return Err("copy into the encrypted bucket was not encrypted".to_string());
+        }
+
+        for path in [self.object_path("copied.txt"), format!("{}/copied-back.txt", encrypted_path)] {
+            let response = self.client.send(Method::GET, &path, &[], &[], Vec::new()).await?;
+            expect_status(&response, StatusCode::OK)?;
+            if response.body != body {
+                return Err(format!("content mismatch for {}: got {:?}", path, response.text()));
+            }
+        }
+
+        for path in [encrypted_object, format!("{}/copied-back.txt", encrypted_path), self.object_path("copied.txt")] {
+            let response = self.client.send(Method::DELETE, &path, &[], &[], Vec::new()).await?;
+            expect_status(&response, StatusCode::NO_CONTENT)?;
+        }
+        let response = self.client.send(Method::DELETE, &encrypted_path, &[], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::NO_CONTENT)
+    }
+
+    async fn key_rotation(&self) -> Result<(), String> {
+        let encrypted_path = format!("/{}-rotate", self.bucket);
+        let rotation_path = format!("/_ironbucket/key-rotation/{}-rotate", self.bucket);
+        let response = self.client.send(Method::PUT, &encrypted_path, &[], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::OK)?;
+
+        // Only AES256 buckets can be rotated
+        let response = self.client.send(Method::POST, &rotation_path, &[], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::BAD_REQUEST)?;
+
+        let config = r#"<ServerSideEncryptionConfiguration><Rule><ApplyServerSideEncryptionByDefault><SSEAlgorithm>AES256</SSEAlgorithm></ApplyServerSideEncryptionByDefault></Rule></ServerSideEncryptionConfiguration>"#;
+        let response = self.client.send(Method::PUT, &encrypted_path, &[("encryption", "")], &[], config.as_bytes().to_vec()).await?;
+        expect_status(&response, StatusCode::OK)?;
+        let keys = ["a.txt", "nested/b.txt"];
+        for key in keys {
+            let response = self.client.send(Method::PUT, &format!("{}/{}", encrypted_path, key), &[], &[], key.as_bytes().to_vec()).await?;
+            expect_status(&response, StatusCode::OK)?;
+        }
+
+        let result = async {
+            let response = self.client.send(Method::POST, &rotation_path, &[], &[], Vec::new()).await?;
+            expect_status(&response, StatusCode::ACCEPTED)?;
+
+            let mut status = serde_json::Value::Null;
+            for _ in 0..50 {
+                let response = self.client.send(Method::GET, &rotation_path, &[], &[], Vec::new()).await?;
+                expect_status(&response, StatusCode::OK)?;
+                status = serde_json::from_slice(&response.body).map_err(|e| format!("invalid rotation JSON: {}", e))?;
+                if status["state"] == "completed" {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+            if status["state"] != "completed" || status["rotated"] != 2 || status["failed"] != 0 {
+                return Err(format!("unexpected rotation status: {}", status));
+            }
+
+            for key in keys {
+                let response = self.client.send(Method::GET, &format!("{}/{}", encrypted_path, key), &[], &[], Vec::new()).await?;
+                expect_status(&response, StatusCode::OK)?;
+                if response.body != key.as_bytes() {
+                    return Err(format!("content mismatch for {} after rotation: {:?}", key, response.text()));
+                }
+            }
+            Ok(())
+        }.await;
+
+        for key in keys.into_iter().chain(["nested/"]) {
+            let response = self.client.send(Method::DELETE, &format!("{}/{}", encrypted_path, key), &[], &[], Vec::new()).await?;
+            expect_status(&response, StatusCode::NO_CONTENT)?;
+        }
+        let response = self.client.send(Method::DELETE, &encrypted_path, &[], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::NO_CONTENT)?;
+        result
+    }
+
+    async fn sse_customer_key(&self) -> Result<(), String> {
+        fn sse_headers<'a>(key: &'a str, key_md5: &'a str) -> [(&'static str, &'a str); 3] {
+            [
+                ("x-amz-server-side-encryption-customer-algorithm", "AES256"),
+                ("x-amz-server-side-encryption-customer-key", key),
+                ("x-amz-server-side-encryption-customer-key-MD5", key_md5),
+            ]
+        }
+        let customer_key = |key: [u8; 32]| (BASE64.encode(key), BASE64.encode(md5::compute(key).0));
+        let (key, key_md5) = customer_key([7u8; 32]);
+        let (wrong_key, wrong_key_md5) = customer_key([8u8; 32]);
+
+        let path = self.object_path("sse-c.txt");
+        let body = b"encrypted with a customer key".to_vec();
+        let response = self.client.send(Method::PUT, &path, &[], &sse_headers(&key, &key_md5), body.clone()).await?;
+        expect_status(&response, StatusCode::OK)?;
+
+        // Reading requires the same key
+        let response = self.client.send(Method::GET, &path, &[], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::BAD_REQUEST)?;
+        let response = self.client.send(Method::GET, &path, &[], &sse_headers(&wrong_key, &wrong_key_md5), Vec::new()).await?;
+        expect_status(&response, StatusCode::FORBIDDEN)?;
+
+        for method in [Method::HEAD, Method::GET] {
+            let response = self.client.send(method.clone(), &path, &[], &sse_headers(&key, &key_md5), Vec::new()).await?;
+            expect_status(&response, StatusCode::OK)?;
+            if response.header("x-amz-server-side-encryption-customer-key-md5").as_deref() != Some(key_md5.as_str()) {
+                return Err(format!("{} did not echo the customer key MD5", method));
+            }
+            if method == Method::GET && response.body != body {
+                return Err(format!("content mismatch: got {:?}", response.text()));
+            }
+        }
+
+        let response = self.client.send(Method::DELETE, &path, &[], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::NO_CONTENT)
+    }
+
+    // Ranges of an encrypted object, including ones crossing chunk boundaries when the server runs with
+    // ENCRYPTION_CHUNK_SIZE=65536 or similar, return exactly the requested plaintext bytes
+    async fn encrypted_ranges(&self) -> Result<(), String> {
+        let encrypted_bucket = format!("/{}-ranges", self.bucket);
+        let response = self.client.send(Method::PUT, &encrypted_bucket, &[], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::OK)?;
+
+        let result = async {
+            let config = r#"<ServerSideEncryptionConfiguration><Rule><ApplyServerSideEncryptionByDefault><SSEAlgorithm>AES256</SSEAlgorithm></ApplyServerSideEncryptionByDefault></Rule></ServerSideEncryptionConfiguration>"#;
+            let response = self.client.send(Method::PUT, &encrypted_bucket, &[("encryption", "")], &[], config.as_bytes().to_vec()).await?;
+            expect_status(&response, StatusCode::OK)?;
+
+            let path = format!("{}/large.bin", encrypted_bucket);
+            let body: Vec<u8> = (0..200_000u32).map(|i| (i * 31 % 251) as u8).collect();
+            let response = self.client.send(Method::PUT, &path, &[], &[], body.clone()).await?;
+            expect_status(&response, StatusCode::OK)?;
+
+            let ranges = [
+                ("bytes=0-99", 0, 99),
+                ("bytes=65530-65545", 65530, 65545),
+                ("bytes=131000-199999", 131000, 199999),
+                ("bytes=199990-", 199990, 199999),
+                ("bytes=-500", 199500, 199999),
+            ];
+            for (range, start, end) in ranges {
+                let response = self.client.send(Method::GET, &path, &[], &[("range", range)], Vec::new()).await?;
+                expect_status(&response, StatusCode::PARTIAL_CONTENT)?;
+                let content_range = format!("bytes {}-{}/{}", start, end, body.len());
+                if response.header("content-range").as_deref() != Some(content_range.as_str()) {
+                    return Err(format!("{} returned Content-Range {:?}, expected {}", range, response.header("content-range"), content_range));
+                }
+                if response.body != body[start..=end] {
+                    return Err(format!("{} returned the wrong {} bytes", range, response.body.len()));
+                }
+            }
+
+            let response = self.client.send(Method::GET, &path, &[], &[("range", "bytes=200000-")], Vec::new()).await?;
+            expect_status(&response, StatusCode::RANGE_NOT_SATISFIABLE)?;
+            let response = self.client.send(Method::GET, &path, &[], &[], Vec::new()).await?;
+            expect_status(&response, StatusCode::OK)?;
+            if response.body != body {
+                return Err(format!("full GET returned {} bytes that differ from the upload", response.body.len()));
+            }
+            Ok(())
+        }.await;
+
+        let response = self.client.send(Method::DELETE, &encrypted_bucket, &[("force", "")], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::NO_CONTENT)?;
+        result
+    }
+
+    // Repeated, batch and folder deletes leave the object count exactly where it started.
+    // Passes trivially unless the server runs with ENABLE_QUOTA_AND_STATS=1.
+    async fn quota_object_count(&self) -> Result<(), String> {
+        let quota_bucket = format!("{}-quota", self.bucket);
+        let quota_path = format!("/{}", quota_bucket);
+        let response = self.client.send(Method::PUT, &quota_path, &[], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::OK)?;
+
+        let object_count = || async {
+            let response = self.client.send(Method::GET, &quota_path, &[("quota", "")], &[], Vec::new()).await?;
+            expect_status(&response, StatusCode::OK)?;
+            let quota: serde_json::Value = serde_json::from_slice(&response.body).map_err(|e| e.to_string())?;
+            quota["object_count"].as_u64().ok_or_else(|| format!("no object_count in {}", response.text()))
+        };
+
+        let result = async {
+            for key in ["a.txt", "b.txt", "dir/", "dir/c.txt"] {
+                let response = self.client.send(Method::PUT, &format!("{}/{}", quota_path, key), &[], &[], b"counted".to_vec()).await?;
+                expect_status(&response, StatusCode::OK)?;
+            }
+            let count = object_count().await?;
+            if count != 3 && count != 0 {
+                return Err(format!("object_count is {} after storing 3 objects and a folder marker", count));
+            }
+
+            for key in ["a.txt", "a.txt", "dir/"] {
+                self.client.send(Method::DELETE, &format!("{}/{}", quota_path, key), &[], &[], Vec::new()).await?;
+            }
+            let batch = "<Delete><Object><Key>b.txt</Key></Object><Object><Key>b.txt</Key></Object>\
+                <Object><Key>dir/c.txt</Key></Object><Object><Key>missing.txt</Key></Object></Delete>";
+            let response = self.client.send(Method::POST, &quota_path, &[("delete", "")], &[], batch.as_bytes().to_vec()).await?;
+            expect_status(&response, StatusCode::OK)?;
+
+            match object_count().await? {
+                0 => Ok(()),
+                count => Err(format!("object_count is {} after deleting everything", count)),
+            }
+        }.await;
+
+        let response = self.client.send(Method::DELETE, &quota_path, &[("force", "")], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::NO_CONTENT)?;
+        result
+    }
+
+    // With MULTIPART_MAX_UPLOADS_PER_BUCKET at most 4, one of five initiations is refused until an upload is aborted.
+    // Without a cap (or a larger one) all five start.
+    async fn multipart_upload_cap(&self) -> Result<(), String> {
+        let uploads_path = format!("/{}-uploads", self.bucket);
+        let response = self.client.send(Method::PUT, &uploads_path, &[], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::OK)?;
+
+        let result = async {
+            let path = format!("{}/capped.bin", uploads_path);
+            let mut upload_ids: Vec<String> = Vec::new();
+            for _ in 0..5 {
+                let response = self.client.send(Method::POST, &path, &[("uploads", "")], &[], Vec::new()).await?;
+                if response.status == StatusCode::BAD_REQUEST {
+                    if xml_value(&response.text(), "Code").as_deref() != Some("TooManyUploads") {
+                        return Err(format!("unexpected refusal: {}", response.text()));
+                    }
+                    let first = upload_ids.first().ok_or("the first upload was refused")?.as_str();
+                    let response = self.client.send(Method::DELETE, &path, &[("uploadId", first)], &[], Vec::new()).await?;
+                    expect_status(&response, StatusCode::NO_CONTENT)?;
+                    let response = self.client.send(Method::POST, &path, &[("uploads", "")], &[], Vec::new()).await?;
+                    return expect_status(&response, StatusCode::OK);
+                }
+                expect_status(&response, StatusCode::OK)?;
+                upload_ids.push(xml_value(&response.text(), "UploadId")
+                    .ok_or_else(|| format!("no UploadId in response: {}", response.text()))?);
+            }
+            Ok(())
+        }.await;
+
+        // ?force also aborts the uploads still in progress
+        let response = self.client.send(Method::DELETE, &uploads_path, &[("force", "")], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::NO_CONTENT)?;
+        result
+    }
+
+    // Keys are accepted exactly when they match the server's KEY_ALLOW_PATTERN, given to the self-test as well;
+    // without one every key is
+    async fn key_naming_policy(&self) -> Result<(), String> {
+        let pattern = match std::env::var("KEY_ALLOW_PATTERN").ok().filter(|p| !p.is_empty()) {
+            Some(pattern) => Some(regex::Regex::new(&format!("^(?:{})$", pattern)).map_err(|e| e.to_string())?),
+            None => None,
+        };
+
+        for key in ["naming policy.txt", "naming-policy.txt"] {
+            let allowed = pattern.as_ref().is_none_or(|pattern| pattern.is_match(key));
+            let expected = if allowed { StatusCode::OK } else { StatusCode::BAD_REQUEST };
+
+            let response = self.client.send(Method::PUT, &self.object_path(key), &[], &[], b"named".to_vec()).await?;
+            expect_status(&response, expected)?;
+            if allowed {
+                let response = self.client.send(Method::DELETE, &self.object_path(key), &[], &[], Vec::new()).await?;
+                expect_status(&response, StatusCode::NO_CONTENT)?;
+            }
+
+            let response = self.client.send(Method::POST, &self.object_path(key), &[("uploads", "")], &[], Vec::new()).await?;
+            expect_status(&response, expected)?;
+            if let Some(upload_id) = xml_value(&response.text(), "UploadId") {
+                let response = self.client.send(Method::DELETE, &self.object_path(key), &[("uploadId", &upload_id)], &[], Vec::new()).await?;
+                expect_status(&response, StatusCode::NO_CONTENT)?;
+            }
+        }
+        Ok(())
+    }
+
+    // A signed POST policy is enforced: content-length-range, starts-with and exact conditions, and its signature
+    async fn post_form_upload(&self) -> Result<(), String> {
+        let form_bucket = format!("{}-form", self.bucket);
+        let form_path = format!("/{}", form_bucket);
+        let response = self.client.send(Method::PUT, &form_path, &[], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::OK)?;
+
+        let result = async {
+            let conditions = [
+                serde_json::json!({"bucket": form_bucket}),
+                serde_json::json!(["starts-with", "$key", "form/"]),
+                serde_json::json!(["starts-with", "$Content-Type", "text/"]),
+                serde_json::json!(["content-length-range", 1, 16]),
+            ];
+            let fields = [("key", "form/${filename}"), ("Content-Type", "text/plain")];
+
+            let response = self.client.send_form(&form_path, &conditions, &fields, b"hello form").await?;
+            expect_status(&response, StatusCode::NO_CONTENT)?;
+            let response = self.client.send(Method::GET, &format!("{}/form/upload.txt", form_path), &[], &[], Vec::new()).await?;
+            expect_status(&response, StatusCode::OK)?;
+            if response.body != b"hello form" || response.header("content-type").as_deref() != Some("text/plain") {
+                return Err(format!("form upload stored {:?} as {:?}", response.text(), response.header("content-type")));
+            }
+
+            // The file size is checked against content-length-range
+            for (file, code) in [(&b"seventeen bytes!!"[..], "EntityTooLarge"), (&b""[..], "EntityTooSmall")] {
+                let response = self.client.send_form(&form_path, &conditions, &fields, file).await?;
+                expect_status(&response, StatusCode::BAD_REQUEST)?;
+                if xml_value(&response.text(), "Code").as_deref() != Some(code) {
+                    return Err(format!("expected {} for a {} byte file, got {}", code, file.len(), response.text()));
+                }
+            }
+
+            let rejected = [
+                (vec![("key", "elsewhere/upload.txt"), ("Content-Type", "text/plain")], "AccessDenied"),
+                (vec![("key", "form/upload.txt"), ("Content-Type", "image/png")], "AccessDenied"),
+                (vec![("key", "form/upload.txt"), ("Content-Type", "text/plain"), ("x-amz-meta-extra", "1")], "AccessDenied"),
+                (vec![("key", "form/upload.txt"), ("Content-Type", "text/plain"), ("x-amz-signature", "00")], "SignatureDoesNotMatch"),
+            ];
+            for (fields, code) in rejected {
+                let response = self.client.send_form(&form_path, &conditions, &fields, b"hello").await?;
+                expect_status(&response, StatusCode::FORBIDDEN)?;
+                if xml_value(&response.text(), "Code").as_deref() != Some(code) {
+                    return Err(format!("expected {} for {:?}, got {}", code, fields, response.text()));
+                }
+            }
+
+            // A key that climbs out of the bucket is refused, even when the policy allows its prefix
+            let escaping_key = format!("form/../../{}/escaped.txt", self.bucket);
+            let fields = [("key", escaping_key.as_str()), ("Content-Type", "text/plain")];
+            let response = self.client.send_form(&form_path, &conditions, &fields, b"escaped").await?;
+            expect_status(&response, StatusCode::BAD_REQUEST)?;
+            if xml_value(&response.text(), "Code").as_deref() != Some("InvalidArgument") {
+                return Err(format!("expected InvalidArgument for {}, got {}", escaping_key, response.text()));
+            }
+            let response = self.client.send(Method::GET, &self.object_path("escaped.txt"), &[], &[], Vec::new()).await?;
+            expect_status(&response, StatusCode::NOT_FOUND)?;
+
+            let fields = [("key", "form/created.txt"), ("Content-Type", "text/plain"), ("success_action_status", "201")];
+            let conditions = [conditions.as_slice(), &[serde_json::json!({"success_action_status": "201"})]].concat();
+            let response = self.client.send_form(&form_path, &conditions, &fields, b"created").await?;
+            expect_status(&response, StatusCode::CREATED)?;
+            if xml_value(&response.text(), "Key").as_deref() != Some("form/created.txt") {
+                return Err(format!("unexpected PostResponse: {}", response.text()));
+            }
+            Ok(())
+        }.await;
+
+        let response = self.client.send(Method::DELETE, &form_path, &[("force", "")], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::NO_CONTENT)?;
+        result
+    }
+
+    async fn delete_objects(&self) -> Result<(), String> {
+        for key in ["hello.txt", "multipart.bin", "reupload.bin"] {
+            let response = self.client.send(Method::DELETE, &self.object_path(key), &[], &[], Vec::new()).await?;
+            expect_status(&response, StatusCode::NO_CONTENT)?;
+        }
+
+        let response = self.client.send(Method::GET, &self.object_path("hello.txt"), &[], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::NOT_FOUND)
+    }
+
+    // ?force removes the versioned bucket along with its versions and an upload in progress
+    async fn force_delete_bucket(&self) -> Result<(), String> {
+        // delete_markers removed the bucket once it was empty
+        let response = self.client.send(Method::PUT, &self.versioned_bucket_path(), &[], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::OK)?;
+        let config = r#"<VersioningConfiguration><Status>Enabled</Status></VersioningConfiguration>"#;
+        let response = self.client.send(Method::PUT, &self.versioned_bucket_path(), &[("versioning", "")], &[], config.as_bytes().to_vec()).await?;
+        expect_status(&response, StatusCode::OK)?;
+
+        let path = format!("{}/left-behind.txt", self.versioned_bucket_path());
+        let response = self.client.send(Method::PUT, &path, &[], &[], b"first version".to_vec()).await?;
+        expect_status(&response, StatusCode::OK)?;
+        let response = self.client.send(Method::PUT, &path, &[], &[], b"still versioned".to_vec()).await?;
+        expect_status(&response, StatusCode::OK)?;
+        let response = self.client.send(Method::POST, &path, &[("uploads", "")], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::OK)?;
+
+        let response = self.client.send(Method::DELETE, &self.versioned_bucket_path(), &[], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::CONFLICT)?;
+        let response = self.client.send(Method::DELETE, &self.versioned_bucket_path(), &[("force", "")], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::NO_CONTENT)?;
+
+        let response = self.client.send(Method::HEAD, &self.versioned_bucket_path(), &[], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::NOT_FOUND)
+    }
+
+    async fn delete_bucket(&self) -> Result<(), String> {
+        let response = self.client.send(Method::DELETE, &self.bucket_path(), &[], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::NO_CONTENT)?;
+
+        let response = self.client.send(Method::HEAD, &self.bucket_path(), &[], &[], Vec::new()).await?;
+        expect_status(&response, StatusCode::NOT_FOUND)
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    dotenv::dotenv().ok();
+
+    let config = SelfTestConfig::from_env();
+    println!("IronBucket self-test against {} using bucket {}", config.endpoint, config.bucket);
+
+    let mut self_test = SelfTest::new(config);
+    self_test.run().await;
+
+    println!("\n{} passed, {} failed", self_test.passed, self_test.failed);
+    if self_test.failed > 0 {
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,158 @@
+use std::env;
+use std::fs;
+use std::net::IpAddr;
+use std::path::Path;
+
+use crate::filesystem::create_storage_dir;
+use crate::settings::{compile_key_pattern, load_content_type_overrides};
+use crate::utils::CANNED_ACLS;
+
+// Numeric settings; an unparsable value would otherwise fall back to its default without notice
+const UNSIGNED_VARS: [&str; 17] = [
+    "MULTIPART_MEMORY_THRESHOLD",
+    "MULTIPART_MAX_PARTS",
+    "CONFIG_BODY_MAX_BYTES",
+    "LIST_MAX_DEPTH",
+    "GET_MMAP_THRESHOLD",
+    "ENCRYPTION_CHUNK_SIZE",
+    "MAX_BUCKETS",
+    "MULTIPART_MAX_UPLOADS_PER_BUCKET",
+    "ACCESS_LOG_FLUSH_INTERVAL_SECS",
+    "METRICS_EXPORT_INTERVAL_SECS",
+    "LIFECYCLE_SWEEP_EVERY_X_MIN",
+    "AUTO_REMOVE_EMPTY_FOLDERS_EVERY_X_MIN",
+    "QUOTA_FLUSH_INTERVAL_MS",
+    "QUOTA_FLUSH_MAX_BACKOFF_MS",
+    "BUCKET_QUOTA_BYTES",
+    "MAX_CLOCK_SKEW_SECS",
+    "WAL_SEND_TIMEOUT_MS",
+];
+
+const OCTAL_MODE_VARS: [&str; 2] = ["STORAGE_FILE_MODE", "STORAGE_DIR_MODE"];
+
+// Flags compared against "true"
+const TRUE_FALSE_VARS: [&str; 11] = [
+    "LIST_FETCH_METADATA",
+    "LIST_FOLLOW_SYMLINKS",
+    "LIST_INDEX",
+    "GET_MMAP",
+    "VERIFY_ON_READ",
+    "PARTIAL_UPLOADS",
+    "DEDUP_IDENTICAL_VERSIONS",
+    "CREATE_FOLDER_MARKERS",
+    "ENABLE_WAL",
+    "QUOTA_STRICT",
+    "REQUIRE_TLS",
+];
+
+// Flags compared against "1"
+const ONE_ZERO_VARS: [&str; 2] = ["ENABLE_QUOTA_AND_STATS", "AUTO_REMOVE_EMPTY_FOLDERS"];
+
+// Check the environment before the server starts and collect every problem, so operators can fix them in one go.
+// Creates the storage directory (and the WAL directory when enabled) and checks both are writable.
+pub fn validate_environment(storage_path: &Path) -> Result<(), Vec<String>> {
+    let mut problems = Vec::new();
+
+    for var in ["ACCESS_KEY", "SECRET_KEY"] {
+        match env::var(var) {
+            Ok(value) if !value.trim().is_empty() => {}
+            Ok(_) => problems.push(format!("{} is empty; set it to the credential clients sign requests with", var)),
+            Err(_) => problems.push(format!("{} is not set; set it to the credential clients sign requests with", var)),
+        }
+    }
+
+    if let Ok(value) = env::var("BIND_ADDRESS") {
+        if value.trim().parse::<IpAddr>().is_err() {
+            problems.push(format!("BIND_ADDRESS={:?} is not an IP address such as 0.0.0.0 or 127.0.0.1", value));
+        }
+    }
+
+    if let Ok(value) = env::var("PORT") {
+        if value.trim().parse::<u16>().is_err() {
+            problems.push(format!("PORT={:?} is not a port number between 0 and 65535", value));
+        }
+    }
+
+    if let Ok(value) = env::var("ACCOUNT_ID") {
+        let value = value.trim();
+        if value.len() != 12 || !value.bytes().all(|b| b.is_ascii_digit()) {
+            problems.push(format!("ACCOUNT_ID={:?} is not a 12-digit account ID", value));
+        }
+    }
+
+    for var in UNSIGNED_VARS {
+        if let Ok(value) = env::var(var) {
+            if value.trim().parse::<u64>().is_err() {
+                problems.push(format!("{}={:?} is not a non-negative integer", var, value));
+            }
+        }
+    }
+
+    for var in OCTAL_MODE_VARS {
+        if let Ok(value) = env::var(var) {
+            let mode = u32::from_str_radix(value.trim().trim_start_matches("0o"), 8).ok();
+            if mode.is_none_or(|mode| mode > 0o7777) {
+                problems.push(format!("{}={:?} is not an octal permission mode such as 0640", var, value));
+            }
+        }
+    }
+
+    for var in TRUE_FALSE_VARS {
+        if let Ok(value) = env::var(var) {
+            if value != "true" && value != "false" {
+                problems.push(format!("{}={:?} must be \"true\" or \"false\"", var, value));
+            }
+        }
+    }
+
+    for var in ONE_ZERO_VARS {
+        if let Ok(value) = env::var(var) {
+            if value != "1" && value != "0" {
+                problems.push(format!("{}={:?} must be \"1\" or \"0\"", var, value));
+            }
+        }
+    }
+
+    if let Ok(value) = env::var("DEFAULT_OBJECT_ACL") {
+        if !CANNED_ACLS.contains(&value.trim()) {
+            problems.push(format!("DEFAULT_OBJECT_ACL={:?} is not one of the canned ACLs {}", value, CANNED_ACLS.join(", ")));
+        }
+    }
+
+    if let Ok(pattern) = env::var("KEY_ALLOW_PATTERN") {
+        if let Err(e) = compile_key_pattern(&pattern) {
+            problems.push(format!("KEY_ALLOW_PATTERN {:?} is not a valid regular expression: {}", pattern, e));
+        }
+    }
+
+    if let Ok(path) = env::var("CONTENT_TYPE_OVERRIDES_FILE") {
+        if let Err(e) = load_content_type_overrides(Path::new(&path)) {
+            problems.push(format!("CONTENT_TYPE_OVERRIDES_FILE {:?} cannot be used: {}", path, e));
+        }
+    }
+
+    if let Err(e) = create_storage_dir(storage_path).and_then(|_| check_writable(storage_path)) {
+        problems.push(format!("STORAGE_PATH {:?} is not a writable directory: {}", storage_path, e));
+    }
+
+    if env::var("ENABLE_WAL").as_deref() == Ok("true") {
+        let wal_dir = env::var("WAL_PATH").unwrap_or_else(|_| "/wal".to_string());
+        let wal_dir = Path::new(&wal_dir);
+        if let Err(e) = fs::create_dir_all(wal_dir).and_then(|_| check_writable(wal_dir)) {
+            problems.push(format!("WAL_PATH {:?} is not a writable directory (ENABLE_WAL=true): {}", wal_dir, e));
+        }
+    }
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(problems)
+    }
+}
+
+// Write and remove a probe file; hidden names are never listed as buckets
+fn check_writable(dir: &Path) -> Result<(), std::io::Error> {
+    let probe = dir.join(format!(".ironbucket-write-check-{}", std::process::id()));
+    fs::write(&probe, b"")?;
+    fs::remove_file(&probe)
+}
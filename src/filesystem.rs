@@ -1,8 +1,130 @@
 use std::fs;
-use std::path::PathBuf;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Condvar, Mutex, OnceLock};
 use chrono::{DateTime, Utc};
 // Note: serde imports removed as they're not needed
-use crate::{BucketEncryption, CorsConfiguration, LifecycleConfiguration};
+use crate::{BucketDefaultAcl, BucketEncryption, BucketLogging, BucketStorageClass, BucketTtl, BucketWebsite, CorsConfiguration, LifecycleConfiguration, MultipartUpload};
+
+// (file mode, dir mode) applied to everything created under the storage path, set once at startup
+static STORAGE_MODES: OnceLock<(u32, u32)> = OnceLock::new();
+
+/// Set the permission bits used by `write_storage_file` and `create_storage_dir`
+pub fn init_storage_modes(file_mode: u32, dir_mode: u32) {
+    let _ = STORAGE_MODES.set((file_mode, dir_mode));
+}
+
+fn storage_modes() -> (u32, u32) {
+    *STORAGE_MODES.get().unwrap_or(&(0o600, 0o700))
+}
+
+/// Paths locked for an update that spans several files, such as an object's data and its sidecar.
+/// Writers of the same path take turns; writers of other paths are not held up.
+pub struct PathLocks {
+    held: Mutex<Vec<PathBuf>>,
+    released: Condvar,
+}
+
+pub struct PathLockGuard<'a> {
+    locks: &'a PathLocks,
+    path: PathBuf,
+}
+
+impl Default for PathLocks {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PathLocks {
+    pub const fn new() -> Self {
+        PathLocks { held: Mutex::new(Vec::new()), released: Condvar::new() }
+    }
+
+    /// Block until no one else holds the path. Only call this from blocking threads.
+    pub fn lock(&self, path: &Path) -> PathLockGuard<'_> {
+        let mut held = self.held.lock().unwrap();
+        while held.iter().any(|locked| locked == path) {
+            held = self.released.wait(held).unwrap();
+        }
+        held.push(path.to_path_buf());
+        PathLockGuard { locks: self, path: path.to_path_buf() }
+    }
+}
+
+impl Drop for PathLockGuard<'_> {
+    fn drop(&mut self) {
+        let mut held = self.locks.held.lock().unwrap();
+        if let Some(index) = held.iter().position(|locked| *locked == self.path) {
+            held.swap_remove(index);
+        }
+        self.locks.released.notify_all();
+    }
+}
+
+/// Held, by data path, while an object's data and metadata sidecar are replaced or removed together.
+/// PUT, copy, multipart completion, DELETE and key rotation all take it, so their file updates never interleave.
+pub static OBJECT_WRITE_LOCKS: PathLocks = PathLocks::new();
+
+/// Write a file and apply the configured storage file mode.
+/// The file is replaced by rename, never truncated in place, so readers holding it open or mapped are unaffected.
+pub fn write_storage_file<P: AsRef<Path>, C: AsRef<[u8]>>(path: P, contents: C) -> Result<(), std::io::Error> {
+    let path = path.as_ref();
+    let file_name = path.file_name().map(|n| n.to_string_lossy()).unwrap_or_default();
+    let temp_path = path.with_file_name(format!(".{}.{}.tmp", file_name, uuid::Uuid::new_v4()));
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(storage_modes().0);
+    }
+
+    let result = options.open(&temp_path)
+        .and_then(|mut file| file.write_all(contents.as_ref()))
+        .and_then(|_| set_storage_mode(&temp_path, storage_modes().0))
+        .and_then(|_| fs::rename(&temp_path, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
+/// Open a file for in-place writes, creating it with the configured storage file mode if missing.
+/// Unlike `write_storage_file` this neither truncates nor replaces the file.
+pub fn open_storage_file<P: AsRef<Path>>(path: P) -> Result<fs::File, std::io::Error> {
+    let mut options = fs::OpenOptions::new();
+    options.read(true).write(true).create(true).truncate(false);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(storage_modes().0);
+    }
+    options.open(path)
+}
+
+/// Create a directory and any missing parents, applying the configured storage dir mode to each one created
+pub fn create_storage_dir<P: AsRef<Path>>(path: P) -> Result<(), std::io::Error> {
+    let path = path.as_ref();
+    let missing: Vec<&Path> = path.ancestors().take_while(|p| !p.as_os_str().is_empty() && !p.exists()).collect();
+    fs::create_dir_all(path)?;
+    for dir in missing {
+        set_storage_mode(dir, storage_modes().1)?;
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn set_storage_mode(path: &Path, mode: u32) -> Result<(), std::io::Error> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))
+}
+
+#[cfg(not(unix))]
+fn set_storage_mode(_path: &Path, _mode: u32) -> Result<(), std::io::Error> {
+    Ok(())
+}
 
 /// Check if a bucket exists on the filesystem
 pub fn bucket_exists(storage_path: &PathBuf, bucket: &str) -> bool {
@@ -10,6 +132,75 @@ pub fn bucket_exists(storage_path: &PathBuf, bucket: &str) -> bool {
     bucket_path.exists() && bucket_path.is_dir()
 }
 
+/// Check whether a bucket holds any user data, stopping at the first entry found.
+/// Objects, stored versions and delete markers (under `.versions`) and staged resumable uploads
+/// (under `.partial`) all count. Bucket configuration files (`.policy`, `.cors`, `.lifecycle`, ...),
+/// `.metadata` sidecars, `.stats`/`.quota` and `.multipart` are ignored; multipart uploads in
+/// progress are tracked in memory and checked by the caller.
+pub fn bucket_has_data(storage_path: &PathBuf, bucket: &str) -> bool {
+    let bucket_path = storage_path.join(bucket);
+    let entries = match fs::read_dir(&bucket_path) {
+        Ok(entries) => entries,
+        Err(_) => return false,
+    };
+
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name == ".versions" || name == ".partial" {
+            if dir_contains_file(&entry.path()) {
+                return true;
+            }
+        } else if !name.starts_with('.') && !name.ends_with(".metadata") {
+            return true;
+        }
+    }
+    false
+}
+
+/// Count a bucket's current objects (folder markers included) and stored versions and delete markers,
+/// for reporting what a forced bucket deletion removed
+pub fn count_bucket_objects(storage_path: &Path, bucket: &str) -> (u64, u64) {
+    let bucket_path = storage_path.join(bucket);
+    let (mut objects, mut versions) = (0, 0);
+    let walker = walkdir::WalkDir::new(&bucket_path)
+        .into_iter()
+        .filter_entry(|entry| {
+            let name = entry.file_name().to_string_lossy();
+            !entry.file_type().is_dir() || entry.depth() == 0 || !name.starts_with('.')
+                || (entry.depth() == 1 && name == ".versions")
+        });
+
+    for entry in walker.filter_map(|e| e.ok()) {
+        let name = entry.file_name().to_string_lossy();
+        if !entry.file_type().is_file() || name.ends_with(".metadata") {
+            continue;
+        }
+        if entry.path().starts_with(bucket_path.join(".versions")) {
+            versions += 1;
+        } else if !name.starts_with('.') || name == crate::utils::FOLDER_MARKER_NAME {
+            objects += 1;
+        }
+    }
+    (objects, versions)
+}
+
+// Depth-first search that returns as soon as any file is found
+fn dir_contains_file(dir: &Path) -> bool {
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        if let Ok(entries) = fs::read_dir(&dir) {
+            for entry in entries.flatten() {
+                match entry.file_type() {
+                    Ok(file_type) if file_type.is_dir() => pending.push(entry.path()),
+                    Ok(_) => return true,
+                    Err(_) => {}
+                }
+            }
+        }
+    }
+    false
+}
+
 /// Get bucket creation time from filesystem
 pub fn get_bucket_created_time(storage_path: &PathBuf, bucket: &str) -> Option<DateTime<Utc>> {
     let bucket_path = storage_path.join(bucket);
@@ -26,6 +217,15 @@ pub fn get_bucket_created_time(storage_path: &PathBuf, bucket: &str) -> Option<D
     None
 }
 
+/// Read the access key that created a bucket from its .bucket_metadata file
+pub fn read_bucket_owner(storage_path: &Path, bucket: &str) -> Option<String> {
+    let metadata_path = storage_path.join(bucket).join(".bucket_metadata");
+    fs::read_to_string(metadata_path)
+        .ok()
+        .and_then(|content| serde_json::from_str::<serde_json::Value>(&content).ok())
+        .and_then(|json| json.get("owner")?.as_str().map(String::from))
+}
+
 /// Read bucket policy from filesystem
 pub fn read_bucket_policy(storage_path: &PathBuf, bucket: &str) -> Option<String> {
     let policy_file = storage_path.join(bucket).join(".policy");
@@ -39,7 +239,7 @@ pub fn read_bucket_policy(storage_path: &PathBuf, bucket: &str) -> Option<String
 /// Write bucket policy to filesystem
 pub fn write_bucket_policy(storage_path: &PathBuf, bucket: &str, policy: &str) -> Result<(), std::io::Error> {
     let policy_file = storage_path.join(bucket).join(".policy");
-    fs::write(&policy_file, policy)
+    write_storage_file(&policy_file, policy)
 }
 
 /// Delete bucket policy from filesystem
@@ -70,7 +270,7 @@ pub fn read_bucket_encryption(storage_path: &PathBuf, bucket: &str) -> Option<Bu
 pub fn write_bucket_encryption(storage_path: &PathBuf, bucket: &str, encryption: &BucketEncryption) -> Result<(), Box<dyn std::error::Error>> {
     let encryption_file = storage_path.join(bucket).join(".encryption");
     let encryption_json = serde_json::to_string_pretty(encryption)?;
-    fs::write(&encryption_file, encryption_json)?;
+    write_storage_file(&encryption_file, encryption_json)?;
     Ok(())
 }
 
@@ -102,7 +302,7 @@ pub fn read_bucket_cors(storage_path: &PathBuf, bucket: &str) -> Option<CorsConf
 pub fn write_bucket_cors(storage_path: &PathBuf, bucket: &str, cors: &CorsConfiguration) -> Result<(), Box<dyn std::error::Error>> {
     let cors_file = storage_path.join(bucket).join(".cors");
     let cors_json = serde_json::to_string_pretty(cors)?;
-    fs::write(&cors_file, cors_json)?;
+    write_storage_file(&cors_file, cors_json)?;
     Ok(())
 }
 
@@ -116,6 +316,148 @@ pub fn delete_bucket_cors(storage_path: &PathBuf, bucket: &str) -> Result<(), st
     }
 }
 
+/// Read bucket access log delivery configuration from filesystem
+pub fn read_bucket_logging(storage_path: &Path, bucket: &str) -> Option<BucketLogging> {
+    let logging_file = storage_path.join(bucket).join(".logging");
+    if logging_file.exists() {
+        if let Ok(logging_json) = fs::read_to_string(&logging_file) {
+            serde_json::from_str::<BucketLogging>(&logging_json).ok()
+        } else {
+            None
+        }
+    } else {
+        None
+    }
+}
+
+/// Write bucket access log delivery configuration to filesystem
+pub fn write_bucket_logging(storage_path: &Path, bucket: &str, logging: &BucketLogging) -> Result<(), Box<dyn std::error::Error>> {
+    let logging_file = storage_path.join(bucket).join(".logging");
+    let logging_json = serde_json::to_string_pretty(logging)?;
+    write_storage_file(&logging_file, logging_json)?;
+    Ok(())
+}
+
+/// Delete bucket access log delivery configuration from filesystem
+pub fn delete_bucket_logging(storage_path: &Path, bucket: &str) -> Result<(), std::io::Error> {
+    let logging_file = storage_path.join(bucket).join(".logging");
+    if logging_file.exists() {
+        fs::remove_file(&logging_file)
+    } else {
+        Ok(())
+    }
+}
+
+/// Read bucket static website configuration from filesystem
+pub fn read_bucket_website(storage_path: &Path, bucket: &str) -> Option<BucketWebsite> {
+    let website_file = storage_path.join(bucket).join(".website");
+    fs::read_to_string(&website_file)
+        .ok()
+        .and_then(|website_json| serde_json::from_str::<BucketWebsite>(&website_json).ok())
+}
+
+/// Write bucket static website configuration to filesystem
+pub fn write_bucket_website(storage_path: &Path, bucket: &str, website: &BucketWebsite) -> Result<(), Box<dyn std::error::Error>> {
+    let website_file = storage_path.join(bucket).join(".website");
+    let website_json = serde_json::to_string_pretty(website)?;
+    write_storage_file(&website_file, website_json)?;
+    Ok(())
+}
+
+/// Delete bucket static website configuration from filesystem
+pub fn delete_bucket_website(storage_path: &Path, bucket: &str) -> Result<(), std::io::Error> {
+    let website_file = storage_path.join(bucket).join(".website");
+    if website_file.exists() {
+        fs::remove_file(&website_file)
+    } else {
+        Ok(())
+    }
+}
+
+/// Read bucket object TTL from filesystem
+pub fn read_bucket_ttl(storage_path: &PathBuf, bucket: &str) -> Option<BucketTtl> {
+    let ttl_file = storage_path.join(bucket).join(".ttl");
+    if ttl_file.exists() {
+        if let Ok(ttl_json) = fs::read_to_string(&ttl_file) {
+            serde_json::from_str::<BucketTtl>(&ttl_json).ok()
+        } else {
+            None
+        }
+    } else {
+        None
+    }
+}
+
+/// Write bucket object TTL to filesystem
+pub fn write_bucket_ttl(storage_path: &PathBuf, bucket: &str, ttl: &BucketTtl) -> Result<(), Box<dyn std::error::Error>> {
+    let ttl_file = storage_path.join(bucket).join(".ttl");
+    let ttl_json = serde_json::to_string_pretty(ttl)?;
+    write_storage_file(&ttl_file, ttl_json)?;
+    Ok(())
+}
+
+/// Delete bucket object TTL from filesystem
+pub fn delete_bucket_ttl(storage_path: &PathBuf, bucket: &str) -> Result<(), std::io::Error> {
+    let ttl_file = storage_path.join(bucket).join(".ttl");
+    if ttl_file.exists() {
+        fs::remove_file(&ttl_file)
+    } else {
+        Ok(())
+    }
+}
+
+/// Read bucket default storage class from filesystem
+pub fn read_bucket_storage_class(storage_path: &Path, bucket: &str) -> Option<BucketStorageClass> {
+    let storage_class_file = storage_path.join(bucket).join(".storage_class");
+    fs::read_to_string(&storage_class_file)
+        .ok()
+        .and_then(|json| serde_json::from_str::<BucketStorageClass>(&json).ok())
+}
+
+/// Write bucket default storage class to filesystem
+pub fn write_bucket_storage_class(storage_path: &Path, bucket: &str, storage_class: &BucketStorageClass) -> Result<(), Box<dyn std::error::Error>> {
+    let storage_class_file = storage_path.join(bucket).join(".storage_class");
+    let storage_class_json = serde_json::to_string_pretty(storage_class)?;
+    write_storage_file(&storage_class_file, storage_class_json)?;
+    Ok(())
+}
+
+/// Delete bucket default storage class from filesystem
+pub fn delete_bucket_storage_class(storage_path: &Path, bucket: &str) -> Result<(), std::io::Error> {
+    let storage_class_file = storage_path.join(bucket).join(".storage_class");
+    if storage_class_file.exists() {
+        fs::remove_file(&storage_class_file)
+    } else {
+        Ok(())
+    }
+}
+
+/// Read bucket default object ACL from filesystem
+pub fn read_bucket_default_acl(storage_path: &Path, bucket: &str) -> Option<BucketDefaultAcl> {
+    let default_acl_file = storage_path.join(bucket).join(".default_acl");
+    fs::read_to_string(&default_acl_file)
+        .ok()
+        .and_then(|json| serde_json::from_str::<BucketDefaultAcl>(&json).ok())
+}
+
+/// Write bucket default object ACL to filesystem
+pub fn write_bucket_default_acl(storage_path: &Path, bucket: &str, default_acl: &BucketDefaultAcl) -> Result<(), Box<dyn std::error::Error>> {
+    let default_acl_file = storage_path.join(bucket).join(".default_acl");
+    let default_acl_json = serde_json::to_string_pretty(default_acl)?;
+    write_storage_file(&default_acl_file, default_acl_json)?;
+    Ok(())
+}
+
+/// Delete bucket default object ACL from filesystem
+pub fn delete_bucket_default_acl(storage_path: &Path, bucket: &str) -> Result<(), std::io::Error> {
+    let default_acl_file = storage_path.join(bucket).join(".default_acl");
+    if default_acl_file.exists() {
+        fs::remove_file(&default_acl_file)
+    } else {
+        Ok(())
+    }
+}
+
 /// Read bucket lifecycle configuration from filesystem
 pub fn read_bucket_lifecycle(storage_path: &PathBuf, bucket: &str) -> Option<LifecycleConfiguration> {
     let lifecycle_file = storage_path.join(bucket).join(".lifecycle");
@@ -134,7 +476,7 @@ pub fn read_bucket_lifecycle(storage_path: &PathBuf, bucket: &str) -> Option<Lif
 pub fn write_bucket_lifecycle(storage_path: &PathBuf, bucket: &str, lifecycle: &LifecycleConfiguration) -> Result<(), Box<dyn std::error::Error>> {
     let lifecycle_file = storage_path.join(bucket).join(".lifecycle");
     let lifecycle_json = serde_json::to_string_pretty(lifecycle)?;
-    fs::write(&lifecycle_file, lifecycle_json)?;
+    write_storage_file(&lifecycle_file, lifecycle_json)?;
     Ok(())
 }
 
@@ -150,18 +492,19 @@ pub fn delete_bucket_lifecycle(storage_path: &PathBuf, bucket: &str) -> Result<(
 
 /// Read bucket versioning status from filesystem
 pub fn read_bucket_versioning(storage_path: &PathBuf, bucket: &str) -> Option<String> {
+    // No .versioning file means versioning was never configured; suspending writes "Suspended" rather than
+    // removing the file, so the two states stay distinguishable
     let versioning_file = storage_path.join(bucket).join(".versioning");
-    if versioning_file.exists() {
-        fs::read_to_string(&versioning_file).ok()
-    } else {
-        None
-    }
+    fs::read_to_string(&versioning_file)
+        .ok()
+        .map(|status| status.trim().to_string())
+        .filter(|status| status == "Enabled" || status == "Suspended")
 }
 
 /// Write bucket versioning status to filesystem
 pub fn write_bucket_versioning(storage_path: &PathBuf, bucket: &str, status: &str) -> Result<(), std::io::Error> {
     let versioning_file = storage_path.join(bucket).join(".versioning");
-    fs::write(&versioning_file, status)
+    write_storage_file(&versioning_file, status)
 }
 
 /// List all buckets from filesystem
@@ -185,4 +528,44 @@ pub fn list_bucket_names(storage_path: &PathBuf) -> Result<Vec<String>, std::io:
 
     buckets.sort();
     Ok(buckets)
-}
\ No newline at end of file
+}
+
+/// Read the in-progress multipart uploads persisted in the bucket's `.multipart` directory.
+/// Parts are not loaded; the uploads only carry their id, key and initiation time.
+pub fn read_bucket_uploads(storage_path: &Path, bucket: &str) -> Vec<MultipartUpload> {
+    let multipart_dir = storage_path.join(bucket).join(".multipart");
+    let Ok(entries) = fs::read_dir(&multipart_dir) else {
+        return Vec::new();
+    };
+
+    let mut uploads = Vec::new();
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("upload") {
+            continue;
+        }
+        let Some(json) = fs::read_to_string(&path)
+            .ok()
+            .and_then(|contents| serde_json::from_str::<serde_json::Value>(&contents).ok())
+        else {
+            continue;
+        };
+
+        let field = |name: &str| json.get(name).and_then(|v| v.as_str()).map(String::from);
+        let (Some(upload_id), Some(key)) = (field("upload_id"), field("key")) else {
+            continue;
+        };
+        let initiated = field("initiated")
+            .and_then(|t| DateTime::parse_from_rfc3339(&t).ok())
+            .map(|t| t.with_timezone(&Utc))
+            .unwrap_or_else(Utc::now);
+        uploads.push(MultipartUpload {
+            upload_id,
+            bucket: bucket.to_string(),
+            key,
+            parts: Default::default(),
+            initiated,
+        });
+    }
+    uploads
+}
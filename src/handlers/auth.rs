@@ -1,28 +1,35 @@
 use axum::{
     body::Body,
     extract::State,
-    http::{HeaderMap, Method, Request, StatusCode},
+    http::{HeaderMap, Method, Request, StatusCode, header},
     middleware::Next,
     response::Response,
 };
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDateTime, Utc};
 use std::collections::HashMap;
 use tracing::{debug, info};
 
-use crate::{AppState, check_policy_permission, filesystem::read_bucket_policy};
+use crate::{
+    AppState, AuthenticatedUser, ObjectMetadata, check_policy_permission, is_explicitly_denied, Principal,
+    filesystem::read_bucket_policy,
+    utils::{is_reserved_key, is_secure_transport, object_storage_key, parse_sigv4_authorization},
+};
+
+// Query parameters that may accompany an anonymous ListObjects request
+const LIST_QUERY_PARAMS: [&str; 9] = [
+    "prefix", "delimiter", "max-keys", "continuation-token", "list-type",
+    "start-after", "marker", "encoding-type", "fetch-owner",
+];
 
 pub async fn auth_middleware(
     State(state): State<AppState>,
     headers: HeaderMap,
-    request: Request<Body>,
+    mut request: Request<Body>,
     next: Next,
 ) -> Response {
-    // Extract client IP from headers, defaulting to localhost if not found
-    let client_ip = headers.get("x-real-ip")
-        .or_else(|| headers.get("x-forwarded-for"))
-        .and_then(|v| v.to_str().ok())
-        .map(|s| s.split(',').next().unwrap_or(s).trim().to_string())
-        .or_else(|| Some("127.0.0.1".to_string()));  // Default to localhost for direct connections
+    let client_ip = Some(request_client_ip(&headers));
+    // For aws:SecureTransport conditions in bucket policies
+    let secure_transport = is_secure_transport(&headers);
 
     // Log all requests for debugging
     debug!("Request: {:?} {} {:?} from IP: {:?}", request.method(), request.uri(), headers, client_ip);
@@ -35,14 +42,9 @@ pub async fn auth_middleware(
         None
     };
 
-    // Determine S3 action from request method and path
-    let action = match request.method() {
-        &Method::GET => "s3:GetObject",
-        &Method::PUT => "s3:PutObject",
-        &Method::DELETE => "s3:DeleteObject",
-        &Method::HEAD => "s3:GetObject",
-        _ => "s3:*",
-    };
+    // Determine S3 action from request method, path and subresource
+    let has_key = path.trim_start_matches('/').split_once('/').is_some_and(|(_, key)| !key.is_empty());
+    let action = s3_action(request.method(), has_key, request.uri().query());
 
     // OPTIONS requests bypass auth for CORS
     if request.method() == Method::OPTIONS {
@@ -131,8 +133,10 @@ pub async fn auth_middleware(
                                     policy_str,
                                     action,
                                     &resource,
-                                    "*", // Principal for presigned URLs
-                                    client_ip.as_deref()
+                                    Principal::Anonymous, // Principal for presigned URLs
+                                    client_ip.as_deref(),
+                                    secure_transport,
+                                    None
                                 );
 
                                 if !allowed {
@@ -146,6 +150,7 @@ pub async fn auth_middleware(
                             }
                         }
 
+                        request.extensions_mut().insert(AuthenticatedUser { access_key: access_key.to_string() });
                         return next.run(request).await;
                     }
                 }
@@ -154,63 +159,346 @@ pub async fn auth_middleware(
     }
 
     // Check for AWS Signature V4 authentication in headers
-    if let Some(auth_header) = headers.get("Authorization") {
-        if let Ok(auth_str) = auth_header.to_str() {
-            if auth_str.starts_with("AWS4-HMAC-SHA256") {
-                // Parse the authorization header
-                let parts: Vec<&str> = auth_str.split(' ').collect();
-                if parts.len() >= 2 {
-                    let credential_part = parts[1];
-                    if let Some(credential) = credential_part.strip_prefix("Credential=") {
-                        let cred_parts: Vec<&str> = credential.split('/').collect();
-                        if !cred_parts.is_empty() {
-                            let access_key = cred_parts[0].split(',').next().unwrap_or("");
-
-                            // Check if access key exists
-                            if state.access_keys.contains_key(access_key) {
-                                debug!("Authenticated request with access key: {}", access_key);
-
-                                // Check bucket policy with IP conditions
-                                if let Some(bucket) = bucket_name {
-                                    // Read policy from filesystem
-                                    let policy_json = read_bucket_policy(&state.storage_path, bucket);
-
-                                    if let Some(ref policy_str) = policy_json {
-                                        let resource = format!("arn:aws:s3:::{}/{}*", bucket,
-                                            path.trim_start_matches('/').trim_start_matches(bucket).trim_start_matches('/'));
-
-                                        let allowed = check_policy_permission(
-                                            policy_str,
-                                            action,
-                                            &resource,
-                                            access_key, // Use actual access key as principal
-                                            client_ip.as_deref()
-                                        );
-
-                                        if !allowed {
-                                            info!("Access denied by bucket policy: bucket={}, action={}, client_ip={:?}",
-                                                  bucket, action, client_ip);
-                                            return Response::builder()
-                                                .status(StatusCode::FORBIDDEN)
-                                                .body(Body::from("Access Denied by bucket policy"))
-                                                .unwrap();
-                                        }
-                                    }
-                                }
+    if let Some(authorization) = headers.get("Authorization")
+        .and_then(|v| v.to_str().ok())
+        .and_then(parse_sigv4_authorization)
+        .filter(|authorization| authorization.has_well_formed_signature())
+    {
+        let access_key = authorization.access_key();
 
-                                return next.run(request).await;
-                            }
-                        }
+        // A signed request is only valid close to the time it was signed, so captured requests cannot be replayed later
+        if let Some(max_skew) = state.settings.max_clock_skew_secs {
+            if let Err(response) = check_request_time(&headers, max_skew) {
+                return *response;
+            }
+        }
+
+        // Check if access key exists
+        if state.access_keys.contains_key(access_key) {
+            debug!("Authenticated request with access key: {} (signed headers: {})", access_key, authorization.signed_headers);
+
+            // Check bucket policy with IP conditions
+            if let Some(bucket) = bucket_name {
+                // Read policy from filesystem
+                let policy_json = read_bucket_policy(&state.storage_path, bucket);
+
+                if let Some(ref policy_str) = policy_json {
+                    let resource = format!("arn:aws:s3:::{}/{}*", bucket,
+                        path.trim_start_matches('/').trim_start_matches(bucket).trim_start_matches('/'));
+
+                    let allowed = check_policy_permission(
+                        policy_str,
+                        action,
+                        &resource,
+                        Principal::AccessKey { access_key, account_id: &state.settings.account_id },
+                        client_ip.as_deref(),
+                        secure_transport,
+                        None
+                    );
+
+                    if !allowed {
+                        info!("Access denied by bucket policy: bucket={}, action={}, client_ip={:?}",
+                              bucket, action, client_ip);
+                        return Response::builder()
+                            .status(StatusCode::FORBIDDEN)
+                            .body(Body::from("Access Denied by bucket policy"))
+                            .unwrap();
                     }
                 }
             }
+
+            request.extensions_mut().insert(AuthenticatedUser { access_key: access_key.to_string() });
+            return next.run(request).await;
         }
     }
 
+    // Browser form uploads are authenticated by the bucket handler, which verifies the signed policy in the form
+    if is_form_upload(&request) {
+        debug!("Passing POST form upload to the bucket handler for policy verification");
+        return next.run(request).await;
+    }
+
+    // Allow anonymous listing when the bucket policy grants s3:ListBucket to everyone
+    if is_anonymous_listing_allowed(&state, &request, client_ip.as_deref(), secure_transport) {
+        debug!("Allowing anonymous bucket listing via bucket policy");
+        return next.run(request).await;
+    }
+
+    // Allow anonymous reads of objects whose ACL is public-read, unless the bucket policy denies them
+    if is_anonymous_object_read_allowed(&state, &request, client_ip.as_deref(), secure_transport) {
+        debug!("Allowing anonymous object read via object ACL");
+        return next.run(request).await;
+    }
+
     // Return 403 Forbidden for unauthenticated requests
     debug!("Request without authentication, returning 403 Forbidden");
     Response::builder()
         .status(StatusCode::FORBIDDEN)
         .body(Body::from("Access Denied: Authentication required"))
         .unwrap()
-}
\ No newline at end of file
+}
+
+// Extract client IP from headers, defaulting to localhost for direct connections
+fn request_client_ip(headers: &HeaderMap) -> String {
+    headers.get("x-real-ip")
+        .or_else(|| headers.get("x-forwarded-for"))
+        .and_then(|v| v.to_str().ok())
+        .map(|s| s.split(',').next().unwrap_or(s).trim().to_string())
+        .unwrap_or_else(|| "127.0.0.1".to_string())
+}
+
+// A POST Object upload: a multipart/form-data POST to the bucket itself, with no subresource
+fn is_form_upload(request: &Request<Body>) -> bool {
+    let path = request.uri().path().trim_matches('/');
+    request.method() == Method::POST
+        && request.uri().query().is_none()
+        && !path.is_empty()
+        && !path.contains('/')
+        && request.headers().get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|content_type| content_type.trim_start().to_ascii_lowercase().starts_with("multipart/form-data"))
+}
+
+// Whether the bucket policy lets an access key that signed a POST form upload put `key`, with the same IP and
+// transport conditions the middleware applies to signed requests
+pub fn is_form_upload_allowed(state: &AppState, headers: &HeaderMap, bucket: &str, key: &str, access_key: &str) -> bool {
+    let Some(policy_str) = read_bucket_policy(&state.storage_path, bucket) else {
+        return true;
+    };
+    let allowed = check_policy_permission(
+        &policy_str,
+        "s3:PutObject",
+        &format!("arn:aws:s3:::{}/{}", bucket, key),
+        Principal::AccessKey { access_key, account_id: &state.settings.account_id },
+        Some(&request_client_ip(headers)),
+        is_secure_transport(headers),
+        None
+    );
+    if !allowed {
+        info!("Access denied by bucket policy for POST form upload: bucket={}, key={}", bucket, key);
+    }
+    allowed
+}
+
+// With REQUIRE_TLS, refuse requests that did not reach the proxy over HTTPS before they are authenticated or routed
+pub async fn require_tls_middleware(State(state): State<AppState>, request: Request<Body>, next: Next) -> Response {
+    if !state.settings.require_tls || is_secure_transport(request.headers()) {
+        return next.run(request).await;
+    }
+
+    info!("Rejecting plaintext request {} {}: REQUIRE_TLS is set", request.method(), request.uri());
+    Response::builder()
+        .status(StatusCode::FORBIDDEN)
+        .header(header::CONTENT_TYPE, "application/xml")
+        .body(Body::from(r#"<?xml version="1.0" encoding="UTF-8"?>
+<Error>
+    <Code>AccessDenied</Code>
+    <Message>This server only accepts requests made over HTTPS</Message>
+</Error>"#))
+        .unwrap()
+}
+
+// Compare the signing time (x-amz-date, or Date when it is absent) with the server clock
+fn check_request_time(headers: &HeaderMap, max_skew_secs: u64) -> Result<(), Box<Response>> {
+    let amz_date = headers.get("x-amz-date")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| NaiveDateTime::parse_from_str(v.trim(), "%Y%m%dT%H%M%SZ").ok())
+        .map(|date| date.and_utc());
+    let request_time = amz_date.or_else(|| {
+        headers.get(header::DATE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| DateTime::parse_from_rfc2822(v.trim()).ok())
+            .map(|date| date.with_timezone(&Utc))
+    });
+
+    let Some(request_time) = request_time else {
+        debug!("Signed request without a valid x-amz-date or Date header");
+        return Err(Box::new(Response::builder()
+            .status(StatusCode::FORBIDDEN)
+            .header(header::CONTENT_TYPE, "application/xml")
+            .body(Body::from(r#"<?xml version="1.0" encoding="UTF-8"?>
+<Error>
+    <Code>AccessDenied</Code>
+    <Message>AWS authentication requires a valid Date or x-amz-date header</Message>
+</Error>"#))
+            .unwrap()));
+    };
+
+    let now = Utc::now();
+    if now.signed_duration_since(request_time).num_seconds().unsigned_abs() <= max_skew_secs {
+        return Ok(());
+    }
+
+    debug!("Request time {} is more than {} seconds from server time {}", request_time, max_skew_secs, now);
+    Err(Box::new(Response::builder()
+        .status(StatusCode::FORBIDDEN)
+        .header(header::CONTENT_TYPE, "application/xml")
+        .body(Body::from(format!(r#"<?xml version="1.0" encoding="UTF-8"?>
+<Error>
+    <Code>RequestTimeTooSkewed</Code>
+    <Message>The difference between the request time and the current time is too large.</Message>
+    <RequestTime>{}</RequestTime>
+    <ServerTime>{}</ServerTime>
+    <MaxAllowedSkewMilliseconds>{}</MaxAllowedSkewMilliseconds>
+</Error>"#, request_time.format("%Y%m%dT%H%M%SZ"), now.format("%Y-%m-%dT%H:%M:%SZ"), max_skew_secs * 1000)))
+        .unwrap()))
+}
+
+// Map a request to the IAM action a bucket policy must allow, as S3 does for each operation.
+// IronBucket's own subresources map to the closest S3 action: ttl and storage-class to lifecycle,
+// default-acl to the bucket ACL, quota and stats to ListBucket.
+fn s3_action(method: &Method, has_key: bool, query: Option<&str>) -> &'static str {
+    let params: Vec<&str> = query.unwrap_or("")
+        .split('&')
+        .map(|param| param.split('=').next().unwrap_or(""))
+        .filter(|name| !name.is_empty())
+        .collect();
+    let has = |name: &str| params.contains(&name);
+
+    if has_key {
+        return match *method {
+            Method::GET if has("uploadId") => "s3:ListMultipartUploadParts",
+            Method::GET if has("acl") => "s3:GetObjectAcl",
+            Method::GET if has("tagging") => "s3:GetObjectTagging",
+            Method::GET if has("versions") => "s3:ListBucketVersions",
+            Method::GET | Method::HEAD if has("versionId") => "s3:GetObjectVersion",
+            Method::GET | Method::HEAD => "s3:GetObject",
+            Method::PUT if has("acl") => "s3:PutObjectAcl",
+            Method::PUT if has("tagging") => "s3:PutObjectTagging",
+            // Upload part, copy and plain PUT
+            Method::PUT => "s3:PutObject",
+            // Initiate and complete multipart upload
+            Method::POST if has("uploads") || has("uploadId") => "s3:PutObject",
+            Method::POST if has("restore") => "s3:RestoreObject",
+            Method::DELETE if has("uploadId") => "s3:AbortMultipartUpload",
+            Method::DELETE if has("tagging") => "s3:DeleteObjectTagging",
+            Method::DELETE if has("versionId") => "s3:DeleteObjectVersion",
+            Method::DELETE => "s3:DeleteObject",
+            _ => "s3:*",
+        };
+    }
+
+    match *method {
+        Method::GET if has("policy") => "s3:GetBucketPolicy",
+        Method::GET if has("acl") || has("default-acl") => "s3:GetBucketAcl",
+        Method::GET if has("cors") => "s3:GetBucketCORS",
+        Method::GET if has("encryption") => "s3:GetEncryptionConfiguration",
+        Method::GET if has("lifecycle") || has("ttl") || has("storage-class") => "s3:GetLifecycleConfiguration",
+        Method::GET if has("versioning") => "s3:GetBucketVersioning",
+        Method::GET if has("location") => "s3:GetBucketLocation",
+        Method::GET if has("logging") => "s3:GetBucketLogging",
+        Method::GET if has("tagging") => "s3:GetBucketTagging",
+        Method::GET if has("versions") => "s3:ListBucketVersions",
+        Method::GET if has("uploads") => "s3:ListBucketMultipartUploads",
+        Method::GET if has("website") => "s3:GetBucketWebsite",
+        Method::GET | Method::HEAD => "s3:ListBucket",
+        Method::PUT if has("policy") => "s3:PutBucketPolicy",
+        Method::PUT if has("acl") || has("default-acl") => "s3:PutBucketAcl",
+        Method::PUT if has("cors") => "s3:PutBucketCORS",
+        Method::PUT if has("encryption") => "s3:PutEncryptionConfiguration",
+        Method::PUT if has("lifecycle") || has("ttl") || has("storage-class") => "s3:PutLifecycleConfiguration",
+        Method::PUT if has("versioning") => "s3:PutBucketVersioning",
+        Method::PUT if has("logging") => "s3:PutBucketLogging",
+        Method::PUT if has("tagging") => "s3:PutBucketTagging",
+        Method::PUT if has("website") => "s3:PutBucketWebsite",
+        Method::PUT => "s3:CreateBucket",
+        // Removing these configurations needs the permission that sets them, as in S3
+        Method::DELETE if has("policy") => "s3:DeleteBucketPolicy",
+        Method::DELETE if has("default-acl") => "s3:PutBucketAcl",
+        Method::DELETE if has("cors") => "s3:PutBucketCORS",
+        Method::DELETE if has("encryption") => "s3:PutEncryptionConfiguration",
+        Method::DELETE if has("lifecycle") || has("ttl") || has("storage-class") => "s3:PutLifecycleConfiguration",
+        Method::DELETE if has("logging") => "s3:PutBucketLogging",
+        Method::DELETE if has("tagging") => "s3:PutBucketTagging",
+        Method::DELETE if has("website") => "s3:DeleteBucketWebsite",
+        Method::DELETE => "s3:DeleteBucket",
+        // Multi-object delete is authorized as DeleteObject
+        Method::POST if has("delete") => "s3:DeleteObject",
+        _ => "s3:*",
+    }
+}
+
+// Check whether an unauthenticated GET on a bucket is a listing the bucket policy grants to "*"
+fn is_anonymous_listing_allowed(state: &AppState, request: &Request<Body>, client_ip: Option<&str>, secure_transport: bool) -> bool {
+    if request.method() != Method::GET {
+        return false;
+    }
+
+    // Only the bucket itself, never an object key
+    let path = request.uri().path().trim_start_matches('/');
+    let bucket = path.strip_suffix('/').unwrap_or(path);
+    if bucket.is_empty() || bucket.contains('/') {
+        return false;
+    }
+
+    // Subresources like ?policy or ?acl are never anonymous
+    let mut prefix = String::new();
+    for param in request.uri().query().unwrap_or("").split('&').filter(|p| !p.is_empty()) {
+        let (name, value) = param.split_once('=').unwrap_or((param, ""));
+        if !LIST_QUERY_PARAMS.contains(&name) {
+            return false;
+        }
+        if name == "prefix" {
+            prefix = urlencoding::decode(value).map(|v| v.into_owned()).unwrap_or_else(|_| value.to_string());
+        }
+    }
+
+    match read_bucket_policy(&state.storage_path, bucket) {
+        Some(policy) => check_policy_permission(
+            &policy,
+            "s3:ListBucket",
+            &format!("arn:aws:s3:::{}", bucket),
+            Principal::Anonymous,
+            client_ip,
+            secure_transport,
+            Some(&prefix),
+        ),
+        None => false,
+    }
+}
+
+// Check whether an unauthenticated GET or HEAD reads an object that a canned ACL grants to everyone.
+// The ACL is read from the current object's metadata; versions and subresources are never anonymous.
+fn is_anonymous_object_read_allowed(state: &AppState, request: &Request<Body>, client_ip: Option<&str>, secure_transport: bool) -> bool {
+    if request.method() != Method::GET && request.method() != Method::HEAD {
+        return false;
+    }
+
+    let Some((bucket, key)) = request.uri().path().trim_start_matches('/').split_once('/') else {
+        return false;
+    };
+    let key = urlencoding::decode(key).map(|k| k.into_owned()).unwrap_or_else(|_| key.to_string());
+    if bucket.is_empty() || key.is_empty() || is_reserved_key(&key) || key.split('/').any(|segment| segment == "..") {
+        return false;
+    }
+    // Response header overrides are part of a plain GET; anything else selects a subresource or version
+    let only_overrides = request.uri().query().unwrap_or("")
+        .split('&')
+        .filter(|param| !param.is_empty())
+        .all(|param| param.starts_with("response-"));
+    if !only_overrides {
+        return false;
+    }
+
+    let metadata_path = state.storage_path.join(bucket).join(format!("{}.metadata", object_storage_key(&key)));
+    let acl = std::fs::read_to_string(metadata_path)
+        .ok()
+        .and_then(|json| serde_json::from_str::<ObjectMetadata>(&json).ok())
+        .and_then(|metadata| metadata.acl);
+    if !matches!(acl.as_deref(), Some("public-read") | Some("public-read-write")) {
+        return false;
+    }
+
+    // An explicit deny in the bucket policy wins over the ACL grant
+    match read_bucket_policy(&state.storage_path, bucket) {
+        Some(policy) => !is_explicitly_denied(
+            &policy,
+            "s3:GetObject",
+            &format!("arn:aws:s3:::{}/{}", bucket, key),
+            Principal::Anonymous,
+            client_ip,
+            secure_transport,
+        ),
+        None => true,
+    }
+}
@@ -1,28 +1,141 @@
 use axum::{
     body::Body,
-    extract::{Path, Query, State},
-    http::{StatusCode, header},
+    extract::{Extension, Path, Query, State},
+    http::{HeaderMap, HeaderValue, StatusCode, header},
     response::{IntoResponse, Response},
 };
+use base64::{Engine as _, engine::general_purpose::URL_SAFE_NO_PAD};
 use bytes::Bytes;
-use chrono::{Utc, TimeZone};
+use chrono::{DateTime, Utc};
 use serde_json;
-use std::{collections::HashSet, fs};
+use std::{collections::{HashMap, HashSet}, fmt::Write, fs};
 use tracing::{debug, info, warn, error};
 
 use crate::{
-    AppState, BucketEncryption, CorsConfiguration, CorsRule, LifecycleConfiguration,
+    AppState, AuthenticatedUser, BucketEncryption, CorsConfiguration, CorsRule, LifecycleConfiguration,
     LifecycleRule, LifecycleFilter, LifecycleTag, LifecycleExpiration, LifecycleTransition,
-    ObjectData, Operation, BucketQueryParams,
+    ObjectData, ObjectMetadata, ObjectQueryParams, Operation, BucketQueryParams, BucketTtl, BucketStorageClass, BucketDefaultAcl, BucketLogging, BucketWebsite,
     // Import filesystem functions
-    bucket_exists, read_bucket_versioning, read_bucket_policy, read_bucket_encryption,
+    bucket_exists, read_bucket_owner, read_bucket_versioning, read_bucket_policy, read_bucket_encryption,
     read_bucket_cors, read_bucket_lifecycle, write_bucket_versioning, write_bucket_policy,
     write_bucket_encryption, write_bucket_cors, write_bucket_lifecycle,
-    delete_bucket_policy, delete_bucket_encryption, delete_bucket_cors, delete_bucket_lifecycle
+    delete_bucket_policy, delete_bucket_encryption, delete_bucket_cors, delete_bucket_lifecycle,
+    read_bucket_ttl, write_bucket_ttl, delete_bucket_ttl,
+    read_bucket_storage_class, write_bucket_storage_class, delete_bucket_storage_class,
+    read_bucket_default_acl, write_bucket_default_acl, delete_bucket_default_acl,
+    read_bucket_logging, write_bucket_logging, delete_bucket_logging, list_bucket_names, bucket_has_data, count_bucket_objects,
+    read_bucket_website, write_bucket_website, delete_bucket_website, read_bucket_uploads,
+    create_storage_dir, write_storage_file,
+    list_index::listing_object_data,
+    handlers::{auth::is_form_upload_allowed, object::handle_object_put},
+    post_policy::{check_policy, form_boundary, parse_form, policy_signature},
+    utils::{CANNED_ACLS, FOLDER_MARKER_NAME, STORAGE_CLASSES, is_reserved_key, object_storage_key, push_xml_escaped, quoted_etag, xml_escape},
 };
 
 // Use BucketQueryParams from models
 
+// Form fields of a POST upload that are stored like the request headers of the same name on a PUT
+const POST_FORM_HEADER_FIELDS: [&str; 6] = [
+    "cache-control", "content-disposition", "content-encoding", "expires",
+    "x-amz-storage-class", "x-amz-server-side-encryption",
+];
+
+// Build the 501 response returned for subresources IronBucket does not support
+fn not_implemented_response(subresource: &str) -> Response {
+    Response::builder()
+        .status(StatusCode::NOT_IMPLEMENTED)
+        .header(header::CONTENT_TYPE, "application/xml")
+        .body(Body::from(format!(r#"<?xml version="1.0" encoding="UTF-8"?>
+<Error>
+    <Code>NotImplemented</Code>
+    <Message>The {} subresource is not implemented</Message>
+</Error>"#, subresource)))
+        .unwrap()
+}
+
+// Build the 404 response returned whenever the target bucket does not exist
+fn no_such_bucket_response(bucket: &str) -> Response {
+    Response::builder()
+        .status(StatusCode::NOT_FOUND)
+        .header(header::CONTENT_TYPE, "application/xml")
+        .body(Body::from(format!(r#"<?xml version="1.0" encoding="UTF-8"?>
+<Error>
+    <Code>NoSuchBucket</Code>
+    <Message>The specified bucket does not exist</Message>
+    <BucketName>{}</BucketName>
+</Error>"#, bucket)))
+        .unwrap()
+}
+
+// How a listing was requested beyond prefix, delimiter and paging. The IronBucket extensions are off unless requested.
+pub struct ListExtensions {
+    // ListObjects V1: the page starts after `marker` and ends with NextMarker instead of a continuation token
+    pub v1_markers: bool,
+    // V2 request parameters echoed in the response, as SDKs use them for pagination bookkeeping
+    pub continuation_token: Option<String>,
+    pub start_after: Option<String>,
+    // Add user metadata and tags to each entry
+    pub include_metadata: bool,
+    // Keep objects whose sidecar last-modified time is at or after `modified_since` and before `modified_before`
+    pub modified_since: Option<DateTime<Utc>>,
+    pub modified_before: Option<DateTime<Utc>>,
+}
+
+// Parse an RFC 3339 listing filter, answering 400 InvalidArgument for anything else
+fn parse_listing_timestamp(name: &str, value: Option<&str>) -> Result<Option<DateTime<Utc>>, Box<Response>> {
+    let value = match value {
+        Some(value) => value,
+        None => return Ok(None),
+    };
+    DateTime::parse_from_rfc3339(value)
+        .map(|time| Some(time.with_timezone(&Utc)))
+        .map_err(|_| {
+            Box::new(Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .header(header::CONTENT_TYPE, "application/xml")
+                .body(Body::from(format!(r#"<?xml version="1.0" encoding="UTF-8"?>
+<Error>
+    <Code>InvalidArgument</Code>
+    <Message>{} must be an RFC 3339 timestamp such as 2025-01-01T00:00:00Z</Message>
+    <ArgumentName>{}</ArgumentName>
+    <ArgumentValue>{}</ArgumentValue>
+</Error>"#, name, name, xml_escape(value))))
+                .unwrap())
+        })
+}
+
+// Continuation tokens carry the last key or common prefix of the previous page, base64url-encoded so that
+// any key survives the round trip through XML and the query string unchanged
+fn encode_continuation_token(last_entry: &str) -> String {
+    URL_SAFE_NO_PAD.encode(last_entry)
+}
+
+// Decode a continuation token into the entry the next page starts after, answering 400 InvalidArgument
+// for tokens this server did not issue
+fn parse_continuation_token(token: Option<&str>) -> Result<Option<String>, Box<Response>> {
+    let token = match token {
+        Some(token) if !token.is_empty() => token,
+        _ => return Ok(None),
+    };
+    URL_SAFE_NO_PAD.decode(token)
+        .ok()
+        .and_then(|bytes| String::from_utf8(bytes).ok())
+        .map(Some)
+        .ok_or_else(|| {
+            Box::new(Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .header(header::CONTENT_TYPE, "application/xml")
+                .body(Body::from(format!(r#"<?xml version="1.0" encoding="UTF-8"?>
+<Error>
+    <Code>InvalidArgument</Code>
+    <Message>The continuation token provided is incorrect</Message>
+    <ArgumentName>continuation-token</ArgumentName>
+    <ArgumentValue>{}</ArgumentValue>
+</Error>"#, xml_escape(token))))
+                .unwrap())
+        })
+}
+
 // Handle bucket GET with query parameters
 pub async fn handle_bucket_get(
     State(state): State<AppState>,
@@ -33,10 +146,11 @@ pub async fn handle_bucket_get(
 
     // Check if bucket exists on filesystem
     if !bucket_exists(&state.storage_path, &bucket) {
-        return Response::builder()
-            .status(StatusCode::NOT_FOUND)
-            .body(Body::empty())
-            .unwrap();
+        return no_such_bucket_response(&bucket);
+    }
+
+    if let Some(subresource) = params.unimplemented_subresource() {
+        return not_implemented_response(subresource);
     }
 
     // Handle different query parameters
@@ -52,32 +166,23 @@ pub async fn handle_bucket_get(
     }
 
     if params.versioning.is_some() {
-        // Return versioning status from filesystem
+        // Like S3, a bucket whose versioning was never configured has no Status element; once
+        // enabled it is reported as Enabled or Suspended and can never go back to that state
         let status = read_bucket_versioning(&state.storage_path, &bucket);
-
-        let versioning_xml = if let Some(status) = status {
-            format!(r#"<?xml version="1.0" encoding="UTF-8"?>
+        let versioning_xml = match status {
+            Some(status) => format!(r#"<?xml version="1.0" encoding="UTF-8"?>
 <VersioningConfiguration xmlns="http://s3.amazonaws.com/doc/2006-03-01/">
     <Status>{}</Status>
-</VersioningConfiguration>"#, status)
-        } else {
-            // AWS returns empty body when versioning is not configured
-            String::new()
+</VersioningConfiguration>"#, status),
+            None => r#"<?xml version="1.0" encoding="UTF-8"?>
+<VersioningConfiguration xmlns="http://s3.amazonaws.com/doc/2006-03-01/"/>"#.to_string(),
         };
 
-        if versioning_xml.is_empty() {
-            // Return empty body for no versioning configuration
-            return Response::builder()
-                .status(StatusCode::OK)
-                .body(Body::empty())
-                .unwrap();
-        } else {
-            return Response::builder()
-                .status(StatusCode::OK)
-                .header(header::CONTENT_TYPE, "application/xml")
-                .body(Body::from(versioning_xml))
-                .unwrap();
-        }
+        return Response::builder()
+            .status(StatusCode::OK)
+            .header(header::CONTENT_TYPE, "application/xml")
+            .body(Body::from(versioning_xml))
+            .unwrap();
     }
 
     if params.acl.is_some() {
@@ -307,6 +412,132 @@ pub async fn handle_bucket_get(
             .unwrap();
     }
 
+    if params.logging.is_some() {
+        // Return the access log delivery configuration; an empty status means logging is off
+        let mut xml = String::from(r#"<?xml version="1.0" encoding="UTF-8"?>
+<BucketLoggingStatus xmlns="http://s3.amazonaws.com/doc/2006-03-01/">"#);
+        if let Some(logging) = read_bucket_logging(&state.storage_path, &bucket) {
+            xml.push_str(&format!(r#"
+    <LoggingEnabled>
+        <TargetBucket>{}</TargetBucket>
+        <TargetPrefix>{}</TargetPrefix>
+    </LoggingEnabled>"#, logging.target_bucket, xml_escape(&logging.target_prefix)));
+        }
+        xml.push_str("\n</BucketLoggingStatus>");
+
+        return Response::builder()
+            .status(StatusCode::OK)
+            .header(header::CONTENT_TYPE, "application/xml")
+            .body(Body::from(xml))
+            .unwrap();
+    }
+
+    if params.website.is_some() {
+        if !bucket_exists(&state.storage_path, &bucket) {
+            return no_such_bucket_response(&bucket);
+        }
+
+        let Some(website) = read_bucket_website(&state.storage_path, &bucket) else {
+            return Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .header(header::CONTENT_TYPE, "application/xml")
+                .body(Body::from(r#"<?xml version="1.0" encoding="UTF-8"?>
+<Error>
+    <Code>NoSuchWebsiteConfiguration</Code>
+    <Message>The specified bucket does not have a website configuration</Message>
+</Error>"#))
+                .unwrap();
+        };
+
+        let mut xml = format!(r#"<?xml version="1.0" encoding="UTF-8"?>
+<WebsiteConfiguration xmlns="http://s3.amazonaws.com/doc/2006-03-01/">
+    <IndexDocument>
+        <Suffix>{}</Suffix>
+    </IndexDocument>"#, xml_escape(&website.index_document));
+        if let Some(ref error_document) = website.error_document {
+            xml.push_str(&format!(r#"
+    <ErrorDocument>
+        <Key>{}</Key>
+    </ErrorDocument>"#, xml_escape(error_document)));
+        }
+        xml.push_str("\n</WebsiteConfiguration>");
+
+        return Response::builder()
+            .status(StatusCode::OK)
+            .header(header::CONTENT_TYPE, "application/xml")
+            .body(Body::from(xml))
+            .unwrap();
+    }
+
+    if params.ttl.is_some() {
+        // Return the bucket's object TTL as JSON, like quota and stats
+        if !bucket_exists(&state.storage_path, &bucket) {
+            return no_such_bucket_response(&bucket);
+        }
+        return match read_bucket_ttl(&state.storage_path, &bucket) {
+            Some(ttl) => Response::builder()
+                .status(StatusCode::OK)
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(serde_json::to_string_pretty(&ttl).unwrap()))
+                .unwrap(),
+            None => Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .header(header::CONTENT_TYPE, "application/xml")
+                .body(Body::from(r#"<?xml version="1.0" encoding="UTF-8"?>
+<Error>
+    <Code>NoSuchTTLConfiguration</Code>
+    <Message>The bucket has no object TTL</Message>
+</Error>"#))
+                .unwrap(),
+        };
+    }
+
+    if params.storage_class.is_some() {
+        // Return the bucket's default storage class as JSON, like the object TTL
+        if !bucket_exists(&state.storage_path, &bucket) {
+            return no_such_bucket_response(&bucket);
+        }
+        return match read_bucket_storage_class(&state.storage_path, &bucket) {
+            Some(storage_class) => Response::builder()
+                .status(StatusCode::OK)
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(serde_json::to_string_pretty(&storage_class).unwrap()))
+                .unwrap(),
+            None => Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .header(header::CONTENT_TYPE, "application/xml")
+                .body(Body::from(r#"<?xml version="1.0" encoding="UTF-8"?>
+<Error>
+    <Code>NoSuchStorageClassConfiguration</Code>
+    <Message>The bucket has no default storage class</Message>
+</Error>"#))
+                .unwrap(),
+        };
+    }
+
+    if params.default_acl.is_some() {
+        // Return the bucket's default object ACL as JSON, like the default storage class
+        if !bucket_exists(&state.storage_path, &bucket) {
+            return no_such_bucket_response(&bucket);
+        }
+        return match read_bucket_default_acl(&state.storage_path, &bucket) {
+            Some(default_acl) => Response::builder()
+                .status(StatusCode::OK)
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(serde_json::to_string_pretty(&default_acl).unwrap()))
+                .unwrap(),
+            None => Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .header(header::CONTENT_TYPE, "application/xml")
+                .body(Body::from(r#"<?xml version="1.0" encoding="UTF-8"?>
+<Error>
+    <Code>NoSuchDefaultAclConfiguration</Code>
+    <Message>The bucket has no default object ACL</Message>
+</Error>"#))
+                .unwrap(),
+        };
+    }
+
     if params.quota.is_some() {
         // Return bucket quota information
         match state.quota_manager.get_quota(&bucket).await {
@@ -316,7 +547,8 @@ pub async fn handle_bucket_get(
                     "current_usage_bytes": quota.current_usage_bytes,
                     "object_count": quota.object_count,
                     "last_updated": quota.last_updated.to_rfc3339(),
-                    "usage_percentage": (quota.current_usage_bytes as f64 / quota.max_size_bytes as f64 * 100.0)
+                    "usage_percentage": (quota.current_usage_bytes as f64 / quota.max_size_bytes as f64 * 100.0),
+                    "flush_failures": state.quota_manager.flush_failures()
                 });
 
                 return Response::builder()
@@ -386,23 +618,38 @@ pub async fn handle_bucket_get(
     }
 
     if params.uploads.is_some() {
-        // List multipart uploads
-        let uploads = state.multipart_uploads.lock().unwrap();
+        // List multipart uploads. The .upload files on disk are the source of truth, so uploads from
+        // before a restart or initiated on a peer show up too; in-memory ones cover writes still in flight.
+        let persisted = {
+            let (storage_path, bucket) = (state.storage_path.clone(), bucket.clone());
+            tokio::task::spawn_blocking(move || read_bucket_uploads(&storage_path, &bucket)).await.unwrap_or_default()
+        };
+        let mut uploads: HashMap<String, (String, DateTime<Utc>)> = persisted.into_iter()
+            .map(|upload| (upload.upload_id, (upload.key, upload.initiated)))
+            .collect();
+        for (upload_id, upload) in state.multipart_uploads.lock().unwrap().iter() {
+            if upload.bucket == bucket {
+                uploads.entry(upload_id.clone()).or_insert_with(|| (upload.key.clone(), upload.initiated));
+            }
+        }
+        let mut uploads: Vec<_> = uploads.into_iter().collect();
+        uploads.sort_by(|(a_id, (a_key, a_initiated)), (b_id, (b_key, b_initiated))| {
+            (a_key, a_initiated, a_id).cmp(&(b_key, b_initiated, b_id))
+        });
+
         let mut xml = format!(r#"<?xml version="1.0" encoding="UTF-8"?>
 <ListMultipartUploadsResult>
     <Bucket>{}</Bucket>
     <MaxUploads>1000</MaxUploads>
     <IsTruncated>false</IsTruncated>"#, bucket);
 
-        for (upload_id, upload) in uploads.iter() {
-            if upload.bucket == bucket {
-                xml.push_str(&format!(r#"
+        for (upload_id, (key, initiated)) in uploads {
+            xml.push_str(&format!(r#"
     <Upload>
         <Key>{}</Key>
         <UploadId>{}</UploadId>
         <Initiated>{}</Initiated>
-    </Upload>"#, upload.key, upload_id, upload.initiated.to_rfc3339()));
-            }
+    </Upload>"#, xml_escape(&key), upload_id, initiated.to_rfc3339()));
         }
 
         xml.push_str("\n</ListMultipartUploadsResult>");
@@ -415,14 +662,6 @@ pub async fn handle_bucket_get(
 
     // List object versions
     if params.versions.is_some() {
-        // Check if bucket exists
-        if !bucket_exists(&state.storage_path, &bucket) {
-            return Response::builder()
-                .status(StatusCode::NOT_FOUND)
-                .body(Body::empty())
-                .unwrap();
-        }
-
         let mut xml = format!(r#"<?xml version="1.0" encoding="UTF-8"?>
 <ListVersionsResult xmlns="http://s3.amazonaws.com/doc/2006-03-01/">
     <Name>{}</Name>
@@ -460,13 +699,40 @@ pub async fn handle_bucket_get(
     // Default: list objects (handles both v1 and v2)
     // list-type=2 uses continuation-token, v1 uses marker
     info!("Handling list objects request for bucket: {}, list_type: {:?}", bucket, params.list_type);
+    let modified_since = match parse_listing_timestamp("modified-since", params.modified_since.as_deref()) {
+        Ok(time) => time,
+        Err(response) => return *response,
+    };
+    let modified_before = match parse_listing_timestamp("modified-before", params.modified_before.as_deref()) {
+        Ok(time) => time,
+        Err(response) => return *response,
+    };
+    // V1 resumes after the marker; V2 after the continuation token, or start-after on the first page
+    let v1_markers = params.list_type.as_deref() != Some("2");
+    let start_after = if v1_markers {
+        params.marker.filter(|marker| !marker.is_empty())
+    } else {
+        match parse_continuation_token(params.continuation_token.as_deref()) {
+            Ok(Some(start_after)) => Some(start_after),
+            Ok(None) => params.start_after.clone().filter(|start_after| !start_after.is_empty()),
+            Err(response) => return *response,
+        }
+    };
     list_objects_impl(
         State(state),
         bucket,
         params.prefix,
         params.delimiter,
-        params.continuation_token,
-        params.max_keys
+        start_after,
+        params.max_keys,
+        ListExtensions {
+            v1_markers,
+            continuation_token: params.continuation_token.filter(|_| !v1_markers),
+            start_after: params.start_after.filter(|_| !v1_markers),
+            include_metadata: params.metadata.as_deref() == Some("true"),
+            modified_since,
+            modified_before,
+        },
     ).await
 }
 
@@ -475,10 +741,15 @@ pub async fn handle_bucket_put(
     State(state): State<AppState>,
     Path(bucket): Path<String>,
     Query(params): Query<BucketQueryParams>,
+    user: Option<Extension<AuthenticatedUser>>,
     body: Bytes,
 ) -> impl IntoResponse {
     debug!("PUT bucket: {} with params: {:?}", bucket, params);
 
+    if let Some(subresource) = params.unimplemented_subresource() {
+        return not_implemented_response(subresource);
+    }
+
     if params.versioning.is_some() {
         // Parse versioning configuration from body
         let body_str = String::from_utf8_lossy(&body);
@@ -495,10 +766,7 @@ pub async fn handle_bucket_put(
 
         // Check if bucket exists first
         if !bucket_exists(&state.storage_path, &bucket) {
-            return Response::builder()
-                .status(StatusCode::NOT_FOUND)
-                .body(Body::from("NoSuchBucket"))
-                .unwrap();
+            return no_such_bucket_response(&bucket);
         }
 
         // Update bucket versioning status directly on filesystem
@@ -542,10 +810,7 @@ pub async fn handle_bucket_put(
 
         // Check if bucket exists first
         if !bucket_exists(&state.storage_path, &bucket) {
-            return Response::builder()
-                .status(StatusCode::NOT_FOUND)
-                .body(Body::from("NoSuchBucket"))
-                .unwrap();
+            return no_such_bucket_response(&bucket);
         }
 
         // Update bucket policy directly on filesystem
@@ -561,8 +826,9 @@ pub async fn handle_bucket_put(
         state.wal_writer.log_update_metadata(&bucket, "policy", &policy_str);
         info!("Set policy for bucket {}", bucket);
 
+        // PutBucketPolicy is the one configuration PUT that S3 answers with 204
         return Response::builder()
-            .status(StatusCode::OK)
+            .status(StatusCode::NO_CONTENT)
             .body(Body::empty())
             .unwrap();
     }
@@ -608,10 +874,7 @@ pub async fn handle_bucket_put(
 
         // Check if bucket exists first
         if !bucket_exists(&state.storage_path, &bucket) {
-            return Response::builder()
-                .status(StatusCode::NOT_FOUND)
-                .body(Body::from("NoSuchBucket"))
-                .unwrap();
+            return no_such_bucket_response(&bucket);
         }
 
         // Update bucket encryption directly on filesystem
@@ -748,10 +1011,7 @@ pub async fn handle_bucket_put(
 
         // Check if bucket exists first
         if !bucket_exists(&state.storage_path, &bucket) {
-            return Response::builder()
-                .status(StatusCode::NOT_FOUND)
-                .body(Body::from("NoSuchBucket"))
-                .unwrap();
+            return no_such_bucket_response(&bucket);
         }
 
         // Store CORS configuration using filesystem function
@@ -775,83 +1035,323 @@ pub async fn handle_bucket_put(
             .unwrap();
     }
 
-    if params.lifecycle.is_some() {
-        // Parse lifecycle configuration from body (XML format from AWS CLI)
+    if params.logging.is_some() {
+        // Parse BucketLoggingStatus; without LoggingEnabled the request turns logging off
         let body_str = String::from_utf8_lossy(&body);
-        debug!("Lifecycle configuration body: {}", body_str);
-
-        // Parse XML to extract lifecycle rules
-        let mut lifecycle_rules = Vec::new();
+        debug!("Logging configuration body: {}", body_str);
 
-        // Split by Rule tags to parse each rule
-        let rule_parts: Vec<&str> = body_str.split("<Rule>").collect();
-        for (i, rule_part) in rule_parts.iter().enumerate() {
-            if i == 0 { continue; } // Skip the part before first Rule
+        if !bucket_exists(&state.storage_path, &bucket) {
+            return no_such_bucket_response(&bucket);
+        }
 
-            let mut id = None;
-            let mut status = String::from("Enabled");
-            let mut filter = None;
-            let mut expiration = None;
-            let mut transitions = None;
+        let extract = |tag: &str| {
+            let open = format!("<{}>", tag);
+            let close = format!("</{}>", tag);
+            body_str.find(&open).and_then(|start| {
+                let value_start = start + open.len();
+                body_str[value_start..].find(&close).map(|end| body_str[value_start..value_start + end].trim().to_string())
+            })
+        };
 
-            // Extract ID
-            if let Some(id_start) = rule_part.find("<ID>") {
-                if let Some(id_end) = rule_part.find("</ID>") {
-                    id = Some(rule_part[id_start + 4..id_end].to_string());
-                }
+        if !body_str.contains("<LoggingEnabled>") {
+            if let Err(e) = delete_bucket_logging(&state.storage_path, &bucket) {
+                warn!("Failed to delete logging configuration: {}", e);
+                return Response::builder()
+                    .status(StatusCode::INTERNAL_SERVER_ERROR)
+                    .body(Body::from("InternalError"))
+                    .unwrap();
             }
+            info!("Disabled access logging for bucket {}", bucket);
+            return Response::builder()
+                .status(StatusCode::OK)
+                .body(Body::empty())
+                .unwrap();
+        }
 
-            // Extract Status
-            if let Some(status_start) = rule_part.find("<Status>") {
-                if let Some(status_end) = rule_part.find("</Status>") {
-                    status = rule_part[status_start + 8..status_end].to_string();
-                }
-            }
+        let target_bucket = extract("TargetBucket").unwrap_or_default();
+        if target_bucket.is_empty() || !bucket_exists(&state.storage_path, &target_bucket) {
+            return Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .header(header::CONTENT_TYPE, "application/xml")
+                .body(Body::from(format!(r#"<?xml version="1.0" encoding="UTF-8"?>
+<Error>
+    <Code>InvalidTargetBucketForLogging</Code>
+    <Message>The target bucket for logging does not exist</Message>
+    <TargetBucket>{}</TargetBucket>
+</Error>"#, target_bucket)))
+                .unwrap();
+        }
 
-            // Extract Filter
-            if let Some(filter_start) = rule_part.find("<Filter>") {
-                if let Some(filter_end) = rule_part.find("</Filter>") {
-                    let filter_xml = &rule_part[filter_start + 8..filter_end];
-                    let mut prefix = None;
-                    let mut tag = None;
+        let logging = BucketLogging {
+            target_bucket,
+            target_prefix: extract("TargetPrefix").unwrap_or_default(),
+        };
 
-                    // Extract Prefix
-                    if let Some(prefix_start) = filter_xml.find("<Prefix>") {
-                        if let Some(prefix_end) = filter_xml.find("</Prefix>") {
-                            prefix = Some(filter_xml[prefix_start + 8..prefix_end].to_string());
-                        }
-                    }
+        if let Err(e) = write_bucket_logging(&state.storage_path, &bucket, &logging) {
+            warn!("Failed to persist logging configuration: {}", e);
+            return Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(Body::from("InternalError"))
+                .unwrap();
+        }
 
-                    // Extract Tag
-                    if let Some(tag_start) = filter_xml.find("<Tag>") {
-                        if let Some(tag_end) = filter_xml.find("</Tag>") {
-                            let tag_xml = &filter_xml[tag_start + 5..tag_end];
-                            let mut key = String::new();
-                            let mut value = String::new();
+        info!("Access logs for bucket {} will be delivered to {}/{}", bucket, logging.target_bucket, logging.target_prefix);
 
-                            if let Some(key_start) = tag_xml.find("<Key>") {
-                                if let Some(key_end) = tag_xml.find("</Key>") {
-                                    key = tag_xml[key_start + 5..key_end].to_string();
-                                }
-                            }
-                            if let Some(value_start) = tag_xml.find("<Value>") {
-                                if let Some(value_end) = tag_xml.find("</Value>") {
-                                    value = tag_xml[value_start + 7..value_end].to_string();
-                                }
-                            }
+        return Response::builder()
+            .status(StatusCode::OK)
+            .body(Body::empty())
+            .unwrap();
+    }
 
-                            if !key.is_empty() {
-                                tag = Some(LifecycleTag { key, value });
-                            }
-                        }
-                    }
+    if params.website.is_some() {
+        // Parse WebsiteConfiguration; only IndexDocument and ErrorDocument are supported
+        let body_str = String::from_utf8_lossy(&body);
+        debug!("Website configuration body: {}", body_str);
 
-                    if prefix.is_some() || tag.is_some() {
-                        filter = Some(LifecycleFilter {
-                            prefix,
-                            tag,
-                            and: None,
-                        });
+        if !bucket_exists(&state.storage_path, &bucket) {
+            return no_such_bucket_response(&bucket);
+        }
+
+        let extract = |tag: &str| {
+            let open = format!("<{}>", tag);
+            let close = format!("</{}>", tag);
+            body_str.find(&open).and_then(|start| {
+                let value_start = start + open.len();
+                body_str[value_start..].find(&close).map(|end| body_str[value_start..value_start + end].trim().to_string())
+            })
+        };
+
+        let index_document = extract("Suffix").unwrap_or_default();
+        if index_document.is_empty() || index_document.contains('/') {
+            return Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .header(header::CONTENT_TYPE, "application/xml")
+                .body(Body::from(r#"<?xml version="1.0" encoding="UTF-8"?>
+<Error>
+    <Code>InvalidArgument</Code>
+    <Message>The IndexDocument Suffix is not well formed</Message>
+</Error>"#))
+                .unwrap();
+        }
+
+        let error_document = extract("Key").filter(|key| !key.is_empty());
+        if error_document.as_deref().is_some_and(is_reserved_key) {
+            return Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .header(header::CONTENT_TYPE, "application/xml")
+                .body(Body::from(r#"<?xml version="1.0" encoding="UTF-8"?>
+<Error>
+    <Code>InvalidArgument</Code>
+    <Message>The ErrorDocument Key is not a valid object key</Message>
+</Error>"#))
+                .unwrap();
+        }
+
+        let website = BucketWebsite { index_document, error_document };
+        if let Err(e) = write_bucket_website(&state.storage_path, &bucket, &website) {
+            warn!("Failed to persist website configuration: {}", e);
+            return Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(Body::from("InternalError"))
+                .unwrap();
+        }
+
+        info!("Set website configuration for bucket {}", bucket);
+
+        return Response::builder()
+            .status(StatusCode::OK)
+            .body(Body::empty())
+            .unwrap();
+    }
+
+    if params.ttl.is_some() {
+        // Object TTL is a JSON body: {"seconds": N}
+        if !bucket_exists(&state.storage_path, &bucket) {
+            return no_such_bucket_response(&bucket);
+        }
+
+        let ttl = match serde_json::from_slice::<BucketTtl>(&body) {
+            Ok(ttl) if ttl.seconds > 0 => ttl,
+            _ => {
+                return Response::builder()
+                    .status(StatusCode::BAD_REQUEST)
+                    .header(header::CONTENT_TYPE, "application/xml")
+                    .body(Body::from(r#"<?xml version="1.0" encoding="UTF-8"?>
+<Error>
+    <Code>MalformedTTLConfiguration</Code>
+    <Message>Expected a JSON body of the form {"seconds": N} with N greater than zero</Message>
+</Error>"#))
+                    .unwrap();
+            }
+        };
+
+        if let Err(e) = write_bucket_ttl(&state.storage_path, &bucket, &ttl) {
+            warn!("Failed to persist object TTL: {}", e);
+            return Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(Body::from("InternalError"))
+                .unwrap();
+        }
+
+        info!("Set object TTL of {} seconds for bucket {}", ttl.seconds, bucket);
+
+        return Response::builder()
+            .status(StatusCode::OK)
+            .body(Body::empty())
+            .unwrap();
+    }
+
+    if params.storage_class.is_some() {
+        // Default storage class is a JSON body: {"storage_class": "STANDARD_IA"}
+        if !bucket_exists(&state.storage_path, &bucket) {
+            return no_such_bucket_response(&bucket);
+        }
+
+        let storage_class = match serde_json::from_slice::<BucketStorageClass>(&body) {
+            Ok(config) if STORAGE_CLASSES.contains(&config.storage_class.as_str()) => config,
+            _ => {
+                return Response::builder()
+                    .status(StatusCode::BAD_REQUEST)
+                    .header(header::CONTENT_TYPE, "application/xml")
+                    .body(Body::from(format!(r#"<?xml version="1.0" encoding="UTF-8"?>
+<Error>
+    <Code>InvalidStorageClass</Code>
+    <Message>Expected a JSON body of the form {{"storage_class": "..."}} with one of {}</Message>
+</Error>"#, STORAGE_CLASSES.join(", "))))
+                    .unwrap();
+            }
+        };
+
+        if let Err(e) = write_bucket_storage_class(&state.storage_path, &bucket, &storage_class) {
+            warn!("Failed to persist default storage class: {}", e);
+            return Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(Body::from("InternalError"))
+                .unwrap();
+        }
+
+        info!("Set default storage class {} for bucket {}", storage_class.storage_class, bucket);
+
+        return Response::builder()
+            .status(StatusCode::OK)
+            .body(Body::empty())
+            .unwrap();
+    }
+
+    if params.default_acl.is_some() {
+        // Default object ACL is a JSON body: {"acl": "public-read"}
+        if !bucket_exists(&state.storage_path, &bucket) {
+            return no_such_bucket_response(&bucket);
+        }
+
+        let default_acl = match serde_json::from_slice::<BucketDefaultAcl>(&body) {
+            Ok(config) if CANNED_ACLS.contains(&config.acl.as_str()) => config,
+            _ => {
+                return Response::builder()
+                    .status(StatusCode::BAD_REQUEST)
+                    .header(header::CONTENT_TYPE, "application/xml")
+                    .body(Body::from(format!(r#"<?xml version="1.0" encoding="UTF-8"?>
+<Error>
+    <Code>InvalidArgument</Code>
+    <Message>Expected a JSON body of the form {{"acl": "..."}} with one of {}</Message>
+</Error>"#, CANNED_ACLS.join(", "))))
+                    .unwrap();
+            }
+        };
+
+        if let Err(e) = write_bucket_default_acl(&state.storage_path, &bucket, &default_acl) {
+            warn!("Failed to persist default object ACL: {}", e);
+            return Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(Body::from("InternalError"))
+                .unwrap();
+        }
+
+        info!("Set default object ACL {} for bucket {}", default_acl.acl, bucket);
+
+        return Response::builder()
+            .status(StatusCode::OK)
+            .body(Body::empty())
+            .unwrap();
+    }
+
+    if params.lifecycle.is_some() {
+        // Parse lifecycle configuration from body (XML format from AWS CLI)
+        let body_str = String::from_utf8_lossy(&body);
+        debug!("Lifecycle configuration body: {}", body_str);
+
+        // Parse XML to extract lifecycle rules
+        let mut lifecycle_rules = Vec::new();
+
+        // Split by Rule tags to parse each rule
+        let rule_parts: Vec<&str> = body_str.split("<Rule>").collect();
+        for (i, rule_part) in rule_parts.iter().enumerate() {
+            if i == 0 { continue; } // Skip the part before first Rule
+
+            let mut id = None;
+            let mut status = String::from("Enabled");
+            let mut filter = None;
+            let mut expiration = None;
+            let mut transitions = None;
+
+            // Extract ID
+            if let Some(id_start) = rule_part.find("<ID>") {
+                if let Some(id_end) = rule_part.find("</ID>") {
+                    id = Some(rule_part[id_start + 4..id_end].to_string());
+                }
+            }
+
+            // Extract Status
+            if let Some(status_start) = rule_part.find("<Status>") {
+                if let Some(status_end) = rule_part.find("</Status>") {
+                    status = rule_part[status_start + 8..status_end].to_string();
+                }
+            }
+
+            // Extract Filter
+            if let Some(filter_start) = rule_part.find("<Filter>") {
+                if let Some(filter_end) = rule_part.find("</Filter>") {
+                    let filter_xml = &rule_part[filter_start + 8..filter_end];
+                    let mut prefix = None;
+                    let mut tag = None;
+
+                    // Extract Prefix
+                    if let Some(prefix_start) = filter_xml.find("<Prefix>") {
+                        if let Some(prefix_end) = filter_xml.find("</Prefix>") {
+                            prefix = Some(filter_xml[prefix_start + 8..prefix_end].to_string());
+                        }
+                    }
+
+                    // Extract Tag
+                    if let Some(tag_start) = filter_xml.find("<Tag>") {
+                        if let Some(tag_end) = filter_xml.find("</Tag>") {
+                            let tag_xml = &filter_xml[tag_start + 5..tag_end];
+                            let mut key = String::new();
+                            let mut value = String::new();
+
+                            if let Some(key_start) = tag_xml.find("<Key>") {
+                                if let Some(key_end) = tag_xml.find("</Key>") {
+                                    key = tag_xml[key_start + 5..key_end].to_string();
+                                }
+                            }
+                            if let Some(value_start) = tag_xml.find("<Value>") {
+                                if let Some(value_end) = tag_xml.find("</Value>") {
+                                    value = tag_xml[value_start + 7..value_end].to_string();
+                                }
+                            }
+
+                            if !key.is_empty() {
+                                tag = Some(LifecycleTag { key, value });
+                            }
+                        }
+                    }
+
+                    if prefix.is_some() || tag.is_some() {
+                        filter = Some(LifecycleFilter {
+                            prefix,
+                            tag,
+                            and: None,
+                        });
                     }
                 }
             }
@@ -947,10 +1447,7 @@ pub async fn handle_bucket_put(
 
         // Check if bucket exists first
         if !bucket_exists(&state.storage_path, &bucket) {
-            return Response::builder()
-                .status(StatusCode::NOT_FOUND)
-                .body(Body::from("NoSuchBucket"))
-                .unwrap();
+            return no_such_bucket_response(&bucket);
         }
 
         // Store lifecycle configuration using filesystem function
@@ -979,7 +1476,135 @@ pub async fn handle_bucket_put(
     }
 
     // Default: create bucket
-    create_bucket(State(state), Path(bucket)).await.into_response()
+    let owner = user.map(|Extension(user)| user.access_key);
+    create_bucket(State(state), Path(bucket), owner).await.into_response()
+}
+
+fn post_object_error(status: StatusCode, code: &str, message: &str) -> Response {
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/xml")
+        .body(Body::from(format!(r#"<?xml version="1.0" encoding="UTF-8"?>
+<Error>
+    <Code>{}</Code>
+    <Message>{}</Message>
+</Error>"#, code, xml_escape(message))))
+        .unwrap()
+}
+
+// POST Object: a browser form upload. The form carries a base64 policy document signed with SigV4; once the
+// signature and the policy's conditions check out, the file is stored as a PUT of the form's key would store it.
+async fn handle_post_object(state: AppState, bucket: String, headers: &HeaderMap, body: &Bytes, boundary: &str) -> Response {
+    if !bucket_exists(&state.storage_path, &bucket) {
+        return no_such_bucket_response(&bucket);
+    }
+
+    let form = match parse_form(body, boundary) {
+        Ok(form) => form,
+        Err(e) => {
+            info!("Malformed POST form upload to bucket {}: {}", bucket, e);
+            return post_object_error(StatusCode::BAD_REQUEST, "MalformedPOSTRequest",
+                "The body of your POST request is not well-formed multipart/form-data.");
+        }
+    };
+    let Some(file) = form.file.clone() else {
+        return post_object_error(StatusCode::BAD_REQUEST, "InvalidArgument", "POST requires exactly one file upload per request.");
+    };
+    let Some(key) = form.fields.get("key") else {
+        return post_object_error(StatusCode::BAD_REQUEST, "InvalidArgument", "Bucket POST must contain a field named 'key'.");
+    };
+    // As in S3, ${filename} in the key stands for the name of the uploaded file
+    let key = key.replace("${filename}", form.file_name.as_deref().unwrap_or(""));
+    // The key becomes a path below the bucket, so it must not lead out of it
+    if key.split('/').any(|segment| segment.is_empty() || segment == "." || segment == "..") {
+        return post_object_error(StatusCode::BAD_REQUEST, "InvalidArgument", "The key must not contain empty, '.' or '..' path segments.");
+    }
+
+    // Authenticate the signed policy
+    let Some(policy) = form.fields.get("policy") else {
+        return post_object_error(StatusCode::FORBIDDEN, "AccessDenied", "Bucket POST must contain a policy");
+    };
+    if form.fields.get("x-amz-algorithm").map(String::as_str) != Some("AWS4-HMAC-SHA256") {
+        return post_object_error(StatusCode::BAD_REQUEST, "InvalidArgument", "Only the AWS4-HMAC-SHA256 x-amz-algorithm is supported");
+    }
+    let credential: Vec<&str> = form.fields.get("x-amz-credential").map(|c| c.split('/').collect()).unwrap_or_default();
+    let [access_key, date, region, service, "aws4_request"] = credential[..] else {
+        return post_object_error(StatusCode::BAD_REQUEST, "InvalidArgument", "The x-amz-credential field is malformed");
+    };
+    let Some(secret_key) = state.access_keys.get(access_key) else {
+        return post_object_error(StatusCode::FORBIDDEN, "InvalidAccessKeyId", "The AWS Access Key Id you provided does not exist in our records.");
+    };
+    let signature = form.fields.get("x-amz-signature").map(String::as_str).unwrap_or("");
+    if policy_signature(secret_key, date, region, service, policy) != signature.to_ascii_lowercase() {
+        info!("POST form upload to bucket {} with a bad policy signature for access key {}", bucket, access_key);
+        return post_object_error(StatusCode::FORBIDDEN, "SignatureDoesNotMatch",
+            "The request signature we calculated does not match the signature you provided.");
+    }
+
+    let policy = base64::engine::general_purpose::STANDARD.decode(policy.trim()).ok()
+        .and_then(|json| serde_json::from_slice::<serde_json::Value>(&json).ok());
+    let Some(policy) = policy else {
+        return post_object_error(StatusCode::BAD_REQUEST, "InvalidPolicyDocument", "Invalid Policy: Invalid JSON.");
+    };
+    // Conditions on $bucket match the bucket the form was posted to
+    let mut policy_fields = form.fields.clone();
+    policy_fields.insert("bucket".to_string(), bucket.clone());
+    if let Err(violation) = check_policy(&policy, &policy_fields, file.len() as u64, Utc::now()) {
+        info!("POST form upload to {}/{} rejected: {} ({})", bucket, key, violation.code, violation.message);
+        return post_object_error(violation.status, violation.code, &violation.message);
+    }
+    if !is_form_upload_allowed(&state, headers, &bucket, &key, access_key) {
+        return post_object_error(StatusCode::FORBIDDEN, "AccessDenied", "Access Denied");
+    }
+
+    // Store the file with the form fields as the PUT headers
+    let mut put_headers = HeaderMap::new();
+    for (name, value) in &form.fields {
+        let header_name = match name.as_str() {
+            "acl" => "x-amz-acl",
+            "content-type" => "content-type",
+            name if name.starts_with("x-amz-meta-") || POST_FORM_HEADER_FIELDS.contains(&name) => name,
+            _ => continue,
+        };
+        if let (Ok(header_name), Ok(value)) = (header::HeaderName::from_bytes(header_name.as_bytes()), HeaderValue::from_str(value)) {
+            put_headers.insert(header_name, value);
+        }
+    }
+    if !put_headers.contains_key(header::CONTENT_TYPE) {
+        if let Some(value) = form.file_content_type.as_deref().and_then(|v| HeaderValue::from_str(v).ok()) {
+            put_headers.insert(header::CONTENT_TYPE, value);
+        }
+    }
+
+    let params = ObjectQueryParams::default();
+    let response = handle_object_put(State(state), Path((bucket.clone(), key.clone())), Query(params), put_headers, file).await.into_response();
+    if !response.status().is_success() {
+        return response;
+    }
+
+    let etag = response.headers().get(header::ETAG).and_then(|v| v.to_str().ok()).unwrap_or("").to_string();
+    let location = format!("/{}/{}", bucket, urlencoding::encode(&key).replace("%2F", "/"));
+    let (status, body) = match form.fields.get("success_action_status").map(String::as_str) {
+        Some("200") => (StatusCode::OK, Body::empty()),
+        Some("201") => (StatusCode::CREATED, Body::from(format!(r#"<?xml version="1.0" encoding="UTF-8"?>
+<PostResponse>
+    <Location>{}</Location>
+    <Bucket>{}</Bucket>
+    <Key>{}</Key>
+    <ETag>{}</ETag>
+</PostResponse>"#, xml_escape(&location), bucket, xml_escape(&key), xml_escape(&etag)))),
+        _ => (StatusCode::NO_CONTENT, Body::empty()),
+    };
+
+    info!("Stored {}/{} from a POST form upload", bucket, key);
+    let mut builder = Response::builder()
+        .status(status)
+        .header(header::ETAG, etag.as_str())
+        .header(header::LOCATION, location.as_str());
+    if status == StatusCode::CREATED {
+        builder = builder.header(header::CONTENT_TYPE, "application/xml");
+    }
+    builder.body(body).unwrap()
 }
 
 // Handle bucket POST with query parameters
@@ -987,10 +1612,18 @@ pub async fn handle_bucket_post(
     State(state): State<AppState>,
     Path(bucket): Path<String>,
     Query(params): Query<BucketQueryParams>,
+    headers: HeaderMap,
     body: Bytes,
 ) -> impl IntoResponse {
     debug!("POST bucket: {} with params: {:?}", bucket, params);
 
+    let form_boundary = headers.get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(form_boundary);
+    if let (None, Some(boundary)) = (params.delete.as_ref(), form_boundary) {
+        return handle_post_object(state, bucket, &headers, &body, boundary).await;
+    }
+
     if params.delete.is_some() {
         // Parse batch delete request
         let body_str = String::from_utf8_lossy(&body);
@@ -1065,19 +1698,26 @@ pub async fn handle_bucket_post(
 
         // Process each delete request
         for delete_obj in objects_to_delete {
-            let object_path = state.storage_path.join(&bucket).join(&delete_obj.key);
-            let metadata_path = state.storage_path.join(&bucket).join(format!("{}.metadata", delete_obj.key));
+            if is_reserved_key(&delete_obj.key) {
+                result.errors.push(DeleteError {
+                    key: delete_obj.key.clone(),
+                    code: "InvalidArgument".to_string(),
+                    message: "The key name is reserved for internal use".to_string(),
+                    version_id: delete_obj.version_id,
+                });
+                continue;
+            }
+
+            let storage_key = object_storage_key(&delete_obj.key);
+            let object_path = state.storage_path.join(&bucket).join(&storage_key);
+            let metadata_path = state.storage_path.join(&bucket).join(format!("{}.metadata", storage_key));
 
             if object_path.exists() {
-                // Get file size BEFORE deletion for quota update
-                let file_size = if object_path.is_file() {
-                    fs::metadata(&object_path).ok().map(|m| m.len()).unwrap_or(0)
-                } else {
-                    0
-                };
+                // Get file size BEFORE deletion for quota update; None for a directory, which is not counted
+                let file_size = fs::metadata(&object_path).ok().filter(|m| m.is_file()).map(|m| m.len());
 
                 // Check if it's a directory or a file
-                let deletion_result = if object_path.is_dir() {
+                let deletion_result = if file_size.is_none() {
                     // If it's a directory, try to remove it (only if empty)
                     fs::remove_dir(&object_path)
                 } else {
@@ -1092,10 +1732,14 @@ pub async fn handle_bucket_post(
                         if metadata_path.exists() {
                             let _ = fs::remove_file(&metadata_path);
                         }
+                        if delete_obj.key.ends_with('/') {
+                            let _ = fs::remove_dir(state.storage_path.join(&bucket).join(&delete_obj.key));
+                        }
+                        state.list_index.record_delete(&bucket, &delete_obj.key);
 
-                        // Update quota for successful deletion
-                        if !object_path.is_dir() {
-                            if let Err(e) = state.quota_manager.update_quota_remove(&bucket, file_size).await {
+                        // Update quota for successful deletion; the path is gone now, so the type is known from before
+                        if let Some(file_size) = file_size {
+                            if let Err(e) = state.quota_manager.update_quota_remove(&bucket, &delete_obj.key, file_size).await {
                                 warn!("Failed to update quota for bucket {} after batch delete: {}", bucket, e);
                             }
 
@@ -1210,14 +1854,45 @@ pub async fn handle_bucket_post(
 pub async fn create_bucket(
     State(state): State<AppState>,
     Path(bucket): Path<String>,
+    owner: Option<String>,
 ) -> impl IntoResponse {
     info!("Creating bucket: {}", bucket);
 
+    // S3 bucket names start with a letter or digit; the leading '_' is kept for the /_ironbucket endpoints
+    if bucket.starts_with('_') {
+        return Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .header(header::CONTENT_TYPE, "application/xml")
+            .body(Body::from(format!(r#"<?xml version="1.0" encoding="UTF-8"?>
+<Error>
+    <Code>InvalidBucketName</Code>
+    <Message>The specified bucket is not valid.</Message>
+    <BucketName>{}</BucketName>
+</Error>"#, xml_escape(&bucket))))
+            .unwrap();
+    }
+
     let bucket_path = state.storage_path.join(&bucket);
 
     // Check if bucket already exists on filesystem
     if bucket_path.exists() {
-        // AWS S3 behavior: if bucket exists and owned by same user, return 200 OK
+        // Buckets created before owners were recorded are treated as owned by everyone
+        let existing_owner = read_bucket_owner(&state.storage_path, &bucket);
+        if existing_owner.is_some() && existing_owner != owner {
+            debug!("Bucket {} already exists and is owned by another key", bucket);
+            return Response::builder()
+                .status(StatusCode::CONFLICT)
+                .header(header::CONTENT_TYPE, "application/xml")
+                .body(Body::from(format!(r#"<?xml version="1.0" encoding="UTF-8"?>
+<Error>
+    <Code>BucketAlreadyExists</Code>
+    <Message>The requested bucket name is not available. The bucket namespace is shared by all users of the system. Please select a different name and try again.</Message>
+    <BucketName>{}</BucketName>
+</Error>"#, bucket)))
+                .unwrap();
+        }
+
+        // AWS S3 behavior (us-east-1): if bucket exists and owned by same user, return 200 OK
         debug!("Bucket {} already exists, returning OK (AWS S3 compatible behavior)", bucket);
         return Response::builder()
             .status(StatusCode::OK)
@@ -1228,8 +1903,25 @@ pub async fn create_bucket(
             .unwrap();
     }
 
-    // Create the bucket directory on filesystem
-    match fs::create_dir_all(&bucket_path) {
+    if let Some(max_buckets) = state.settings.max_buckets {
+        let bucket_count = list_bucket_names(&state.storage_path).map(|names| names.len()).unwrap_or(0);
+        if bucket_count >= max_buckets {
+            warn!("Refusing to create bucket {}: {} of {} buckets already exist", bucket, bucket_count, max_buckets);
+            return Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .header(header::CONTENT_TYPE, "application/xml")
+                .body(Body::from(format!(r#"<?xml version="1.0" encoding="UTF-8"?>
+<Error>
+    <Code>TooManyBuckets</Code>
+    <Message>You have attempted to create more buckets than allowed</Message>
+    <BucketName>{}</BucketName>
+</Error>"#, bucket)))
+                .unwrap();
+        }
+    }
+
+    // Create the bucket directory on filesystem
+    match create_storage_dir(&bucket_path) {
         Ok(_) => {
             info!("Successfully created bucket directory: {:?}", bucket_path);
 
@@ -1241,9 +1933,10 @@ pub async fn create_bucket(
             let metadata = serde_json::json!({
                 "created": Utc::now().to_rfc3339(),
                 "versioning_status": null,
+                "owner": owner,
             });
 
-            if let Err(e) = fs::write(metadata_path, metadata.to_string()) {
+            if let Err(e) = write_storage_file(metadata_path, metadata.to_string()) {
                 warn!("Failed to write bucket metadata: {}", e);
             }
 
@@ -1275,17 +1968,19 @@ pub async fn delete_bucket(
     State(state): State<AppState>,
     Path(bucket): Path<String>,
     Query(params): Query<BucketQueryParams>,
+    user: Option<Extension<AuthenticatedUser>>,
 ) -> impl IntoResponse {
     info!("Deleting bucket: {} with params: {:?}", bucket, params);
 
+    if let Some(subresource) = params.unimplemented_subresource() {
+        return not_implemented_response(subresource);
+    }
+
     // Handle policy deletion
     if params.policy.is_some() {
         // Check if bucket exists
         if !bucket_exists(&state.storage_path, &bucket) {
-            return Response::builder()
-                .status(StatusCode::NOT_FOUND)
-                .body(Body::from("NoSuchBucket"))
-                .unwrap();
+            return no_such_bucket_response(&bucket);
         }
 
         // Check if policy exists
@@ -1303,32 +1998,20 @@ pub async fn delete_bucket(
             state.wal_writer.log_delete_metadata(&bucket, "policy");
 
             info!("Deleted policy for bucket {}", bucket);
-            return Response::builder()
-                .status(StatusCode::NO_CONTENT)
-                .body(Body::empty())
-                .unwrap();
-        } else {
-            // No policy to delete
-            return Response::builder()
-                .status(StatusCode::NOT_FOUND)
-                .header(header::CONTENT_TYPE, "application/xml")
-                .body(Body::from(r#"<?xml version="1.0" encoding="UTF-8"?>
-<Error>
-    <Code>NoSuchBucketPolicy</Code>
-    <Message>The bucket policy does not exist</Message>
-</Error>"#))
-                .unwrap();
         }
+
+        // Deleting a configuration that was never set still succeeds, as in S3
+        return Response::builder()
+            .status(StatusCode::NO_CONTENT)
+            .body(Body::empty())
+            .unwrap();
     }
 
     // Handle encryption deletion
     if params.encryption.is_some() {
         // Check if bucket exists
         if !bucket_exists(&state.storage_path, &bucket) {
-            return Response::builder()
-                .status(StatusCode::NOT_FOUND)
-                .body(Body::from("NoSuchBucket"))
-                .unwrap();
+            return no_such_bucket_response(&bucket);
         }
 
         // Check if encryption exists
@@ -1346,32 +2029,19 @@ pub async fn delete_bucket(
             state.wal_writer.log_delete_metadata(&bucket, "encryption");
 
             info!("Deleted encryption configuration for bucket {}", bucket);
-            return Response::builder()
-                .status(StatusCode::NO_CONTENT)
-                .body(Body::empty())
-                .unwrap();
-        } else {
-            // No encryption to delete
-            return Response::builder()
-                .status(StatusCode::NOT_FOUND)
-                .header(header::CONTENT_TYPE, "application/xml")
-                .body(Body::from(r#"<?xml version="1.0" encoding="UTF-8"?>
-<Error>
-    <Code>ServerSideEncryptionConfigurationNotFoundError</Code>
-    <Message>The server side encryption configuration was not found</Message>
-</Error>"#))
-                .unwrap();
         }
+
+        return Response::builder()
+            .status(StatusCode::NO_CONTENT)
+            .body(Body::empty())
+            .unwrap();
     }
 
     // Handle CORS deletion
     if params.cors.is_some() {
         // Check if bucket exists
         if !bucket_exists(&state.storage_path, &bucket) {
-            return Response::builder()
-                .status(StatusCode::NOT_FOUND)
-                .body(Body::from("NoSuchBucket"))
-                .unwrap();
+            return no_such_bucket_response(&bucket);
         }
 
         // Check if CORS exists
@@ -1386,32 +2056,123 @@ pub async fn delete_bucket(
             }
 
             info!("Deleted CORS configuration for bucket {}", bucket);
+        }
+
+        return Response::builder()
+            .status(StatusCode::NO_CONTENT)
+            .body(Body::empty())
+            .unwrap();
+    }
+
+    // S3 has no DELETE ?logging; treat it as turning logging off rather than deleting the bucket
+    if params.logging.is_some() {
+        if !bucket_exists(&state.storage_path, &bucket) {
+            return no_such_bucket_response(&bucket);
+        }
+
+        if let Err(e) = delete_bucket_logging(&state.storage_path, &bucket) {
+            warn!("Failed to delete logging configuration: {}", e);
             return Response::builder()
-                .status(StatusCode::NO_CONTENT)
-                .body(Body::empty())
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(Body::from("InternalError"))
                 .unwrap();
-        } else {
-            // No CORS to delete
+        }
+
+        info!("Disabled access logging for bucket {}", bucket);
+        return Response::builder()
+            .status(StatusCode::NO_CONTENT)
+            .body(Body::empty())
+            .unwrap();
+    }
+
+    if params.website.is_some() {
+        if !bucket_exists(&state.storage_path, &bucket) {
+            return no_such_bucket_response(&bucket);
+        }
+
+        if let Err(e) = delete_bucket_website(&state.storage_path, &bucket) {
+            warn!("Failed to delete website configuration: {}", e);
             return Response::builder()
-                .status(StatusCode::NOT_FOUND)
-                .header(header::CONTENT_TYPE, "application/xml")
-                .body(Body::from(r#"<?xml version="1.0" encoding="UTF-8"?>
-<Error>
-    <Code>NoSuchCORSConfiguration</Code>
-    <Message>The CORS configuration does not exist</Message>
-</Error>"#))
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(Body::from("InternalError"))
                 .unwrap();
         }
+
+        info!("Deleted website configuration for bucket {}", bucket);
+        return Response::builder()
+            .status(StatusCode::NO_CONTENT)
+            .body(Body::empty())
+            .unwrap();
+    }
+
+    // Handle object TTL deletion
+    if params.ttl.is_some() {
+        if !bucket_exists(&state.storage_path, &bucket) {
+            return no_such_bucket_response(&bucket);
+        }
+
+        if let Err(e) = delete_bucket_ttl(&state.storage_path, &bucket) {
+            warn!("Failed to delete object TTL: {}", e);
+            return Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(Body::from("InternalError"))
+                .unwrap();
+        }
+
+        info!("Deleted object TTL for bucket {}", bucket);
+        return Response::builder()
+            .status(StatusCode::NO_CONTENT)
+            .body(Body::empty())
+            .unwrap();
+    }
+
+    // Handle default storage class deletion
+    if params.storage_class.is_some() {
+        if !bucket_exists(&state.storage_path, &bucket) {
+            return no_such_bucket_response(&bucket);
+        }
+
+        if let Err(e) = delete_bucket_storage_class(&state.storage_path, &bucket) {
+            warn!("Failed to delete default storage class: {}", e);
+            return Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(Body::from("InternalError"))
+                .unwrap();
+        }
+
+        info!("Deleted default storage class for bucket {}", bucket);
+        return Response::builder()
+            .status(StatusCode::NO_CONTENT)
+            .body(Body::empty())
+            .unwrap();
+    }
+
+    // Handle default object ACL deletion
+    if params.default_acl.is_some() {
+        if !bucket_exists(&state.storage_path, &bucket) {
+            return no_such_bucket_response(&bucket);
+        }
+
+        if let Err(e) = delete_bucket_default_acl(&state.storage_path, &bucket) {
+            warn!("Failed to delete default object ACL: {}", e);
+            return Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(Body::from("InternalError"))
+                .unwrap();
+        }
+
+        info!("Deleted default object ACL for bucket {}", bucket);
+        return Response::builder()
+            .status(StatusCode::NO_CONTENT)
+            .body(Body::empty())
+            .unwrap();
     }
 
     // Handle lifecycle deletion
     if params.lifecycle.is_some() {
         // Check if bucket exists
         if !bucket_exists(&state.storage_path, &bucket) {
-            return Response::builder()
-                .status(StatusCode::NOT_FOUND)
-                .body(Body::from("NoSuchBucket"))
-                .unwrap();
+            return no_such_bucket_response(&bucket);
         }
 
         // Check if lifecycle exists
@@ -1426,75 +2187,78 @@ pub async fn delete_bucket(
             }
 
             info!("Deleted lifecycle configuration for bucket {}", bucket);
+        }
+
+        return Response::builder()
+            .status(StatusCode::NO_CONTENT)
+            .body(Body::empty())
+            .unwrap();
+    }
+
+    // Default: delete the bucket itself
+    let bucket_path = state.storage_path.join(&bucket);
+
+    // Check if bucket exists on filesystem
+    if !bucket_path.exists() {
+        return no_such_bucket_response(&bucket);
+    }
+
+    // ?force (like `aws s3 rb --force`) removes objects, versions and uploads along with the bucket.
+    // Only the bucket's owner may do this; buckets created before owners were recorded belong to everyone.
+    if params.force.is_some() {
+        let owner = read_bucket_owner(&state.storage_path, &bucket);
+        let requester = user.map(|Extension(user)| user.access_key);
+        if requester.is_none() || (owner.is_some() && owner != requester) {
+            warn!("Refusing to force-delete bucket {} for {:?}, owned by {:?}", bucket, requester, owner);
             return Response::builder()
-                .status(StatusCode::NO_CONTENT)
-                .body(Body::empty())
-                .unwrap();
-        } else {
-            // No lifecycle to delete
-            return Response::builder()
-                .status(StatusCode::NOT_FOUND)
+                .status(StatusCode::FORBIDDEN)
                 .header(header::CONTENT_TYPE, "application/xml")
                 .body(Body::from(r#"<?xml version="1.0" encoding="UTF-8"?>
 <Error>
-    <Code>NoSuchLifecycleConfiguration</Code>
-    <Message>The lifecycle configuration does not exist</Message>
+    <Code>AccessDenied</Code>
+    <Message>Only the bucket owner can force-delete a bucket</Message>
 </Error>"#))
                 .unwrap();
         }
-    }
 
-    // Default: delete the bucket itself
-    let bucket_path = state.storage_path.join(&bucket);
+        let (objects, versions) = {
+            let (storage_path, bucket) = (state.storage_path.clone(), bucket.clone());
+            tokio::task::spawn_blocking(move || count_bucket_objects(&storage_path, &bucket)).await.unwrap_or_default()
+        };
+        let uploads = {
+            let mut multipart_uploads = state.multipart_uploads.lock().unwrap();
+            let before = multipart_uploads.len();
+            multipart_uploads.retain(|_, upload| upload.bucket != bucket);
+            before - multipart_uploads.len()
+        };
+        info!("Force-deleting bucket {}: removing {} objects, {} versions and {} multipart uploads",
+              bucket, objects, versions, uploads);
+    }
 
-    // Check if bucket exists on filesystem
-    if !bucket_path.exists() {
+    // S3 doesn't allow deleting buckets with objects, versions, delete markers or uploads in progress
+    let has_active_upload = state.multipart_uploads.lock().unwrap()
+        .values()
+        .any(|upload| upload.bucket == bucket);
+    if params.force.is_none() && (has_active_upload || bucket_has_data(&state.storage_path, &bucket)) {
+        debug!("Refusing to delete bucket {} (multipart upload in progress: {})", bucket, has_active_upload);
         return Response::builder()
-            .status(StatusCode::NOT_FOUND)
+            .status(StatusCode::CONFLICT)
             .header(header::CONTENT_TYPE, "application/xml")
             .body(Body::from(format!(r#"<?xml version="1.0" encoding="UTF-8"?>
-<Error>
-    <Code>NoSuchBucket</Code>
-    <Message>The specified bucket does not exist</Message>
-    <BucketName>{}</BucketName>
-</Error>"#, bucket)))
-            .unwrap();
-    }
-
-    // Check if bucket is empty (S3 doesn't allow deleting non-empty buckets)
-    // Only check if the directory exists on filesystem
-    if bucket_path.exists() {
-        if let Ok(entries) = fs::read_dir(&bucket_path) {
-            let mut has_objects = false;
-            for entry in entries.flatten() {
-                if let Some(name) = entry.file_name().to_str() {
-                    // Ignore hidden files like .policy, .cors, .multipart, etc.
-                    if !name.starts_with('.') && !name.ends_with(".metadata") {
-                        has_objects = true;
-                        break;
-                    }
-                }
-            }
-
-            if has_objects {
-                return Response::builder()
-                    .status(StatusCode::CONFLICT)
-                    .header(header::CONTENT_TYPE, "application/xml")
-                    .body(Body::from(format!(r#"<?xml version="1.0" encoding="UTF-8"?>
 <Error>
     <Code>BucketNotEmpty</Code>
     <Message>The bucket you tried to delete is not empty</Message>
     <BucketName>{}</BucketName>
 </Error>"#, bucket)))
-                    .unwrap();
-            }
-        }
+            .unwrap();
     }
 
     // Delete the bucket directory from filesystem
     match fs::remove_dir_all(&bucket_path) {
         Ok(_) => {
             info!("Successfully deleted bucket: {}", bucket);
+            state.quota_manager.forget_bucket(&bucket);
+            state.list_index.forget_bucket(&bucket);
 
             // Log to WAL for replication
             state.wal_writer.log_delete_bucket(&bucket);
@@ -1536,11 +2300,13 @@ pub async fn list_objects_impl(
     bucket: String,
     prefix: Option<String>,
     delimiter: Option<String>,
-    continuation_token: Option<String>,
+    start_after: Option<String>,
     max_keys: Option<usize>,
+    extensions: ListExtensions,
 ) -> Response {
-    info!("Listing objects in bucket: {} with prefix: {:?}, delimiter: {:?}, continuation_token: {:?}, max_keys: {:?}",
-           bucket, prefix, delimiter, continuation_token, max_keys);
+    let include_metadata = extensions.include_metadata;
+    info!("Listing objects in bucket: {} with prefix: {:?}, delimiter: {:?}, start_after: {:?}, max_keys: {:?}",
+           bucket, prefix, delimiter, start_after, max_keys);
 
     // Increment stats for LIST operation
     if let Err(e) = state.quota_manager.increment_stat(&bucket, Operation::List).await {
@@ -1550,31 +2316,31 @@ pub async fn list_objects_impl(
     // First check if bucket exists on filesystem
     let bucket_path = state.storage_path.join(&bucket);
     if !bucket_path.exists() || !bucket_path.is_dir() {
-        return Response::builder()
-            .status(StatusCode::NOT_FOUND)
-            .body(Body::empty())
-            .unwrap();
+        return no_such_bucket_response(&bucket);
     }
 
     let prefix_str = prefix.as_deref().unwrap_or("");
     let max_keys = max_keys.unwrap_or(1000);
 
-    // Collect all matching objects into a sorted vector for consistent pagination
-    let _all_objects: Vec<(String, ObjectData)> = Vec::new();
-
-    // Always scan filesystem for objects to ensure consistency
-    // Use a recursive approach to handle prefixes that represent directories
-    info!("Scanning filesystem for objects at: {:?} with prefix: {:?}", bucket_path, prefix_str);
+    // Bounds on the recursive walk, so a pathologically deep tree or a symlink loop cannot exhaust the stack
+    struct ScanState {
+        max_depth: usize,
+        follow_symlinks: bool,
+        ancestors: Vec<std::path::PathBuf>, // directories being scanned, from the scan root down
+        depth_exceeded: bool,
+    }
 
-    fn scan_directory(base_path: &std::path::Path, current_path: &std::path::Path, target_prefix: &str, _delimiter: Option<&str>) -> Vec<(String, ObjectData)> {
+    fn scan_directory(base_path: &std::path::Path, current_path: &std::path::Path, target_prefix: &str, _delimiter: Option<&str>, scan: &mut ScanState) -> Vec<(String, ObjectData)> {
         let mut results = Vec::new();
 
         if let Ok(entries) = fs::read_dir(current_path) {
             for entry in entries.flatten() {
-                if let Ok(metadata) = entry.metadata() {
+                // DirEntry::metadata describes a symlink itself, which is neither a file nor a directory and so is skipped
+                let metadata = if scan.follow_symlinks { fs::metadata(entry.path()) } else { entry.metadata() };
+                if let Ok(metadata) = metadata {
                     if let Some(name) = entry.file_name().to_str() {
-                        // Skip metadata files and hidden files (except .bucket_metadata)
-                        if !name.ends_with(".metadata") && (!name.starts_with(".") || name == ".bucket_metadata") {
+                        // Skip metadata files and hidden files (except .bucket_metadata and folder markers)
+                        if !name.ends_with(".metadata") && (!name.starts_with(".") || name == ".bucket_metadata" || name == FOLDER_MARKER_NAME) {
                             // Build the full key path relative to bucket
                             let relative_path = if let Ok(rel) = entry.path().strip_prefix(base_path) {
                                 rel.to_string_lossy().to_string()
@@ -1590,27 +2356,37 @@ pub async fn list_objects_impl(
                             // Convert Windows paths to forward slashes
                             let key = relative_path.replace('\\', "/");
 
-                            // Check if this key matches our target prefix
-                            if key.starts_with(target_prefix) {
+                            // A folder marker such as photos/.folder lists as the key photos/
+                            let key = match key.strip_suffix(FOLDER_MARKER_NAME) {
+                                Some(folder) if name == FOLDER_MARKER_NAME => folder.to_string(),
+                                _ => key,
+                            };
+
+                            // Directories are entered when they can hold keys under the prefix, e.g. photos for photos/2024/
+                            let leads_to_prefix = metadata.is_dir() && target_prefix.starts_with(&format!("{}/", key));
+                            if key.starts_with(target_prefix) || leads_to_prefix {
                                 if metadata.is_file() {
-                                    let size = metadata.len() as usize;
-                                    let last_modified = metadata.modified()
-                                        .ok()
-                                        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
-                                        .map(|d| Utc.timestamp_opt(d.as_secs() as i64, d.subsec_nanos()).unwrap())
-                                        .unwrap_or_else(Utc::now);
-
-                                    let etag = format!("{:x}", md5::compute(format!("{}-{}", size, last_modified.timestamp()).as_bytes()));
-
-                                    results.push((key, ObjectData {
-                                        data: Vec::new(),
-                                        size,
-                                        last_modified,
-                                        etag,
-                                    }));
+                                    results.push((key, listing_object_data(&metadata)));
                                 } else if metadata.is_dir() {
+                                    if scan.ancestors.len() > scan.max_depth {
+                                        scan.depth_exceeded = true;
+                                        continue;
+                                    }
+                                    // A followed symlink may lead back to a directory that is already being scanned
+                                    let dir = if scan.follow_symlinks {
+                                        fs::canonicalize(entry.path()).unwrap_or_else(|_| entry.path())
+                                    } else {
+                                        entry.path()
+                                    };
+                                    if scan.ancestors.contains(&dir) {
+                                        warn!("Not listing {:?} again, a symlink leads back to it", dir);
+                                        continue;
+                                    }
+
                                     // Recursively scan subdirectories
-                                    let sub_results = scan_directory(base_path, &entry.path(), target_prefix, _delimiter);
+                                    scan.ancestors.push(dir);
+                                    let sub_results = scan_directory(base_path, &entry.path(), target_prefix, _delimiter, scan);
+                                    scan.ancestors.pop();
                                     results.extend(sub_results);
                                 }
                             }
@@ -1623,93 +2399,270 @@ pub async fn list_objects_impl(
         results
     }
 
-    let mut all_objects = scan_directory(&bucket_path, &bucket_path, prefix_str, delimiter.as_deref());
-    let object_count = all_objects.len();
-    info!("Scan complete: {} total objects matching prefix '{}' in bucket {}",
-          object_count, prefix_str, bucket);
-
-    all_objects.sort_by_key(|(key, _)| key.clone());
+    // Scan the keys under the prefix; also reports whether directories were skipped for being too deep
+    fn scan_bucket(base_path: &std::path::Path, prefix: &str, delimiter: Option<&str>, max_depth: usize, follow_symlinks: bool) -> (Vec<(String, ObjectData)>, bool) {
+        let root = if follow_symlinks { fs::canonicalize(base_path).unwrap_or_else(|_| base_path.to_path_buf()) } else { base_path.to_path_buf() };
+        let mut scan = ScanState { max_depth, follow_symlinks, ancestors: vec![root], depth_exceeded: false };
+        let objects = scan_directory(base_path, base_path, prefix, delimiter, &mut scan);
+        (objects, scan.depth_exceeded)
+    }
 
-    // Apply pagination
-    let start_after = continuation_token.as_deref().unwrap_or("");
-    let start_index = if !start_after.is_empty() {
-        // Find the index of the first object after the continuation token
-        all_objects.iter().position(|(key, _)| key.as_str() > start_after).unwrap_or(all_objects.len())
+    // With LIST_INDEX a page is read from the bucket's sorted key index instead of scanning and sorting every key.
+    // The time filters need each object's sidecar, so they always take the scan.
+    let time_filtered = extensions.modified_since.is_some() || extensions.modified_before.is_some();
+    let indexed_entries = if state.list_index.is_enabled() && !time_filtered {
+        if state.list_index.begin_build(&bucket) {
+            info!("Building listing index for bucket {}", bucket);
+            let scan_base = bucket_path.clone();
+            let (max_depth, follow_symlinks) = (state.settings.list_max_depth, state.settings.list_follow_symlinks);
+            match tokio::task::spawn_blocking(move || scan_bucket(&scan_base, "", None, max_depth, follow_symlinks)).await {
+                Ok((objects, depth_exceeded)) => {
+                    if depth_exceeded {
+                        warn!("Listing index of bucket {} skipped directories nested deeper than LIST_MAX_DEPTH={}", bucket, max_depth);
+                    }
+                    state.list_index.finish_build(&bucket, objects);
+                }
+                Err(e) => {
+                    error!("Failed to scan bucket {} for the listing index: {}", bucket, e);
+                    state.list_index.abort_build(&bucket);
+                }
+            }
+        }
+        // None while another listing is still building the index
+        state.list_index.page(&bucket, prefix_str, delimiter.as_deref(), start_after.as_deref().unwrap_or(""), max_keys)
     } else {
-        0
+        None
     };
 
-    // Get the requested page of objects
-    let end_index = (start_index + max_keys).min(all_objects.len());
-    let page_objects = &all_objects[start_index..end_index];
+    let (entries, start_index) = match indexed_entries {
+        Some(entries) => (entries, 0),
+        None => {
+            // Always scan filesystem for objects to ensure consistency
+            // Use a recursive approach to handle prefixes that represent directories
+            info!("Scanning filesystem for objects at: {:?} with prefix: {:?}", bucket_path, prefix_str);
+
+            // The walk can touch many directories, so it runs on a blocking thread
+            let scan_base = bucket_path.clone();
+            let (scan_prefix, scan_delimiter) = (prefix_str.to_string(), delimiter.clone());
+            let (max_depth, follow_symlinks) = (state.settings.list_max_depth, state.settings.list_follow_symlinks);
+            let mut all_objects = match tokio::task::spawn_blocking(move || {
+                scan_bucket(&scan_base, &scan_prefix, scan_delimiter.as_deref(), max_depth, follow_symlinks)
+            }).await {
+                Ok((objects, depth_exceeded)) => {
+                    // Keys below the limit are left out of the listing rather than failing it
+                    if depth_exceeded {
+                        warn!("Listing of bucket {} skipped directories nested deeper than LIST_MAX_DEPTH={}", bucket, max_depth);
+                    }
+                    objects
+                }
+                Err(e) => {
+                    error!("Failed to scan bucket {} for listing: {}", bucket, e);
+                    return Response::builder()
+                        .status(StatusCode::INTERNAL_SERVER_ERROR)
+                        .body(Body::empty())
+                        .unwrap();
+                }
+            };
+            let object_count = all_objects.len();
+            info!("Scan complete: {} total objects matching prefix '{}' in bucket {}",
+                  object_count, prefix_str, bucket);
+
+            // The time filter needs every candidate's sidecar, so it runs before pagination, in one blocking batch
+            if extensions.modified_since.is_some() || extensions.modified_before.is_some() {
+                let sidecar_base = bucket_path.clone();
+                let ListExtensions { modified_since, modified_before, .. } = extensions;
+                all_objects = match tokio::task::spawn_blocking(move || {
+                    all_objects.into_iter()
+                        .filter_map(|(key, mut obj)| {
+                            // Objects written without a sidecar fall back to the file's modification time
+                            if let Some(metadata) = fs::read_to_string(sidecar_base.join(format!("{}.metadata", object_storage_key(&key))))
+                                .ok()
+                                .and_then(|json| serde_json::from_str::<ObjectMetadata>(&json).ok())
+                            {
+                                obj.last_modified = metadata.last_modified;
+                            }
+                            let in_range = modified_since.is_none_or(|since| obj.last_modified >= since)
+                                && modified_before.is_none_or(|before| obj.last_modified < before);
+                            in_range.then_some((key, obj))
+                        })
+                        .collect()
+                }).await {
+                    Ok(objects) => objects,
+                    Err(e) => {
+                        error!("Failed to filter listing of {} by modification time: {}", bucket, e);
+                        return Response::builder()
+                            .status(StatusCode::INTERNAL_SERVER_ERROR)
+                            .body(Body::empty())
+                            .unwrap();
+                    }
+                };
+                info!("{} of {} objects in bucket {} are in the requested modification time range",
+                      all_objects.len(), object_count, bucket);
+            }
 
-    // Check if there are more objects
-    let is_truncated = end_index < all_objects.len();
-    let next_continuation_token = if is_truncated {
-        page_objects.last().map(|(key, _)| key.to_string())
-    } else {
-        None
+            all_objects.sort_by_key(|(key, _)| key.clone());
+
+            // With a delimiter, keys containing it after the prefix roll up into one common prefix each.
+            // Keys and prefixes share one sorted sequence, so max-keys and continuation tokens count both.
+            let mut entries: Vec<(String, Option<ObjectData>)> = Vec::with_capacity(all_objects.len());
+            let mut seen_prefixes = HashSet::new();
+            for (key, obj) in all_objects {
+                let rolled_up = delimiter.as_deref()
+                    .filter(|delim| !delim.is_empty())
+                    .and_then(|delim| key[prefix_str.len()..].find(delim).map(|idx| key[..prefix_str.len() + idx + delim.len()].to_string()));
+                match rolled_up {
+                    Some(common_prefix) => {
+                        if seen_prefixes.insert(common_prefix.clone()) {
+                            entries.push((common_prefix, None));
+                        }
+                    }
+                    None => entries.push((key, Some(obj))),
+                }
+            }
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+            // Apply pagination. The listing is rescanned for every page, so entries are skipped by comparison with the
+            // last one returned rather than by position: keys added or removed meanwhile never shift the next page.
+            let start_after = start_after.as_deref().unwrap_or("");
+            let start_index = if !start_after.is_empty() {
+                // Find the index of the first entry after the continuation token
+                entries.partition_point(|(name, _)| name.as_str() <= start_after)
+            } else {
+                0
+            };
+            (entries, start_index)
+        }
     };
 
-    info!("Pagination debug: all_objects.len()={}, start_index={}, end_index={}, is_truncated={}, next_token={:?}",
-           all_objects.len(), start_index, end_index, is_truncated, next_continuation_token);
-
-    // Build common prefixes when delimiter is set
+    // Get the requested page of objects and common prefixes
+    let end_index = (start_index + max_keys).min(entries.len());
+    let mut page_objects: Vec<(String, ObjectData, String, Option<ObjectMetadata>)> = Vec::new();
     let mut common_prefixes = Vec::new();
-    if let Some(delim) = &delimiter {
-        let mut seen_prefixes = HashSet::new();
-        for (key, _) in &all_objects {
-            if let Some(idx) = key[prefix_str.len()..].find(delim) {
-                let prefix_with_delim = format!("{}{}",
-                    &key[..prefix_str.len() + idx], delim);
-                if seen_prefixes.insert(prefix_with_delim.clone()) {
-                    common_prefixes.push(prefix_with_delim);
+    for (name, obj) in &entries[start_index..end_index] {
+        match obj {
+            Some(obj) => page_objects.push((name.clone(), obj.clone(), "STANDARD".to_string(), None)),
+            None => common_prefixes.push(name.clone()),
+        }
+    }
+
+    // Sidecars are read only for the returned page, in a single blocking batch
+    if (state.settings.list_fetch_metadata || include_metadata) && !page_objects.is_empty() {
+        let sidecar_base = bucket_path.clone();
+        page_objects = match tokio::task::spawn_blocking(move || {
+            for (key, obj, storage_class, extension) in page_objects.iter_mut() {
+                let sidecar = fs::read_to_string(sidecar_base.join(format!("{}.metadata", object_storage_key(key))))
+                    .ok()
+                    .and_then(|json| serde_json::from_str::<ObjectMetadata>(&json).ok());
+                if let Some(metadata) = sidecar {
+                    obj.etag = metadata.etag.clone();
+                    storage_class.clone_from(&metadata.storage_class);
+                    if include_metadata {
+                        *extension = Some(metadata);
+                    }
                 }
             }
-        }
-        common_prefixes.sort();
+            page_objects
+        }).await {
+            Ok(page) => page,
+            Err(e) => {
+                error!("Failed to read object metadata for listing of {}: {}", bucket, e);
+                return Response::builder()
+                    .status(StatusCode::INTERNAL_SERVER_ERROR)
+                    .body(Body::empty())
+                    .unwrap();
+            }
+        };
     }
 
-    // Build XML response
-    let mut xml = format!(r#"<?xml version="1.0" encoding="UTF-8"?>
+    // Check if there are more entries. The next page resumes after the last key or common prefix of this one;
+    // an empty max-keys=0 page resumes where it started, so it still carries a token or marker
+    let is_truncated = end_index < entries.len();
+    let last_entry = if is_truncated {
+        entries[..end_index].last()
+            .map(|(name, _)| name.clone())
+            .or_else(|| Some(start_after.clone().unwrap_or_default()))
+    } else {
+        None
+    };
+    let next_continuation_token = last_entry.as_deref()
+        .filter(|_| !extensions.v1_markers)
+        .map(encode_continuation_token);
+
+    info!("Pagination debug: entries.len()={}, start_index={}, end_index={}, is_truncated={}, next_token={:?}",
+           entries.len(), start_index, end_index, is_truncated, next_continuation_token);
+
+    // Build the XML in one buffer sized for the page up front; keys and prefixes are escaped as they are written
+    let capacity = 512
+        + page_objects.iter().map(|(key, ..)| key.len() + 256).sum::<usize>()
+        + common_prefixes.iter().map(|prefix| prefix.len() + 64).sum::<usize>();
+    let mut xml = String::with_capacity(capacity);
+    xml.push_str(r#"<?xml version="1.0" encoding="UTF-8"?>
 <ListBucketResult xmlns="http://s3.amazonaws.com/doc/2006-03-01/">
-    <Name>{}</Name>
-    <Prefix>{}</Prefix>
-    <MaxKeys>{}</MaxKeys>
-    <IsTruncated>{}</IsTruncated>"#,
-        bucket,
-        prefix_str,
-        max_keys,
-        if is_truncated { "true" } else { "false" }
-    );
+    <Name>"#);
+    xml.push_str(&bucket);
+    xml.push_str("</Name>\n    <Prefix>");
+    push_xml_escaped(&mut xml, prefix_str);
+    xml.push_str("</Prefix>");
+    if extensions.v1_markers {
+        xml.push_str("\n    <Marker>");
+        push_xml_escaped(&mut xml, start_after.as_deref().unwrap_or(""));
+        xml.push_str("</Marker>");
+    }
+    if let Some(delim) = delimiter.as_deref() {
+        xml.push_str("\n    <Delimiter>");
+        push_xml_escaped(&mut xml, delim);
+        xml.push_str("</Delimiter>");
+    }
+    let _ = write!(xml, "\n    <MaxKeys>{}</MaxKeys>\n    <IsTruncated>{}</IsTruncated>", max_keys, is_truncated);
 
+    if let Some(token) = extensions.continuation_token.as_deref() {
+        xml.push_str("\n    <ContinuationToken>");
+        push_xml_escaped(&mut xml, token);
+        xml.push_str("</ContinuationToken>");
+    }
+    if let Some(request_start_after) = extensions.start_after.as_deref() {
+        xml.push_str("\n    <StartAfter>");
+        push_xml_escaped(&mut xml, request_start_after);
+        xml.push_str("</StartAfter>");
+    }
     if let Some(ref token) = next_continuation_token {
-        xml.push_str(&format!("\n    <NextContinuationToken>{}</NextContinuationToken>", token));
+        xml.push_str("\n    <NextContinuationToken>");
+        push_xml_escaped(&mut xml, token);
+        xml.push_str("</NextContinuationToken>");
+    } else if let Some(next_marker) = last_entry.as_deref().filter(|_| extensions.v1_markers) {
+        xml.push_str("\n    <NextMarker>");
+        push_xml_escaped(&mut xml, next_marker);
+        xml.push_str("</NextMarker>");
     }
 
-    xml.push_str(&format!("\n    <KeyCount>{}</KeyCount>", page_objects.len()));
+    // KeyCount is part of the V2 schema only
+    if !extensions.v1_markers {
+        let _ = write!(xml, "\n    <KeyCount>{}</KeyCount>", page_objects.len() + common_prefixes.len());
+    }
 
-    for (key, obj) in page_objects {
-        xml.push_str(&format!(r#"
-    <Contents>
-        <Key>{}</Key>
+    for (key, obj, storage_class, extension) in &page_objects {
+        xml.push_str("\n    <Contents>\n        <Key>");
+        push_xml_escaped(&mut xml, key);
+        let _ = write!(xml, r#"</Key>
         <LastModified>{}</LastModified>
-        <ETag>"{}"</ETag>
+        <ETag>{}</ETag>
         <Size>{}</Size>
-        <StorageClass>STANDARD</StorageClass>
-    </Contents>"#,
-            key,
+        <StorageClass>{}</StorageClass>"#,
             obj.last_modified.to_rfc3339(),
-            obj.etag,
-            obj.size
-        ));
+            quoted_etag(&obj.etag),
+            obj.size,
+            storage_class
+        );
+        if include_metadata {
+            push_listing_metadata_xml(&mut xml, extension.as_ref());
+        }
+        xml.push_str("\n    </Contents>");
     }
 
-    for prefix in common_prefixes {
-        xml.push_str(&format!(r#"
-    <CommonPrefixes>
-        <Prefix>{}</Prefix>
-    </CommonPrefixes>"#, prefix));
+    for prefix in &common_prefixes {
+        xml.push_str("\n    <CommonPrefixes>\n        <Prefix>");
+        push_xml_escaped(&mut xml, prefix);
+        xml.push_str("</Prefix>\n    </CommonPrefixes>");
     }
 
     xml.push_str("\n</ListBucketResult>");
@@ -1719,4 +2672,36 @@ pub async fn list_objects_impl(
         .header(header::CONTENT_TYPE, "application/xml")
         .body(Body::from(xml))
         .unwrap()
+}
+
+// IronBucket extension elements for a listed object: creation time, then user metadata and tags, sorted by key
+fn push_listing_metadata_xml(xml: &mut String, metadata: Option<&ObjectMetadata>) {
+    if let Some(metadata) = metadata {
+        let _ = write!(xml, "\n        <Created>{}</Created>", metadata.created_at().to_rfc3339());
+    }
+    let mut user_metadata: Vec<(&String, &String)> = metadata.map(|m| m.metadata.iter().collect()).unwrap_or_default();
+    user_metadata.sort();
+    let mut tags: Vec<(&String, &String)> = metadata
+        .and_then(|m| m.tags.as_ref())
+        .map(|t| t.iter().collect())
+        .unwrap_or_default();
+    tags.sort();
+
+    xml.push_str("\n        <UserMetadata>");
+    for (key, value) in user_metadata {
+        xml.push_str("\n            <Entry><Key>x-amz-meta-");
+        push_xml_escaped(xml, key);
+        xml.push_str("</Key><Value>");
+        push_xml_escaped(xml, value);
+        xml.push_str("</Value></Entry>");
+    }
+    xml.push_str("\n        </UserMetadata>\n        <UserTags>");
+    for (key, value) in tags {
+        xml.push_str("\n            <Tag><Key>");
+        push_xml_escaped(xml, key);
+        xml.push_str("</Key><Value>");
+        push_xml_escaped(xml, value);
+        xml.push_str("</Value></Tag>");
+    }
+    xml.push_str("\n        </UserTags>");
 }
\ No newline at end of file
@@ -0,0 +1,245 @@
+use axum::{
+    body::Body,
+    extract::{Path, Request, State},
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use tracing::debug;
+
+use crate::{AppState, CorsConfiguration, CorsRule, bucket_exists, read_bucket_cors};
+
+// Handle CORS preflight on a bucket
+pub async fn handle_bucket_options(
+    State(state): State<AppState>,
+    Path(bucket): Path<String>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    preflight_response(&state, &bucket, &headers)
+}
+
+// Handle CORS preflight on an object
+pub async fn handle_object_options(
+    State(state): State<AppState>,
+    Path((bucket, _key)): Path<(String, String)>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    preflight_response(&state, &bucket, &headers)
+}
+
+// Methods served on the root, advertised by OPTIONS /
+const ROOT_METHODS: &str = "GET, POST, OPTIONS";
+
+// Handle OPTIONS on the root, sent by browser preflights and liveness probes.
+// The root has no bucket CORS configuration, so ROOT_CORS_ALLOW_ORIGIN decides which origin is allowed.
+pub async fn handle_root_options(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    let mut response = Response::builder()
+        .status(StatusCode::OK)
+        .header(header::ALLOW, ROOT_METHODS);
+
+    let origin = headers.get(header::ORIGIN).and_then(|v| v.to_str().ok());
+    if let (Some(origin), Some(allowed)) = (origin, state.settings.root_cors_allow_origin.as_deref()) {
+        if allowed == "*" || allowed == origin {
+            response = response
+                .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, allowed)
+                .header(header::ACCESS_CONTROL_ALLOW_METHODS, ROOT_METHODS)
+                .header(header::VARY, "Origin, Access-Control-Request-Headers");
+            if let Some(request_headers) = headers.get(header::ACCESS_CONTROL_REQUEST_HEADERS) {
+                response = response.header(header::ACCESS_CONTROL_ALLOW_HEADERS, request_headers);
+            }
+        } else {
+            debug!("Preflight on / from {} does not match ROOT_CORS_ALLOW_ORIGIN", origin);
+        }
+    }
+
+    response.body(Body::empty()).unwrap()
+}
+
+// Answer a preflight from the bucket's CORS configuration.
+// Buckets without a configuration keep the server-wide permissive behaviour.
+fn preflight_response(state: &AppState, bucket: &str, headers: &HeaderMap) -> Response {
+    let origin = headers.get(header::ORIGIN).and_then(|v| v.to_str().ok());
+    let request_method = headers
+        .get(header::ACCESS_CONTROL_REQUEST_METHOD)
+        .and_then(|v| v.to_str().ok());
+
+    let (origin, request_method) = match (origin, request_method) {
+        (Some(origin), Some(method)) => (origin, method),
+        _ => {
+            return cors_error_response(
+                StatusCode::BAD_REQUEST,
+                "BadRequest",
+                "Insufficient information. Origin request header needed.",
+            );
+        }
+    };
+
+    let request_headers: Vec<String> = headers
+        .get(header::ACCESS_CONTROL_REQUEST_HEADERS)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| {
+            v.split(',')
+                .map(|h| h.trim().to_lowercase())
+                .filter(|h| !h.is_empty())
+                .collect()
+        })
+        .unwrap_or_default();
+
+    if !bucket_exists(&state.storage_path, bucket) {
+        return cors_error_response(
+            StatusCode::FORBIDDEN,
+            "AccessForbidden",
+            "CORSResponse: Bucket not found",
+        );
+    }
+
+    let config = match read_bucket_cors(&state.storage_path, bucket) {
+        Some(config) => config,
+        None => {
+            debug!("No CORS configuration for bucket {}, answering preflight permissively", bucket);
+            let mut response = Response::builder()
+                .status(StatusCode::OK)
+                .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+                .header(header::ACCESS_CONTROL_ALLOW_METHODS, request_method);
+            if !request_headers.is_empty() {
+                response = response.header(header::ACCESS_CONTROL_ALLOW_HEADERS, request_headers.join(", "));
+            }
+            return response.body(Body::empty()).unwrap();
+        }
+    };
+
+    let rule = match find_cors_rule(&config, origin, request_method, &request_headers) {
+        Some(rule) => rule,
+        None => {
+            debug!("Preflight from {} for {} denied by CORS configuration of bucket {}", origin, request_method, bucket);
+            return cors_error_response(
+                StatusCode::FORBIDDEN,
+                "AccessForbidden",
+                "CORSResponse: This CORS request is not allowed.",
+            );
+        }
+    };
+
+    let allow_origin = if rule.allowed_origins.iter().any(|o| o == "*") { "*" } else { origin };
+
+    let mut response = Response::builder()
+        .status(StatusCode::OK)
+        .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, allow_origin)
+        .header(header::ACCESS_CONTROL_ALLOW_METHODS, rule.allowed_methods.join(", "))
+        .header(header::VARY, "Origin, Access-Control-Request-Headers, Access-Control-Request-Method");
+
+    if !request_headers.is_empty() {
+        response = response.header(header::ACCESS_CONTROL_ALLOW_HEADERS, request_headers.join(", "));
+    }
+    if let Some(expose_headers) = rule.expose_headers.as_ref().filter(|h| !h.is_empty()) {
+        response = response.header(header::ACCESS_CONTROL_EXPOSE_HEADERS, expose_headers.join(", "));
+    }
+    if let Some(max_age) = rule.max_age_seconds {
+        response = response.header(header::ACCESS_CONTROL_MAX_AGE, max_age.to_string());
+    }
+
+    response.body(Body::empty()).unwrap()
+}
+
+// CORS headers of actual (non-preflight) requests. The permissive CorsLayer has already answered every Origin;
+// for buckets with a CORS configuration its headers are replaced by those of the first rule matching the
+// origin and method, or removed when no rule matches, so browsers cannot read the response.
+pub async fn bucket_cors_middleware(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let origin = request.headers().get(header::ORIGIN).and_then(|v| v.to_str().ok()).map(|v| v.to_string());
+    // Bucket names cannot start with '_', which keeps /_ironbucket endpoints on the permissive headers
+    let bucket = request.uri().path().trim_start_matches('/').split('/').next().unwrap_or("").to_string();
+    let method = request.method().to_string();
+
+    let mut response = next.run(request).await;
+    let Some(origin) = origin else {
+        return response;
+    };
+    if bucket.is_empty() || bucket.starts_with('_') {
+        return response;
+    }
+    let Some(config) = read_bucket_cors(&state.storage_path, &bucket) else {
+        return response;
+    };
+
+    let headers = response.headers_mut();
+    for name in [
+        header::ACCESS_CONTROL_ALLOW_ORIGIN,
+        header::ACCESS_CONTROL_ALLOW_METHODS,
+        header::ACCESS_CONTROL_ALLOW_HEADERS,
+        header::ACCESS_CONTROL_ALLOW_CREDENTIALS,
+        header::ACCESS_CONTROL_EXPOSE_HEADERS,
+        header::ACCESS_CONTROL_MAX_AGE,
+    ] {
+        headers.remove(name);
+    }
+    headers.append(header::VARY, HeaderValue::from_static("Origin"));
+
+    let Some(rule) = find_cors_rule(&config, &origin, &method, &[]) else {
+        debug!("{} request from {} not allowed by CORS configuration of bucket {}", method, origin, bucket);
+        return response;
+    };
+
+    let allow_origin = if rule.allowed_origins.iter().any(|o| o == "*") { "*" } else { origin.as_str() };
+    let mut cors_headers = vec![
+        (header::ACCESS_CONTROL_ALLOW_ORIGIN, allow_origin.to_string()),
+        (header::ACCESS_CONTROL_ALLOW_METHODS, rule.allowed_methods.join(", ")),
+    ];
+    if let Some(expose_headers) = rule.expose_headers.as_ref().filter(|h| !h.is_empty()) {
+        cors_headers.push((header::ACCESS_CONTROL_EXPOSE_HEADERS, expose_headers.join(", ")));
+    }
+    if let Some(max_age) = rule.max_age_seconds {
+        cors_headers.push((header::ACCESS_CONTROL_MAX_AGE, max_age.to_string()));
+    }
+    for (name, value) in cors_headers {
+        if let Ok(value) = HeaderValue::from_str(&value) {
+            headers.insert(name, value);
+        }
+    }
+    response
+}
+
+// Find the first rule allowing this origin, method and every requested header
+pub fn find_cors_rule<'a>(
+    config: &'a CorsConfiguration,
+    origin: &str,
+    method: &str,
+    request_headers: &[String],
+) -> Option<&'a CorsRule> {
+    config.cors_rules.iter().find(|rule| {
+        rule.allowed_origins.iter().any(|o| wildcard_match(o, origin))
+            && rule.allowed_methods.iter().any(|m| m.eq_ignore_ascii_case(method))
+            && request_headers.iter().all(|h| {
+                rule.allowed_headers
+                    .as_ref()
+                    .map(|allowed| allowed.iter().any(|a| wildcard_match(&a.to_lowercase(), h)))
+                    .unwrap_or(false)
+            })
+    })
+}
+
+// S3 allows at most one '*' in an origin or header pattern
+fn wildcard_match(pattern: &str, value: &str) -> bool {
+    match pattern.split_once('*') {
+        Some((prefix, suffix)) => {
+            value.len() >= prefix.len() + suffix.len()
+                && value.starts_with(prefix)
+                && value.ends_with(suffix)
+        }
+        None => pattern == value,
+    }
+}
+
+fn cors_error_response(status: StatusCode, code: &str, message: &str) -> Response {
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/xml")
+        .body(Body::from(format!(r#"<?xml version="1.0" encoding="UTF-8"?>
+<Error>
+    <Code>{}</Code>
+    <Message>{}</Message>
+</Error>"#, code, message)))
+        .unwrap()
+}
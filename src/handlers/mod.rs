@@ -1,9 +1,13 @@
 pub mod auth;
 pub mod bucket;
+pub mod cors;
 pub mod object;
 pub mod root;
+pub mod website;
 
 pub use auth::*;
 pub use bucket::*;
+pub use cors::*;
 pub use object::*;
-pub use root::*;
\ No newline at end of file
+pub use root::*;
+pub use website::*;
\ No newline at end of file
@@ -6,8 +6,14 @@ use axum::{
 };
 use bytes::Bytes;
 use chrono::{DateTime, Utc, TimeZone};
-use std::{collections::HashMap, fs};
-use tracing::{debug, info, warn};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    io::{Read, Seek, SeekFrom, Write},
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+use tracing::{debug, error, info, warn};
 use uuid::Uuid;
 use aes_gcm::{
     aead::{Aead, KeyInit, OsRng},
@@ -15,32 +21,91 @@ use aes_gcm::{
 };
 use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
 use rand::RngCore;
+use sha2::Digest;
 
 use crate::{
-    AppState, ObjectMetadata, ObjectEncryption,
+    AppState, CompletedUpload, DeleteMarker, ObjectMetadata, ObjectEncryption, PartialUpload,
     MultipartUpload, UploadPart, format_http_date,
-    filesystem::{read_bucket_versioning, read_bucket_encryption},
+    utils::{
+        ARCHIVE_STORAGE_CLASSES, CANNED_ACLS, STORAGE_CLASSES, encode_metadata_value, extract_custom_metadata, is_reserved_key,
+        object_storage_key, quoted_etag, xml_escape,
+    },
+    filesystem::{
+        read_bucket_versioning, read_bucket_encryption, read_bucket_storage_class, read_bucket_default_acl, create_storage_dir, open_storage_file,
+        write_storage_file, PathLocks, OBJECT_WRITE_LOCKS,
+    },
     models::Operation, ObjectQueryParams,
+    settings::Settings,
 };
 
 // Use ObjectQueryParams from models
 
+// Build the 400 response for keys that collide with IronBucket's internal files
+fn reserved_key_response(key: &str) -> Response {
+    Response::builder()
+        .status(StatusCode::BAD_REQUEST)
+        .header(header::CONTENT_TYPE, "application/xml")
+        .body(Body::from(format!(r#"<?xml version="1.0" encoding="UTF-8"?>
+<Error>
+    <Code>InvalidArgument</Code>
+    <Message>The key name is reserved for internal use</Message>
+    <Key>{}</Key>
+</Error>"#, key)))
+        .unwrap()
+}
+
+// Build the 400 response for new keys that KEY_ALLOW_PATTERN does not accept
+fn disallowed_key_response(key: &str) -> Response {
+    Response::builder()
+        .status(StatusCode::BAD_REQUEST)
+        .header(header::CONTENT_TYPE, "application/xml")
+        .body(Body::from(format!(r#"<?xml version="1.0" encoding="UTF-8"?>
+<Error>
+    <Code>InvalidArgument</Code>
+    <Message>The key name is not allowed by the server's key naming policy</Message>
+    <Key>{}</Key>
+</Error>"#, xml_escape(key))))
+        .unwrap()
+}
+
 // Handle object GET with query parameters
 pub async fn handle_object_get(
     State(state): State<AppState>,
     Path((bucket, key)): Path<(String, String)>,
     Query(params): Query<ObjectQueryParams>,
+    headers: HeaderMap,
 ) -> impl IntoResponse {
     debug!("GET object: {}/{} with params: {:?}", bucket, key, params);
 
+    if is_reserved_key(&key) {
+        return reserved_key_response(&key);
+    }
+
     // Increment stats for GET operation
     if let Err(e) = state.quota_manager.increment_stat(&bucket, Operation::Get).await {
         warn!("Failed to update GET stats for bucket {}: {}", bucket, e);
     }
 
     if params.acl.is_some() {
-        // Return object ACL
-        let acl_xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+        // Return object ACL: the owner's full control plus the grants of a stored canned ACL
+        let acl = fs::read_to_string(state.storage_path.join(&bucket).join(format!("{}.metadata", object_storage_key(&key))))
+            .ok()
+            .and_then(|json| serde_json::from_str::<ObjectMetadata>(&json).ok())
+            .and_then(|metadata| metadata.acl);
+        let group_grants: &[(&str, &str)] = match acl.as_deref() {
+            Some("public-read") => &[("AllUsers", "READ")],
+            Some("public-read-write") => &[("AllUsers", "READ"), ("AllUsers", "WRITE")],
+            Some("authenticated-read") => &[("AuthenticatedUsers", "READ")],
+            _ => &[],
+        };
+        let group_grants_xml: String = group_grants.iter().map(|(group, permission)| format!(r#"
+        <Grant>
+            <Grantee xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance" xsi:type="Group">
+                <URI>http://acs.amazonaws.com/groups/global/{}</URI>
+            </Grantee>
+            <Permission>{}</Permission>
+        </Grant>"#, group, permission)).collect();
+        let acl_xml = format!(r#"<?xml version="1.0" encoding="UTF-8"?>
 <AccessControlPolicy>
     <Owner>
         <ID>ironbucket</ID>
@@ -53,9 +118,9 @@ pub async fn handle_object_get(
                 <DisplayName>IronBucket</DisplayName>
             </Grantee>
             <Permission>FULL_CONTROL</Permission>
-        </Grant>
+        </Grant>{}
     </AccessControlList>
-</AccessControlPolicy>"#;
+</AccessControlPolicy>"#, group_grants_xml);
         return Response::builder()
             .status(StatusCode::OK)
             .header(header::CONTENT_TYPE, "application/xml")
@@ -65,7 +130,7 @@ pub async fn handle_object_get(
 
     if params.tagging.is_some() {
         // Return object tags from metadata
-        let metadata_path = state.storage_path.join(&bucket).join(format!("{}.metadata", key));
+        let metadata_path = state.storage_path.join(&bucket).join(format!("{}.metadata", object_storage_key(&key)));
 
         let tags_xml = if metadata_path.exists() {
             // Read metadata file
@@ -126,13 +191,7 @@ pub async fn handle_object_get(
         // Get current version info
         let object_path = state.storage_path.join(&bucket).join(&key);
         if object_path.exists() {
-            let metadata = fs::metadata(&object_path).unwrap();
-            let size = metadata.len();
-            let last_modified = metadata.modified()
-                .ok()
-                .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
-                .map(|d| Utc.timestamp_opt(d.as_secs() as i64, d.subsec_nanos()).unwrap())
-                .unwrap_or_else(Utc::now);
+            let (last_modified, etag, size) = listed_version_info(&object_path, &state.storage_path.join(&bucket).join(format!("{}.metadata", key)));
 
             // Add current version (latest)
             xml.push_str(&format!(r#"
@@ -141,7 +200,7 @@ pub async fn handle_object_get(
         <VersionId>null</VersionId>
         <IsLatest>true</IsLatest>
         <LastModified>{}</LastModified>
-        <ETag>"{}"</ETag>
+        <ETag>{}</ETag>
         <Size>{}</Size>
         <StorageClass>STANDARD</StorageClass>
         <Owner>
@@ -151,7 +210,7 @@ pub async fn handle_object_get(
     </Version>"#,
                 key,
                 last_modified.to_rfc3339(),
-                format!("{:x}", md5::compute(fs::read(&object_path).unwrap_or_default())),
+                quoted_etag(&etag),
                 size
             ));
         }
@@ -159,54 +218,21 @@ pub async fn handle_object_get(
         // List versions from .versions directory
         if versions_dir.exists() && versions_dir.is_dir() {
             if let Ok(entries) = fs::read_dir(&versions_dir) {
-                let mut versions: Vec<(String, DateTime<Utc>, u64)> = Vec::new();
+                let mut versions: Vec<(String, DateTime<Utc>, String, u64)> = Vec::new();
 
                 for entry in entries.flatten() {
                     if let Ok(metadata) = entry.metadata() {
                         if metadata.is_file() {
                             let file_name = entry.file_name().to_string_lossy().to_string();
 
-                            // Skip metadata files
-                            if file_name.ends_with(".metadata") {
+                            // Skip metadata files and delete markers
+                            if file_name.ends_with(".metadata") || file_name.ends_with(".delete_marker") {
                                 continue;
                             }
 
-                            let version_id = file_name.clone();
-
-                            // Try to read last_modified from version metadata file
                             let version_metadata_path = versions_dir.join(format!("{}.metadata", &file_name));
-                            let last_modified = if version_metadata_path.exists() {
-                                if let Ok(metadata_str) = fs::read_to_string(&version_metadata_path) {
-                                    if let Ok(obj_metadata) = serde_json::from_str::<ObjectMetadata>(&metadata_str) {
-                                        obj_metadata.last_modified
-                                    } else {
-                                        // Fallback to file system time if metadata parsing fails
-                                        metadata.modified()
-                                            .ok()
-                                            .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
-                                            .map(|d| Utc.timestamp_opt(d.as_secs() as i64, d.subsec_nanos()).unwrap())
-                                            .unwrap_or_else(Utc::now)
-                                    }
-                                } else {
-                                    // Fallback to file system time if metadata file can't be read
-                                    metadata.modified()
-                                        .ok()
-                                        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
-                                        .map(|d| Utc.timestamp_opt(d.as_secs() as i64, d.subsec_nanos()).unwrap())
-                                        .unwrap_or_else(Utc::now)
-                                }
-                            } else {
-                                // Use file system time if no metadata file exists
-                                metadata.modified()
-                                    .ok()
-                                    .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
-                                    .map(|d| Utc.timestamp_opt(d.as_secs() as i64, d.subsec_nanos()).unwrap())
-                                    .unwrap_or_else(Utc::now)
-                            };
-
-                            let size = metadata.len();
-
-                            versions.push((version_id, last_modified, size));
+                            let (last_modified, etag, size) = listed_version_info(&entry.path(), &version_metadata_path);
+                            versions.push((file_name, last_modified, etag, size));
                         }
                     }
                 }
@@ -215,21 +241,14 @@ pub async fn handle_object_get(
                 versions.sort_by(|a, b| b.1.cmp(&a.1));
 
                 // Add each version to XML
-                for (version_id, last_modified, size) in versions {
-                    let version_path = versions_dir.join(&version_id);
-                    let etag = if let Ok(data) = fs::read(&version_path) {
-                        format!("{:x}", md5::compute(&data))
-                    } else {
-                        "unknown".to_string()
-                    };
-
+                for (version_id, last_modified, etag, size) in versions {
                     xml.push_str(&format!(r#"
     <Version>
         <Key>{}</Key>
         <VersionId>{}</VersionId>
         <IsLatest>false</IsLatest>
         <LastModified>{}</LastModified>
-        <ETag>"{}"</ETag>
+        <ETag>{}</ETag>
         <Size>{}</Size>
         <StorageClass>STANDARD</StorageClass>
         <Owner>
@@ -240,13 +259,36 @@ pub async fn handle_object_get(
                         key,
                         version_id,
                         last_modified.to_rfc3339(),
-                        etag,
+                        quoted_etag(&etag),
                         size
                     ));
                 }
             }
         }
 
+        let bucket_path = state.storage_path.join(&bucket);
+        let latest_marker = latest_delete_marker(&bucket_path, &key).map(|marker| marker.version_id);
+        let (mut markers, _) = read_version_history(&bucket_path, &key);
+        markers.sort_by_key(|marker| std::cmp::Reverse(marker.last_modified));
+        for marker in markers {
+            xml.push_str(&format!(r#"
+    <DeleteMarker>
+        <Key>{}</Key>
+        <VersionId>{}</VersionId>
+        <IsLatest>{}</IsLatest>
+        <LastModified>{}</LastModified>
+        <Owner>
+            <ID>ironbucket</ID>
+            <DisplayName>IronBucket</DisplayName>
+        </Owner>
+    </DeleteMarker>"#,
+                key,
+                marker.version_id,
+                latest_marker.as_ref() == Some(&marker.version_id),
+                marker.last_modified.to_rfc3339()
+            ));
+        }
+
         xml.push_str("\n</ListVersionsResult>");
 
         return Response::builder()
@@ -275,9 +317,9 @@ pub async fn handle_object_get(
                 xml.push_str(&format!(r#"
     <Part>
         <PartNumber>{}</PartNumber>
-        <ETag>"{}"</ETag>
+        <ETag>{}</ETag>
         <Size>{}</Size>
-    </Part>"#, part.part_number, part.etag, part.size));
+    </Part>"#, part.part_number, quoted_etag(&part.etag), part.size));
             }
 
             xml.push_str("\n</ListPartsResult>");
@@ -288,14 +330,11 @@ pub async fn handle_object_get(
                 .unwrap();
         }
 
-        return Response::builder()
-            .status(StatusCode::NOT_FOUND)
-            .body(Body::empty())
-            .unwrap();
+        return no_such_upload_response(upload_id);
     }
 
     // Default: get object
-    get_object(State(state), Path((bucket, key)), params.version_id).await.into_response()
+    get_object(State(state), Path((bucket, key)), params.version_id, headers).await.into_response()
 }
 
 // Handle object PUT with query parameters
@@ -308,6 +347,16 @@ pub async fn handle_object_put(
 ) -> impl IntoResponse {
     debug!("PUT object: {}/{} with params: {:?}", bucket, key, params);
 
+    if is_reserved_key(&key) {
+        return reserved_key_response(&key);
+    }
+
+    // Objects are created by plain PUTs and copies; parts belong to an upload whose key was checked when it started
+    let creates_object = params.acl.is_none() && params.tagging.is_none() && params.upload_id.is_none();
+    if creates_object && !state.settings.is_key_allowed(&key) {
+        return disallowed_key_response(&key);
+    }
+
     // Check quota before accepting upload (skip for ACL/tagging operations)
     if params.acl.is_none() && params.tagging.is_none() {
         let content_length = body.len() as u64;
@@ -320,9 +369,21 @@ pub async fn handle_object_put(
                     .body(Body::from("Bucket quota exceeded"))
                     .unwrap();
             }
+            Err(e) if state.settings.quota_strict => {
+                error!("Failed to check quota for bucket {}, rejecting upload: {}", bucket, e);
+                return Response::builder()
+                    .status(StatusCode::SERVICE_UNAVAILABLE)
+                    .header(header::CONTENT_TYPE, "application/xml")
+                    .body(Body::from(r#"<?xml version="1.0" encoding="UTF-8"?>
+<Error>
+    <Code>ServiceUnavailable</Code>
+    <Message>The bucket quota could not be checked, please retry</Message>
+</Error>"#))
+                    .unwrap();
+            }
             Err(e) => {
                 warn!("Failed to check quota for bucket {}: {}", bucket, e);
-                // Continue anyway - don't fail on quota check errors
+                // Continue anyway unless QUOTA_STRICT is set
             }
             Ok(true) => {
                 // Quota ok, continue
@@ -331,7 +392,35 @@ pub async fn handle_object_put(
     }
 
     if params.acl.is_some() {
-        // Set object ACL (just accept but don't actually implement)
+        // Set the object's canned ACL from x-amz-acl, or from the grants of an AccessControlPolicy body
+        let acl = match canned_acl(&headers) {
+            Ok(Some(acl)) => Some(acl),
+            Ok(None) => acl_from_grants(&String::from_utf8_lossy(&body)),
+            Err(response) => return *response,
+        };
+
+        let metadata_path = state.storage_path.join(&bucket).join(format!("{}.metadata", object_storage_key(&key)));
+        let Some(mut metadata) = fs::read_to_string(&metadata_path)
+            .ok()
+            .and_then(|json| serde_json::from_str::<ObjectMetadata>(&json).ok())
+        else {
+            return Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(Body::from("Object not found"))
+                .unwrap();
+        };
+        metadata.acl = acl.filter(|acl| acl != "private");
+
+        if let Err(e) = write_storage_file(&metadata_path, serde_json::to_string(&metadata).unwrap()) {
+            warn!("Failed to write metadata file: {}", e);
+            return Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(Body::from("Failed to save ACL"))
+                .unwrap();
+        }
+
+        state.wal_writer.log_update_object_metadata(&bucket, &key);
+        info!("ACL of {}/{} set to {}", bucket, key, metadata.acl.as_deref().unwrap_or("private"));
         return Response::builder()
             .status(StatusCode::OK)
             .body(Body::empty())
@@ -378,7 +467,7 @@ pub async fn handle_object_put(
         }
 
         // Read existing metadata
-        let metadata_path = state.storage_path.join(&bucket).join(format!("{}.metadata", key));
+        let metadata_path = state.storage_path.join(&bucket).join(format!("{}.metadata", object_storage_key(&key)));
 
         let metadata = if metadata_path.exists() {
             // Read existing metadata
@@ -415,7 +504,7 @@ pub async fn handle_object_put(
         };
 
         // Write updated metadata
-        if let Err(e) = fs::write(&metadata_path, serde_json::to_string(&metadata).unwrap()) {
+        if let Err(e) = write_storage_file(&metadata_path, serde_json::to_string(&metadata).unwrap()) {
             warn!("Failed to write metadata file: {}", e);
             return Response::builder()
                 .status(StatusCode::INTERNAL_SERVER_ERROR)
@@ -423,6 +512,8 @@ pub async fn handle_object_put(
                 .unwrap();
         }
 
+        sync_version_tags(&state.storage_path.join(&bucket), &key, &metadata);
+        state.wal_writer.log_update_object_metadata(&bucket, &key);
         info!("Tags saved successfully for {}/{}", bucket, key);
 
         // Return success without modifying the actual object
@@ -433,66 +524,109 @@ pub async fn handle_object_put(
     }
 
     if let (Some(upload_id), Some(part_number)) = (&params.upload_id, params.part_number) {
-        // Upload part for multipart upload
-        let mut data = body.to_vec();
-
-        // Check if this is chunked transfer encoding with signature
-        if data.len() > 100 {
-            let preview = String::from_utf8_lossy(&data[0..100]);
-            if preview.contains(";chunk-signature=") {
-                debug!("Detected chunked transfer encoding in multipart upload part, parsing chunks");
-                data = parse_chunked_data(&data);
-            }
+        let max_parts = state.settings.multipart_max_parts;
+        if !(1..=max_parts).contains(&part_number) {
+            return multipart_error_response(
+                "InvalidArgument",
+                &format!("Part number must be an integer between 1 and {}, inclusive", max_parts),
+            );
         }
 
+        // Upload part for multipart upload, decoding aws-chunked bodies and their checksum trailers
+        let data = match decode_request_body(&headers, &body) {
+            Ok(data) => data,
+            Err(response) => return *response,
+        };
+
         let etag = format!("{:x}", md5::compute(&data));
 
-        let mut uploads = state.multipart_uploads.lock().unwrap();
-        if let Some(upload) = uploads.get_mut(upload_id) {
-            // Store part in memory
-            upload.parts.insert(part_number, UploadPart {
-                part_number,
-                etag: etag.clone(),
-                size: data.len(),
-                data: data.clone(),
-            });
+        // Only look the upload up under the lock; the part is written to disk without holding it
+        let upload_bucket = match state.multipart_uploads.lock().unwrap().get(upload_id) {
+            Some(upload) => upload.bucket.clone(),
+            None => return no_such_upload_response(upload_id),
+        };
 
-            // Also persist part to disk
-            let multipart_dir = state.storage_path.join(&upload.bucket).join(".multipart").join(upload_id);
-            if let Err(e) = fs::create_dir_all(&multipart_dir) {
-                warn!("Failed to create multipart parts directory: {}", e);
-            }
+        // Persist part to disk
+        let multipart_dir = state.storage_path.join(&upload_bucket).join(".multipart").join(upload_id);
+        let part_path = multipart_dir.join(format!("part-{}", part_number));
+        let part_meta_path = multipart_dir.join(format!("part-{}.meta", part_number));
+        let (data, written_to_disk) = {
+            let (part_path, part_meta_path, etag) = (part_path.clone(), part_meta_path.clone(), etag.clone());
+            match tokio::task::spawn_blocking(move || {
+                if let Err(e) = create_storage_dir(&multipart_dir) {
+                    warn!("Failed to create multipart parts directory: {}", e);
+                }
 
-            let part_path = multipart_dir.join(format!("part-{}", part_number));
-            if let Err(e) = fs::write(&part_path, &data) {
-                warn!("Failed to write part {} to disk: {}", part_number, e);
+                let written_to_disk = match write_storage_file(&part_path, &data) {
+                    Ok(_) => true,
+                    Err(e) => {
+                        warn!("Failed to write part {} to disk: {}", part_number, e);
+                        // Don't leave an earlier upload of this part behind on disk
+                        let _ = fs::remove_file(&part_path);
+                        let _ = fs::remove_file(&part_meta_path);
+                        false
+                    }
+                };
+
+                // Save part metadata
+                let part_metadata = serde_json::json!({
+                    "part_number": part_number,
+                    "etag": etag,
+                    "size": data.len(),
+                });
+
+                if let Err(e) = write_storage_file(&part_meta_path, part_metadata.to_string()) {
+                    warn!("Failed to write part metadata: {}", e);
+                }
+                (data, written_to_disk)
+            }).await {
+                Ok(written) => written,
+                Err(e) => {
+                    error!("Failed to store part {} of upload {}: {}", part_number, upload_id, e);
+                    return Response::builder()
+                        .status(StatusCode::INTERNAL_SERVER_ERROR)
+                        .body(Body::empty())
+                        .unwrap();
+                }
             }
+        };
 
-            // Save part metadata
-            let part_meta_path = multipart_dir.join(format!("part-{}.meta", part_number));
-            let part_metadata = serde_json::json!({
-                "part_number": part_number,
-                "etag": etag,
-                "size": data.len(),
-            });
+        // Large parts are only kept on disk; completion reads them back from there
+        let size = data.len() as u64;
+        let keep_in_memory = data.len() <= state.settings.multipart_memory_threshold;
+        if !keep_in_memory && !written_to_disk {
+            return Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(Body::empty())
+                .unwrap();
+        }
 
-            if let Err(e) = fs::write(&part_meta_path, part_metadata.to_string()) {
-                warn!("Failed to write part metadata: {}", e);
-            } else {
-                info!("Uploaded part {} for upload {}, size: {} bytes", part_number, upload_id, data.len());
+        let mut uploads = state.multipart_uploads.lock().unwrap();
+        if let Some(upload) = uploads.get_mut(upload_id) {
+            // Re-uploading a part replaces it; the last upload wins, as in S3
+            if let Some(previous) = upload.parts.get(&part_number) {
+                debug!("Part {} of upload {} re-uploaded, replacing previous ETag {}", part_number, upload_id, previous.etag);
             }
 
+            upload.parts.insert(part_number, UploadPart {
+                part_number,
+                etag: etag.clone(),
+                size,
+                data: if keep_in_memory { data } else { Vec::new() },
+            });
+            info!("Uploaded part {} for upload {}, size: {} bytes", part_number, upload_id, size);
+
             return Response::builder()
                 .status(StatusCode::OK)
-                .header(header::ETAG, format!("\"{}\"", etag))
+                .header(header::ETAG, quoted_etag(&etag))
                 .body(Body::empty())
                 .unwrap();
         }
 
-        return Response::builder()
-            .status(StatusCode::NOT_FOUND)
-            .body(Body::empty())
-            .unwrap();
+        // Completed or aborted while the part was being written
+        let _ = fs::remove_file(&part_path);
+        let _ = fs::remove_file(&part_meta_path);
+        return no_such_upload_response(upload_id);
     }
 
     // Default: put object
@@ -504,16 +638,41 @@ pub async fn handle_object_post(
     State(state): State<AppState>,
     Path((bucket, key)): Path<(String, String)>,
     Query(params): Query<ObjectQueryParams>,
+    headers: HeaderMap,
     body: Bytes,
 ) -> impl IntoResponse {
     debug!("POST object: {}/{} with params: {:?}", bucket, key, params);
 
+    if is_reserved_key(&key) {
+        return reserved_key_response(&key);
+    }
+
     if params.uploads.is_some() {
+        if !state.settings.is_key_allowed(&key) {
+            return disallowed_key_response(&key);
+        }
+
         // Initiate multipart upload
         let upload_id = Uuid::new_v4().to_string();
 
-        // Default Content-Type for multipart upload
-        let content_type = "application/octet-stream".to_string();
+        // Content type and user metadata are given when the upload starts and applied when it completes,
+        // chosen the same way as for a single PUT
+        let content_type = headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .filter(|ct| !ct.trim().eq_ignore_ascii_case("application/octet-stream"))
+            .or_else(|| state.settings.content_type_override(&key))
+            .unwrap_or("application/octet-stream")
+            .to_string();
+        let custom_metadata = extract_custom_metadata(&headers);
+        let storage_class = match upload_storage_class(&state, &bucket, &headers) {
+            Ok(storage_class) => storage_class,
+            Err(response) => return *response,
+        };
+        let acl = match canned_acl(&headers) {
+            Ok(acl) => upload_acl(&state, &bucket, acl),
+            Err(response) => return *response,
+        };
 
         let initiated = Utc::now();
         let upload = MultipartUpload {
@@ -524,11 +683,23 @@ pub async fn handle_object_post(
             initiated,
         };
 
-        state.multipart_uploads.lock().unwrap().insert(upload_id.clone(), upload);
+        {
+            // Counted and inserted under the lock, so concurrent initiations cannot both take the last slot
+            let mut uploads = state.multipart_uploads.lock().unwrap();
+            if let Some(max_uploads) = state.settings.multipart_max_uploads_per_bucket {
+                if uploads_in_progress(&state.storage_path, &bucket, &uploads) >= max_uploads {
+                    return multipart_error_response(
+                        "TooManyUploads",
+                        &format!("The bucket already has {} multipart uploads in progress; complete or abort some first", max_uploads),
+                    );
+                }
+            }
+            uploads.insert(upload_id.clone(), upload);
+        }
 
         // Persist multipart upload metadata to disk
         let multipart_dir = state.storage_path.join(&bucket).join(".multipart");
-        if let Err(e) = fs::create_dir_all(&multipart_dir) {
+        if let Err(e) = create_storage_dir(&multipart_dir) {
             warn!("Failed to create multipart directory: {}", e);
         }
 
@@ -539,9 +710,12 @@ pub async fn handle_object_post(
             "key": key,
             "initiated": initiated.to_rfc3339(),
             "content_type": content_type,
+            "metadata": custom_metadata,
+            "storage_class": storage_class,
+            "acl": acl,
         });
 
-        if let Err(e) = fs::write(&upload_meta_path, upload_metadata.to_string()) {
+        if let Err(e) = write_storage_file(&upload_meta_path, upload_metadata.to_string()) {
             warn!("Failed to write multipart upload metadata: {}", e);
         } else {
             info!("Initiated multipart upload: {} for {}/{}", upload_id, bucket, key);
@@ -561,123 +735,193 @@ pub async fn handle_object_post(
             .unwrap();
     }
 
+    if params.restore.is_some() {
+        // Make an archived object readable again. The RestoreRequest body (Days, tier) is accepted but not used:
+        // the restored data stays readable until lifecycle archives the object again
+        let bucket_path = state.storage_path.join(&bucket);
+        let (_, metadata_path) = object_version_paths(&bucket_path, &key, params.version_id.as_deref());
+        let Some(mut metadata) = fs::read_to_string(&metadata_path)
+            .ok()
+            .and_then(|json| serde_json::from_str::<ObjectMetadata>(&json).ok())
+        else {
+            return Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .header(header::CONTENT_TYPE, "application/xml")
+                .body(Body::from(r#"<?xml version="1.0" encoding="UTF-8"?>
+<Error>
+    <Code>NoSuchKey</Code>
+    <Message>The specified key does not exist.</Message>
+</Error>"#))
+                .unwrap();
+        };
+
+        if !ARCHIVE_STORAGE_CLASSES.contains(&metadata.storage_class.as_str()) {
+            return Response::builder()
+                .status(StatusCode::FORBIDDEN)
+                .header(header::CONTENT_TYPE, "application/xml")
+                .body(Body::from(format!(r#"<?xml version="1.0" encoding="UTF-8"?>
+<Error>
+    <Code>InvalidObjectState</Code>
+    <Message>Restore is not allowed for the object's current storage class</Message>
+    <StorageClass>{}</StorageClass>
+</Error>"#, metadata.storage_class)))
+                .unwrap();
+        }
+
+        if !metadata.restore_required {
+            // Already restored
+            return Response::builder()
+                .status(StatusCode::OK)
+                .body(Body::empty())
+                .unwrap();
+        }
+
+        metadata.restore_required = false;
+        if let Err(e) = write_storage_file(&metadata_path, serde_json::to_string(&metadata).unwrap()) {
+            warn!("Failed to write metadata file: {}", e);
+            return Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(Body::from("Failed to restore object"))
+                .unwrap();
+        }
+
+        state.wal_writer.log_update_object_metadata(&bucket, &key);
+        info!("Restored {}/{} from {}", bucket, key, metadata.storage_class);
+        return Response::builder()
+            .status(StatusCode::ACCEPTED)
+            .body(Body::empty())
+            .unwrap();
+    }
+
     if let Some(upload_id) = &params.upload_id {
-        // Complete multipart upload
-        let mut uploads = state.multipart_uploads.lock().unwrap();
-        if let Some(upload) = uploads.remove(upload_id) {
-            // Read the stored content type from upload metadata
-            let multipart_dir = state.storage_path.join(&bucket).join(".multipart");
-            let upload_meta_path = multipart_dir.join(format!("{}.upload", upload_id));
-
-            let stored_content_type = if let Ok(metadata_str) = fs::read_to_string(&upload_meta_path) {
-                if let Ok(metadata_json) = serde_json::from_str::<serde_json::Value>(&metadata_str) {
-                    metadata_json.get("content_type")
-                        .and_then(|v| v.as_str())
-                        .unwrap_or("application/octet-stream")
-                        .to_string()
-                } else {
-                    "application/octet-stream".to_string()
+        // Complete multipart upload; take the upload out of the map so the lock isn't held across awaits
+        let upload = state.multipart_uploads.lock().unwrap().remove(upload_id);
+        if let Some(mut upload) = upload {
+            // The client lists the parts it expects, in order, with the ETags it was given
+            let requested_parts = match parse_complete_multipart_parts(&String::from_utf8_lossy(&body)) {
+                Some(parts) if !parts.is_empty() => parts,
+                _ => {
+                    state.multipart_uploads.lock().unwrap().insert(upload_id.clone(), upload);
+                    return multipart_error_response(
+                        "MalformedXML",
+                        "The XML you provided was not well-formed or did not validate against our published schema.",
+                    );
                 }
-            } else {
-                "application/octet-stream".to_string()
             };
 
-            // Combine all parts
-            let mut combined_data = Vec::new();
-            let mut parts: Vec<_> = upload.parts.into_iter().collect();
-            parts.sort_by_key(|(num, _)| *num);
-
-            for (_, part) in parts {
-                combined_data.extend(part.data);
+            // Part numbers are checked on upload, but the limit may have been lowered since
+            let max_parts = state.settings.multipart_max_parts;
+            if requested_parts.len() > max_parts as usize {
+                state.multipart_uploads.lock().unwrap().insert(upload_id.clone(), upload);
+                return multipart_error_response(
+                    "InvalidArgument",
+                    &format!("An upload can be completed from at most {} parts", max_parts),
+                );
             }
 
-            // Save the combined object
-            let etag = format!("{:x}", md5::compute(&combined_data));
-
-            // Create bucket directory if it doesn't exist
-            let bucket_path = state.storage_path.join(&bucket);
-            let _ = fs::create_dir_all(&bucket_path);
-
-            // Write object to disk
-            let object_path = bucket_path.join(&key);
-            if let Some(parent) = object_path.parent() {
-                let _ = fs::create_dir_all(parent);
+            if requested_parts.windows(2).any(|w| w[0].0 >= w[1].0) {
+                state.multipart_uploads.lock().unwrap().insert(upload_id.clone(), upload);
+                return multipart_error_response(
+                    "InvalidPartOrder",
+                    "The list of parts was not in ascending order. Parts must be ordered by part number.",
+                );
             }
 
-            if let Err(e) = fs::write(&object_path, &combined_data) {
-                warn!("Failed to write multipart object: {}", e);
-                return Response::builder()
-                    .status(StatusCode::INTERNAL_SERVER_ERROR)
-                    .body(Body::empty())
-                    .unwrap();
+            let invalid_part = requested_parts.iter().find(|(part_number, etag)| {
+                upload.parts.get(part_number).map(|p| p.etag != *etag).unwrap_or(true)
+            });
+            if let Some((part_number, _)) = invalid_part {
+                debug!("Part {} of upload {} is missing or its ETag does not match", part_number, upload_id);
+                state.multipart_uploads.lock().unwrap().insert(upload_id.clone(), upload);
+                return multipart_error_response(
+                    "InvalidPart",
+                    "One or more of the specified parts could not be found. The part may not have been uploaded, or the specified entity tag may not match the part's entity tag.",
+                );
             }
 
+            // Assemble the object off the async runtime; the upload is already out of the map, so no lock is held
+            let parts: Vec<UploadPart> = requested_parts.iter()
+                .map(|(part_number, _)| upload.parts.remove(part_number).unwrap())
+                .collect();
+            let bucket_path = state.storage_path.join(&bucket);
+            let (object_key, blocking_upload_id) = (key.clone(), upload_id.clone());
+            let assembled = tokio::task::spawn_blocking(move || {
+                assemble_multipart_object(&bucket_path, &object_key, &blocking_upload_id, parts)
+            }).await;
+            let (etag, size) = match assembled {
+                Ok(Ok(assembled)) => assembled,
+                Ok(Err(e)) => {
+                    warn!("Failed to complete multipart upload {} for {}/{}: {}", upload_id, bucket, key, e);
+                    return Response::builder()
+                        .status(StatusCode::INTERNAL_SERVER_ERROR)
+                        .body(Body::empty())
+                        .unwrap();
+                }
+                Err(e) => {
+                    error!("Multipart completion task for upload {} failed: {}", upload_id, e);
+                    return Response::builder()
+                        .status(StatusCode::INTERNAL_SERVER_ERROR)
+                        .body(Body::empty())
+                        .unwrap();
+                }
+            };
+            info!("Multipart upload completed: {}/{}, size: {} bytes", bucket, key, size);
+
             // Log to WAL for replication
-            state.wal_writer.log_put(&bucket, &key, combined_data.len() as u64, Some(etag.clone()));
+            state.wal_writer.log_put(&bucket, &key, size, Some(etag.clone()));
+            state.list_index.record_write(&bucket, &key);
+            create_folder_markers(&state, &bucket, &key);
+
+            // Remember the completion so a client retrying after a lost response is answered the same way
+            {
+                let mut completed_uploads = state.completed_uploads.lock().unwrap();
+                completed_uploads.retain(|_, completed| completed.completed.elapsed() < COMPLETED_UPLOAD_RETENTION);
+                completed_uploads.insert(upload_id.clone(), CompletedUpload {
+                    bucket: bucket.clone(),
+                    key: key.clone(),
+                    etag: etag.clone(),
+                    completed: Instant::now(),
+                });
+            }
 
             // Update quota and stats after successful multipart upload
-            if let Err(e) = state.quota_manager.update_quota_add(&bucket, combined_data.len() as u64).await {
+            if let Err(e) = state.quota_manager.update_quota_add(&bucket, &key, size).await {
                 warn!("Failed to update quota for bucket {} after multipart upload: {}", bucket, e);
             }
             if let Err(e) = state.quota_manager.increment_stat(&bucket, Operation::Multipart).await {
                 warn!("Failed to update multipart stats for bucket {}: {}", bucket, e);
             }
 
-            // Save object metadata
-            let metadata_path = state.storage_path.join(&bucket).join(format!("{}.metadata", key));
-            let metadata = ObjectMetadata {
-                key: key.clone(),
-                size: combined_data.len() as u64,
-                etag: etag.clone(),
-                last_modified: Utc::now(),
-                content_type: stored_content_type, // Use the content type from initiation
-                storage_class: "STANDARD".to_string(),
-                metadata: HashMap::new(),
-                version_id: None,
-                encryption: None, // TODO: Add encryption support for multipart
-                tags: None,
-            };
-
-            if let Ok(metadata_json) = serde_json::to_string(&metadata) {
-                if let Err(e) = fs::write(&metadata_path, metadata_json) {
-                    warn!("Failed to write multipart object metadata: {}", e);
-                } else {
-                    info!("Multipart upload completed: {}/{}, size: {} bytes", bucket, key, combined_data.len());
-                }
-            }
-
-            // Clean up multipart upload directory
-            let multipart_dir = state.storage_path.join(&bucket).join(".multipart").join(upload_id);
-            if let Err(e) = fs::remove_dir_all(&multipart_dir) {
-                warn!("Failed to clean up multipart directory: {}", e);
-            }
-
             // Note: In-memory metadata tracking removed - using filesystem-only approach
 
-            let xml = format!(r#"<?xml version="1.0" encoding="UTF-8"?>
-<CompleteMultipartUploadResult>
-    <Location>http://s3.amazonaws.com/{}/{}</Location>
-    <Bucket>{}</Bucket>
-    <Key>{}</Key>
-    <ETag>"{}"</ETag>
-</CompleteMultipartUploadResult>"#, bucket, key, bucket, key, etag);
+            return complete_multipart_response(&bucket, &key, &etag);
+        }
 
-            return Response::builder()
-                .status(StatusCode::OK)
-                .header(header::CONTENT_TYPE, "application/xml")
-                .body(Body::from(xml))
-                .unwrap();
+        // A retry of a completion that already succeeded, as long as the object it produced was not replaced since
+        let completed = state.completed_uploads.lock().unwrap()
+            .get(upload_id)
+            .filter(|completed| completed.completed.elapsed() < COMPLETED_UPLOAD_RETENTION)
+            .filter(|completed| completed.bucket == bucket && completed.key == key)
+            .cloned();
+        if let Some(completed) = completed {
+            let metadata_path = state.storage_path.join(&bucket).join(format!("{}.metadata", key));
+            let current_etag = fs::read_to_string(&metadata_path)
+                .ok()
+                .and_then(|json| serde_json::from_str::<ObjectMetadata>(&json).ok())
+                .map(|metadata| metadata.etag);
+            if current_etag.as_deref() == Some(completed.etag.as_str()) {
+                info!("Multipart upload {} for {}/{} was already completed, answering the retry", upload_id, bucket, key);
+                return complete_multipart_response(&bucket, &key, &completed.etag);
+            }
         }
 
-        return Response::builder()
-            .status(StatusCode::NOT_FOUND)
-            .body(Body::empty())
-            .unwrap();
+        return no_such_upload_response(upload_id);
     }
 
-    // Default: method not allowed
+    // POST on an object is only for multipart uploads (?uploads or ?uploadId) and restores
     Response::builder()
         .status(StatusCode::METHOD_NOT_ALLOWED)
+        .header(header::ALLOW, "GET, HEAD, PUT, DELETE")
         .body(Body::empty())
         .unwrap()
 }
@@ -691,9 +935,13 @@ pub async fn handle_object_delete(
     info!("DELETE object: {}/{} with params: {:?}", bucket, key, params);
     info!("version_id specifically: {:?}", params.version_id);
 
+    if is_reserved_key(&key) {
+        return reserved_key_response(&key);
+    }
+
     if params.tagging.is_some() {
         // Delete object tags from metadata
-        let metadata_path = state.storage_path.join(&bucket).join(format!("{}.metadata", key));
+        let metadata_path = state.storage_path.join(&bucket).join(format!("{}.metadata", object_storage_key(&key)));
 
         if metadata_path.exists() {
             // Read existing metadata
@@ -705,7 +953,7 @@ pub async fn handle_object_delete(
                             metadata.tags = None;
 
                             // Write updated metadata
-                            if let Err(e) = fs::write(&metadata_path, serde_json::to_string(&metadata).unwrap()) {
+                            if let Err(e) = write_storage_file(&metadata_path, serde_json::to_string(&metadata).unwrap()) {
                                 warn!("Failed to write metadata file: {}", e);
                                 return Response::builder()
                                     .status(StatusCode::INTERNAL_SERVER_ERROR)
@@ -713,6 +961,8 @@ pub async fn handle_object_delete(
                                     .unwrap();
                             }
 
+                            sync_version_tags(&state.storage_path.join(&bucket), &key, &metadata);
+                            state.wal_writer.log_update_object_metadata(&bucket, &key);
                             info!("Tags deleted for object: {}/{}", bucket, key);
                         }
                         Err(e) => {
@@ -741,9 +991,9 @@ pub async fn handle_object_delete(
     }
 
     if let Some(upload_id) = &params.upload_id {
-        // Abort multipart upload
-        let mut uploads = state.multipart_uploads.lock().unwrap();
-        if let Some(upload) = uploads.remove(upload_id) {
+        // Abort multipart upload; the lock is released before the parts are removed from disk
+        let upload = state.multipart_uploads.lock().unwrap().remove(upload_id);
+        if let Some(upload) = upload {
             // Clean up multipart upload directory and parts
             let multipart_dir = state.storage_path.join(&upload.bucket).join(".multipart").join(upload_id);
             if let Err(e) = fs::remove_dir_all(&multipart_dir) {
@@ -759,49 +1009,112 @@ pub async fn handle_object_delete(
             info!("Aborted multipart upload: {}", upload_id);
             return StatusCode::NO_CONTENT.into_response();
         }
-        return StatusCode::NOT_FOUND.into_response();
+        return no_such_upload_response(upload_id);
     }
 
+    let bucket_path = state.storage_path.join(&bucket);
+
     // Check if deleting a specific version
     if let Some(version_id) = &params.version_id {
         info!("Attempting to delete version {} of object {}/{}", version_id, bucket, key);
-        if version_id != "null" {
-            // Delete the specific version file
-            let version_path = state.storage_path.join(&bucket).join(".versions").join(&key).join(version_id);
-            let version_metadata_path = state.storage_path.join(&bucket).join(".versions").join(&key).join(format!("{}.metadata", version_id));
-
-            info!("Version path: {:?}, exists: {}", version_path, version_path.exists());
-            info!("Version metadata path: {:?}, exists: {}", version_metadata_path, version_metadata_path.exists());
-
-            if version_path.exists() {
-                // Delete version file
-                if let Err(e) = fs::remove_file(&version_path) {
-                    warn!("Failed to delete version file: {}", e);
-                    return Response::builder()
-                        .status(StatusCode::INTERNAL_SERVER_ERROR)
-                        .body(Body::from("Failed to delete version"))
-                        .unwrap();
-                }
 
-                // Delete version metadata file if it exists
-                if version_metadata_path.exists() {
-                    if let Err(e) = fs::remove_file(&version_metadata_path) {
-                        warn!("Failed to delete version metadata: {}", e);
-                    }
-                }
+        // Removing a delete marker makes the version beneath it visible again
+        let marker_path = delete_marker_path(&bucket_path, &key, version_id);
+        if marker_path.is_file() {
+            if let Err(e) = fs::remove_file(&marker_path) {
+                warn!("Failed to delete marker {} of {}/{}: {}", version_id, bucket, key, e);
+                return Response::builder()
+                    .status(StatusCode::INTERNAL_SERVER_ERROR)
+                    .body(Body::from("Failed to delete version"))
+                    .unwrap();
+            }
+            restore_latest_version(&state, &bucket, &key).await;
+            state.list_index.record_write(&bucket, &key);
+            info!("Deleted delete marker {} of object {}/{}", version_id, bucket, key);
+            return Response::builder()
+                .status(StatusCode::NO_CONTENT)
+                .header("x-amz-delete-marker", "true")
+                .header("x-amz-version-id", version_id)
+                .body(Body::empty())
+                .unwrap();
+        }
 
-                info!("Deleted version {} of object {}/{}", version_id, bucket, key);
-                return StatusCode::NO_CONTENT.into_response();
-            } else {
-                // Version not found
+        // A "null" version that was never moved into .versions is the current object, but only while the current
+        // object carries no version id: a versioned write that replaced it would have preserved it
+        if version_id == "null" && !bucket_path.join(".versions").join(&key).join("null").exists() {
+            if current_version_id(&bucket_path, &key).is_some_and(|vid| vid != "null") {
                 return Response::builder()
                     .status(StatusCode::NOT_FOUND)
                     .body(Body::from("Version not found"))
                     .unwrap();
             }
+            let response = delete_object(State(state.clone()), Path((bucket.clone(), key.clone()))).await.into_response();
+            // Older versions stay, and the newest of them becomes current as after deleting any current version
+            restore_latest_version(&state, &bucket, &key).await;
+            state.list_index.record_write(&bucket, &key);
+            info!("Deleted null version of object {}/{}", bucket, key);
+            return response;
+        }
+
+        // Delete the specific version file
+        let version_path = state.storage_path.join(&bucket).join(".versions").join(&key).join(version_id);
+        let version_metadata_path = state.storage_path.join(&bucket).join(".versions").join(&key).join(format!("{}.metadata", version_id));
+
+        info!("Version path: {:?}, exists: {}", version_path, version_path.exists());
+        info!("Version metadata path: {:?}, exists: {}", version_metadata_path, version_metadata_path.exists());
+
+        if version_path.exists() {
+            // Delete version file
+            if let Err(e) = fs::remove_file(&version_path) {
+                warn!("Failed to delete version file: {}", e);
+                return Response::builder()
+                    .status(StatusCode::INTERNAL_SERVER_ERROR)
+                    .body(Body::from("Failed to delete version"))
+                    .unwrap();
+            }
+
+            // Delete version metadata file if it exists
+            if version_metadata_path.exists() {
+                if let Err(e) = fs::remove_file(&version_metadata_path) {
+                    warn!("Failed to delete version metadata: {}", e);
+                }
+            }
+
+            // Deleting the current version removes the current copy and promotes the next newest version
+            let current_metadata_path = bucket_path.join(format!("{}.metadata", key));
+            if current_version_id(&bucket_path, &key).as_deref() == Some(version_id.as_str()) {
+                let object_path = bucket_path.join(&key);
+                let object_size = fs::metadata(&object_path).map(|m| m.len()).unwrap_or(0);
+                if fs::remove_file(&object_path).is_ok() {
+                    let _ = fs::remove_file(&current_metadata_path);
+                    state.wal_writer.log_delete(&bucket, &key);
+                    if let Err(e) = state.quota_manager.update_quota_remove(&bucket, &key, object_size).await {
+                        warn!("Failed to update quota for bucket {} after deletion: {}", bucket, e);
+                    }
+                }
+                restore_latest_version(&state, &bucket, &key).await;
+                state.list_index.record_write(&bucket, &key);
+            }
+
+            info!("Deleted version {} of object {}/{}", version_id, bucket, key);
+            return StatusCode::NO_CONTENT.into_response();
+        } else {
+            // Version not found
+            return Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(Body::from("Version not found"))
+                .unwrap();
         }
     }
 
+    // With versioning enabled a plain DELETE only hides the object behind a delete marker
+    let versioning_enabled = read_bucket_versioning(&state.storage_path, &bucket)
+        .map(|status| status == "Enabled")
+        .unwrap_or(false);
+    if versioning_enabled && !key.ends_with('/') && !bucket_path.join(&key).is_dir() {
+        return create_delete_marker(&state, &bucket, &key).await;
+    }
+
     // Default: delete object
     delete_object(State(state), Path((bucket, key))).await.into_response()
 }
@@ -814,6 +1127,19 @@ pub async fn put_object(
 ) -> impl IntoResponse {
     info!("Uploading object: {}/{}", bucket, key);
 
+    let website_redirect_location = match website_redirect_location(&headers) {
+        Ok(location) => location,
+        Err(response) => return *response,
+    };
+    let acl = match canned_acl(&headers) {
+        Ok(acl) => upload_acl(&state, &bucket, acl),
+        Err(response) => return *response,
+    };
+    let storage_class = match upload_storage_class(&state, &bucket, &headers) {
+        Ok(storage_class) => storage_class,
+        Err(response) => return *response,
+    };
+
     // Check if this is a copy operation
     if let Some(copy_source) = headers.get("x-amz-copy-source") {
         let copy_source_str = copy_source.to_str().unwrap_or("");
@@ -852,426 +1178,444 @@ pub async fn put_object(
             .unwrap_or_else(|_| std::borrow::Cow::Borrowed(source_key))
             .into_owned();
 
+        if is_reserved_key(&decoded_source_key) {
+            return reserved_key_response(&decoded_source_key);
+        }
+
         info!("Copying from bucket: {} key: {} version: {:?} to bucket: {} key: {}",
               source_bucket, decoded_source_key, version_id, bucket, key);
 
-        // Read the source object (with version support)
-        let source_path = if let Some(ref vid) = version_id {
-            if vid != "null" {
-                state.storage_path.join(source_bucket).join(".versions").join(&decoded_source_key).join(vid)
-            } else {
-                state.storage_path.join(source_bucket).join(&decoded_source_key)
+        // Read the source object (with version support); delete markers have no data to copy
+        let source_bucket_path = state.storage_path.join(source_bucket);
+        match version_id.as_deref() {
+            Some(vid) if read_delete_marker(&source_bucket_path, &decoded_source_key, vid).is_some() => {
+                return copy_source_error(StatusCode::BAD_REQUEST, "InvalidRequest",
+                    "The source of a copy request may not specifically refer to a delete marker by version id.",
+                    &decoded_source_key, Some(vid));
             }
-        } else {
-            state.storage_path.join(source_bucket).join(&decoded_source_key)
-        };
-
-        let source_metadata_path = if let Some(ref vid) = version_id {
-            if vid != "null" {
-                state.storage_path.join(source_bucket).join(".versions").join(&decoded_source_key).join(format!("{}.metadata", vid))
-            } else {
-                state.storage_path.join(source_bucket).join(format!("{}.metadata", &decoded_source_key))
+            None if latest_delete_marker(&source_bucket_path, &decoded_source_key).is_some() => {
+                return copy_source_error(StatusCode::NOT_FOUND, "NoSuchKey",
+                    "The specified key does not exist.", &decoded_source_key, None);
             }
-        } else {
-            state.storage_path.join(source_bucket).join(format!("{}.metadata", &decoded_source_key))
+            _ => {}
+        }
+        let (source_path, source_metadata_path) =
+            object_version_paths(&source_bucket_path, &decoded_source_key, version_id.as_deref());
+
+        // Read the source object and its sidecar on a blocking thread
+        let source = {
+            let source_path = source_path.clone();
+            tokio::task::spawn_blocking(move || {
+                let source_metadata = fs::read_to_string(&source_metadata_path)
+                    .ok()
+                    .and_then(|json| serde_json::from_str::<ObjectMetadata>(&json).ok());
+                fs::read(&source_path).map(|source_data| (source_data, source_metadata))
+            }).await
         };
-
-        match fs::read(&source_path) {
-            Ok(source_data) => {
-                // Use the source data for the copy
-                let data = source_data;
-                let etag = format!("{:x}", md5::compute(&data));
-
-                // Continue with normal put operation using the copied data
-                let bucket_path = state.storage_path.join(&bucket);
-                if let Err(e) = fs::create_dir_all(&bucket_path) {
-                    warn!("Failed to create bucket directory: {}", e);
-                }
-
-                let object_path = bucket_path.join(&key);
-                let dest_metadata_path = bucket_path.join(format!("{}.metadata", &key));
-
-                // Create parent directory if needed
-                if let Some(parent) = object_path.parent() {
-                    if let Err(e) = fs::create_dir_all(parent) {
-                        warn!("Failed to create object parent directory: {}", e);
+        let (source_data, source_metadata) = match source {
+            Ok(Ok(source)) => source,
+            Ok(Err(e)) => {
+                warn!("Failed to read source object {}/{}: {}", source_bucket, decoded_source_key, e);
+                return match version_id.as_deref() {
+                    Some(vid) if vid != "null" || source_bucket_path.join(&decoded_source_key).exists() => {
+                        copy_source_error(StatusCode::NOT_FOUND, "NoSuchVersion",
+                            "The specified version does not exist.", &decoded_source_key, Some(vid))
+                    }
+                    _ => copy_source_error(StatusCode::NOT_FOUND, "NoSuchKey",
+                        "The specified key does not exist.", &decoded_source_key, None),
+                };
+            }
+            Err(e) => {
+                error!("Failed to read source object {}/{}: {}", source_bucket, decoded_source_key, e);
+                return Response::builder()
+                    .status(StatusCode::INTERNAL_SERVER_ERROR)
+                    .body(Body::from("Failed to copy object"))
+                    .unwrap();
+            }
+        };
+        let source_version_id = source_metadata.as_ref()
+            .and_then(|m| m.version_id.clone())
+            .or_else(|| version_id.clone())
+            .unwrap_or_else(|| "null".to_string());
+
+        // Decrypt with the source's key; the destination bucket decides how the copy is stored
+        let data = match source_metadata.as_ref().and_then(|m| m.encryption.as_ref()) {
+            Some(encryption) if encryption.algorithm == "AES256" => {
+                let source_key = if encryption.customer_key_md5.is_some() {
+                    match require_customer_key(&headers, COPY_SOURCE_SSE_CUSTOMER_HEADERS, encryption) {
+                        Ok(customer_key) => customer_key.key,
+                        Err(response) => return *response,
+                    }
+                } else {
+                    BASE64.decode(&encryption.key_base64).unwrap_or_default()
+                };
+                match decrypt_object(&source_data, &source_key, encryption) {
+                    Ok(decrypted) => decrypted,
+                    Err(e) => {
+                        warn!("Failed to decrypt copy source {}/{}: {}", source_bucket, decoded_source_key, e);
+                        return Response::builder()
+                            .status(StatusCode::INTERNAL_SERVER_ERROR)
+                            .body(Body::from("Failed to decrypt copy source"))
+                            .unwrap();
                     }
                 }
+            }
+            _ => source_data,
+        };
+        let etag = format!("{:x}", md5::compute(&data));
+        let (stored_data, object_encryption) = encrypt_for_bucket(&state, &bucket, &data);
 
-                // Write the copied data
-                if let Err(e) = fs::write(&object_path, &data) {
-                    warn!("Failed to write copied object: {}", e);
-                    return Response::builder()
-                        .status(StatusCode::INTERNAL_SERVER_ERROR)
-                        .body(Body::from("Failed to copy object"))
-                        .unwrap();
-                }
-
-                // Log to WAL for replication
-                let copy_etag = format!("{:x}", md5::compute(&data));
-                state.wal_writer.log_put(&bucket, &key, data.len() as u64, Some(copy_etag.clone()));
+        // Continue with normal put operation using the copied data
+        let bucket_path = state.storage_path.join(&bucket);
+        if let Err(e) = create_storage_dir(&bucket_path) {
+            warn!("Failed to create bucket directory: {}", e);
+        }
 
-                // Check for metadata directive
-                let metadata_directive = headers
-                    .get("x-amz-metadata-directive")
-                    .and_then(|v| v.to_str().ok())
-                    .unwrap_or("COPY");
-
-                // Extract custom metadata from headers
-                let mut custom_metadata = HashMap::new();
-                for (name, value) in &headers {
-                    let key_str = name.as_str();
-                    if key_str.starts_with("x-amz-meta-") {
-                        if let Ok(value_str) = value.to_str() {
-                            let meta_key = key_str.strip_prefix("x-amz-meta-").unwrap();
-                            custom_metadata.insert(meta_key.to_string(), value_str.to_string());
-                            debug!("Found custom metadata: {} = {}", meta_key, value_str);
-                        }
-                    }
-                }
+        let object_path = bucket_path.join(object_storage_key(&key));
+        let dest_metadata_path = bucket_path.join(format!("{}.metadata", object_storage_key(&key)));
 
-                // Copy metadata file if it exists, or create new metadata
-                let content_type = if source_metadata_path.exists() {
-                    // Read and copy the metadata, updating the key
-                    match fs::read_to_string(&source_metadata_path) {
-                        Ok(metadata_str) => {
-                            if let Ok(mut metadata) = serde_json::from_str::<ObjectMetadata>(&metadata_str) {
-                                // Update the metadata for the new location
-                                metadata.key = key.clone();
-                                metadata.last_modified = Utc::now();
-                                metadata.etag = etag.clone();
-
-                                // Handle metadata directive
-                                if metadata_directive == "REPLACE" {
-                                    // Replace all custom metadata with new ones
-                                    metadata.metadata = custom_metadata.clone();
-                                    info!("REPLACE directive: replacing metadata with {:?}", custom_metadata);
-                                } else {
-                                    // COPY directive: merge new metadata with existing
-                                    for (k, v) in custom_metadata.iter() {
-                                        metadata.metadata.insert(k.clone(), v.clone());
-                                    }
-                                }
+        // Check for metadata directive
+        let metadata_directive = headers
+            .get("x-amz-metadata-directive")
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or("COPY");
 
-                                // Update content-type if provided
-                                if let Some(content_type_header) = headers.get(header::CONTENT_TYPE) {
-                                    if let Ok(ct) = content_type_header.to_str() {
-                                        metadata.content_type = ct.to_string();
-                                    }
-                                }
+        // Extract custom metadata from headers
+        let custom_metadata = extract_custom_metadata(&headers);
+        debug!("Found custom metadata: {:?}", custom_metadata);
 
-                                let ct = metadata.content_type.clone();
+        // Tags are copied from the source unless x-amz-tagging-directive: REPLACE takes them from x-amz-tagging
+        let source_tags = source_metadata.as_ref().and_then(|m| m.tags.clone());
+        let tags = match headers.get("x-amz-tagging-directive").and_then(|v| v.to_str().ok()) {
+            Some("REPLACE") => parse_tagging_header(&headers),
+            _ => source_tags,
+        };
 
-                                // Save the updated metadata
-                                if let Ok(metadata_json) = serde_json::to_string(&metadata) {
-                                    if let Err(e) = fs::write(&dest_metadata_path, metadata_json) {
-                                        warn!("Failed to write copied metadata: {}", e);
-                                    } else {
-                                        debug!("Metadata copied to: {:?}", dest_metadata_path);
-                                    }
-                                }
-                                ct
-                            } else {
-                                "application/octet-stream".to_string()
-                            }
-                        }
-                        Err(e) => {
-                            warn!("Failed to read source metadata: {}", e);
-                            "application/octet-stream".to_string()
-                        }
-                    }
+        // Copy the source metadata, updating the key, or create new metadata if the source has none
+        let metadata = match source_metadata {
+            Some(mut metadata) => {
+                // Update the metadata for the new location
+                // A copy is a new object, created now
+                metadata.key = key.clone();
+                metadata.last_modified = Utc::now();
+                metadata.created = Some(metadata.last_modified);
+                metadata.etag = etag.clone();
+                metadata.size = data.len() as u64;
+                metadata.encryption = object_encryption.clone();
+                // As in S3 the redirect is not copied, only set from this request
+                metadata.website_redirect_location = website_redirect_location;
+                metadata.acl = acl;
+                metadata.tags = tags;
+                metadata.storage_class = storage_class;
+                metadata.restore_required = false;
+
+                let request_content_type = headers.get(header::CONTENT_TYPE)
+                    .and_then(|v| v.to_str().ok())
+                    .map(|ct| ct.to_string());
+
+                // Handle metadata directive
+                if metadata_directive == "REPLACE" {
+                    // Replace all custom metadata and the content type; nothing is inherited from the source
+                    metadata.metadata = custom_metadata.clone();
+                    metadata.content_type = request_content_type.unwrap_or_else(|| "binary/octet-stream".to_string());
+                    info!("REPLACE directive: replacing metadata with {:?}", custom_metadata);
                 } else {
-                    // No metadata file exists, create basic metadata
-                    let content_type_header = headers.get(header::CONTENT_TYPE)
-                        .and_then(|v| v.to_str().ok())
-                        .unwrap_or("application/octet-stream")
-                        .to_string();
-
-                    let metadata = ObjectMetadata {
-                        key: key.clone(),
-                        size: data.len() as u64,
-                        etag: etag.clone(),
-                        last_modified: Utc::now(),
-                        content_type: content_type_header.clone(),
-                        storage_class: "STANDARD".to_string(),
-                        metadata: custom_metadata, // Use the extracted custom metadata
-                        version_id: None,
-                        encryption: None,
-                        tags: None,
-                    };
-
-                    if let Ok(metadata_json) = serde_json::to_string(&metadata) {
-                        if let Err(e) = fs::write(&dest_metadata_path, metadata_json) {
-                            warn!("Failed to write metadata: {}", e);
-                        }
+                    // COPY directive: merge new metadata with existing
+                    for (k, v) in custom_metadata.iter() {
+                        metadata.metadata.insert(k.clone(), v.clone());
                     }
-                    content_type_header
-                };
-
-                info!("Successfully copied object from {}/{} to {}/{} with content-type: {}",
-                      source_bucket, decoded_source_key, bucket, key, content_type);
 
-                // Update quota and stats after successful copy
-                if let Err(e) = state.quota_manager.update_quota_add(&bucket, data.len() as u64).await {
-                    warn!("Failed to update quota for bucket {} after copy: {}", bucket, e);
+                    // Update content-type if provided
+                    if let Some(ct) = request_content_type {
+                        metadata.content_type = ct;
+                    }
                 }
-                if let Err(e) = state.quota_manager.increment_stat(&bucket, Operation::Put).await {
-                    warn!("Failed to update PUT stats for bucket {} after copy: {}", bucket, e);
+                metadata
+            }
+            None => {
+                // No metadata file exists, create basic metadata
+                let content_type_header = headers.get(header::CONTENT_TYPE)
+                    .and_then(|v| v.to_str().ok())
+                    .unwrap_or("application/octet-stream")
+                    .to_string();
+
+                let now = Utc::now();
+                ObjectMetadata {
+                    key: key.clone(),
+                    size: data.len() as u64,
+                    etag: etag.clone(),
+                    last_modified: now,
+                    created: Some(now),
+                    content_type: content_type_header,
+                    storage_class,
+                    metadata: custom_metadata, // Use the extracted custom metadata
+                    version_id: None,
+                    encryption: object_encryption.clone(),
+                    tags,
+                    restore_required: false,
+                    website_redirect_location,
+                    acl,
                 }
-
-                // Return success response with ETag
+            }
+        };
+        let content_type = metadata.content_type.clone();
+
+        // Write the copied data and its metadata on a blocking thread, as for a plain PUT
+        let written = {
+            let object_path = object_path.clone();
+            tokio::task::spawn_blocking(move || {
+                write_object_files(&object_path, &stored_data, &dest_metadata_path, &metadata)
+            }).await
+        };
+        match written {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => {
+                warn!("Failed to write copied object: {}", e);
                 return Response::builder()
-                    .status(StatusCode::OK)
-                    .header(header::ETAG, format!("\"{}\"", etag))
-                    .header("x-amz-copy-source-version-id", "null")
-                    .body(Body::from(format!(
-                        r#"<?xml version="1.0" encoding="UTF-8"?>
-<CopyObjectResult>
-    <LastModified>{}</LastModified>
-    <ETag>"{}"</ETag>
-</CopyObjectResult>"#,
-                        Utc::now().to_rfc3339(),
-                        etag
-                    )))
+                    .status(StatusCode::INTERNAL_SERVER_ERROR)
+                    .body(Body::from("Failed to copy object"))
                     .unwrap();
             }
             Err(e) => {
-                warn!("Failed to read source object {}/{}: {}", source_bucket, decoded_source_key, e);
+                error!("Failed to write copied object {}/{}: {}", bucket, key, e);
                 return Response::builder()
-                    .status(StatusCode::NOT_FOUND)
-                    .body(Body::from("NoSuchKey: The specified key does not exist"))
+                    .status(StatusCode::INTERNAL_SERVER_ERROR)
+                    .body(Body::from("Failed to copy object"))
                     .unwrap();
             }
         }
+
+        // Log to WAL for replication
+        state.wal_writer.log_put(&bucket, &key, data.len() as u64, Some(etag.clone()));
+        state.list_index.record_write(&bucket, &key);
+        create_folder_markers(&state, &bucket, &key);
+
+        info!("Successfully copied object from {}/{} to {}/{} with content-type: {}",
+              source_bucket, decoded_source_key, bucket, key, content_type);
+
+        // Update quota and stats after successful copy
+        if let Err(e) = state.quota_manager.update_quota_add(&bucket, &key, data.len() as u64).await {
+            warn!("Failed to update quota for bucket {} after copy: {}", bucket, e);
+        }
+        if let Err(e) = state.quota_manager.increment_stat(&bucket, Operation::Put).await {
+            warn!("Failed to update PUT stats for bucket {} after copy: {}", bucket, e);
+        }
+
+        // Return success response with ETag
+        let mut response = Response::builder()
+            .status(StatusCode::OK)
+            .header(header::ETAG, quoted_etag(&etag))
+            .header("x-amz-copy-source-version-id", source_version_id);
+        if let Some(encryption) = &object_encryption {
+            response = response.header("x-amz-server-side-encryption", encryption.algorithm.clone());
+        }
+        return response
+            .body(Body::from(format!(
+                r#"<?xml version="1.0" encoding="UTF-8"?>
+<CopyObjectResult>
+    <LastModified>{}</LastModified>
+    <ETag>{}</ETag>
+</CopyObjectResult>"#,
+                Utc::now().to_rfc3339(),
+                quoted_etag(&etag)
+            )))
+            .unwrap();
     }
 
-    // Check if this is chunked transfer encoding with signature
-    let mut data = body.to_vec();
+    // SSE-C: the object is encrypted with the key the client sends, and only its MD5 is kept
+    let customer_key = match parse_customer_key(&headers, SSE_CUSTOMER_HEADERS) {
+        Ok(customer_key) => customer_key,
+        Err(response) => return *response,
+    };
+
+    // Decode aws-chunked bodies and validate any checksum sent in the trailer
+    let mut data = match decode_request_body(&headers, &body) {
+        Ok(data) => data,
+        Err(response) => return *response,
+    };
 
-    // Check if the data starts with chunk size (hex) followed by ";chunk-signature="
-    // Format: "3e8;chunk-signature=<64-char-hex>\r\n<data>\r\n0;chunk-signature=<64-char-hex>\r\n\r\n"
-    if data.len() > 100 {
-        let preview = String::from_utf8_lossy(&data[0..100]);
-        if preview.contains(";chunk-signature=") {
-            debug!("Detected chunked transfer encoding with signature, parsing chunks");
-            data = parse_chunked_data(&data);
+    // Resumable upload extension: stage the range and only store the object once every byte has arrived
+    if let Some(content_range) = headers.get(header::CONTENT_RANGE).filter(|_| state.settings.partial_uploads) {
+        let bucket_path = state.storage_path.join(&bucket);
+        let (staging_key, content_range) = (key.clone(), content_range.to_str().unwrap_or("").to_string());
+        let range_data = std::mem::take(&mut data);
+        let staged = tokio::task::spawn_blocking(move || {
+            stage_partial_upload(&bucket_path, &staging_key, &content_range, &range_data)
+        }).await;
+        match staged {
+            Ok(Ok(PartialUploadProgress::Complete(full))) => {
+                info!("Resumable upload of {}/{} complete ({} bytes)", bucket, key, full.len());
+                data = full;
+            }
+            Ok(Ok(PartialUploadProgress::Incomplete(received))) => {
+                let ranges: Vec<String> = received.iter().map(|(start, end)| format!("{}-{}", start, end)).collect();
+                return Response::builder()
+                    .status(StatusCode::ACCEPTED)
+                    .header(header::RANGE, format!("bytes={}", ranges.join(",")))
+                    .body(Body::empty())
+                    .unwrap();
+            }
+            Ok(Err(response)) => return *response,
+            Err(e) => {
+                error!("Failed to stage resumable upload of {}/{}: {}", bucket, key, e);
+                return Response::builder()
+                    .status(StatusCode::INTERNAL_SERVER_ERROR)
+                    .body(Body::from("Failed to store object"))
+                    .unwrap();
+            }
         }
     }
     let etag = format!("{:x}", md5::compute(&data));
 
     // Create bucket directory if it doesn't exist
     let bucket_path = state.storage_path.join(&bucket);
-    if let Err(e) = fs::create_dir_all(&bucket_path) {
+    if let Err(e) = create_storage_dir(&bucket_path) {
         warn!("Failed to create bucket directory: {}", e);
     }
 
-    // Write object to disk
-    let object_path = bucket_path.join(&key);
-
-    // Handle folder creation (keys ending with / or empty)
-    if key.ends_with('/') || key.is_empty() {
-        // This is a folder creation request
-        // Special case: if the key is empty or just "/" it refers to the bucket itself
-        // which already exists after bucket creation, so just return success
-        if key == "/" || key.is_empty() {
-            info!("Bucket root folder already exists: {}", bucket);
-            return Response::builder()
-                .status(StatusCode::OK)
-                .header(header::ETAG, format!("\"{}\"", etag))
-                .body(Body::empty())
-                .unwrap();
-        }
-
-        if let Err(e) = fs::create_dir_all(&object_path) {
-            warn!("Failed to create folder: {}", e);
-            return Response::builder()
-                .status(StatusCode::INTERNAL_SERVER_ERROR)
-                .body(Body::from("Failed to create folder"))
-                .unwrap();
-        }
-
-        info!("Created folder: {}/{}", bucket, key);
-
-        // Return success for folder creation
+    // An empty key or "/" refers to the bucket itself, which already exists after bucket creation
+    if key == "/" || key.is_empty() {
+        info!("Bucket root folder already exists: {}", bucket);
         return Response::builder()
             .status(StatusCode::OK)
-            .header(header::ETAG, format!("\"{}\"", etag))
+            .header(header::ETAG, quoted_etag(&etag))
             .body(Body::empty())
             .unwrap();
     }
 
-    // Regular file handling
-    if let Some(parent) = object_path.parent() {
-        if let Err(e) = fs::create_dir_all(parent) {
-            warn!("Failed to create object parent directory: {}", e);
-        }
-    }
+    // Write object to disk; folder keys like "photos/" become a marker file inside photos
+    let object_path = bucket_path.join(object_storage_key(&key));
 
-    // Check if versioning is enabled for this bucket
-    let version_id = {
-        let versioning_status = read_bucket_versioning(&state.storage_path, &bucket);
-        let versioning_enabled = versioning_status
-            .as_ref()
-            .map(|s| s == "Enabled")
-            .unwrap_or(false);
-
-        if versioning_enabled {
-            let vid = uuid::Uuid::new_v4().to_string();
-
-            // Save versioned object to disk
-            let versions_dir = bucket_path.join(".versions").join(&key);
-            if let Err(e) = fs::create_dir_all(&versions_dir) {
-                warn!("Failed to create versions directory: {}", e);
-            }
+    // Generic or missing types are replaced by the type configured for the key's extension, e.g. for website assets
+    let request_content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .filter(|ct| !ct.trim().eq_ignore_ascii_case("application/octet-stream"));
+    let content_type = request_content_type
+        .or_else(|| state.settings.content_type_override(&key))
+        .unwrap_or("application/octet-stream")
+        .to_string();
+    let custom_metadata = extract_custom_metadata(&headers);
 
-            let version_path = versions_dir.join(&vid);
-            if let Err(e) = fs::write(&version_path, &data) {
-                warn!("Failed to write versioned object: {}", e);
-            }
+    // Check if versioning is enabled for this bucket.
+    // Folder markers are not versioned: .versions/photos/ already holds the versions of the key "photos".
+    let versioning_enabled = !key.ends_with('/') && read_bucket_versioning(&state.storage_path, &bucket)
+        .as_ref()
+        .map(|s| s == "Enabled")
+        .unwrap_or(false);
 
-            // Save metadata for this version
-            let version_metadata_path = versions_dir.join(format!("{}.metadata", &vid));
-
-            // Get content type from headers
-            let version_content_type = headers
-                .get(header::CONTENT_TYPE)
-                .and_then(|v| v.to_str().ok())
-                .unwrap_or("application/octet-stream")
-                .to_string();
-
-            // Extract custom metadata for this version
-            let mut version_custom_metadata = HashMap::new();
-            for (name, value) in &headers {
-                let key_str = name.as_str();
-                if key_str.starts_with("x-amz-meta-") {
-                    if let Ok(value_str) = value.to_str() {
-                        let meta_key = key_str.strip_prefix("x-amz-meta-").unwrap();
-                        version_custom_metadata.insert(meta_key.to_string(), value_str.to_string());
-                    }
-                }
-            }
+    // Shared with the blocking writers below without copying the body
+    let data = Bytes::from(data);
 
-            // Note: For now, we'll save version metadata without encryption info
-            // The version data is saved unencrypted in the current implementation
-            // TODO: Consider encrypting version data if bucket has encryption enabled
-            let version_metadata = ObjectMetadata {
-                key: key.clone(),
-                size: data.len() as u64,
-                etag: etag.clone(),
-                last_modified: Utc::now(),
-                content_type: version_content_type,
-                storage_class: "STANDARD".to_string(),
-                metadata: version_custom_metadata,
-                version_id: Some(vid.clone()),
-                encryption: None, // Versions are not encrypted in current implementation
-                tags: None, // TODO: Copy tags from current version if they exist
-            };
+    // Every write of the content creates a new object, so an overwrite restarts the lifecycle age
+    let now = Utc::now();
 
-            if let Ok(metadata_json) = serde_json::to_string(&version_metadata) {
-                if let Err(e) = fs::write(&version_metadata_path, metadata_json) {
-                    warn!("Failed to write version metadata: {}", e);
-                } else {
-                    debug!("Version metadata saved to: {:?}", version_metadata_path);
-                }
+    let version_id = if versioning_enabled {
+        let (bucket_path, data) = (bucket_path.clone(), data.clone());
+        let version_metadata = ObjectMetadata {
+            key: key.clone(),
+            size: data.len() as u64,
+            etag: etag.clone(),
+            last_modified: now,
+            created: Some(now),
+            content_type: content_type.clone(),
+            storage_class: storage_class.clone(),
+            metadata: custom_metadata.clone(),
+            version_id: None,
+            encryption: None, // Versions are not encrypted in current implementation
+            tags: None, // A new version starts without tags, as in S3
+            restore_required: false,
+            website_redirect_location: website_redirect_location.clone(),
+            acl: acl.clone(),
+        };
+        let dedup = state.settings.dedup_identical_versions;
+        match tokio::task::spawn_blocking(move || {
+            store_new_version(&bucket_path, &data, version_metadata, dedup)
+        }).await {
+            Ok(vid) => Some(vid),
+            Err(e) => {
+                error!("Failed to store a new version of {}/{}: {}", bucket, key, e);
+                return Response::builder()
+                    .status(StatusCode::INTERNAL_SERVER_ERROR)
+                    .body(Body::from("Failed to store object"))
+                    .unwrap();
             }
-
-            // Note: Version tracking is now filesystem-only (no in-memory tracking)
-
-            info!("Created version {} for object {}/{}", vid, bucket, key);
-            Some(vid)
-        } else {
-            None
         }
+    } else {
+        None
     };
 
     // Save metadata to a separate file
     // Append .metadata to the full filename (including extension)
-    let metadata_path = state.storage_path.join(&bucket).join(format!("{}.metadata", key));
-
-    // Ensure parent directory exists for metadata file
-    if let Some(parent) = metadata_path.parent() {
-        if let Err(e) = fs::create_dir_all(parent) {
-            warn!("Failed to create metadata parent directory: {}", e);
-        }
-    }
-
-    let content_type = headers
-        .get(header::CONTENT_TYPE)
-        .and_then(|v| v.to_str().ok())
-        .unwrap_or("application/octet-stream")
-        .to_string();
+    let metadata_path = state.storage_path.join(&bucket).join(format!("{}.metadata", object_storage_key(&key)));
 
-    // Check if bucket has encryption enabled
-    let encryption_info = {
-        if let Some(encryption) = read_bucket_encryption(&state.storage_path, &bucket) {
-            if encryption.algorithm == "AES256" {
-                // Generate encryption key and encrypt data
-                let key = generate_encryption_key();
-                match encrypt_data(&data, &key) {
-                    Ok((encrypted_data, nonce)) => {
-                        Some((encrypted_data, ObjectEncryption {
-                            algorithm: "AES256".to_string(),
-                            key_base64: BASE64.encode(&key),
-                            nonce_base64: BASE64.encode(&nonce),
-                        }))
-                    },
-                    Err(e) => {
-                        warn!("Failed to encrypt object: {}", e);
-                        None
-                    }
-                }
-            } else {
-                None // KMS encryption not implemented
+    // Encrypt with the customer's key, or otherwise if the bucket has encryption enabled
+    let (final_data, object_encryption) = match &customer_key {
+        Some(customer_key) => match encrypt_with_customer_key(customer_key, &data, state.settings.encryption_chunk_size) {
+            Ok((encrypted_data, encryption)) => (encrypted_data, Some(encryption)),
+            Err(e) => {
+                warn!("Failed to encrypt object with customer key: {}", e);
+                return Response::builder()
+                    .status(StatusCode::INTERNAL_SERVER_ERROR)
+                    .body(Body::from("Failed to store object"))
+                    .unwrap();
             }
-        } else {
-            None
-        }
+        },
+        None => encrypt_for_bucket(&state, &bucket, &data),
     };
-
-    // Use encrypted data if encryption is enabled
-    let (final_data, object_encryption) = if let Some((encrypted_data, enc_info)) = encryption_info {
-        (encrypted_data, Some(enc_info))
-    } else {
-        (data.clone(), None)
-    };
-
-    // Write the (possibly encrypted) data to disk
-    if let Err(e) = fs::write(&object_path, &final_data) {
-        warn!("Failed to write object to disk: {}", e);
-        return Response::builder()
-            .status(StatusCode::INTERNAL_SERVER_ERROR)
-            .body(Body::from("Failed to store object"))
-            .unwrap();
-    }
+    let stored_size = final_data.len() as u64;
 
     let metadata = ObjectMetadata {
         key: key.clone(),
-        size: final_data.len() as u64,
+        size: stored_size,
         etag: etag.clone(),
-        last_modified: Utc::now(),
+        last_modified: now,
+        created: Some(now),
         content_type,
-        storage_class: "STANDARD".to_string(),
-        metadata: HashMap::new(),
+        storage_class,
+        metadata: custom_metadata,
         version_id: version_id.clone(),
         encryption: object_encryption,
         tags: None,
+        restore_required: false,
+        website_redirect_location,
+        acl,
     };
 
-    if let Ok(metadata_json) = serde_json::to_string(&metadata) {
-        if let Err(e) = fs::write(&metadata_path, metadata_json) {
-            warn!("Failed to write metadata file: {}", e);
-        } else {
-            debug!("Metadata saved to: {:?}", metadata_path);
+    // Write the (possibly encrypted) data and its metadata on a blocking thread
+    let written = {
+        let object_path = object_path.clone();
+        tokio::task::spawn_blocking(move || {
+            write_object_files(&object_path, &final_data, &metadata_path, &metadata)
+        }).await
+    };
+    match written {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => {
+            warn!("Failed to write object to disk: {}", e);
+            return Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(Body::from("Failed to store object"))
+                .unwrap();
+        }
+        Err(e) => {
+            error!("Failed to write object {}/{}: {}", bucket, key, e);
+            return Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(Body::from("Failed to store object"))
+                .unwrap();
         }
     }
 
     info!("Object stored at: {:?}", object_path);
 
     // Log to WAL for replication
-    state.wal_writer.log_put(&bucket, &key, final_data.len() as u64, Some(etag.clone()));
+    state.wal_writer.log_put(&bucket, &key, stored_size, Some(etag.clone()));
+    state.list_index.record_write(&bucket, &key);
+    create_folder_markers(&state, &bucket, &key);
 
     // Update quota and stats after successful write
-    if let Err(e) = state.quota_manager.update_quota_add(&bucket, final_data.len() as u64).await {
+    if let Err(e) = state.quota_manager.update_quota_add(&bucket, &key, stored_size).await {
         warn!("Failed to update quota for bucket {}: {}", bucket, e);
     }
     if let Err(e) = state.quota_manager.increment_stat(&bucket, Operation::Put).await {
@@ -1280,13 +1624,19 @@ pub async fn put_object(
 
     let mut response = Response::builder()
         .status(StatusCode::OK)
-        .header(header::ETAG, format!("\"{}\"", etag));
+        .header(header::ETAG, quoted_etag(&etag));
 
     // Add version ID header if versioning is enabled
     if let Some(ref vid) = version_id {
         response = response.header("x-amz-version-id", vid);
     }
 
+    if let Some(customer_key) = &customer_key {
+        response = response
+            .header("x-amz-server-side-encryption-customer-algorithm", "AES256")
+            .header("x-amz-server-side-encryption-customer-key-md5", &customer_key.key_md5);
+    }
+
     response.body(Body::empty()).unwrap()
 }
 
@@ -1294,69 +1644,101 @@ pub async fn get_object(
     State(state): State<AppState>,
     Path((bucket, key)): Path<(String, String)>,
     version_id: Option<String>,
+    headers: HeaderMap,
 ) -> impl IntoResponse {
     debug!("Getting object: {}/{} version: {:?}", bucket, key, version_id);
 
-    // Determine which file to read based on version_id
-    let object_path = if let Some(ref vid) = version_id {
-        if vid != "null" {
-            // Read from version directory
-            state.storage_path.join(&bucket).join(".versions").join(&key).join(vid)
-        } else {
-            // "null" means current version
-            state.storage_path.join(&bucket).join(&key)
-        }
-    } else {
-        // No version specified, read current
-        state.storage_path.join(&bucket).join(&key)
-    };
+    // All filesystem reads happen on a blocking thread, keeping the runtime's workers free
+    let bucket_path = state.storage_path.join(&bucket);
+    let lookup = {
+        let (settings, key, version_id) = (state.settings.clone(), key.clone(), version_id.clone());
+        tokio::task::spawn_blocking(move || {
+            let marker = match version_id.as_deref() {
+                Some(vid) => read_delete_marker(&bucket_path, &key, vid),
+                None => latest_delete_marker(&bucket_path, &key),
+            };
+            if let Some(marker) = marker {
+                return Err(Some(marker));
+            }
 
-    // First check if file exists on disk
-    let data = match fs::read(&object_path) {
-        Ok(data) => data,
-        Err(_) => {
+            // Determine which file to read based on version_id
+            let (object_path, metadata_path) = object_version_paths(&bucket_path, &key, version_id.as_deref());
+            let data = read_object_bytes(&object_path, &settings).map_err(|_| None)?;
+            Ok((data, fs::read_to_string(&metadata_path)))
+        }).await
+    };
+    let (data, metadata_json) = match lookup {
+        Ok(Ok(found)) => found,
+        Ok(Err(Some(marker))) => return delete_marker_response(&marker, &key, version_id.is_some(), true),
+        Ok(Err(None)) => {
             // File doesn't exist on disk
             return Response::builder()
                 .status(StatusCode::NOT_FOUND)
                 .body(Body::empty())
                 .unwrap();
         }
+        Err(e) => {
+            error!("Failed to read object {}/{}: {}", bucket, key, e);
+            return Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(Body::empty())
+                .unwrap();
+        }
     };
 
     // Try to read metadata from file
-    let metadata_path = if let Some(ref vid) = version_id {
-        if vid != "null" {
-            // Read metadata from version directory
-            state.storage_path.join(&bucket).join(".versions").join(&key).join(format!("{}.metadata", vid))
-        } else {
-            // Current version metadata
-            state.storage_path.join(&bucket).join(format!("{}.metadata", key))
-        }
-    } else {
-        state.storage_path.join(&bucket).join(format!("{}.metadata", key))
-    };
-    let (data_to_return, etag, last_modified, content_type, encryption_header, custom_metadata) = if let Ok(metadata_json) = fs::read_to_string(&metadata_path) {
+    let mut customer_key_md5 = None;
+    let mut website_redirect_location = None;
+    let range_header = headers.get(header::RANGE).and_then(|v| v.to_str().ok());
+    let mut decrypted_range = None;
+    let (data_to_return, etag, last_modified, content_type, encryption_header, custom_metadata) = if let Ok(metadata_json) = metadata_json {
         if let Ok(metadata) = serde_json::from_str::<ObjectMetadata>(&metadata_json) {
+            website_redirect_location = metadata.website_redirect_location.clone();
+            // Archived objects must be restored before their data can be read
+            if metadata.restore_required {
+                return Response::builder()
+                    .status(StatusCode::FORBIDDEN)
+                    .header(header::CONTENT_TYPE, "application/xml")
+                    .body(Body::from(format!(r#"<?xml version="1.0" encoding="UTF-8"?>
+<Error>
+    <Code>InvalidObjectState</Code>
+    <Message>The operation is not valid for the object's storage class</Message>
+    <StorageClass>{}</StorageClass>
+</Error>"#, metadata.storage_class)))
+                    .unwrap();
+            }
+
             // Check if object is encrypted and decrypt if necessary
             let (final_data, enc_header) = if let Some(encryption) = &metadata.encryption {
-                if encryption.algorithm == "AES256" {
-                    // Decode the key and nonce
-                    let key = BASE64.decode(&encryption.key_base64).unwrap_or_default();
-                    let nonce = BASE64.decode(&encryption.nonce_base64).unwrap_or_default();
-
-                    // Decrypt the data
-                    match decrypt_data(&data, &key, &nonce) {
-                        Ok(decrypted) => (decrypted, Some("AES256".to_string())),
+                let (encryption_key, enc_header) = if encryption.customer_key_md5.is_some() {
+                    // SSE-C objects are only readable with the key they were written with
+                    let customer_key = match require_customer_key(&headers, SSE_CUSTOMER_HEADERS, encryption) {
+                        Ok(customer_key) => customer_key,
+                        Err(response) => return *response,
+                    };
+                    customer_key_md5 = Some(customer_key.key_md5);
+                    (Some(customer_key.key), None)
+                } else if encryption.algorithm == "AES256" {
+                    (Some(BASE64.decode(&encryption.key_base64).unwrap_or_default()), Some("AES256".to_string()))
+                } else {
+                    (None, Some(encryption.algorithm.clone()))
+                };
+
+                match encryption_key {
+                    Some(encryption_key) => match decrypt_for_read(&data, &encryption_key, encryption, range_header) {
+                        Ok((decrypted, range)) => {
+                            decrypted_range = range;
+                            (decrypted, enc_header)
+                        }
                         Err(e) => {
-                            warn!("Failed to decrypt object: {}", e);
+                            warn!("Failed to decrypt object {}/{}: {}", bucket, key, e);
                             return Response::builder()
                                 .status(StatusCode::INTERNAL_SERVER_ERROR)
                                 .body(Body::from("Failed to decrypt object"))
                                 .unwrap();
                         }
-                    }
-                } else {
-                    (data.clone(), Some(encryption.algorithm.clone()))
+                    },
+                    None => (data.clone(), enc_header),
                 }
             } else {
                 (data.clone(), None)
@@ -1373,23 +1755,79 @@ pub async fn get_object(
         (data.clone(), etag, Utc::now(), "application/octet-stream".to_string(), None, HashMap::new())
     };
 
+    // Multipart ETags are not an MD5 of the content, so only single-part objects can be checked, and only
+    // when the whole object was decrypted
+    if state.settings.verify_on_read && !etag.contains('-') && decrypted_range.is_none() {
+        let actual = format!("{:x}", md5::compute(&data_to_return));
+        if actual != etag {
+            error!("Corruption detected reading {}/{}: stored ETag {}, content MD5 {}", bucket, key, etag, actual);
+            return Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .header(header::CONTENT_TYPE, "application/xml")
+                .body(Body::from(r#"<?xml version="1.0" encoding="UTF-8"?>
+<Error>
+    <Code>InternalError</Code>
+    <Message>We encountered an internal error. Please try again.</Message>
+</Error>"#))
+                .unwrap();
+        }
+    }
+
+    // A satisfiable Range is served by slicing the bytes, which never copies them. Chunked encrypted
+    // objects were already cut down to the range while decrypting.
+    let total_size = decrypted_range.map_or(data_to_return.len() as u64, |(_, _, total_size)| total_size);
+    let range = match range_header.map(|v| parse_range(v, total_size)) {
+        Some(Err(())) => {
+            return Response::builder()
+                .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                .header(header::CONTENT_TYPE, "application/xml")
+                .header(header::CONTENT_RANGE, format!("bytes */{}", total_size))
+                .body(Body::from(r#"<?xml version="1.0" encoding="UTF-8"?>
+<Error>
+    <Code>InvalidRange</Code>
+    <Message>The requested range is not satisfiable</Message>
+</Error>"#))
+                .unwrap();
+        }
+        Some(Ok(range)) => range,
+        None => None,
+    };
+    let (status, data_to_return) = match range {
+        Some(_) if decrypted_range.is_some() => (StatusCode::PARTIAL_CONTENT, data_to_return),
+        Some((start, end)) => (StatusCode::PARTIAL_CONTENT, data_to_return.slice(start as usize..=end as usize)),
+        None => (StatusCode::OK, data_to_return),
+    };
+
     let mut response = Response::builder()
-        .status(StatusCode::OK)
+        .status(status)
         .header(header::CONTENT_TYPE, content_type)
         .header(header::CONTENT_LENGTH, data_to_return.len().to_string())
-        .header(header::ETAG, format!("\"{}\"", etag))
+        .header(header::ACCEPT_RANGES, "bytes")
+        .header(header::ETAG, quoted_etag(&etag))
         .header(header::LAST_MODIFIED, format_http_date(&last_modified));
 
+    if let Some((start, end)) = range {
+        response = response.header(header::CONTENT_RANGE, format!("bytes {}-{}/{}", start, end, total_size));
+    }
+
     // Add custom metadata headers
     for (key, value) in custom_metadata {
         let header_name = format!("x-amz-meta-{}", key);
-        response = response.header(header_name, value);
+        response = response.header(header_name, encode_metadata_value(&value));
     }
 
     // Add encryption header if object was encrypted
     if let Some(enc_algorithm) = encryption_header {
         response = response.header("x-amz-server-side-encryption", enc_algorithm);
     }
+    if let Some(key_md5) = customer_key_md5 {
+        response = response
+            .header("x-amz-server-side-encryption-customer-algorithm", "AES256")
+            .header("x-amz-server-side-encryption-customer-key-md5", key_md5);
+    }
+    if let Some(location) = website_redirect_location {
+        response = response.header("x-amz-website-redirect-location", location);
+    }
 
     response.body(Body::from(data_to_return)).unwrap()
 }
@@ -1405,81 +1843,82 @@ pub async fn delete_object(
         warn!("Failed to update DELETE stats for bucket {}: {}", bucket, e);
     }
 
-    // Check if the path is a directory
-    let object_path = state.storage_path.join(&bucket).join(&key);
+    // Check if the path is a directory; folder keys resolve to their marker file instead
+    let object_path = state.storage_path.join(&bucket).join(object_storage_key(&key));
 
     // Get object size before deletion for quota update (only if it's a file)
-    let object_size = if object_path.is_file() {
-        fs::metadata(&object_path).ok().map(|m| m.len())
-    } else {
-        None
-    };
+    let object_metadata = tokio::fs::metadata(&object_path).await.ok();
+    let object_size = object_metadata.as_ref().filter(|m| m.is_file()).map(|m| m.len());
+    let is_dir = object_metadata.as_ref().is_some_and(|m| m.is_dir());
 
-    // If path ends with '/' or is a directory, handle it as a prefix deletion
-    if key.ends_with('/') || object_path.is_dir() {
+    // A key without the trailing '/' that names a directory is handled as a prefix deletion
+    if is_dir {
         // In S3, deleting a "directory" (prefix) succeeds if it's empty
         // For filesystem-based storage, we try to remove the directory
-        if object_path.is_dir() {
-            // First try to remove as empty directory
-            match fs::remove_dir(&object_path) {
-                Ok(_) => {
-                    info!("Deleted empty directory: {}/{}", bucket, key);
-                    return StatusCode::NO_CONTENT;
-                }
-                Err(_) => {
-                    // If directory is not empty, recursively delete all contents
-                    match fs::remove_dir_all(&object_path) {
-                        Ok(_) => {
-                            info!("Deleted directory and all contents: {}/{}", bucket, key);
-                            return StatusCode::NO_CONTENT;
-                        }
-                        Err(e) => {
-                            warn!("Failed to delete directory {}/{}: {}", bucket, key, e);
-                            // In S3, attempting to delete a non-existent prefix returns 204
-                            return StatusCode::NO_CONTENT;
-                        }
+        // First try to remove as empty directory
+        match tokio::fs::remove_dir(&object_path).await {
+            Ok(_) => {
+                info!("Deleted empty directory: {}/{}", bucket, key);
+                return StatusCode::NO_CONTENT;
+            }
+            Err(_) => {
+                // If directory is not empty, recursively delete all contents
+                match tokio::fs::remove_dir_all(&object_path).await {
+                    Ok(_) => {
+                        // The keys below it are gone too; the bucket is scanned afresh on its next listing
+                        state.list_index.forget_bucket(&bucket);
+                        info!("Deleted directory and all contents: {}/{}", bucket, key);
+                        return StatusCode::NO_CONTENT;
+                    }
+                    Err(e) => {
+                        warn!("Failed to delete directory {}/{}: {}", bucket, key, e);
+                        // In S3, attempting to delete a non-existent prefix returns 204
+                        return StatusCode::NO_CONTENT;
                     }
                 }
             }
-        } else {
-            // Path doesn't exist, but in S3 this is still successful
-            return StatusCode::NO_CONTENT;
         }
     }
 
-    // Delete from disk - check if it's a file or directory
-    let disk_deleted = if object_path.is_dir() {
-        // If it's a directory, try to remove it (only if empty)
-        // In S3, directories are just prefixes, so we can safely ignore directory deletions
-        fs::remove_dir(&object_path).is_ok() || true  // Always treat directory deletion as successful
-    } else {
-        // If it's a file, remove it normally
-        fs::remove_file(&object_path).is_ok()
-    };
-
-    // Also delete metadata file
+    // Directories were handled above, so only a file is left to remove, together with its metadata file.
     // Metadata is stored as filename.ext.metadata (not filename.metadata)
-    let metadata_path = state.storage_path.join(&bucket).join(format!("{}.metadata", key));
-    let metadata_deleted = fs::remove_file(&metadata_path).is_ok();
+    let metadata_path = state.storage_path.join(&bucket).join(format!("{}.metadata", object_storage_key(&key)));
+    let removed = {
+        let object_path = object_path.clone();
+        tokio::task::spawn_blocking(move || {
+            let _guard = OBJECT_WRITE_LOCKS.lock(&object_path);
+            (fs::remove_file(&object_path).is_ok(), fs::remove_file(&metadata_path).is_ok())
+        }).await
+    };
+    let (disk_deleted, metadata_deleted) = removed.unwrap_or_else(|e| {
+        error!("Failed to delete object {}/{}: {}", bucket, key, e);
+        (false, false)
+    });
 
     if metadata_deleted {
         debug!("Deleted metadata file for {}/{}", bucket, key);
     }
 
-    // Update quota if we successfully deleted a file
-    if disk_deleted && !object_path.is_dir() {
+    // Removing a folder marker also removes its directory once nothing else is left in it
+    if key.ends_with('/') {
+        let _ = tokio::fs::remove_dir(state.storage_path.join(&bucket).join(&key)).await;
+    }
+
+    // Update quota if we successfully deleted a file; a concurrent delete of the same key fails remove_file,
+    // so the object is subtracted only once
+    if disk_deleted {
         // Log to WAL for replication
         state.wal_writer.log_delete(&bucket, &key);
+        state.list_index.record_delete(&bucket, &key);
 
-        // Always update quota for successful file deletion
-        // Use the size if we have it, otherwise use 0 (object count will still be decremented)
         let size_to_remove = object_size.unwrap_or(0);
-        if let Err(e) = state.quota_manager.update_quota_remove(&bucket, size_to_remove).await {
+        if let Err(e) = state.quota_manager.update_quota_remove(&bucket, &key, size_to_remove).await {
             warn!("Failed to update quota for bucket {} after deletion: {}", bucket, e);
         }
     }
 
-    if disk_deleted || metadata_deleted {
+    // In S3, deleting a folder that doesn't exist is still successful
+    if disk_deleted || metadata_deleted || key.ends_with('/') {
         StatusCode::NO_CONTENT
     } else {
         StatusCode::NOT_FOUND
@@ -1489,16 +1928,32 @@ pub async fn delete_object(
 pub async fn head_object(
     State(state): State<AppState>,
     Path((bucket, key)): Path<(String, String)>,
+    Query(params): Query<ObjectQueryParams>,
+    headers: HeaderMap,
 ) -> impl IntoResponse {
+    if is_reserved_key(&key) {
+        return StatusCode::BAD_REQUEST.into_response();
+    }
+
     // Increment stats for HEAD operation
     if let Err(e) = state.quota_manager.increment_stat(&bucket, Operation::Head).await {
         warn!("Failed to update HEAD stats for bucket {}: {}", bucket, e);
     }
 
+    let bucket_path = state.storage_path.join(&bucket);
+    let version_id = params.version_id;
+    let marker = match version_id.as_deref() {
+        Some(vid) => read_delete_marker(&bucket_path, &key, vid),
+        None => latest_delete_marker(&bucket_path, &key),
+    };
+    if let Some(marker) = marker {
+        return delete_marker_response(&marker, &key, version_id.is_some(), false);
+    }
+
     // Check if object exists on disk
-    let object_path = state.storage_path.join(&bucket).join(&key);
+    let (object_path, metadata_path) = object_version_paths(&bucket_path, &key, version_id.as_deref());
 
-    if !object_path.exists() {
+    if !object_path.is_file() {
         return Response::builder()
             .status(StatusCode::NOT_FOUND)
             .body(Body::empty())
@@ -1506,47 +1961,1135 @@ pub async fn head_object(
     }
 
     // Try to read metadata from file first
-    let metadata_path = state.storage_path.join(&bucket).join(format!("{}.metadata", key));
-    let (size, etag, last_modified, content_type, custom_metadata) = if let Ok(metadata_json) = fs::read_to_string(&metadata_path) {
+    let mut stored_version_id = None;
+    let mut customer_key_md5 = None;
+    let mut website_redirect_location = None;
+    let (size, etag, last_modified, content_type, custom_metadata, storage_class) = if let Ok(metadata_json) = fs::read_to_string(&metadata_path) {
         if let Ok(metadata) = serde_json::from_str::<ObjectMetadata>(&metadata_json) {
-            (metadata.size, metadata.etag, metadata.last_modified, metadata.content_type, metadata.metadata)
+            stored_version_id = metadata.version_id;
+            website_redirect_location = metadata.website_redirect_location;
+            if let Some(encryption) = metadata.encryption.as_ref().filter(|e| e.customer_key_md5.is_some()) {
+                match require_customer_key(&headers, SSE_CUSTOMER_HEADERS, encryption) {
+                    Ok(customer_key) => customer_key_md5 = Some(customer_key.key_md5),
+                    // HEAD responses carry no body
+                    Err(response) => return (response.status(), Body::empty()).into_response(),
+                }
+            }
+            (metadata.size, metadata.etag, metadata.last_modified, metadata.content_type, metadata.metadata, metadata.storage_class)
         } else {
             // Metadata file exists but couldn't parse, fall back to file stats
-            let file_metadata = fs::metadata(&object_path).unwrap();
-            let size = file_metadata.len();
-            let data = fs::read(&object_path).unwrap_or_default();
-            let etag = format!("{:x}", md5::compute(&data));
-            (size, etag, Utc::now(), "application/octet-stream".to_string(), HashMap::new())
+            head_fallback_metadata(object_path).await
         }
     } else {
         // No metadata file, use file stats
-        let file_metadata = fs::metadata(&object_path).unwrap();
-        let size = file_metadata.len();
-        let data = fs::read(&object_path).unwrap_or_default();
-        let etag = format!("{:x}", md5::compute(&data));
-        (size, etag, Utc::now(), "application/octet-stream".to_string(), HashMap::new())
+        head_fallback_metadata(object_path).await
     };
 
     let mut response = Response::builder()
         .status(StatusCode::OK)
         .header(header::CONTENT_TYPE, content_type)
         .header(header::CONTENT_LENGTH, size.to_string())
-        .header(header::ETAG, format!("\"{}\"", etag))
+        .header(header::ETAG, quoted_etag(&etag))
         .header(header::LAST_MODIFIED, format_http_date(&last_modified));
 
     // Add custom metadata headers
     for (key, value) in custom_metadata {
         let header_name = format!("x-amz-meta-{}", key);
-        response = response.header(header_name, value);
+        response = response.header(header_name, encode_metadata_value(&value));
     }
 
-    response.body(Body::empty()).unwrap()
-}
+    // S3 omits the storage class header for STANDARD objects
+    if storage_class != "STANDARD" {
+        response = response.header("x-amz-storage-class", storage_class);
+    }
 
-// Helper functions for chunked data and encryption
-fn parse_chunked_data(input: &[u8]) -> Vec<u8> {
-    let mut result = Vec::new();
-    let mut pos = 0;
+    if let Some(vid) = stored_version_id.or(version_id) {
+        response = response.header("x-amz-version-id", vid);
+    }
+    if let Some(key_md5) = customer_key_md5 {
+        response = response
+            .header("x-amz-server-side-encryption-customer-algorithm", "AES256")
+            .header("x-amz-server-side-encryption-customer-key-md5", key_md5);
+    }
+    if let Some(location) = website_redirect_location {
+        response = response.header("x-amz-website-redirect-location", location);
+    }
+
+    response.body(Body::empty()).unwrap()
+}
+
+// Record a versioned PUT under .versions, keeping the object it replaces as a noncurrent version.
+// With dedup enabled an unchanged upload keeps the current version id instead. Runs on a blocking thread.
+fn store_new_version(bucket_path: &std::path::Path, data: &[u8], mut version_metadata: ObjectMetadata, dedup: bool) -> String {
+    let key = version_metadata.key.clone();
+    if let Some(vid) = unchanged_current_version(bucket_path, &key, &version_metadata.etag).filter(|_| dedup) {
+        refresh_version_last_modified(bucket_path, &key, &vid);
+        info!("Content of {} is unchanged, keeping version {}", key, vid);
+        return vid;
+    }
+
+    // Keep the object being overwritten as a noncurrent version
+    preserve_current_version(bucket_path, &key);
+
+    let vid = uuid::Uuid::new_v4().to_string();
+
+    // Save versioned object to disk
+    let versions_dir = bucket_path.join(".versions").join(&key);
+    if let Err(e) = create_storage_dir(&versions_dir) {
+        warn!("Failed to create versions directory: {}", e);
+    }
+
+    let version_path = versions_dir.join(&vid);
+    if let Err(e) = write_storage_file(&version_path, data) {
+        warn!("Failed to write versioned object: {}", e);
+    }
+
+    // Save metadata for this version
+    let version_metadata_path = versions_dir.join(format!("{}.metadata", &vid));
+
+    // Note: For now, we'll save version metadata without encryption info
+    // The version data is saved unencrypted in the current implementation
+    // TODO: Consider encrypting version data if bucket has encryption enabled
+    version_metadata.version_id = Some(vid.clone());
+
+    if let Ok(metadata_json) = serde_json::to_string(&version_metadata) {
+        if let Err(e) = write_storage_file(&version_metadata_path, metadata_json) {
+            warn!("Failed to write version metadata: {}", e);
+        } else {
+            debug!("Version metadata saved to: {:?}", version_metadata_path);
+        }
+    }
+
+    info!("Created version {} for object {}", vid, key);
+    vid
+}
+
+// Move the current object and its metadata into .versions, unless that version is already stored there.
+// Returns false if the current object could not be preserved.
+// Tags set on the current object also belong to its stored version, so they survive it becoming noncurrent
+// and are found when the version is read or copied by versionId
+fn sync_version_tags(bucket_path: &std::path::Path, key: &str, metadata: &ObjectMetadata) {
+    let Some(vid) = metadata.version_id.as_deref() else {
+        return;
+    };
+    let version_metadata_path = bucket_path.join(".versions").join(key).join(format!("{}.metadata", vid));
+    let Some(mut version_metadata) = fs::read_to_string(&version_metadata_path)
+        .ok()
+        .and_then(|json| serde_json::from_str::<ObjectMetadata>(&json).ok())
+    else {
+        return;
+    };
+
+    version_metadata.tags = metadata.tags.clone();
+    if let Err(e) = serde_json::to_string(&version_metadata)
+        .map_err(std::io::Error::from)
+        .and_then(|json| write_storage_file(&version_metadata_path, json))
+    {
+        warn!("Failed to update the tags of version {} of {}: {}", vid, key, e);
+    }
+}
+
+// Tags from an x-amz-tagging header, URL-encoded like a query string: "team=web&stage=prod"
+fn parse_tagging_header(headers: &HeaderMap) -> Option<HashMap<String, String>> {
+    let tagging = headers.get("x-amz-tagging").and_then(|v| v.to_str().ok())?;
+    let decode = |s: &str| urlencoding::decode(&s.replace('+', " ")).map(|d| d.into_owned()).unwrap_or_else(|_| s.to_string());
+    let tags: HashMap<String, String> = tagging.split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (decode(key), decode(value))
+        })
+        .collect();
+    Some(tags).filter(|tags| !tags.is_empty())
+}
+
+fn preserve_current_version(bucket_path: &std::path::Path, key: &str) -> bool {
+    let object_path = bucket_path.join(key);
+    if !object_path.is_file() {
+        return true;
+    }
+
+    // Objects written before versioning was enabled carry the "null" version id
+    let metadata_path = bucket_path.join(format!("{}.metadata", key));
+    let current_vid = current_version_id(bucket_path, key).unwrap_or_else(|| "null".to_string());
+
+    let versions_dir = bucket_path.join(".versions").join(key);
+    let version_path = versions_dir.join(&current_vid);
+    if version_path.exists() {
+        return true;
+    }
+
+    if let Err(e) = create_storage_dir(&versions_dir) {
+        warn!("Failed to create versions directory: {}", e);
+        return false;
+    }
+
+    if let Err(e) = fs::rename(&object_path, &version_path) {
+        warn!("Failed to preserve current version of {}: {}", key, e);
+        return false;
+    }
+
+    if metadata_path.exists() {
+        let version_metadata_path = versions_dir.join(format!("{}.metadata", current_vid));
+        if let Err(e) = fs::rename(&metadata_path, &version_metadata_path) {
+            warn!("Failed to preserve metadata of version {} for {}: {}", current_vid, key, e);
+        }
+    }
+
+    debug!("Preserved previous version {} of {}", current_vid, key);
+    true
+}
+
+// Version id recorded in the current object's metadata; objects written while versioning was off have none
+fn current_version_id(bucket_path: &std::path::Path, key: &str) -> Option<String> {
+    fs::read_to_string(bucket_path.join(format!("{}.metadata", key)))
+        .ok()
+        .and_then(|json| serde_json::from_str::<ObjectMetadata>(&json).ok())
+        .and_then(|metadata| metadata.version_id)
+}
+
+// Version id of the current object if its content has the given ETag
+fn unchanged_current_version(bucket_path: &std::path::Path, key: &str, etag: &str) -> Option<String> {
+    if !bucket_path.join(key).is_file() {
+        return None;
+    }
+    fs::read_to_string(bucket_path.join(format!("{}.metadata", key)))
+        .ok()
+        .and_then(|json| serde_json::from_str::<ObjectMetadata>(&json).ok())
+        .filter(|metadata| metadata.etag == etag)
+        .and_then(|metadata| metadata.version_id)
+}
+
+// Bump the stored copy of a version to now, as when the same content is written again
+fn refresh_version_last_modified(bucket_path: &std::path::Path, key: &str, version_id: &str) {
+    let (_, version_metadata_path) = object_version_paths(bucket_path, key, Some(version_id));
+    let Some(mut metadata) = fs::read_to_string(&version_metadata_path)
+        .ok()
+        .and_then(|json| serde_json::from_str::<ObjectMetadata>(&json).ok())
+    else {
+        return;
+    };
+
+    metadata.last_modified = Utc::now();
+    if let Err(e) = write_storage_file(&version_metadata_path, serde_json::to_string(&metadata).unwrap()) {
+        warn!("Failed to refresh metadata of version {} for {}: {}", version_id, key, e);
+    }
+}
+
+// Data and metadata paths of the current object or of one of its versions
+fn object_version_paths(bucket_path: &std::path::Path, key: &str, version_id: Option<&str>) -> (PathBuf, PathBuf) {
+    let versions_dir = bucket_path.join(".versions").join(key);
+    match version_id {
+        // The "null" version was preserved when a versioned write replaced it; otherwise it is the current object,
+        // unless that object has a version id of its own
+        Some("null") if !versions_dir.join("null").exists()
+            && current_version_id(bucket_path, key).is_none_or(|vid| vid == "null") => {
+            current_object_paths(bucket_path, key)
+        }
+        Some(vid) => (versions_dir.join(vid), versions_dir.join(format!("{}.metadata", vid))),
+        None => current_object_paths(bucket_path, key),
+    }
+}
+
+fn current_object_paths(bucket_path: &std::path::Path, key: &str) -> (PathBuf, PathBuf) {
+    let storage_key = object_storage_key(key);
+    (bucket_path.join(&storage_key), bucket_path.join(format!("{}.metadata", storage_key)))
+}
+
+fn delete_marker_path(bucket_path: &std::path::Path, key: &str, version_id: &str) -> PathBuf {
+    bucket_path.join(".versions").join(key).join(format!("{}.delete_marker", version_id))
+}
+
+fn read_delete_marker(bucket_path: &std::path::Path, key: &str, version_id: &str) -> Option<DeleteMarker> {
+    fs::read_to_string(delete_marker_path(bucket_path, key, version_id))
+        .ok()
+        .and_then(|json| serde_json::from_str(&json).ok())
+}
+
+// Delete markers and the last-modified times of stored versions, from the key's .versions directory
+fn read_version_history(bucket_path: &std::path::Path, key: &str) -> (Vec<DeleteMarker>, Vec<(String, DateTime<Utc>)>) {
+    let mut markers = Vec::new();
+    let mut versions = Vec::new();
+    let entries = match fs::read_dir(bucket_path.join(".versions").join(key)) {
+        Ok(entries) => entries,
+        Err(_) => return (markers, versions),
+    };
+
+    for entry in entries.flatten().filter(|entry| entry.path().is_file()) {
+        let file_name = entry.file_name().to_string_lossy().to_string();
+        let content = fs::read_to_string(entry.path()).unwrap_or_default();
+        if file_name.ends_with(".delete_marker") {
+            if let Ok(marker) = serde_json::from_str::<DeleteMarker>(&content) {
+                markers.push(marker);
+            }
+        } else if let Some(vid) = file_name.strip_suffix(".metadata") {
+            if let Ok(metadata) = serde_json::from_str::<ObjectMetadata>(&content) {
+                versions.push((vid.to_string(), metadata.last_modified));
+            }
+        }
+    }
+    (markers, versions)
+}
+
+// The delete marker that is the key's latest version, if any.
+// A current object always wins; otherwise the marker must be newer than every stored version.
+fn latest_delete_marker(bucket_path: &std::path::Path, key: &str) -> Option<DeleteMarker> {
+    if bucket_path.join(key).is_file() {
+        return None;
+    }
+    let (markers, versions) = read_version_history(bucket_path, key);
+    let newest_version = versions.iter().map(|(_, last_modified)| *last_modified).max();
+    markers.into_iter()
+        .max_by_key(|marker| marker.last_modified)
+        .filter(|marker| newest_version.is_none_or(|newest| marker.last_modified > newest))
+}
+
+// Hide the key behind a new delete marker, keeping the current object as a noncurrent version
+async fn create_delete_marker(state: &AppState, bucket: &str, key: &str) -> Response {
+    if let Err(e) = state.quota_manager.increment_stat(bucket, Operation::Delete).await {
+        warn!("Failed to update DELETE stats for bucket {}: {}", bucket, e);
+    }
+
+    let bucket_path = state.storage_path.join(bucket);
+    let object_path = bucket_path.join(key);
+    let object_size = fs::metadata(&object_path).ok().filter(|m| m.is_file()).map(|m| m.len());
+
+    if !preserve_current_version(&bucket_path, key) {
+        return Response::builder()
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .body(Body::from("Failed to delete object"))
+            .unwrap();
+    }
+    // Versioned writes also store their version under .versions, so the current copy may still be in place
+    let _ = fs::remove_file(&object_path);
+    let _ = fs::remove_file(bucket_path.join(format!("{}.metadata", key)));
+
+    let marker = DeleteMarker {
+        version_id: Uuid::new_v4().to_string(),
+        last_modified: Utc::now(),
+    };
+    let marker_path = delete_marker_path(&bucket_path, key, &marker.version_id);
+    let written = marker_path.parent()
+        .map(create_storage_dir)
+        .unwrap_or(Ok(()))
+        .and_then(|_| write_storage_file(&marker_path, serde_json::to_string(&marker).unwrap()));
+    if let Err(e) = written {
+        warn!("Failed to write delete marker for {}/{}: {}", bucket, key, e);
+        return Response::builder()
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .body(Body::from("Failed to delete object"))
+            .unwrap();
+    }
+
+    if let Some(size) = object_size {
+        state.wal_writer.log_delete(bucket, key);
+        state.list_index.record_delete(bucket, key);
+        if let Err(e) = state.quota_manager.update_quota_remove(bucket, key, size).await {
+            warn!("Failed to update quota for bucket {} after deletion: {}", bucket, e);
+        }
+    }
+
+    info!("Created delete marker {} for {}/{}", marker.version_id, bucket, key);
+    Response::builder()
+        .status(StatusCode::NO_CONTENT)
+        .header("x-amz-delete-marker", "true")
+        .header("x-amz-version-id", &marker.version_id)
+        .body(Body::empty())
+        .unwrap()
+}
+
+// Make the newest stored version current again once the delete marker hiding it is removed.
+// The restored object is logged to the WAL and counted in the quota like any other write.
+async fn restore_latest_version(state: &AppState, bucket: &str, key: &str) {
+    let bucket_path = state.storage_path.join(bucket);
+    if bucket_path.join(key).exists() || latest_delete_marker(&bucket_path, key).is_some() {
+        return;
+    }
+    let (_, versions) = read_version_history(&bucket_path, key);
+    let Some((vid, _)) = versions.into_iter().max_by_key(|(_, last_modified)| *last_modified) else {
+        return;
+    };
+
+    let (version_path, version_metadata_path) = object_version_paths(&bucket_path, key, Some(&vid));
+    let restored = fs::read(&version_path).and_then(|data| {
+        let size = data.len() as u64;
+        write_storage_file(bucket_path.join(key), data)?;
+        let metadata = fs::read(&version_metadata_path)?;
+        let etag = serde_json::from_slice::<ObjectMetadata>(&metadata).ok().map(|metadata| metadata.etag);
+        write_storage_file(bucket_path.join(format!("{}.metadata", key)), metadata)?;
+        Ok((size, etag))
+    });
+    match restored {
+        Ok((size, etag)) => {
+            debug!("Version {} of {} is current again", vid, key);
+            state.wal_writer.log_put(bucket, key, size, etag);
+            if let Err(e) = state.quota_manager.update_quota_add(bucket, key, size).await {
+                warn!("Failed to update quota for bucket {} after restoring {}: {}", bucket, key, e);
+            }
+        }
+        Err(e) => warn!("Failed to restore version {} of {}: {}", vid, key, e),
+    }
+}
+
+// GET or HEAD on a key hidden by a delete marker (404), or naming a delete marker's version id (405)
+fn delete_marker_response(marker: &DeleteMarker, key: &str, requested_version: bool, with_body: bool) -> Response {
+    let (status, code, message) = if requested_version {
+        (StatusCode::METHOD_NOT_ALLOWED, "MethodNotAllowed", "The specified method is not allowed against this resource.")
+    } else {
+        (StatusCode::NOT_FOUND, "NoSuchKey", "The specified key does not exist.")
+    };
+
+    let mut response = Response::builder()
+        .status(status)
+        .header("x-amz-delete-marker", "true")
+        .header("x-amz-version-id", &marker.version_id)
+        .header(header::LAST_MODIFIED, format_http_date(&marker.last_modified));
+    if requested_version {
+        response = response.header(header::ALLOW, "DELETE");
+    }
+    if !with_body {
+        return response.body(Body::empty()).unwrap();
+    }
+
+    response
+        .header(header::CONTENT_TYPE, "application/xml")
+        .body(Body::from(format!(r#"<?xml version="1.0" encoding="UTF-8"?>
+<Error>
+    <Code>{}</Code>
+    <Message>{}</Message>
+    <Key>{}</Key>
+</Error>"#, code, message, xml_escape(key))))
+        .unwrap()
+}
+
+// Build a 400 error for a CompleteMultipartUpload request that cannot be honoured
+// Multipart uploads in progress in the bucket: those in memory and those persisted in .multipart,
+// which includes uploads started before a restart
+fn uploads_in_progress(storage_path: &std::path::Path, bucket: &str, uploads: &HashMap<String, MultipartUpload>) -> usize {
+    let mut upload_ids: HashSet<String> = uploads.values()
+        .filter(|upload| upload.bucket == bucket)
+        .map(|upload| upload.upload_id.clone())
+        .collect();
+    if let Ok(entries) = fs::read_dir(storage_path.join(bucket).join(".multipart")) {
+        upload_ids.extend(entries.flatten().filter_map(|entry| {
+            entry.file_name().to_str()?.strip_suffix(".upload").map(String::from)
+        }));
+    }
+    upload_ids.len()
+}
+
+fn multipart_error_response(code: &str, message: &str) -> Response {
+    Response::builder()
+        .status(StatusCode::BAD_REQUEST)
+        .header(header::CONTENT_TYPE, "application/xml")
+        .body(Body::from(format!(r#"<?xml version="1.0" encoding="UTF-8"?>
+<Error>
+    <Code>{}</Code>
+    <Message>{}</Message>
+</Error>"#, code, message)))
+        .unwrap()
+}
+
+// Outcome of staging one Content-Range PUT of a resumable upload
+enum PartialUploadProgress {
+    Complete(Vec<u8>),
+    Incomplete(Vec<(u64, u64)>),
+}
+
+// Held while a key's staging file and state file are updated together
+static PARTIAL_UPLOAD_LOCKS: PathLocks = PathLocks::new();
+
+// Write a "bytes start-end/total" range into the key's staging file under .partial and record it.
+// Staging files are named by the MD5 of the key so keys cannot collide with each other's state files.
+// Runs on a blocking thread.
+fn stage_partial_upload(bucket_path: &std::path::Path, key: &str, content_range: &str, data: &[u8]) -> Result<PartialUploadProgress, Box<Response>> {
+    let invalid = |message: &str| Box::new(Response::builder()
+        .status(StatusCode::BAD_REQUEST)
+        .header(header::CONTENT_TYPE, "application/xml")
+        .body(Body::from(format!(r#"<?xml version="1.0" encoding="UTF-8"?>
+<Error>
+    <Code>InvalidArgument</Code>
+    <Message>{}</Message>
+</Error>"#, message)))
+        .unwrap());
+    let internal_error = |e: std::io::Error| {
+        warn!("Failed to stage resumable upload of {}: {}", key, e);
+        Box::new(Response::builder()
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .body(Body::from("Failed to store object"))
+            .unwrap())
+    };
+
+    let (start, end, total) = content_range.trim()
+        .strip_prefix("bytes ")
+        .and_then(|spec| spec.split_once('/'))
+        .and_then(|(range, total)| {
+            let (start, end) = range.split_once('-')?;
+            Some((start.trim().parse::<u64>().ok()?, end.trim().parse::<u64>().ok()?, total.trim().parse::<u64>().ok()?))
+        })
+        .filter(|(start, end, total)| start <= end && end < total)
+        .ok_or_else(|| invalid("Content-Range must be of the form bytes start-end/total"))?;
+    if data.len() as u64 != end - start + 1 {
+        return Err(invalid("The body length does not match the Content-Range"));
+    }
+
+    let staging_dir = bucket_path.join(".partial");
+    let staging_name = format!("{:x}", md5::compute(key.as_bytes()));
+    let data_path = staging_dir.join(&staging_name);
+    let state_path = staging_dir.join(format!("{}.json", staging_name));
+
+    let _guard = PARTIAL_UPLOAD_LOCKS.lock(&data_path);
+
+    let mut upload = fs::read_to_string(&state_path)
+        .ok()
+        .and_then(|json| serde_json::from_str::<PartialUpload>(&json).ok())
+        .unwrap_or(PartialUpload { total, received: Vec::new() });
+    if upload.total != total {
+        return Err(invalid("Content-Range total does not match the upload in progress"));
+    }
+
+    create_storage_dir(&staging_dir).map_err(internal_error)?;
+    let mut file = open_storage_file(&data_path).map_err(internal_error)?;
+    file.set_len(total)
+        .and_then(|_| file.seek(SeekFrom::Start(start)))
+        .and_then(|_| file.write_all(data))
+        .map_err(internal_error)?;
+
+    // Merge the new range into the sorted list, joining adjacent and overlapping ranges
+    upload.received.push((start, end));
+    upload.received.sort();
+    let mut merged: Vec<(u64, u64)> = Vec::new();
+    for (start, end) in upload.received.drain(..) {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 + 1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    upload.received = merged;
+
+    if upload.received == [(0, total - 1)] {
+        let full = fs::read(&data_path).map_err(internal_error)?;
+        let _ = fs::remove_file(&data_path);
+        let _ = fs::remove_file(&state_path);
+        return Ok(PartialUploadProgress::Complete(full));
+    }
+
+    write_storage_file(&state_path, serde_json::to_string(&upload).unwrap()).map_err(internal_error)?;
+    debug!("Resumable upload of {} has {:?} of {} bytes", key, upload.received, total);
+    Ok(PartialUploadProgress::Incomplete(upload.received))
+}
+
+// How long a completed upload ID is remembered for retried completions
+const COMPLETED_UPLOAD_RETENTION: Duration = Duration::from_secs(15 * 60);
+
+// Build the CompleteMultipartUploadResult for a completed (or retried) completion
+fn complete_multipart_response(bucket: &str, key: &str, etag: &str) -> Response {
+    let (bucket, key) = (xml_escape(bucket), xml_escape(key));
+    let xml = format!(r#"<?xml version="1.0" encoding="UTF-8"?>
+<CompleteMultipartUploadResult>
+    <Location>http://s3.amazonaws.com/{}/{}</Location>
+    <Bucket>{}</Bucket>
+    <Key>{}</Key>
+    <ETag>{}</ETag>
+</CompleteMultipartUploadResult>"#, bucket, key, bucket, key, quoted_etag(etag));
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/xml")
+        .body(Body::from(xml))
+        .unwrap()
+}
+
+// Build the 404 returned by every multipart operation on an unknown or aborted upload
+fn no_such_upload_response(upload_id: &str) -> Response {
+    Response::builder()
+        .status(StatusCode::NOT_FOUND)
+        .header(header::CONTENT_TYPE, "application/xml")
+        .body(Body::from(format!(r#"<?xml version="1.0" encoding="UTF-8"?>
+<Error>
+    <Code>NoSuchUpload</Code>
+    <Message>The specified upload does not exist. The upload ID may be invalid, or the upload may have been aborted or completed.</Message>
+    <UploadId>{}</UploadId>
+</Error>"#, upload_id)))
+        .unwrap()
+}
+
+// Last-modified time, ETag and size of a listed version, as stored in its metadata sidecar.
+// Only data written without a sidecar is hashed, and its times come from the filesystem.
+fn listed_version_info(data_path: &std::path::Path, metadata_path: &std::path::Path) -> (DateTime<Utc>, String, u64) {
+    if let Some(metadata) = fs::read_to_string(metadata_path)
+        .ok()
+        .and_then(|json| serde_json::from_str::<ObjectMetadata>(&json).ok())
+    {
+        return (metadata.last_modified, metadata.etag, metadata.size);
+    }
+
+    let file_metadata = fs::metadata(data_path).ok();
+    let last_modified = file_metadata.as_ref()
+        .and_then(|m| m.modified().ok())
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|d| Utc.timestamp_opt(d.as_secs() as i64, d.subsec_nanos()).unwrap())
+        .unwrap_or_else(Utc::now);
+    let etag = file_md5(data_path).unwrap_or_else(|_| "unknown".to_string());
+    (last_modified, etag, file_metadata.map(|m| m.len()).unwrap_or(0))
+}
+
+// Storage class of an upload: x-amz-storage-class, else the bucket's default storage class, else STANDARD
+fn upload_storage_class(state: &AppState, bucket: &str, headers: &HeaderMap) -> Result<String, Box<Response>> {
+    let Some(value) = headers.get("x-amz-storage-class") else {
+        return Ok(read_bucket_storage_class(&state.storage_path, bucket)
+            .map(|config| config.storage_class)
+            .unwrap_or_else(|| "STANDARD".to_string()));
+    };
+    match value.to_str() {
+        Ok(storage_class) if STORAGE_CLASSES.contains(&storage_class) => Ok(storage_class.to_string()),
+        _ => Err(Box::new(Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .header(header::CONTENT_TYPE, "application/xml")
+            .body(Body::from(r#"<?xml version="1.0" encoding="UTF-8"?>
+<Error>
+    <Code>InvalidStorageClass</Code>
+    <Message>The storage class you specified is not valid</Message>
+</Error>"#))
+            .unwrap())),
+    }
+}
+
+// Canned ACL of an upload: x-amz-acl, else the bucket's default ACL, else DEFAULT_OBJECT_ACL. None is private.
+fn upload_acl(state: &AppState, bucket: &str, requested: Option<String>) -> Option<String> {
+    requested
+        .or_else(|| read_bucket_default_acl(&state.storage_path, bucket).map(|config| config.acl))
+        .or_else(|| state.settings.default_object_acl.clone())
+        .filter(|acl| acl != "private")
+}
+
+// With CREATE_FOLDER_MARKERS, give each folder above a written key its empty marker object, as S3 browsers do,
+// so clients listing without a delimiter still see "a/" and "a/b/" for "a/b/c.txt". Existing markers are kept.
+fn create_folder_markers(state: &AppState, bucket: &str, key: &str) {
+    if !state.settings.create_folder_markers {
+        return;
+    }
+
+    let bucket_path = state.storage_path.join(bucket);
+    let folders = key.match_indices('/')
+        .map(|(idx, _)| &key[..idx + 1])
+        .filter(|folder| folder.len() < key.len() && !folder.ends_with("//") && *folder != "/");
+    for folder in folders {
+        let marker_path = bucket_path.join(object_storage_key(folder));
+        if marker_path.exists() {
+            continue;
+        }
+
+        let now = Utc::now();
+        let etag = format!("{:x}", md5::compute(b""));
+        let metadata = ObjectMetadata {
+            key: folder.to_string(),
+            size: 0,
+            etag: etag.clone(),
+            last_modified: now,
+            created: Some(now),
+            content_type: "application/x-directory".to_string(),
+            storage_class: "STANDARD".to_string(),
+            metadata: HashMap::new(),
+            version_id: None,
+            encryption: None,
+            tags: None,
+            restore_required: false,
+            website_redirect_location: None,
+            acl: None,
+        };
+        let written = write_storage_file(&marker_path, b"").and_then(|_| {
+            let metadata_json = serde_json::to_string(&metadata).map_err(std::io::Error::other)?;
+            write_storage_file(bucket_path.join(format!("{}.metadata", object_storage_key(folder))), metadata_json)
+        });
+        if let Err(e) = written {
+            warn!("Failed to create folder marker {}/{}: {}", bucket, folder, e);
+            continue;
+        }
+
+        debug!("Created folder marker {}/{}", bucket, folder);
+        state.wal_writer.log_put(bucket, folder, 0, Some(etag));
+        state.list_index.record_write(bucket, folder);
+    }
+}
+
+// Write an object's (possibly encrypted) data, then its metadata sidecar. Runs on a blocking thread.
+// Only a failure to write the data is an error; the object is still readable without its sidecar.
+fn write_object_files(
+    object_path: &std::path::Path,
+    data: &[u8],
+    metadata_path: &std::path::Path,
+    metadata: &ObjectMetadata,
+) -> std::io::Result<()> {
+    // Create parent directory if needed
+    if let Some(parent) = object_path.parent() {
+        if let Err(e) = create_storage_dir(parent) {
+            warn!("Failed to create object parent directory: {}", e);
+        }
+    }
+    let _guard = OBJECT_WRITE_LOCKS.lock(object_path);
+    write_storage_file(object_path, data)?;
+
+    // Ensure parent directory exists for metadata file
+    if let Some(parent) = metadata_path.parent() {
+        if let Err(e) = create_storage_dir(parent) {
+            warn!("Failed to create metadata parent directory: {}", e);
+        }
+    }
+    if let Ok(metadata_json) = serde_json::to_string(metadata) {
+        if let Err(e) = write_storage_file(metadata_path, metadata_json) {
+            warn!("Failed to write metadata file: {}", e);
+        } else {
+            debug!("Metadata saved to: {:?}", metadata_path);
+        }
+    }
+    Ok(())
+}
+
+// Combine the listed parts into the object and its metadata sidecar, then remove the staged parts.
+// Runs on a blocking thread; returns the object's ETag and size.
+fn assemble_multipart_object(
+    bucket_path: &std::path::Path,
+    key: &str,
+    upload_id: &str,
+    parts: Vec<UploadPart>,
+) -> Result<(String, u64), String> {
+    // Read the content type, user metadata, storage class and ACL given at initiation from the upload record
+    let multipart_dir = bucket_path.join(".multipart");
+    let upload_record = fs::read_to_string(multipart_dir.join(format!("{}.upload", upload_id)))
+        .ok()
+        .and_then(|json| serde_json::from_str::<serde_json::Value>(&json).ok());
+    let stored_content_type = upload_record.as_ref()
+        .and_then(|json| json.get("content_type").and_then(|v| v.as_str()).map(String::from))
+        .unwrap_or_else(|| "application/octet-stream".to_string());
+    let stored_storage_class = upload_record.as_ref()
+        .and_then(|json| json.get("storage_class").and_then(|v| v.as_str()).map(String::from))
+        .unwrap_or_else(|| "STANDARD".to_string());
+    let stored_acl = upload_record.as_ref()
+        .and_then(|json| json.get("acl").and_then(|v| v.as_str()).map(String::from));
+    // Uploads started before user metadata was recorded have none
+    let stored_metadata = upload_record
+        .and_then(|mut json| json.get_mut("metadata").map(|v| v.take()))
+        .and_then(|metadata| serde_json::from_value::<HashMap<String, String>>(metadata).ok())
+        .unwrap_or_default();
+
+    // Combine only the listed parts, in the order the client gave
+    let mut combined_data = Vec::new();
+    for part in parts {
+        if part.data.len() as u64 == part.size {
+            combined_data.extend(part.data);
+            continue;
+        }
+
+        // Part was not kept in memory, read it back from disk
+        let part_path = multipart_dir.join(upload_id).join(format!("part-{}", part.part_number));
+        let part_data = fs::read(&part_path)
+            .map_err(|e| format!("failed to read part {} from disk: {}", part.part_number, e))?;
+        combined_data.extend(part_data);
+    }
+
+    let etag = format!("{:x}", md5::compute(&combined_data));
+    let size = combined_data.len() as u64;
+
+    // Write object to disk
+    let _ = create_storage_dir(bucket_path);
+    let object_path = bucket_path.join(key);
+    if let Some(parent) = object_path.parent() {
+        let _ = create_storage_dir(parent);
+    }
+    let guard = OBJECT_WRITE_LOCKS.lock(&object_path);
+    write_storage_file(&object_path, &combined_data)
+        .map_err(|e| format!("failed to write multipart object: {}", e))?;
+
+    // Completing the upload creates a new object, whatever the key held before
+    let now = Utc::now();
+
+    let metadata = ObjectMetadata {
+        key: key.to_string(),
+        size,
+        etag: etag.clone(),
+        last_modified: now,
+        created: Some(now),
+        content_type: stored_content_type, // Use the content type from initiation
+        restore_required: false,
+        storage_class: stored_storage_class,
+        metadata: stored_metadata,
+        version_id: None,
+        encryption: None, // TODO: Add encryption support for multipart
+        tags: None,
+        website_redirect_location: None,
+        acl: stored_acl,
+    };
+    if let Ok(metadata_json) = serde_json::to_string(&metadata) {
+        if let Err(e) = write_storage_file(bucket_path.join(format!("{}.metadata", key)), metadata_json) {
+            warn!("Failed to write multipart object metadata: {}", e);
+        }
+    }
+    drop(guard);
+
+    // Clean up multipart upload directory
+    if let Err(e) = fs::remove_dir_all(multipart_dir.join(upload_id)) {
+        warn!("Failed to clean up multipart directory: {}", e);
+    }
+
+    Ok((etag, size))
+}
+
+// Extract (PartNumber, ETag) pairs from a CompleteMultipartUpload body, in document order
+fn parse_complete_multipart_parts(body: &str) -> Option<Vec<(i32, String)>> {
+    let mut parts = Vec::new();
+
+    for part_xml in body.split("<Part>").skip(1) {
+        let part_xml = part_xml.split("</Part>").next()?;
+
+        let number_start = part_xml.find("<PartNumber>")? + "<PartNumber>".len();
+        let number_end = part_xml.find("</PartNumber>")?;
+        let part_number = part_xml.get(number_start..number_end)?.trim().parse::<i32>().ok()?;
+
+        let etag_start = part_xml.find("<ETag>")? + "<ETag>".len();
+        let etag_end = part_xml.find("</ETag>")?;
+        let etag = part_xml.get(etag_start..etag_end)?
+            .trim()
+            .replace("&quot;", "")
+            .replace("&#34;", "")
+            .trim_matches('"')
+            .to_string();
+
+        parts.push((part_number, etag));
+    }
+
+    Some(parts)
+}
+
+// Encrypt data per the bucket's encryption configuration; plaintext is returned when there is none
+fn encrypt_for_bucket(state: &AppState, bucket: &str, data: &[u8]) -> (Vec<u8>, Option<ObjectEncryption>) {
+    match read_bucket_encryption(&state.storage_path, bucket) {
+        Some(encryption) if encryption.algorithm == "AES256" => {
+            // Generate encryption key and encrypt data
+            let key = generate_encryption_key();
+            match encrypt_object(data, &key, state.settings.encryption_chunk_size) {
+                Ok((encrypted_data, encryption)) => (encrypted_data, Some(ObjectEncryption {
+                    key_base64: BASE64.encode(&key),
+                    ..encryption
+                })),
+                Err(e) => {
+                    warn!("Failed to encrypt object: {}", e);
+                    (data.to_vec(), None)
+                }
+            }
+        }
+        // KMS encryption not implemented
+        _ => (data.to_vec(), None),
+    }
+}
+
+// Header prefixes of the SSE-C key for the object itself and for a copy source
+const SSE_CUSTOMER_HEADERS: &str = "x-amz-server-side-encryption-customer";
+const COPY_SOURCE_SSE_CUSTOMER_HEADERS: &str = "x-amz-copy-source-server-side-encryption-customer";
+
+// A validated SSE-C key and the base64 MD5 the client sent for it
+struct CustomerKey {
+    key: Vec<u8>,
+    key_md5: String,
+}
+
+// Read the -algorithm, -key and -key-MD5 headers under the prefix; Ok(None) when none are sent
+fn parse_customer_key(headers: &HeaderMap, prefix: &str) -> Result<Option<CustomerKey>, Box<Response>> {
+    let value = |suffix: &str| headers.get(format!("{}{}", prefix, suffix)).and_then(|v| v.to_str().ok());
+    let (algorithm, key, key_md5) = (value("-algorithm"), value("-key"), value("-key-md5"));
+    if algorithm.is_none() && key.is_none() && key_md5.is_none() {
+        return Ok(None);
+    }
+
+    if algorithm != Some("AES256") {
+        return Err(sse_customer_error(StatusCode::BAD_REQUEST, "InvalidArgument",
+            "The encryption algorithm specified is not valid; only AES256 is supported."));
+    }
+    let key = match key.and_then(|k| BASE64.decode(k).ok()).filter(|k| k.len() == 32) {
+        Some(key) => key,
+        None => return Err(sse_customer_error(StatusCode::BAD_REQUEST, "InvalidArgument",
+            "The secret key was invalid for the specified algorithm.")),
+    };
+    let computed_md5 = BASE64.encode(md5::compute(&key).0);
+    if key_md5 != Some(computed_md5.as_str()) {
+        return Err(sse_customer_error(StatusCode::BAD_REQUEST, "InvalidArgument",
+            "The calculated MD5 hash of the key did not match the hash that was provided."));
+    }
+
+    Ok(Some(CustomerKey { key, key_md5: computed_md5 }))
+}
+
+// x-amz-website-redirect-location must be a path starting with '/' or an absolute http(s) URL
+fn website_redirect_location(headers: &HeaderMap) -> Result<Option<String>, Box<Response>> {
+    let Some(value) = headers.get("x-amz-website-redirect-location") else {
+        return Ok(None);
+    };
+    match value.to_str() {
+        Ok(location) if location.starts_with('/') || location.starts_with("http://") || location.starts_with("https://") => {
+            Ok(Some(location.to_string()))
+        }
+        _ => Err(Box::new(Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .header(header::CONTENT_TYPE, "application/xml")
+            .body(Body::from(r#"<?xml version="1.0" encoding="UTF-8"?>
+<Error>
+    <Code>InvalidArgument</Code>
+    <Message>The website redirect location must have a prefix of 'http://' or 'https://' or '/'</Message>
+</Error>"#))
+            .unwrap())),
+    }
+}
+
+fn canned_acl(headers: &HeaderMap) -> Result<Option<String>, Box<Response>> {
+    let Some(value) = headers.get("x-amz-acl") else {
+        return Ok(None);
+    };
+    match value.to_str() {
+        Ok(acl) if CANNED_ACLS.contains(&acl) => Ok(Some(acl.to_string())),
+        _ => Err(Box::new(Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .header(header::CONTENT_TYPE, "application/xml")
+            .body(Body::from(r#"<?xml version="1.0" encoding="UTF-8"?>
+<Error>
+    <Code>InvalidArgument</Code>
+    <Message>The x-amz-acl header must be one of the canned ACLs</Message>
+</Error>"#))
+            .unwrap())),
+    }
+}
+
+// The canned ACL equivalent of an AccessControlPolicy body: only grants to the AllUsers group are kept
+fn acl_from_grants(xml: &str) -> Option<String> {
+    let all_users_permissions: Vec<&str> = xml.split("<Grant>")
+        .skip(1)
+        .filter(|grant| grant.contains("http://acs.amazonaws.com/groups/global/AllUsers"))
+        .filter_map(|grant| grant.split("<Permission>").nth(1).and_then(|rest| rest.split("</Permission>").next()))
+        .map(|permission| permission.trim())
+        .collect();
+    let has = |permission: &str| all_users_permissions.contains(&permission) || all_users_permissions.contains(&"FULL_CONTROL");
+    match (has("READ"), has("WRITE")) {
+        (true, true) => Some("public-read-write".to_string()),
+        (true, false) => Some("public-read".to_string()),
+        _ => None,
+    }
+}
+
+// The customer key for reading an SSE-C object; it must be sent and must be the key used at PUT time
+fn require_customer_key(headers: &HeaderMap, prefix: &str, encryption: &ObjectEncryption) -> Result<CustomerKey, Box<Response>> {
+    match parse_customer_key(headers, prefix)? {
+        Some(customer_key) if encryption.customer_key_md5.as_deref() == Some(customer_key.key_md5.as_str()) => Ok(customer_key),
+        Some(_) => Err(sse_customer_error(StatusCode::FORBIDDEN, "AccessDenied",
+            "The provided customer key does not match the key the object was encrypted with.")),
+        None => Err(sse_customer_error(StatusCode::BAD_REQUEST, "InvalidRequest",
+            "The object was stored using a form of Server Side Encryption. The correct parameters must be provided to retrieve the object.")),
+    }
+}
+
+fn encrypt_with_customer_key(customer_key: &CustomerKey, data: &[u8], chunk_size: Option<u64>) -> Result<(Vec<u8>, ObjectEncryption), String> {
+    let (encrypted_data, encryption) = encrypt_object(data, &customer_key.key, chunk_size)?;
+    Ok((encrypted_data, ObjectEncryption {
+        customer_key_md5: Some(customer_key.key_md5.clone()),
+        ..encryption
+    }))
+}
+
+// Copy source errors name the source key, and the version when one was requested
+fn copy_source_error(status: StatusCode, code: &str, message: &str, key: &str, version_id: Option<&str>) -> Response {
+    let version = version_id
+        .map(|vid| format!("\n    <VersionId>{}</VersionId>", xml_escape(vid)))
+        .unwrap_or_default();
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/xml")
+        .body(Body::from(format!(r#"<?xml version="1.0" encoding="UTF-8"?>
+<Error>
+    <Code>{}</Code>
+    <Message>{}</Message>
+    <Key>{}</Key>{}
+</Error>"#, code, message, xml_escape(key), version)))
+        .unwrap()
+}
+
+// Boxed, as SSE-C header checks return it as their error
+fn sse_customer_error(status: StatusCode, code: &str, message: &str) -> Box<Response> {
+    Box::new(Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/xml")
+        .body(Body::from(format!(r#"<?xml version="1.0" encoding="UTF-8"?>
+<Error>
+    <Code>{}</Code>
+    <Message>{}</Message>
+</Error>"#, code, message)))
+        .unwrap())
+}
+
+// HEAD values for an object without usable metadata: file size and an ETag hashed from the file on a blocking thread
+async fn head_fallback_metadata(object_path: PathBuf) -> (u64, String, DateTime<Utc>, String, HashMap<String, String>, String) {
+    let (size, etag) = tokio::task::spawn_blocking(move || {
+        let size = fs::metadata(&object_path).map(|m| m.len()).unwrap_or(0);
+        (size, file_md5(&object_path).unwrap_or_default())
+    }).await.unwrap_or_default();
+    (size, etag, Utc::now(), "application/octet-stream".to_string(), HashMap::new(), "STANDARD".to_string())
+}
+
+// Hex MD5 of a file, read in chunks so large objects are never held in memory
+fn file_md5(path: &std::path::Path) -> std::io::Result<String> {
+    let mut file = fs::File::open(path)?;
+    let mut context = md5::Context::new();
+    let mut buffer = vec![0u8; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        context.consume(&buffer[..read]);
+    }
+    Ok(format!("{:x}", context.compute()))
+}
+
+// Load an object's bytes, memory-mapping files at or above the threshold when GET_MMAP is enabled
+fn read_object_bytes(path: &std::path::Path, settings: &Settings) -> std::io::Result<Bytes> {
+    if settings.get_mmap {
+        let file = fs::File::open(path)?;
+        if file.metadata()?.len() >= settings.get_mmap_threshold {
+            // Safety: objects are replaced by rename and never truncated in place, so the mapping stays valid
+            match unsafe { memmap2::Mmap::map(&file) } {
+                Ok(mmap) => return Ok(Bytes::from_owner(mmap)),
+                Err(e) => debug!("Failed to map {:?}, reading it instead: {}", path, e),
+            }
+        }
+    }
+    fs::read(path).map(Bytes::from)
+}
+
+// Resolve a single "bytes=" Range header to an inclusive (start, end); Ok(None) serves the whole object
+fn parse_range(value: &str, size: u64) -> Result<Option<(u64, u64)>, ()> {
+    let spec = match value.trim().strip_prefix("bytes=") {
+        Some(spec) if !spec.contains(',') => spec.trim(),
+        // Other units and multiple ranges are ignored, as S3 does
+        _ => return Ok(None),
+    };
+    let (start, end) = match spec.split_once('-') {
+        Some(bounds) => bounds,
+        None => return Ok(None),
+    };
+
+    let (start, end) = match (start.trim().parse::<u64>().ok(), end.trim().parse::<u64>().ok()) {
+        // bytes=-N: the last N bytes
+        (None, Some(suffix)) if start.trim().is_empty() => {
+            if suffix == 0 || size == 0 {
+                return Err(());
+            }
+            (size.saturating_sub(suffix), size - 1)
+        }
+        (Some(start), None) if end.trim().is_empty() => (start, size.saturating_sub(1)),
+        (Some(start), Some(end)) if start <= end => (start, end.min(size.saturating_sub(1))),
+        _ => return Ok(None),
+    };
+
+    if start >= size {
+        return Err(());
+    }
+    Ok(Some((start, end)))
+}
+
+// Decode the request body, unwrapping aws-chunked encoding and checking the checksum trailer named by x-amz-trailer.
+// Format: "3e8;chunk-signature=<sig>\r\n<data>\r\n0;chunk-signature=<sig>\r\nx-amz-checksum-crc32c:<b64>\r\n\r\n"
+fn decode_request_body(headers: &HeaderMap, body: &[u8]) -> Result<Vec<u8>, Box<Response>> {
+    let streaming = headers
+        .get("x-amz-content-sha256")
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.starts_with("STREAMING-"))
+        .unwrap_or(false)
+        || headers
+            .get(header::CONTENT_ENCODING)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.contains("aws-chunked"))
+            .unwrap_or(false)
+        || (body.len() > 100 && String::from_utf8_lossy(&body[0..100]).contains(";chunk-signature="));
+    if !streaming {
+        return Ok(body.to_vec());
+    }
+
+    debug!("Detected aws-chunked transfer encoding, parsing chunks");
+    let Some((data, trailers)) = parse_chunked_data(body) else {
+        warn!("aws-chunked body ended before its final zero-length chunk, rejecting it as truncated");
+        return Err(body_error_response(
+            "IncompleteBody",
+            "You did not provide the number of bytes specified by the Content-Length HTTP header",
+        ));
+    };
+
+    let declared = headers.get("x-amz-trailer").and_then(|v| v.to_str().ok()).unwrap_or("");
+    for name in declared.split(',').map(|n| n.trim().to_lowercase()).filter(|n| !n.is_empty()) {
+        let Some(algorithm) = name.strip_prefix("x-amz-checksum-") else {
+            continue;
+        };
+        let expected = match trailers.iter().find(|(trailer, _)| *trailer == name) {
+            Some((_, value)) => value,
+            None => {
+                return Err(body_error_response(
+                    "MalformedTrailerError",
+                    &format!("The request did not include the declared {} trailer", name),
+                ));
+            }
+        };
+        let actual = match compute_checksum(algorithm, &data) {
+            Some(actual) => actual,
+            None => {
+                return Err(body_error_response(
+                    "InvalidRequest",
+                    &format!("Unsupported checksum algorithm: {}", algorithm),
+                ));
+            }
+        };
+        if actual != *expected {
+            warn!("{} mismatch: trailer {}, computed {}", name, expected, actual);
+            return Err(body_error_response(
+                "BadDigest",
+                &format!("The {} you specified did not match the calculated checksum.", name),
+            ));
+        }
+    }
+
+    Ok(data)
+}
+
+// Base64 checksum of the payload in the form S3 sends in x-amz-checksum-* values
+fn compute_checksum(algorithm: &str, data: &[u8]) -> Option<String> {
+    let digest = match algorithm {
+        "crc32" => crc32fast::hash(data).to_be_bytes().to_vec(),
+        "crc32c" => crc32c::crc32c(data).to_be_bytes().to_vec(),
+        "sha1" => sha1::Sha1::digest(data).to_vec(),
+        "sha256" => sha2::Sha256::digest(data).to_vec(),
+        _ => return None,
+    };
+    Some(BASE64.encode(digest))
+}
+
+// 400 error for a request body that cannot be decoded or verified, boxed as decode_request_body's error
+fn body_error_response(code: &str, message: &str) -> Box<Response> {
+    Box::new(Response::builder()
+        .status(StatusCode::BAD_REQUEST)
+        .header(header::CONTENT_TYPE, "application/xml")
+        .body(Body::from(format!(r#"<?xml version="1.0" encoding="UTF-8"?>
+<Error>
+    <Code>{}</Code>
+    <Message>{}</Message>
+</Error>"#, code, message)))
+        .unwrap())
+}
+
+// Data and trailers (lowercased name, value) of a decoded aws-chunked body
+type ChunkedBody = (Vec<u8>, Vec<(String, String)>);
+
+// Decode an aws-chunked body into its data and trailers. None when the body stops before the zero-length
+// chunk that ends it, so a truncated upload is never taken for a complete one.
+fn parse_chunked_data(input: &[u8]) -> Option<ChunkedBody> {
+    let mut result = Vec::new();
+    let mut trailers = Vec::new();
+    let mut pos = 0;
+    let mut terminated = false;
 
     while pos < input.len() {
         // Find the chunk size (before semicolon)
@@ -1574,8 +3117,19 @@ fn parse_chunked_data(input: &[u8]) -> Vec<u8> {
         // Skip past header and \r\n
         pos = chunk_header_end + 2;
 
-        // If chunk size is 0, we're done
+        // If chunk size is 0, only trailer lines remain, up to an empty line
         if chunk_size == 0 {
+            while let Some(line_len) = find_sequence(&input[pos..], b"\r\n") {
+                let line = String::from_utf8_lossy(&input[pos..pos + line_len]).to_string();
+                pos += line_len + 2;
+                if line.is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = line.split_once(':') {
+                    trailers.push((name.trim().to_lowercase(), value.trim().to_string()));
+                }
+            }
+            terminated = true;
             break;
         }
 
@@ -1593,20 +3147,20 @@ fn parse_chunked_data(input: &[u8]) -> Vec<u8> {
         }
     }
 
-    result
+    terminated.then_some((result, trailers))
 }
 
 fn find_sequence(haystack: &[u8], needle: &[u8]) -> Option<usize> {
     haystack.windows(needle.len()).position(|window| window == needle)
 }
 
-fn generate_encryption_key() -> Vec<u8> {
+pub fn generate_encryption_key() -> Vec<u8> {
     let mut key = vec![0u8; 32]; // 256-bit key
     OsRng.fill_bytes(&mut key);
     key
 }
 
-fn encrypt_data(data: &[u8], key: &[u8]) -> Result<(Vec<u8>, Vec<u8>), String> {
+pub fn encrypt_data(data: &[u8], key: &[u8]) -> Result<(Vec<u8>, Vec<u8>), String> {
     let key = Key::<Aes256Gcm>::from_slice(key);
     let cipher = Aes256Gcm::new(key);
 
@@ -1620,7 +3174,7 @@ fn encrypt_data(data: &[u8], key: &[u8]) -> Result<(Vec<u8>, Vec<u8>), String> {
     }
 }
 
-fn decrypt_data(ciphertext: &[u8], key: &[u8], nonce: &[u8]) -> Result<Vec<u8>, String> {
+pub fn decrypt_data(ciphertext: &[u8], key: &[u8], nonce: &[u8]) -> Result<Vec<u8>, String> {
     let key = Key::<Aes256Gcm>::from_slice(key);
     let cipher = Aes256Gcm::new(key);
     let nonce = Nonce::from_slice(nonce);
@@ -1629,4 +3183,93 @@ fn decrypt_data(ciphertext: &[u8], key: &[u8], nonce: &[u8]) -> Result<Vec<u8>,
         Ok(plaintext) => Ok(plaintext),
         Err(e) => Err(format!("Decryption failed: {}", e)),
     }
+}
+
+// Bytes the GCM authentication tag adds to every sealed chunk
+const GCM_TAG_LEN: u64 = 16;
+
+// Encrypt an object with AES-256-GCM. Data larger than chunk_size is sealed as consecutive chunks, each with
+// its own nonce. The returned record has no key; callers fill in key_base64 or customer_key_md5.
+pub fn encrypt_object(data: &[u8], key: &[u8], chunk_size: Option<u64>) -> Result<(Vec<u8>, ObjectEncryption), String> {
+    let mut encryption = ObjectEncryption {
+        algorithm: "AES256".to_string(),
+        key_base64: String::new(),
+        nonce_base64: String::new(),
+        customer_key_md5: None,
+        chunk_size: None,
+        chunk_nonces: Vec::new(),
+    };
+    match chunk_size.filter(|&size| data.len() as u64 > size) {
+        Some(size) => {
+            let mut ciphertext = Vec::with_capacity(data.len() + (data.len() as u64 / size + 1) as usize * GCM_TAG_LEN as usize);
+            for chunk in data.chunks(size as usize) {
+                let (sealed, nonce) = encrypt_data(chunk, key)?;
+                ciphertext.extend_from_slice(&sealed);
+                encryption.chunk_nonces.push(BASE64.encode(&nonce));
+            }
+            encryption.chunk_size = Some(size);
+            Ok((ciphertext, encryption))
+        }
+        None => {
+            let (ciphertext, nonce) = encrypt_data(data, key)?;
+            encryption.nonce_base64 = BASE64.encode(&nonce);
+            Ok((ciphertext, encryption))
+        }
+    }
+}
+
+// Decrypt a whole object, however it was sealed
+pub fn decrypt_object(ciphertext: &[u8], key: &[u8], encryption: &ObjectEncryption) -> Result<Vec<u8>, String> {
+    if encryption.chunk_size.is_none() {
+        let nonce = BASE64.decode(&encryption.nonce_base64).unwrap_or_default();
+        return decrypt_data(ciphertext, key, &nonce);
+    }
+    match chunked_plaintext_size(ciphertext.len() as u64, encryption) {
+        0 => Ok(Vec::new()),
+        size => decrypt_chunk_range(ciphertext, key, encryption, 0, size - 1),
+    }
+}
+
+fn chunked_plaintext_size(ciphertext_len: u64, encryption: &ObjectEncryption) -> u64 {
+    ciphertext_len.saturating_sub(GCM_TAG_LEN * encryption.chunk_nonces.len() as u64)
+}
+
+// Decrypt plaintext bytes start..=end of a chunked object, opening only the chunks they fall in
+fn decrypt_chunk_range(ciphertext: &[u8], key: &[u8], encryption: &ObjectEncryption, start: u64, end: u64) -> Result<Vec<u8>, String> {
+    let chunk_size = encryption.chunk_size.ok_or("object is not chunked")?;
+    let sealed_size = chunk_size + GCM_TAG_LEN;
+    let mut plaintext = Vec::with_capacity((end - start + 1) as usize);
+    for index in start / chunk_size..=end / chunk_size {
+        let nonce = encryption.chunk_nonces.get(index as usize)
+            .and_then(|nonce| BASE64.decode(nonce).ok())
+            .filter(|nonce| nonce.len() == 12)
+            .ok_or_else(|| format!("no valid nonce for chunk {}", index))?;
+        let sealed_start = index * sealed_size;
+        let sealed_end = (sealed_start + sealed_size).min(ciphertext.len() as u64);
+        let sealed = ciphertext.get(sealed_start as usize..sealed_end as usize)
+            .ok_or_else(|| format!("chunk {} is missing", index))?;
+        let chunk = decrypt_data(sealed, key, &nonce)?;
+
+        let chunk_start = index * chunk_size;
+        let from = start.saturating_sub(chunk_start) as usize;
+        let to = ((end - chunk_start + 1) as usize).min(chunk.len());
+        plaintext.extend_from_slice(chunk.get(from..to).ok_or_else(|| format!("chunk {} is truncated", index))?);
+    }
+    Ok(plaintext)
+}
+
+// Resolved (start, end, total size) of a Range that was applied while decrypting
+type DecryptedRange = (u64, u64, u64);
+
+// Decrypt an object for GET. A satisfiable Range on a chunked object only decrypts the chunks it overlaps and
+// comes back with its resolved range; anything else decrypts the whole object.
+fn decrypt_for_read(data: &[u8], key: &[u8], encryption: &ObjectEncryption, range: Option<&str>) -> Result<(Bytes, Option<DecryptedRange>), String> {
+    if encryption.chunk_size.is_some() {
+        let total_size = chunked_plaintext_size(data.len() as u64, encryption);
+        if let Some(Ok(Some((start, end)))) = range.map(|value| parse_range(value, total_size)) {
+            let plaintext = decrypt_chunk_range(data, key, encryption, start, end)?;
+            return Ok((Bytes::from(plaintext), Some((start, end, total_size))));
+        }
+    }
+    decrypt_object(data, key, encryption).map(|plaintext| (Bytes::from(plaintext), None))
 }
\ No newline at end of file
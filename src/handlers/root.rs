@@ -1,15 +1,17 @@
 use axum::{
     body::Body,
-    extract::State,
+    extract::{Path, State},
     http::{HeaderMap, StatusCode, header},
     response::{IntoResponse, Response},
 };
 use bytes::Bytes;
 use chrono::{DateTime, Utc, TimeZone};
 use std::fs;
-use tracing::debug;
+use tracing::{debug, info, warn};
 
-use crate::AppState;
+use crate::{AppState, bucket_exists, read_bucket_encryption};
+use crate::key_rotation::{KeyRotationStatus, rotate_bucket_keys};
+use crate::lifecycle_sweeper::preview_bucket;
 
 pub async fn handle_root_post(
     State(_state): State<AppState>,
@@ -25,6 +27,135 @@ pub async fn handle_root_post(
         .unwrap()
 }
 
+// Report the WAL write sequence against what the replicator has processed, for lag monitoring
+pub async fn replication_status(State(state): State<AppState>) -> impl IntoResponse {
+    let wal = &state.wal_writer;
+    let wal_sequence = wal.last_sequence();
+
+    // The replicator persists its progress after every batch
+    let replicator_state = fs::read_to_string(&state.settings.replicator_state_file)
+        .ok()
+        .and_then(|content| serde_json::from_str::<serde_json::Value>(&content).ok());
+    let processed = replicator_state.as_ref()
+        .and_then(|s| s.get("last_processed_sequence").cloned())
+        .unwrap_or_else(|| serde_json::json!({}));
+    let last_flush = replicator_state.as_ref().and_then(|s| s.get("last_flush").cloned());
+
+    // Like the replicator, a node it has not processed yet counts as sequence 0
+    let lag = match (wal_sequence, &replicator_state) {
+        (Some(written), Some(_)) => {
+            let done = processed.get(wal.node_id()).and_then(|v| v.as_u64()).unwrap_or(0);
+            Some(written.saturating_sub(done))
+        }
+        _ => None,
+    };
+
+    let status = serde_json::json!({
+        "node_id": wal.node_id(),
+        "wal_enabled": wal.is_enabled(),
+        "wal_sequence": wal_sequence,
+        "wal_queue_depth": wal.queue_depth(),
+        "wal_dropped_entries": wal.dropped_entries(),
+        "replicator": {
+            "state_available": replicator_state.is_some(),
+            "last_processed_sequence": processed,
+            "last_flush": last_flush,
+        },
+        "lag": lag,
+    });
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(status.to_string()))
+        .unwrap()
+}
+
+// Start re-encrypting every SSE-S3 object of an AES256 bucket under a new key; progress is polled with GET
+pub async fn start_key_rotation(
+    State(state): State<AppState>,
+    Path(bucket): Path<String>,
+) -> impl IntoResponse {
+    if !bucket_exists(&state.storage_path, &bucket) {
+        return error_response(StatusCode::NOT_FOUND, "NoSuchBucket", "The specified bucket does not exist");
+    }
+    if read_bucket_encryption(&state.storage_path, &bucket).map(|e| e.algorithm).as_deref() != Some("AES256") {
+        return error_response(StatusCode::BAD_REQUEST, "InvalidRequest", "The bucket does not use AES256 default encryption");
+    }
+
+    let status = {
+        let mut rotations = state.key_rotations.lock().unwrap();
+        if rotations.get(&bucket).is_some_and(|status| status.state == "running") {
+            return error_response(StatusCode::CONFLICT, "OperationAborted", "A key rotation is already running for this bucket");
+        }
+        let status = KeyRotationStatus::new(&bucket);
+        rotations.insert(bucket.clone(), status.clone());
+        status
+    };
+
+    info!("Starting key rotation for bucket {}", bucket);
+    let (bucket_path, rotations) = (state.storage_path.join(&bucket), state.key_rotations.clone());
+    tokio::task::spawn_blocking(move || rotate_bucket_keys(&bucket_path, &bucket, &rotations));
+
+    Response::builder()
+        .status(StatusCode::ACCEPTED)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(serde_json::to_string(&status).unwrap()))
+        .unwrap()
+}
+
+// List what the next lifecycle sweep would expire and transition in the bucket, without doing any of it
+pub async fn lifecycle_preview(
+    State(state): State<AppState>,
+    Path(bucket): Path<String>,
+) -> impl IntoResponse {
+    if !bucket_exists(&state.storage_path, &bucket) {
+        return error_response(StatusCode::NOT_FOUND, "NoSuchBucket", "The specified bucket does not exist");
+    }
+
+    let storage_path = state.storage_path.clone();
+    let preview = match tokio::task::spawn_blocking(move || preview_bucket(&storage_path, &bucket)).await {
+        Ok(preview) => preview,
+        Err(e) => {
+            warn!("Lifecycle preview task failed: {}", e);
+            return error_response(StatusCode::INTERNAL_SERVER_ERROR, "InternalError", "The lifecycle preview failed");
+        }
+    };
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(serde_json::to_string(&preview).unwrap()))
+        .unwrap()
+}
+
+// Report the progress of the bucket's latest key rotation since the server started
+pub async fn key_rotation_status(
+    State(state): State<AppState>,
+    Path(bucket): Path<String>,
+) -> impl IntoResponse {
+    match state.key_rotations.lock().unwrap().get(&bucket) {
+        Some(status) => Response::builder()
+            .status(StatusCode::OK)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(serde_json::to_string(status).unwrap()))
+            .unwrap(),
+        None => error_response(StatusCode::NOT_FOUND, "NoSuchKeyRotation", "No key rotation has run for this bucket"),
+    }
+}
+
+fn error_response(status: StatusCode, code: &str, message: &str) -> Response {
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/xml")
+        .body(Body::from(format!(r#"<?xml version="1.0" encoding="UTF-8"?>
+<Error>
+    <Code>{}</Code>
+    <Message>{}</Message>
+</Error>"#, code, message)))
+        .unwrap()
+}
+
 pub async fn list_buckets(State(state): State<AppState>) -> impl IntoResponse {
     debug!("Listing buckets");
 
@@ -0,0 +1,230 @@
+use axum::{
+    body::Body,
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use std::fs;
+use tracing::{debug, warn};
+
+use crate::{
+    AppState, ObjectMetadata, BucketWebsite, check_policy_permission, read_bucket_policy, Principal, read_bucket_website,
+    handlers::object::get_object,
+    utils::{is_reserved_key, is_secure_transport},
+};
+
+// Index and error documents larger than this are refused rather than served as pages
+const WEBSITE_DOCUMENT_MAX_SIZE: u64 = 10 * 1024 * 1024;
+
+// Why an index or error document cannot be served
+enum DocumentCheck {
+    Servable,
+    Missing,
+    Unsuitable(String),
+}
+
+// Serve the website root of a bucket, i.e. its index document
+pub async fn handle_website_root(
+    State(state): State<AppState>,
+    Path(bucket): Path<String>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    serve_website(state, bucket, String::new(), headers).await
+}
+
+// Serve a key through the bucket's website configuration
+pub async fn handle_website_object(
+    State(state): State<AppState>,
+    Path((bucket, key)): Path<(String, String)>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    serve_website(state, bucket, key, headers).await
+}
+
+// Resolve a website request in a fixed number of steps: the key or its index document, one redirect
+// to the "directory" form of the key, and at most one error document. The error document is never
+// itself resolved through this path, so a missing or broken one ends in a plain 404 instead of a loop.
+async fn serve_website(state: AppState, bucket: String, key: String, headers: HeaderMap) -> Response {
+    debug!("Website request: {}/{}", bucket, key);
+
+    let website_lookup = {
+        let (storage_path, bucket) = (state.storage_path.clone(), bucket.clone());
+        tokio::task::spawn_blocking(move || {
+            (read_bucket_website(&storage_path, &bucket), read_bucket_policy(&storage_path, &bucket))
+        }).await
+    };
+    let (website, policy) = match website_lookup {
+        Ok((Some(website), policy)) => (website, policy),
+        _ => return website_error(StatusCode::NOT_FOUND, "NoSuchWebsiteConfiguration", "The specified bucket does not have a website configuration"),
+    };
+
+    if key.split('/').any(|segment| segment == "..") || is_reserved_key(&key) {
+        return plain_not_found();
+    }
+    if !is_public(policy.as_deref(), &bucket, &key, &headers) {
+        return website_error(StatusCode::FORBIDDEN, "AccessDenied", "Access Denied");
+    }
+
+    let bucket_path = state.storage_path.join(&bucket);
+    if key.is_empty() || key.ends_with('/') {
+        let index_key = format!("{}{}", key, website.index_document);
+        match check_document(&bucket_path, &index_key).await {
+            DocumentCheck::Servable => return serve_document(&state, &bucket, index_key, headers, StatusCode::OK).await,
+            DocumentCheck::Unsuitable(reason) => warn!("Not serving index document {}/{}: {}", bucket, index_key, reason),
+            DocumentCheck::Missing => {}
+        }
+    } else {
+        let exists = {
+            let object_path = bucket_path.join(&key);
+            tokio::task::spawn_blocking(move || object_path.is_file()).await.unwrap_or(false)
+        };
+        if exists {
+            return serve_document(&state, &bucket, key, headers, StatusCode::OK).await;
+        }
+
+        // "docs" with a docs/index.html behind it is a directory; send the browser to the slash form once
+        let index_key = format!("{}/{}", key, website.index_document);
+        if let DocumentCheck::Servable = check_document(&bucket_path, &index_key).await {
+            return Response::builder()
+                .status(StatusCode::FOUND)
+                .header(header::LOCATION, format!("/_ironbucket/website/{}/{}/", bucket, key))
+                .body(Body::empty())
+                .unwrap();
+        }
+    }
+
+    serve_error_document(&state, &bucket, &website, policy.as_deref(), headers).await
+}
+
+// Serve the configured error document with a 404, or a plain 404 when it cannot be served
+async fn serve_error_document(
+    state: &AppState,
+    bucket: &str,
+    website: &BucketWebsite,
+    policy: Option<&str>,
+    headers: HeaderMap,
+) -> Response {
+    let Some(error_key) = website.error_document.clone() else {
+        return plain_not_found();
+    };
+    if !is_public(policy, bucket, &error_key, &headers) {
+        return plain_not_found();
+    }
+
+    match check_document(&state.storage_path.join(bucket), &error_key).await {
+        DocumentCheck::Servable => serve_document(state, bucket, error_key, headers, StatusCode::NOT_FOUND).await,
+        DocumentCheck::Unsuitable(reason) => {
+            warn!("Not serving error document {}/{}: {}", bucket, error_key, reason);
+            plain_not_found()
+        }
+        DocumentCheck::Missing => {
+            debug!("Error document {}/{} does not exist", bucket, error_key);
+            plain_not_found()
+        }
+    }
+}
+
+// Read the object through the regular GET path, forcing the status for error documents.
+// Objects carrying x-amz-website-redirect-location answer with a 301 instead, except as error documents.
+async fn serve_document(state: &AppState, bucket: &str, key: String, headers: HeaderMap, status: StatusCode) -> Response {
+    let mut response = get_object(State(state.clone()), Path((bucket.to_string(), key)), None, headers)
+        .await
+        .into_response();
+    if !response.status().is_success() {
+        return plain_not_found();
+    }
+
+    let redirect = response.headers().get("x-amz-website-redirect-location").and_then(|v| v.to_str().ok());
+    if let (StatusCode::OK, Some(location)) = (status, redirect) {
+        // A path is relative to the website root, which here is below /_ironbucket/website/{bucket}
+        let location = match location.strip_prefix('/') {
+            Some(path) => format!("/_ironbucket/website/{}/{}", bucket, path),
+            None => location.to_string(),
+        };
+        return Response::builder()
+            .status(StatusCode::MOVED_PERMANENTLY)
+            .header(header::LOCATION, location)
+            .body(Body::empty())
+            .unwrap();
+    }
+
+    *response.status_mut() = status;
+    response
+}
+
+// Index and error documents must be reasonably small pages, not arbitrary objects
+async fn check_document(bucket_path: &std::path::Path, key: &str) -> DocumentCheck {
+    let object_path = bucket_path.join(key);
+    let metadata_path = bucket_path.join(format!("{}.metadata", key));
+    let key = key.to_string();
+    tokio::task::spawn_blocking(move || {
+        let Ok(file_metadata) = fs::metadata(&object_path) else {
+            return DocumentCheck::Missing;
+        };
+        if !file_metadata.is_file() {
+            return DocumentCheck::Missing;
+        }
+
+        let metadata = fs::read_to_string(&metadata_path)
+            .ok()
+            .and_then(|json| serde_json::from_str::<ObjectMetadata>(&json).ok());
+        let size = metadata.as_ref().map(|m| m.size).unwrap_or(file_metadata.len());
+        if size > WEBSITE_DOCUMENT_MAX_SIZE {
+            return DocumentCheck::Unsuitable(format!("{} bytes exceeds the {} byte limit", size, WEBSITE_DOCUMENT_MAX_SIZE));
+        }
+
+        // Without a metadata sidecar there is no content type to go by, so the extension decides
+        match metadata {
+            Some(metadata) if !is_page_content_type(&metadata.content_type) => {
+                DocumentCheck::Unsuitable(format!("content type {:?} is not a web page", metadata.content_type))
+            }
+            None if !key.ends_with(".html") && !key.ends_with(".htm") => {
+                DocumentCheck::Unsuitable("no content type and no .html extension".to_string())
+            }
+            _ => DocumentCheck::Servable,
+        }
+    }).await.unwrap_or(DocumentCheck::Missing)
+}
+
+fn is_page_content_type(content_type: &str) -> bool {
+    let mime = content_type.split(';').next().unwrap_or("").trim().to_ascii_lowercase();
+    mime.starts_with("text/") || mime == "application/xhtml+xml"
+}
+
+// Website endpoints are anonymous: the bucket policy must grant s3:GetObject to everyone
+fn is_public(policy: Option<&str>, bucket: &str, key: &str, headers: &HeaderMap) -> bool {
+    let client_ip = headers.get("x-real-ip")
+        .or_else(|| headers.get("x-forwarded-for"))
+        .and_then(|v| v.to_str().ok())
+        .map(|s| s.split(',').next().unwrap_or(s).trim().to_string())
+        .unwrap_or_else(|| "127.0.0.1".to_string());
+    policy.is_some_and(|policy| check_policy_permission(
+        policy,
+        "s3:GetObject",
+        &format!("arn:aws:s3:::{}/{}", bucket, key),
+        Principal::Anonymous,
+        Some(&client_ip),
+        is_secure_transport(headers),
+        None,
+    ))
+}
+
+fn plain_not_found() -> Response {
+    Response::builder()
+        .status(StatusCode::NOT_FOUND)
+        .header(header::CONTENT_TYPE, "text/plain")
+        .body(Body::from("404 Not Found"))
+        .unwrap()
+}
+
+fn website_error(status: StatusCode, code: &str, message: &str) -> Response {
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/xml")
+        .body(Body::from(format!(r#"<?xml version="1.0" encoding="UTF-8"?>
+<Error>
+    <Code>{}</Code>
+    <Message>{}</Message>
+</Error>"#, code, message)))
+        .unwrap()
+}
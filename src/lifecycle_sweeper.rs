@@ -0,0 +1,392 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::Serialize;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tracing::{debug, info, warn};
+
+use crate::filesystem::{list_bucket_names, read_bucket_lifecycle, read_bucket_ttl, write_storage_file};
+use crate::list_index::ListIndex;
+use crate::models::{LifecycleRule, ObjectMetadata};
+use crate::quota::QuotaManager;
+use crate::utils::ARCHIVE_STORAGE_CLASSES;
+use crate::wal::WALWriter;
+
+// Background task that expires objects past their bucket TTL and applies lifecycle transition rules
+pub async fn run_lifecycle_sweeper(
+    storage_path: PathBuf,
+    quota_manager: Arc<QuotaManager>,
+    wal_writer: Arc<WALWriter>,
+    list_index: Arc<ListIndex>,
+) {
+    let interval_minutes = env::var("LIFECYCLE_SWEEP_EVERY_X_MIN")
+        .unwrap_or_else(|_| "60".to_string())
+        .parse::<u64>()
+        .unwrap_or(60);
+
+    if interval_minutes == 0 {
+        info!("Lifecycle sweeper is disabled");
+        return;
+    }
+
+    info!("Starting lifecycle sweeper task - will run every {} minutes", interval_minutes);
+
+    loop {
+        // Wait for the next interval
+        tokio::time::sleep(Duration::from_secs(interval_minutes * 60)).await;
+
+        // Expire first so transitions never touch an object that is about to be deleted
+        let path = storage_path.clone();
+        match tokio::task::spawn_blocking(move || expire_all_buckets(&path)).await {
+            Ok(expired) => {
+                for (bucket, key, size) in expired {
+                    info!("Expired {}/{} ({} bytes) past the bucket TTL", bucket, key, size);
+                    wal_writer.log_delete(&bucket, &key);
+                    list_index.record_delete(&bucket, &key);
+                    if let Err(e) = quota_manager.update_quota_remove(&bucket, &key, size).await {
+                        warn!("Failed to update quota for bucket {} after expiry: {}", bucket, e);
+                    }
+                }
+            }
+            Err(e) => warn!("TTL expiry task failed: {}", e),
+        }
+
+        debug!("Running lifecycle sweep...");
+        let path = storage_path.clone();
+        match tokio::task::spawn_blocking(move || sweep_all_buckets(&path)).await {
+            Ok(0) => debug!("Lifecycle sweep completed: no transitions applied"),
+            Ok(count) => info!("Lifecycle sweep completed: transitioned {} objects", count),
+            Err(e) => warn!("Lifecycle sweep task failed: {}", e),
+        }
+    }
+}
+
+// What the next sweep would do to one bucket, computed without changing anything
+#[derive(Serialize)]
+pub struct LifecyclePreview {
+    pub bucket: String,
+    pub evaluated_at: String,
+    pub expirations: Vec<ExpirationPreview>,
+    pub transitions: Vec<TransitionPreview>,
+}
+
+#[derive(Serialize)]
+pub struct ExpirationPreview {
+    pub key: String,
+    pub size: u64,
+    // Only the bucket TTL expires objects; Expiration actions in lifecycle rules are not applied
+    pub reason: &'static str,
+}
+
+#[derive(Serialize)]
+pub struct TransitionPreview {
+    pub key: String,
+    pub rule_id: Option<String>,
+    pub from: String,
+    pub to: String,
+}
+
+// Evaluate the bucket's TTL and lifecycle transition rules against its current objects, the way the sweeper would
+pub fn preview_bucket(storage_path: &PathBuf, bucket: &str) -> LifecyclePreview {
+    let bucket_path = storage_path.join(bucket);
+
+    let expirations: Vec<ExpirationPreview> = bucket_expiry_cutoff(storage_path, bucket)
+        .map(|cutoff| expiry_candidates(&bucket_path, &bucket_path, cutoff))
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(_, key, size)| ExpirationPreview { key, size, reason: "ttl" })
+        .collect();
+
+    // Expiry runs first, so objects it deletes are never transitioned
+    let mut transitions = Vec::new();
+    if let Some(lifecycle) = read_bucket_lifecycle(storage_path, bucket) {
+        let rules = transition_rules(&lifecycle.rules);
+        let now = Utc::now();
+        visit_metadata_files(&bucket_path, &mut |metadata_path| {
+            let Some((key, metadata)) = read_object_metadata(&bucket_path, metadata_path) else {
+                return;
+            };
+            if expirations.iter().any(|expiration| expiration.key == key) {
+                return;
+            }
+            if let Some((to, rule_id)) = due_transition(&key, &metadata, &rules, now) {
+                transitions.push(TransitionPreview {
+                    key,
+                    rule_id: rule_id.map(String::from),
+                    from: metadata.storage_class.clone(),
+                    to: to.to_string(),
+                });
+            }
+        });
+    }
+    transitions.sort_by(|a, b| a.key.cmp(&b.key));
+
+    LifecyclePreview {
+        bucket: bucket.to_string(),
+        evaluated_at: Utc::now().to_rfc3339(),
+        expirations,
+        transitions,
+    }
+}
+
+// Enabled rules that carry transitions, the only rule actions the sweeper applies
+fn transition_rules(rules: &[LifecycleRule]) -> Vec<&LifecycleRule> {
+    rules.iter()
+        .filter(|rule| rule.status == "Enabled" && rule.transitions.is_some())
+        .collect()
+}
+
+// Apply lifecycle rules in every bucket that has a lifecycle configuration
+fn sweep_all_buckets(storage_path: &PathBuf) -> usize {
+    let mut transitioned = 0;
+
+    for bucket in list_bucket_names(storage_path).unwrap_or_default() {
+        if let Some(lifecycle) = read_bucket_lifecycle(storage_path, &bucket) {
+            let rules = transition_rules(&lifecycle.rules);
+
+            if !rules.is_empty() {
+                let bucket_path = storage_path.join(&bucket);
+                visit_metadata_files(&bucket_path, &mut |metadata_path| {
+                    if apply_transitions(&bucket_path, metadata_path, &rules) {
+                        transitioned += 1;
+                    }
+                });
+            }
+        }
+    }
+
+    transitioned
+}
+
+// Objects created before this time are past the bucket's TTL
+fn bucket_expiry_cutoff(storage_path: &PathBuf, bucket: &str) -> Option<SystemTime> {
+    let ttl = read_bucket_ttl(storage_path, bucket)?;
+    SystemTime::now().checked_sub(Duration::from_secs(ttl.seconds))
+}
+
+// Delete objects older than their bucket's TTL; returns (bucket, key, size) for each deleted object
+fn expire_all_buckets(storage_path: &PathBuf) -> Vec<(String, String, u64)> {
+    let mut expired = Vec::new();
+
+    for bucket in list_bucket_names(storage_path).unwrap_or_default() {
+        let Some(cutoff) = bucket_expiry_cutoff(storage_path, &bucket) else {
+            continue;
+        };
+        let bucket_path = storage_path.join(&bucket);
+        for (path, key, size) in expiry_candidates(&bucket_path, &bucket_path, cutoff) {
+            match fs::remove_file(&path) {
+                Ok(_) => {
+                    let _ = fs::remove_file(format!("{}.metadata", path.display()));
+                    expired.push((bucket.clone(), key, size));
+                }
+                Err(e) => warn!("Failed to expire {:?}: {}", path, e),
+            }
+        }
+    }
+
+    expired
+}
+
+// Recursively find objects created before the cutoff; returns (path, key, size) for each
+fn expiry_candidates(bucket_path: &Path, dir: &Path, cutoff: SystemTime) -> Vec<(PathBuf, String, u64)> {
+    let mut candidates = Vec::new();
+
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return candidates,
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().to_string();
+
+        // Internal files and directories all start with '.'
+        if name.starts_with('.') || name.ends_with(".metadata") {
+            continue;
+        }
+
+        let metadata = match entry.metadata() {
+            Ok(metadata) => metadata,
+            Err(_) => continue,
+        };
+
+        if metadata.is_dir() {
+            candidates.extend(expiry_candidates(bucket_path, &path, cutoff));
+        } else if object_created(&path, &metadata).is_some_and(|created| created < cutoff) {
+            let key = match path.strip_prefix(bucket_path) {
+                Ok(relative) => relative.to_string_lossy().replace('\\', "/"),
+                Err(_) => continue,
+            };
+            candidates.push((path, key, metadata.len()));
+        }
+    }
+
+    candidates
+}
+
+// An object's creation time from its metadata sidecar, as for transitions, so a key rotation that rewrites the data
+// does not restart the TTL. Objects without a sidecar use the file's modification time.
+fn object_created(path: &Path, file_metadata: &fs::Metadata) -> Option<SystemTime> {
+    fs::read_to_string(format!("{}.metadata", path.display()))
+        .ok()
+        .and_then(|json| serde_json::from_str::<ObjectMetadata>(&json).ok())
+        .map(|metadata| SystemTime::from(metadata.created_at()))
+        .or_else(|| file_metadata.modified().ok())
+}
+
+// Recursively visit object metadata files, skipping internal directories
+fn visit_metadata_files(dir: &Path, visit: &mut dyn FnMut(&Path)) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().to_string();
+
+        if path.is_dir() {
+            // .versions, .multipart, .stats and friends are not objects
+            if !name.starts_with('.') {
+                visit_metadata_files(&path, visit);
+            }
+        } else if name.ends_with(".metadata") {
+            visit(&path);
+        }
+    }
+}
+
+// Read an object's metadata sidecar together with the key it belongs to
+fn read_object_metadata(bucket_path: &Path, metadata_path: &Path) -> Option<(String, ObjectMetadata)> {
+    let metadata = fs::read_to_string(metadata_path)
+        .ok()
+        .and_then(|json| serde_json::from_str::<ObjectMetadata>(&json).ok())?;
+
+    // The key stored in metadata may be stale for folder objects; derive it from the path
+    let key = metadata_path.strip_prefix(bucket_path).ok()?
+        .to_string_lossy()
+        .trim_end_matches(".metadata")
+        .to_string();
+    Some((key, metadata))
+}
+
+// The coldest storage class among the transitions due for the object, with the ID of the rule that asks for it.
+// None unless that class is colder than the current one, as transitions never warm an object.
+fn due_transition<'a>(
+    key: &str,
+    metadata: &ObjectMetadata,
+    rules: &[&'a LifecycleRule],
+    now: DateTime<Utc>,
+) -> Option<(&'a str, Option<&'a str>)> {
+    let age_days = (now - metadata.created_at()).num_days();
+
+    let mut target: Option<(&str, Option<&str>)> = None;
+    for rule in rules.iter().filter(|rule| rule_matches(rule, key, metadata)) {
+        for transition in rule.transitions.iter().flatten() {
+            let due = match (transition.days, &transition.date) {
+                (Some(days), _) => age_days >= days as i64,
+                (None, Some(date)) => parse_lifecycle_date(date).is_some_and(|d| now >= d),
+                (None, None) => false,
+            };
+
+            if due && target.is_none_or(|(t, _)| storage_class_rank(&transition.storage_class) > storage_class_rank(t)) {
+                target = Some((&transition.storage_class, rule.id.as_deref()));
+            }
+        }
+    }
+
+    target.filter(|(class, _)| storage_class_rank(class) > storage_class_rank(&metadata.storage_class))
+}
+
+// Update the object's storage class if a transition rule matches; returns true when changed
+fn apply_transitions(bucket_path: &Path, metadata_path: &Path, rules: &[&LifecycleRule]) -> bool {
+    let Some((key, mut metadata)) = read_object_metadata(bucket_path, metadata_path) else {
+        return false;
+    };
+    let Some((target, _)) = due_transition(&key, &metadata, rules, Utc::now()) else {
+        return false;
+    };
+
+    debug!("Transitioning {:?} from {} to {}", metadata_path, metadata.storage_class, target);
+    metadata.storage_class = target.to_string();
+    metadata.restore_required = ARCHIVE_STORAGE_CLASSES.contains(&target);
+
+    match serde_json::to_string_pretty(&metadata) {
+        Ok(json) => match write_storage_file(metadata_path, json) {
+            Ok(_) => true,
+            Err(e) => {
+                warn!("Failed to write metadata {:?}: {}", metadata_path, e);
+                false
+            }
+        },
+        Err(_) => false,
+    }
+}
+
+// Check a rule's prefix and tag filter against an object
+fn rule_matches(rule: &LifecycleRule, key: &str, metadata: &ObjectMetadata) -> bool {
+    let filter = match &rule.filter {
+        Some(filter) => filter,
+        None => return true,
+    };
+
+    let tag_matches = |tag_key: &str, tag_value: &str| {
+        metadata.tags.as_ref()
+            .and_then(|tags| tags.get(tag_key))
+            .is_some_and(|value| value == tag_value)
+    };
+
+    if let Some(prefix) = &filter.prefix {
+        if !key.starts_with(prefix.as_str()) {
+            return false;
+        }
+    }
+
+    if let Some(tag) = &filter.tag {
+        if !tag_matches(&tag.key, &tag.value) {
+            return false;
+        }
+    }
+
+    if let Some(and) = &filter.and {
+        if let Some(prefix) = &and.prefix {
+            if !key.starts_with(prefix.as_str()) {
+                return false;
+            }
+        }
+        if let Some(tags) = &and.tags {
+            if !tags.iter().all(|tag| tag_matches(&tag.key, &tag.value)) {
+                return false;
+            }
+        }
+    }
+
+    true
+}
+
+// Lifecycle dates are ISO 8601, either a full timestamp or a plain date
+fn parse_lifecycle_date(date: &str) -> Option<DateTime<Utc>> {
+    if let Ok(dt) = DateTime::parse_from_rfc3339(date) {
+        return Some(dt.with_timezone(&Utc));
+    }
+    NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .ok()
+        .and_then(|d| d.and_hms_opt(0, 0, 0))
+        .map(|dt| dt.and_utc())
+}
+
+// Relative temperature of a storage class, colder classes rank higher
+fn storage_class_rank(storage_class: &str) -> u8 {
+    match storage_class {
+        "STANDARD" => 0,
+        "INTELLIGENT_TIERING" => 1,
+        "STANDARD_IA" => 2,
+        "ONEZONE_IA" => 3,
+        "GLACIER_IR" => 4,
+        "GLACIER" => 5,
+        "DEEP_ARCHIVE" => 6,
+        _ => 0,
+    }
+}
@@ -0,0 +1,208 @@
+use chrono::{TimeZone, Utc};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::ops::Bound;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tracing::{debug, info};
+
+use crate::models::ObjectData;
+use crate::utils::object_storage_key;
+
+enum BucketIndex {
+    // The first listing is scanning the bucket; changes made meanwhile are replayed onto the scan result
+    Building(Vec<(String, Option<ObjectData>)>),
+    Ready(BTreeMap<String, ObjectData>),
+}
+
+// Sorted in-memory index of every bucket's keys (LIST_INDEX=true), so a listing page costs O(max-keys)
+// lookups instead of a scan and sort of the whole bucket. A bucket is scanned once, on its first listing,
+// and is then kept current by the write paths. Files changed directly on disk are not seen until a restart.
+pub struct ListIndex {
+    storage_path: PathBuf,
+    enabled: bool,
+    buckets: Mutex<HashMap<String, BucketIndex>>,
+}
+
+impl ListIndex {
+    pub fn new(storage_path: PathBuf, enabled: bool) -> Self {
+        ListIndex {
+            storage_path,
+            enabled,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    // Claim the initial scan of a bucket; false when it is already indexed or another listing is scanning it
+    pub fn begin_build(&self, bucket: &str) -> bool {
+        if !self.enabled {
+            return false;
+        }
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.contains_key(bucket) {
+            return false;
+        }
+        buckets.insert(bucket.to_string(), BucketIndex::Building(Vec::new()));
+        true
+    }
+
+    // Install the scan result, applying the writes that happened while it ran
+    pub fn finish_build(&self, bucket: &str, objects: Vec<(String, ObjectData)>) {
+        let mut buckets = self.buckets.lock().unwrap();
+        // The bucket was deleted during the scan
+        let Some(BucketIndex::Building(pending)) = buckets.remove(bucket) else {
+            return;
+        };
+
+        let mut index: BTreeMap<String, ObjectData> = objects.into_iter().collect();
+        for (key, object) in pending {
+            match object {
+                Some(object) => index.insert(key, object),
+                None => index.remove(&key),
+            };
+        }
+        info!("Listing index for bucket {} built with {} keys", bucket, index.len());
+        buckets.insert(bucket.to_string(), BucketIndex::Ready(index));
+    }
+
+    // Give up on a scan that failed, so the next listing tries again
+    pub fn abort_build(&self, bucket: &str) {
+        let mut buckets = self.buckets.lock().unwrap();
+        if let Some(BucketIndex::Building(_)) = buckets.get(bucket) {
+            buckets.remove(bucket);
+        }
+    }
+
+    // Record that the key was written, or removed when its file is gone
+    pub fn record_write(&self, bucket: &str, key: &str) {
+        if !self.enabled {
+            return;
+        }
+        let object = fs::metadata(self.storage_path.join(bucket).join(object_storage_key(key)))
+            .ok()
+            .filter(|metadata| metadata.is_file())
+            .map(|metadata| listing_object_data(&metadata));
+        self.apply(bucket, key, object);
+    }
+
+    pub fn record_delete(&self, bucket: &str, key: &str) {
+        if !self.enabled {
+            return;
+        }
+        self.apply(bucket, key, None);
+    }
+
+    // Drop a deleted bucket; a bucket of the same name created later is scanned afresh
+    pub fn forget_bucket(&self, bucket: &str) {
+        self.buckets.lock().unwrap().remove(bucket);
+    }
+
+    fn apply(&self, bucket: &str, key: &str, object: Option<ObjectData>) {
+        let mut buckets = self.buckets.lock().unwrap();
+        match buckets.get_mut(bucket) {
+            Some(BucketIndex::Building(pending)) => pending.push((key.to_string(), object)),
+            Some(BucketIndex::Ready(index)) => {
+                debug!("Listing index for bucket {}: {} {}", bucket, if object.is_some() { "updated" } else { "removed" }, key);
+                match object {
+                    Some(object) => index.insert(key.to_string(), object),
+                    None => index.remove(key),
+                };
+            }
+            // Not listed yet; the first listing scans it
+            None => {}
+        }
+    }
+
+    // Up to max_keys + 1 listing entries after start_after, in the shape list_objects_impl builds from a scan:
+    // keys with their data, or common prefixes (None) when a delimiter rolls keys up.
+    // The extra entry only tells whether the page is truncated. None when the bucket is not indexed yet.
+    pub fn page(
+        &self,
+        bucket: &str,
+        prefix: &str,
+        delimiter: Option<&str>,
+        start_after: &str,
+        max_keys: usize,
+    ) -> Option<Vec<(String, Option<ObjectData>)>> {
+        let buckets = self.buckets.lock().unwrap();
+        let Some(BucketIndex::Ready(index)) = buckets.get(bucket) else {
+            return None;
+        };
+        let delimiter = delimiter.filter(|delim| !delim.is_empty());
+
+        let mut entries = Vec::new();
+        let mut from = if start_after >= prefix {
+            Bound::Excluded(start_after.to_string())
+        } else {
+            Bound::Included(prefix.to_string())
+        };
+        while entries.len() <= max_keys {
+            let Some((key, object)) = index.range((from.clone(), Bound::Unbounded)).next() else {
+                break;
+            };
+            if !key.starts_with(prefix) {
+                break;
+            }
+
+            let rolled_up = delimiter
+                .and_then(|delim| key[prefix.len()..].find(delim).map(|idx| key[..prefix.len() + idx + delim.len()].to_string()));
+            match rolled_up {
+                Some(common_prefix) => {
+                    // Jump past every key under the common prefix; a prefix the previous page ended with is not repeated
+                    from = match prefix_successor(&common_prefix) {
+                        Some(successor) => Bound::Included(successor),
+                        None => break,
+                    };
+                    if common_prefix.as_str() > start_after {
+                        entries.push((common_prefix, None));
+                    }
+                }
+                None => {
+                    from = Bound::Excluded(key.clone());
+                    entries.push((key.clone(), Some(object.clone())));
+                }
+            }
+        }
+        Some(entries)
+    }
+}
+
+// Size, modification time and placeholder ETag of a listed file, the same whether scanned or indexed
+pub fn listing_object_data(metadata: &fs::Metadata) -> ObjectData {
+    let size = metadata.len();
+    let last_modified = metadata.modified()
+        .ok()
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|d| Utc.timestamp_opt(d.as_secs() as i64, d.subsec_nanos()).unwrap())
+        .unwrap_or_else(Utc::now);
+    let etag = format!("{:x}", md5::compute(format!("{}-{}", size, last_modified.timestamp()).as_bytes()));
+
+    ObjectData {
+        data: Vec::new(),
+        size,
+        last_modified,
+        etag,
+    }
+}
+
+// Smallest string greater than every string that starts with the prefix: the prefix with its last character
+// incremented. None when no such string exists, i.e. every following key starts with the prefix.
+fn prefix_successor(prefix: &str) -> Option<String> {
+    let mut chars: Vec<char> = prefix.chars().collect();
+    while let Some(last) = chars.pop() {
+        // Skip the surrogate range, which char cannot represent
+        let next = match last as u32 + 1 {
+            0xD800 => Some('\u{E000}'),
+            code => char::from_u32(code),
+        };
+        if let Some(next) = next {
+            chars.push(next);
+            return Some(chars.into_iter().collect());
+        }
+    }
+    None
+}
@@ -1,35 +1,53 @@
 use axum::{
     extract::DefaultBodyLimit,
-    middleware,
-    routing::{delete, get, head, post, put},
+    body::{Body, HttpBody},
+    extract::{Request, State},
+    http::{header, Extensions, HeaderMap, Method, StatusCode, Version},
+    middleware::{self, Next},
+    response::Response,
+    routing::{get, options, post},
     Router,
 };
 use std::{
     collections::HashMap,
     env,
-    fs,
-    net::SocketAddr,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
     path::PathBuf,
     sync::{Arc, Mutex},
+    time::Duration,
 };
-use tower_http::cors::CorsLayer;
-use tracing::info;
+use tower::ServiceBuilder;
+use tower_http::{
+    compression::{predicate::{Predicate, SizeAbove}, CompressionLayer},
+    cors::CorsLayer,
+};
+use tracing::{error, info};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 // Import modules
 mod models;
 mod utils;
 mod cleanup;
+mod lifecycle_sweeper;
 mod policy_check;
+mod post_policy;
 mod filesystem;
 mod handlers;
 mod quota;
+mod settings;
 mod wal;
+mod access_log;
+mod env_check;
+mod key_rotation;
+mod request_id;
+mod metrics_export;
+mod list_index;
+mod shutdown;
 
 // Re-export commonly used items from modules
 pub use models::*;
 pub use utils::format_http_date;
-pub use policy_check::check_policy_permission;
+pub use policy_check::{check_policy_permission, is_explicitly_denied, Principal};
 pub use filesystem::*;
 use handlers::*;
 
@@ -51,14 +69,24 @@ async fn main() {
     let storage_path = env::var("STORAGE_PATH")
         .unwrap_or_else(|_| "/s3".to_string());
     let storage_path = PathBuf::from(storage_path);
-    fs::create_dir_all(&storage_path).unwrap();
+    let settings = settings::Settings::from_env();
+    filesystem::init_storage_modes(settings.storage_file_mode, settings.storage_dir_mode);
+
+    // Refuse to start on a misconfigured environment, listing everything that needs fixing
+    if let Err(problems) = env_check::validate_environment(&storage_path) {
+        eprintln!("IronBucket cannot start, the configuration has {} problem(s):", problems.len());
+        for problem in &problems {
+            eprintln!("  - {}", problem);
+        }
+        eprintln!("See the Configuration section of README.md for all settings.");
+        std::process::exit(1);
+    }
     info!("Using storage path: {:?}", storage_path);
+    info!("Created files use mode {:o}, directories {:o}", settings.storage_file_mode, settings.storage_dir_mode);
 
-    // Load credentials from environment variables (required)
-    let access_key = env::var("ACCESS_KEY")
-        .expect("ACCESS_KEY environment variable must be set");
-    let secret_key = env::var("SECRET_KEY")
-        .expect("SECRET_KEY environment variable must be set");
+    // Load credentials from environment variables (presence checked by validate_environment)
+    let access_key = env::var("ACCESS_KEY").unwrap_or_default();
+    let secret_key = env::var("SECRET_KEY").unwrap_or_default();
 
     let mut access_keys = HashMap::new();
     access_keys.insert(access_key.clone(), secret_key.clone());
@@ -84,9 +112,8 @@ async fn main() {
     let wal_path = if enable_wal {
         let path = env::var("WAL_PATH")
             .unwrap_or_else(|_| "/wal".to_string());
-        let wal_dir = PathBuf::from(&path);
-        fs::create_dir_all(&wal_dir).unwrap();
-        wal_dir.join("wal.log")
+        // Created and checked for writability by validate_environment
+        PathBuf::from(&path).join("wal.log")
     } else {
         PathBuf::from("/dev/null")
     };
@@ -100,54 +127,323 @@ async fn main() {
         info!("WAL disabled");
     }
 
-    let wal_writer = Arc::new(wal::WALWriter::new(wal_path, node_id, enable_wal));
+    // A full WAL queue holds writes back this long before dropping the entry
+    let wal_send_timeout = Duration::from_millis(
+        env::var("WAL_SEND_TIMEOUT_MS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(100)
+    );
+
+    let wal_writer = Arc::new(wal::WALWriter::new(wal_path, node_id, enable_wal, wal_send_timeout));
+
+    info!("Multipart parts above {} bytes are kept on disk only", settings.multipart_memory_threshold);
+
+    let list_index = Arc::new(list_index::ListIndex::new(storage_path.clone(), settings.list_index));
+    if list_index.is_enabled() {
+        info!("Listings are served from an in-memory key index, built per bucket on its first listing");
+    }
+
+    let access_logger = Arc::new(access_log::AccessLogger::new(
+        storage_path.clone(),
+        quota_manager.clone(),
+        wal_writer.clone(),
+        list_index.clone(),
+    ));
+
+    let metrics_exporter = Arc::new(metrics_export::MetricsExporter::new(
+        storage_path.clone(),
+        quota_manager.clone(),
+    ));
 
     let state = AppState {
         storage_path: storage_path.clone(),
         access_keys: Arc::new(access_keys),
         multipart_uploads: Arc::new(Mutex::new(HashMap::new())),
+        completed_uploads: Arc::new(Mutex::new(HashMap::new())),
         quota_manager: quota_manager.clone(),
-        wal_writer,
+        wal_writer: wal_writer.clone(),
+        settings: Arc::new(settings),
+        access_logger: access_logger.clone(),
+        key_rotations: Arc::new(Mutex::new(HashMap::new())),
+        metrics_exporter: metrics_exporter.clone(),
+        list_index: list_index.clone(),
+        in_flight: Arc::new(shutdown::InFlightRequests::default()),
     };
 
+    // Gzip the XML that bucket and root requests generate (listings, configurations) for clients sending
+    // Accept-Encoding: gzip. Object routes are left out so stored bodies are never re-encoded.
+    let xml_compression = ServiceBuilder::new()
+        .layer(CompressionLayer::new().compress_when(SizeAbove::new(1024).and(is_xml_response)))
+        .layer(middleware::from_fn(set_content_length));
+
+    // Root requests only carry small documents, so their bodies get a small limit; the DefaultBodyLimit::disable
+    // below stays in effect for object routes, and bucket routes are limited by limit_bucket_body
+    let config_body_limit = DefaultBodyLimit::max(state.settings.config_body_max_bytes);
+
+    // One method router serves both /:bucket and /:bucket/ so subresources behave the same either way
+    let bucket_routes = get(handle_bucket_get)
+        .put(handle_bucket_put)
+        .post(handle_bucket_post)
+        .delete(delete_bucket)
+        .head(head_bucket)
+        .layer(middleware::from_fn_with_state(state.clone(), limit_bucket_body))
+        .layer(xml_compression.clone());
+
     let app = Router::new()
         // Root endpoints
-        .route("/", get(list_buckets))
-        .route("/", post(handle_root_post))
+        .route("/", get(list_buckets).layer(xml_compression))
+        .route("/", post(handle_root_post).layer(config_body_limit))
+
+        // Replication lag for monitoring; create_bucket rejects names starting with '_'
+        .route("/_ironbucket/replication", get(replication_status))
+
+        // Re-encrypt a bucket's SSE-S3 objects under new keys in the background, and report progress
+        .route("/_ironbucket/key-rotation/:bucket", post(start_key_rotation).get(key_rotation_status))
+
+        // Dry run of the lifecycle sweep for one bucket
+        .route("/_ironbucket/lifecycle-preview/:bucket", get(lifecycle_preview))
 
         // Bucket endpoints with query parameter support
-        .route("/:bucket", get(handle_bucket_get))
-        .route("/:bucket", put(handle_bucket_put))
-        .route("/:bucket", post(handle_bucket_post))
-        .route("/:bucket", delete(delete_bucket))
-        .route("/:bucket", head(head_bucket))
-        .route("/:bucket/", get(handle_bucket_get))
-        .route("/:bucket/", put(handle_bucket_put))
-        .route("/:bucket/", post(handle_bucket_post))
-        .route("/:bucket/", delete(delete_bucket))
-        .route("/:bucket/", head(head_bucket))
-
-        // Object endpoints with query parameter support
-        .route("/:bucket/*key", get(handle_object_get))
-        .route("/:bucket/*key", put(handle_object_put))
-        // .route("/:bucket/*key", post(handle_object_post))  // TODO: Fix handler compilation
-        .route("/:bucket/*key", delete(handle_object_delete))
-        .route("/:bucket/*key", head(head_object))
+        .route("/:bucket", bucket_routes.clone())
+        .route("/:bucket/", bucket_routes)
+
+        // Object endpoints with query parameter support; one method router so a 405 lists each method once
+        .route("/:bucket/*key", get(handle_object_get)
+            .put(handle_object_put)
+            .post(handle_object_post)
+            .delete(handle_object_delete)
+            .head(head_object)
+            .layer(middleware::from_fn_with_state(state.clone(), limit_object_subresource_body)))
 
         .layer(middleware::from_fn_with_state(state.clone(), auth_middleware))
+        // Outside auth so rejected requests are logged too
+        .layer(middleware::from_fn_with_state(state.clone(), access_log::access_log_middleware))
+        .layer(middleware::from_fn_with_state(state.clone(), metrics_export::metrics_middleware))
         .layer(CorsLayer::permissive())
+        // Outside the permissive layer, so a bucket's CORS configuration overrides its headers
+        .layer(middleware::from_fn_with_state(state.clone(), bucket_cors_middleware))
         .layer(DefaultBodyLimit::disable()) // Disable body limit for S3 compatibility
-        .with_state(state);
+        .with_state(state.clone())
+
+        // CORS preflight, merged after the permissive layer so the bucket's CORS configuration decides
+        .merge(preflight_routes(state.clone()))
+
+        // Anonymous static website serving, gated by the bucket policy instead of signatures
+        .merge(website_routes(state.clone()))
+
+        // Give the bare 405 of unrouted methods an S3 error body, for every router above
+        .layer(middleware::from_fn(method_not_allowed_body))
+
+        // Plaintext requests are refused before any other handling, website and preflight requests included
+        .layer(middleware::from_fn_with_state(state.clone(), require_tls_middleware))
+
+        // Outermost, so every response carries the ID the access log records
+        .layer(middleware::from_fn_with_state(state.clone(), request_id::request_id_middleware))
+
+        // Counted for the shutdown summary
+        .layer(middleware::from_fn_with_state(state.clone(), shutdown::in_flight_middleware));
 
     // Spawn the background cleanup task
     tokio::spawn(cleanup::cleanup_empty_directories(storage_path.clone()));
 
+    // Spawn the lifecycle transition sweeper
+    tokio::spawn(lifecycle_sweeper::run_lifecycle_sweeper(
+        storage_path.clone(),
+        quota_manager.clone(),
+        wal_writer.clone(),
+        list_index,
+    ));
+
     // Spawn the quota flush task
     tokio::spawn(quota_manager.start_flush_task());
 
-    let addr = SocketAddr::from(([0, 0, 0, 0], 9000));
+    // Spawn server access log delivery
+    tokio::spawn(access_logger.start_flush_task());
+
+    // Spawn the metrics export, which returns right away unless METRICS_EXPORT is set
+    tokio::spawn(metrics_exporter.start_export_task());
+
+    // Both were validated by validate_environment
+    let bind_address = env::var("BIND_ADDRESS")
+        .ok()
+        .and_then(|v| v.trim().parse::<IpAddr>().ok())
+        .unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+    let port = env::var("PORT")
+        .ok()
+        .and_then(|v| v.trim().parse::<u16>().ok())
+        .unwrap_or(9000);
+    let addr = SocketAddr::new(bind_address, port);
+
+    let listener = match tokio::net::TcpListener::bind(addr).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("IronBucket cannot listen on {}: {}", addr, e);
+            eprintln!("Check BIND_ADDRESS and PORT, and that no other process uses the port.");
+            std::process::exit(1);
+        }
+    };
     info!("IronBucket listening on {} with full S3 API support", addr);
 
-    let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+    axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown_signal(state.in_flight.clone()))
+        .await
+        .unwrap();
+
+    // Requests have drained; persist the cached quotas and make sure their WAL entries reach disk before exiting
+    info!("Shutting down, flushing quotas and the WAL");
+    let quota_flush = state.quota_manager.flush_all().await;
+    let wal_drain = match tokio::task::spawn_blocking(move || wal_writer.shutdown()).await {
+        Ok(drain) => drain,
+        Err(e) => {
+            error!("Failed to shut down the WAL writer: {}", e);
+            None
+        }
+    };
+    let pending_uploads = state.multipart_uploads.lock().unwrap().len();
+    shutdown::log_summary(&state.in_flight, quota_flush, wal_drain, pending_uploads);
+}
+
+// Resolves on Ctrl+C or SIGTERM, the signal container runtimes send on stop
+async fn shutdown_signal(in_flight: Arc<shutdown::InFlightRequests>) {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            error!("Failed to listen for Ctrl+C: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                error!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+    in_flight.signal_received();
+}
+
+// The compression layer hides the body length from hyper, so responses it leaves uncompressed would
+// otherwise go out chunked; an explicit Content-Length survives and is dropped when the body is gzipped
+async fn set_content_length(request: Request, next: Next) -> Response {
+    let is_head = request.method() == Method::HEAD;
+    let mut response = next.run(request).await;
+    if let Some(length) = response.body().size_hint().exact().filter(|_| !is_head) {
+        response.headers_mut().entry(header::CONTENT_LENGTH).or_insert(length.into());
+    }
+    response
+}
+
+// Object routes take unlimited bodies for object data, but ?tagging, ?acl and the multipart POSTs carry small
+// XML documents. Their subresource is in the query, which a per-route body limit cannot see, so it is checked here.
+async fn limit_object_subresource_body(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let is_subresource = match *request.method() {
+        Method::POST => true,
+        Method::PUT => request.uri().query().unwrap_or("").split('&').any(|param| {
+            matches!(param.split('=').next(), Some("tagging") | Some("acl"))
+        }),
+        _ => false,
+    };
+    if !is_subresource {
+        return next.run(request).await;
+    }
+    run_with_config_body_limit(&state, request, next).await
+}
+
+// Bucket requests carry configuration documents and batch deletes, except POST form uploads whose file is object data
+async fn limit_bucket_body(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let is_form_upload = request.method() == Method::POST
+        && request.headers().get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|content_type| content_type.trim_start().to_ascii_lowercase().starts_with("multipart/form-data"));
+    if is_form_upload {
+        return next.run(request).await;
+    }
+    run_with_config_body_limit(&state, request, next).await
+}
+
+// Read the body up to CONFIG_BODY_MAX_BYTES before passing the request on, or reject it with 413
+async fn run_with_config_body_limit(state: &AppState, request: Request, next: Next) -> Response {
+    let limit = state.settings.config_body_max_bytes;
+    let (parts, body) = request.into_parts();
+    match axum::body::to_bytes(body, limit).await {
+        Ok(bytes) => next.run(Request::from_parts(parts, Body::from(bytes))).await,
+        Err(e) => {
+            info!("Rejecting {} {}: body exceeds {} bytes ({})", parts.method, parts.uri, limit, e);
+            Response::builder()
+                .status(StatusCode::PAYLOAD_TOO_LARGE)
+                .header(header::CONTENT_TYPE, "application/xml")
+                .body(Body::from(format!(r#"<?xml version="1.0" encoding="UTF-8"?>
+<Error>
+    <Code>MaxMessageLengthExceeded</Code>
+    <Message>Your request was too big. The limit is {} bytes.</Message>
+</Error>"#, limit)))
+                .unwrap()
+        }
+    }
+}
+
+// Axum answers methods a route does not serve with an empty 405 that already carries the Allow header.
+// S3 clients expect an error document, so one is added; handlers' own 405 responses have a body and are kept.
+async fn method_not_allowed_body(request: Request, next: Next) -> Response {
+    let method = request.method().clone();
+    let resource_type = match request.uri().path().trim_matches('/').split_once('/') {
+        _ if request.uri().path() == "/" => "SERVICE",
+        Some((_, key)) if !key.is_empty() => "OBJECT",
+        _ => "BUCKET",
+    };
+
+    let mut response = next.run(request).await;
+    if response.status() != StatusCode::METHOD_NOT_ALLOWED || response.headers().contains_key(header::CONTENT_TYPE) {
+        return response;
+    }
+
+    let body = format!(r#"<?xml version="1.0" encoding="UTF-8"?>
+<Error>
+    <Code>MethodNotAllowed</Code>
+    <Message>The specified method is not allowed against this resource.</Message>
+    <Method>{}</Method>
+    <ResourceType>{}</ResourceType>
+</Error>"#, method, resource_type);
+    response.headers_mut().remove(header::CONTENT_LENGTH);
+    response.headers_mut().insert(header::CONTENT_TYPE, header::HeaderValue::from_static("application/xml"));
+    *response.body_mut() = Body::from(body);
+    response
+}
+
+fn is_xml_response(_: StatusCode, _: Version, headers: &HeaderMap, _: &Extensions) -> bool {
+    headers.get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|content_type| content_type.starts_with("application/xml"))
+}
+
+fn website_routes(state: AppState) -> Router {
+    Router::new()
+        .route("/_ironbucket/website/:bucket", get(handle_website_root))
+        .route("/_ironbucket/website/:bucket/", get(handle_website_root))
+        .route("/_ironbucket/website/:bucket/*key", get(handle_website_object))
+        .with_state(state)
+}
+
+fn preflight_routes(state: AppState) -> Router {
+    let bucket_preflight = options(handle_bucket_options);
+    Router::new()
+        .route("/", options(handle_root_options))
+        .route("/:bucket", bucket_preflight.clone())
+        .route("/:bucket/", bucket_preflight)
+        .route("/:bucket/*key", options(handle_object_options))
+        .with_state(state)
 }
\ No newline at end of file
@@ -0,0 +1,198 @@
+use axum::{
+    body::HttpBody,
+    extract::{Request, State},
+    http::header,
+    middleware::Next,
+    response::Response,
+};
+use chrono::Utc;
+use std::collections::HashMap;
+use std::env;
+use std::mem;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::interval;
+use tracing::{info, warn};
+
+use crate::filesystem::{list_bucket_names, write_storage_file};
+use crate::models::AppState;
+use crate::quota::QuotaManager;
+
+const DEFAULT_EXPORT_INTERVAL_SECS: u64 = 60;
+const TOP_KEYS: usize = 10;
+
+enum MetricsDestination {
+    Stdout,
+    File(PathBuf),
+}
+
+// Traffic of one bucket since the last export. Nothing identifying clients (addresses, access keys) is kept.
+#[derive(Default)]
+struct BucketTraffic {
+    bytes_in: u64,
+    bytes_out: u64,
+    key_requests: HashMap<String, u64>,
+}
+
+// Periodically writes per-bucket request counts (from BucketStats), storage use, traffic and the most
+// requested keys as one JSON document, for deployments without a metrics stack
+pub struct MetricsExporter {
+    storage_path: PathBuf,
+    destination: Option<MetricsDestination>,
+    export_interval: Duration,
+    traffic: Mutex<HashMap<String, BucketTraffic>>,
+    quota_manager: Arc<QuotaManager>,
+}
+
+impl MetricsExporter {
+    pub fn new(storage_path: PathBuf, quota_manager: Arc<QuotaManager>) -> Self {
+        // "stdout" prints one JSON line per export; anything else is a file replaced on each export
+        let destination = env::var("METRICS_EXPORT")
+            .ok()
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty())
+            .map(|v| if v == "stdout" { MetricsDestination::Stdout } else { MetricsDestination::File(PathBuf::from(v)) });
+
+        let export_interval_secs = env::var("METRICS_EXPORT_INTERVAL_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .filter(|secs| *secs > 0)
+            .unwrap_or(DEFAULT_EXPORT_INTERVAL_SECS);
+
+        MetricsExporter {
+            storage_path,
+            destination,
+            export_interval: Duration::from_secs(export_interval_secs),
+            traffic: Mutex::new(HashMap::new()),
+            quota_manager,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.destination.is_some()
+    }
+
+    fn record(&self, bucket: &str, key: Option<&str>, bytes_in: u64, bytes_out: u64) {
+        let mut traffic = self.traffic.lock().unwrap();
+        let bucket_traffic = traffic.entry(bucket.to_string()).or_default();
+        bucket_traffic.bytes_in += bytes_in;
+        bucket_traffic.bytes_out += bytes_out;
+        if let Some(key) = key {
+            *bucket_traffic.key_requests.entry(key.to_string()).or_default() += 1;
+        }
+    }
+
+    // Build the document for every bucket and write it; the traffic counters restart afterwards
+    pub async fn export(&self) {
+        let Some(destination) = self.destination.as_ref() else {
+            return;
+        };
+        let mut traffic = mem::take(&mut *self.traffic.lock().unwrap());
+        let buckets = list_bucket_names(&self.storage_path).unwrap_or_default();
+        let stats_enabled = self.quota_manager.is_enabled();
+
+        let mut bucket_metrics = Vec::with_capacity(buckets.len());
+        for bucket in buckets {
+            let bucket_traffic = traffic.remove(&bucket).unwrap_or_default();
+            let mut top_keys: Vec<(String, u64)> = bucket_traffic.key_requests.into_iter().collect();
+            top_keys.sort_by(|(a_key, a), (b_key, b)| b.cmp(a).then_with(|| a_key.cmp(b_key)));
+            top_keys.truncate(TOP_KEYS);
+
+            // Request counts and storage use come from quota and stats tracking, when it is enabled
+            let (requests, storage) = if stats_enabled {
+                let stats = self.quota_manager.get_stats(&bucket, None).await.ok();
+                let quota = self.quota_manager.get_quota(&bucket).await.ok();
+                (
+                    stats.map(|s| serde_json::json!({
+                        "get": s.get_count,
+                        "put": s.put_count,
+                        "delete": s.delete_count,
+                        "list": s.list_count,
+                        "head": s.head_count,
+                        "multipart": s.multipart_count,
+                    })),
+                    quota.map(|q| serde_json::json!({
+                        "bytes": q.current_usage_bytes,
+                        "objects": q.object_count,
+                    })),
+                )
+            } else {
+                (None, None)
+            };
+
+            bucket_metrics.push(serde_json::json!({
+                "bucket": bucket,
+                "requests_this_month": requests,
+                "storage": storage,
+                "bytes_in": bucket_traffic.bytes_in,
+                "bytes_out": bucket_traffic.bytes_out,
+                "top_keys": top_keys.into_iter()
+                    .map(|(key, requests)| serde_json::json!({ "key": key, "requests": requests }))
+                    .collect::<Vec<_>>(),
+            }));
+        }
+
+        let document = serde_json::json!({
+            "timestamp": Utc::now().to_rfc3339(),
+            "interval_secs": self.export_interval.as_secs(),
+            "buckets": bucket_metrics,
+        });
+
+        match destination {
+            MetricsDestination::Stdout => println!("{}", document),
+            MetricsDestination::File(path) => {
+                if let Err(e) = write_storage_file(path, document.to_string()) {
+                    warn!("Failed to write metrics to {}: {}", path.display(), e);
+                }
+            }
+        }
+    }
+
+    // Background task to periodically export metrics
+    pub async fn start_export_task(self: Arc<Self>) {
+        if !self.is_enabled() {
+            return;
+        }
+        info!("Exporting access metrics every {} seconds", self.export_interval.as_secs());
+        let mut interval = interval(self.export_interval);
+        // The first tick fires immediately, before any traffic was seen
+        interval.tick().await;
+
+        loop {
+            interval.tick().await;
+            self.export().await;
+        }
+    }
+}
+
+// Count bytes in and out of each bucket and requests per object key for the metrics export
+pub async fn metrics_middleware(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    if !state.metrics_exporter.is_enabled() {
+        return next.run(request).await;
+    }
+
+    // Bucket names cannot start with '_', which keeps /_ironbucket endpoints out of the metrics
+    let path = request.uri().path().trim_start_matches('/');
+    let (bucket, key) = match path.split_once('/') {
+        Some((bucket, key)) if !key.is_empty() => (bucket.to_string(), Some(key.to_string())),
+        Some((bucket, _)) => (bucket.to_string(), None),
+        None => (path.to_string(), None),
+    };
+    if bucket.is_empty() || bucket.starts_with('_') {
+        return next.run(request).await;
+    }
+    let key = key.map(|key| urlencoding::decode(&key).map(|k| k.into_owned()).unwrap_or(key));
+    let bytes_in = request.headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(0);
+
+    let response = next.run(request).await;
+
+    // Streamed bodies of unknown length are not counted
+    let bytes_out = response.body().size_hint().exact().unwrap_or(0);
+    state.metrics_exporter.record(&bucket, key.as_deref(), bytes_in, bytes_out);
+    response
+}
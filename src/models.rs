@@ -10,8 +10,21 @@ pub struct AppState {
     pub storage_path: PathBuf,
     pub access_keys: Arc<HashMap<String, String>>,
     pub multipart_uploads: Arc<Mutex<HashMap<String, MultipartUpload>>>,
+    pub completed_uploads: Arc<Mutex<HashMap<String, CompletedUpload>>>,
     pub quota_manager: Arc<crate::quota::QuotaManager>,
     pub wal_writer: Arc<crate::wal::WALWriter>,
+    pub settings: Arc<crate::settings::Settings>,
+    pub access_logger: Arc<crate::access_log::AccessLogger>,
+    pub key_rotations: crate::key_rotation::KeyRotations,
+    pub metrics_exporter: Arc<crate::metrics_export::MetricsExporter>,
+    pub list_index: Arc<crate::list_index::ListIndex>,
+    pub in_flight: Arc<crate::shutdown::InFlightRequests>,
+}
+
+// Identity established by auth_middleware, attached to the request extensions
+#[derive(Clone, Debug)]
+pub struct AuthenticatedUser {
+    pub access_key: String,
 }
 
 #[derive(Clone)]
@@ -32,6 +45,38 @@ pub struct BucketEncryption {
     pub kms_key_id: Option<String>, // KMS key ID if using KMS
 }
 
+// Server access log delivery: records for the bucket are written as objects under target_prefix in target_bucket
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct BucketLogging {
+    pub target_bucket: String,
+    pub target_prefix: String,
+}
+
+// Static website hosting: "directory" requests get index_document appended; missing keys get error_document
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct BucketWebsite {
+    pub index_document: String,
+    pub error_document: Option<String>,
+}
+
+// IronBucket extension: objects older than this many seconds are deleted by the lifecycle sweeper
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct BucketTtl {
+    pub seconds: u64,
+}
+
+// IronBucket extension: storage class of objects uploaded to the bucket without x-amz-storage-class
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct BucketStorageClass {
+    pub storage_class: String,
+}
+
+// IronBucket extension: canned ACL of objects uploaded to the bucket without x-amz-acl
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct BucketDefaultAcl {
+    pub acl: String,
+}
+
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct CorsConfiguration {
     #[serde(rename = "CORSRules")]
@@ -152,7 +197,7 @@ pub struct ObjectData {
     pub data: Vec<u8>,
     pub etag: String,
     pub last_modified: DateTime<Utc>,
-    pub size: usize,
+    pub size: u64,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -161,30 +206,72 @@ pub struct ObjectVersion {
     pub data: Vec<u8>,
     pub etag: String,
     pub last_modified: DateTime<Utc>,
-    pub size: usize,
+    pub size: u64,
     pub is_latest: bool,
     pub is_delete_marker: bool,
 }
 
+// Stored as .versions/{key}/{version_id}.delete_marker when a versioned object is deleted
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct DeleteMarker {
+    pub version_id: String,
+    pub last_modified: DateTime<Utc>,
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 pub struct ObjectMetadata {
     pub key: String,
     pub size: u64,
     pub etag: String,
-    pub last_modified: DateTime<Utc>,
+    pub last_modified: DateTime<Utc>, // Last write of the content
+    // When a PUT, copy or multipart upload wrote this object; kept across tagging, ACL changes, restores and key rotation.
+    // Metadata written before this was recorded has none and falls back to last_modified.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created: Option<DateTime<Utc>>,
     pub content_type: String,
     pub storage_class: String,
     pub metadata: HashMap<String, String>,
     pub version_id: Option<String>,
     pub encryption: Option<ObjectEncryption>,
     pub tags: Option<HashMap<String, String>>,
+    #[serde(default)]
+    pub restore_required: bool, // Set when lifecycle moves the object to an archive class
+    // x-amz-website-redirect-location: website requests for the object get a 301 here instead of the content
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub website_redirect_location: Option<String>,
+    // Canned ACL such as public-read; None is private
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub acl: Option<String>,
+}
+
+impl ObjectMetadata {
+    // Lifecycle ages are counted from here, so tagging or re-encrypting an object does not restart them
+    pub fn created_at(&self) -> DateTime<Utc> {
+        self.created.unwrap_or(self.last_modified)
+    }
+}
+
+// Progress of a resumable Content-Range PUT: inclusive byte ranges received so far, merged and sorted
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct PartialUpload {
+    pub total: u64,
+    pub received: Vec<(u64, u64)>,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
 pub struct ObjectEncryption {
     pub algorithm: String,
-    pub key_base64: String, // Base64 encoded encryption key
+    pub key_base64: String, // Base64 encoded encryption key, empty for SSE-C
     pub nonce_base64: String, // Base64 encoded nonce for GCM
+    // SSE-C: base64 MD5 of the customer's key, which itself is never stored
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub customer_key_md5: Option<String>,
+    // Set when the object is sealed as independent chunks of this many plaintext bytes, each with its own
+    // nonce, so a range only decrypts the chunks it overlaps; nonce_base64 is then empty
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chunk_size: Option<u64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub chunk_nonces: Vec<String>,
 }
 
 #[derive(Clone)]
@@ -196,11 +283,20 @@ pub struct MultipartUpload {
     pub initiated: DateTime<Utc>,
 }
 
+// A multipart upload that was completed recently, kept so a retried CompleteMultipartUpload gets the same answer
+#[derive(Clone)]
+pub struct CompletedUpload {
+    pub bucket: String,
+    pub key: String,
+    pub etag: String,
+    pub completed: Instant,
+}
+
 #[derive(Clone)]
 pub struct UploadPart {
     pub part_number: i32,
     pub etag: String,
-    pub size: usize,
+    pub size: u64,
     pub data: Vec<u8>,
 }
 
@@ -218,12 +314,22 @@ pub struct BucketQueryParams {
     pub delete: Option<String>,
     pub quota: Option<String>,
     pub stats: Option<String>,
+    pub ttl: Option<String>,
+    #[serde(rename = "storage-class")]
+    pub storage_class: Option<String>,
+    #[serde(rename = "default-acl")]
+    pub default_acl: Option<String>,
     pub month: Option<String>,
+    pub website: Option<String>,
     #[serde(rename = "max-keys")]
     pub max_keys: Option<usize>,
     pub prefix: Option<String>,
     #[serde(rename = "continuation-token")]
     pub continuation_token: Option<String>,
+    #[serde(rename = "start-after")]
+    pub start_after: Option<String>,
+    // ListObjects (V1) pagination
+    pub marker: Option<String>,
     pub delimiter: Option<String>,
     #[serde(rename = "list-type")]
     pub list_type: Option<String>,
@@ -231,9 +337,46 @@ pub struct BucketQueryParams {
     pub version_id_marker: Option<String>,
     #[serde(rename = "key-marker")]
     pub key_marker: Option<String>,
+    // IronBucket extension: metadata=true adds user metadata and tags to listed objects
+    pub metadata: Option<String>,
+    // IronBucket extension: only list objects last modified at or after / before an RFC 3339 time
+    #[serde(rename = "modified-since")]
+    pub modified_since: Option<String>,
+    #[serde(rename = "modified-before")]
+    pub modified_before: Option<String>,
+    // Subresources that are recognized but not implemented
+    pub logging: Option<String>,
+    pub notification: Option<String>,
+    pub accelerate: Option<String>,
+    pub replication: Option<String>,
+    pub analytics: Option<String>,
+    pub inventory: Option<String>,
+    pub metrics: Option<String>,
+    #[serde(rename = "ownershipControls")]
+    pub ownership_controls: Option<String>,
+    // IronBucket extension: DELETE /{bucket}?force removes everything in the bucket first
+    pub force: Option<String>,
 }
 
-#[derive(Deserialize, Debug)]
+impl BucketQueryParams {
+    // Name of the first unimplemented subresource present in the query, if any
+    pub fn unimplemented_subresource(&self) -> Option<&'static str> {
+        [
+            (&self.notification, "notification"),
+            (&self.accelerate, "accelerate"),
+            (&self.replication, "replication"),
+            (&self.analytics, "analytics"),
+            (&self.inventory, "inventory"),
+            (&self.metrics, "metrics"),
+            (&self.ownership_controls, "ownershipControls"),
+        ]
+        .iter()
+        .find(|(value, _)| value.is_some())
+        .map(|(_, name)| *name)
+    }
+}
+
+#[derive(Deserialize, Debug, Default)]
 pub struct ObjectQueryParams {
     pub uploads: Option<String>,
     #[serde(rename = "uploadId")]
@@ -245,6 +388,7 @@ pub struct ObjectQueryParams {
     #[serde(rename = "versionId")]
     pub version_id: Option<String>,
     pub tagging: Option<String>,
+    pub restore: Option<String>,
 }
 
 // Quota and Stats structures
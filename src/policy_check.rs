@@ -1,165 +1,274 @@
 use serde_json;
 use tracing::debug;
 
-// Check if an action is allowed based on bucket policy with IP conditions
+// Who made a request, as matched against the Principal of policy statements
+#[derive(Clone, Copy, Debug)]
+pub enum Principal<'a> {
+    Anonymous,
+    // A signed request; every configured access key belongs to the server's account
+    AccessKey { access_key: &'a str, account_id: &'a str },
+}
+
+// Check if an action is allowed based on bucket policy with IP and transport conditions
 pub fn check_policy_permission(
     policy_json: &str,
     action: &str,
     resource: &str,
-    principal: &str,
+    principal: Principal,
     client_ip: Option<&str>,
+    secure_transport: bool,
+    s3_prefix: Option<&str>,
 ) -> bool {
-    debug!("Checking policy permission: action={}, resource={}, principal={}, client_ip={:?}",
-           action, resource, principal, client_ip);
-
-    // Parse the policy
-    if let Ok(policy) = serde_json::from_str::<serde_json::Value>(policy_json) {
-        if let Some(statements) = policy.get("Statement").and_then(|s| s.as_array()) {
-            for statement in statements {
-                // Check Effect
-                let effect = statement.get("Effect")
-                    .and_then(|e| e.as_str())
-                    .unwrap_or("");
-
-                // Check Principal
-                let principal_match = if let Some(p) = statement.get("Principal") {
-                    if p.as_str() == Some("*") || p == "*" {
-                        true
-                    } else if let Some(aws) = p.get("AWS") {
-                        if let Some(arr) = aws.as_array() {
-                            arr.iter().any(|v| v.as_str() == Some(principal))
-                        } else {
-                            aws.as_str() == Some(principal)
-                        }
-                    } else {
-                        false
-                    }
-                } else {
-                    false
-                };
-
-                // Check Action
-                let action_match = if let Some(actions) = statement.get("Action") {
-                    if let Some(arr) = actions.as_array() {
-                        arr.iter().any(|a| {
-                            if let Some(act) = a.as_str() {
-                                act == action || act == "s3:*" ||
-                                (act.ends_with("*") && action.starts_with(&act[..act.len()-1]))
+    debug!("Checking policy permission: action={}, resource={}, principal={:?}, client_ip={:?}, secure_transport={}, s3_prefix={:?}",
+           action, resource, principal, client_ip, secure_transport, s3_prefix);
+
+    // As in AWS, a matching Deny wins over any Allow regardless of statement order
+    let mut allowed = false;
+    for statement in policy_statements(policy_json) {
+        // Check Effect
+        let effect = statement.get("Effect")
+            .and_then(|e| e.as_str())
+            .unwrap_or("");
+
+        // If all conditions match (including IP and transport conditions)
+        if statement_matches(&statement, action, resource, principal, client_ip, secure_transport, s3_prefix) {
+            debug!("Statement matched with effect: {}", effect);
+            if effect == "Allow" {
+                allowed = true;
+            } else if effect == "Deny" {
+                return false;
+            }
+        }
+    }
+
+    // Default deny if no matching statement
+    if !allowed {
+        debug!("No matching statement found, denying access");
+    }
+    allowed
+}
+
+// Whether any Deny statement matches, regardless of statement order.
+// Used where access is granted outside the policy (object ACLs), since an explicit deny always wins.
+pub fn is_explicitly_denied(
+    policy_json: &str,
+    action: &str,
+    resource: &str,
+    principal: Principal,
+    client_ip: Option<&str>,
+    secure_transport: bool,
+) -> bool {
+    policy_statements(policy_json).iter().any(|statement| {
+        statement.get("Effect").and_then(|e| e.as_str()) == Some("Deny")
+            && statement_matches(statement, action, resource, principal, client_ip, secure_transport, None)
+    })
+}
+
+fn policy_statements(policy_json: &str) -> Vec<serde_json::Value> {
+    serde_json::from_str::<serde_json::Value>(policy_json)
+        .ok()
+        .and_then(|policy| policy.get("Statement").and_then(|s| s.as_array()).cloned())
+        .unwrap_or_default()
+}
+
+// Whether a statement's principal, action, resource and conditions all apply to the request
+fn statement_matches(
+    statement: &serde_json::Value,
+    action: &str,
+    resource: &str,
+    principal: Principal,
+    client_ip: Option<&str>,
+    secure_transport: bool,
+    s3_prefix: Option<&str>,
+) -> bool {
+    // Check Principal: "*" or {"AWS": ...} naming "*", an access key, or the account as an ID or root ARN
+    let principal_match = match statement.get("Principal") {
+        Some(p) if p.as_str() == Some("*") => true,
+        Some(p) => p.get("AWS").is_some_and(|aws| match aws.as_array() {
+            Some(arr) => arr.iter().any(|v| principal_value_matches(v, principal)),
+            None => principal_value_matches(aws, principal),
+        }),
+        None => false,
+    };
+
+    // Check Action, or NotAction which applies to every action it does not list
+    let action_match = match (statement.get("Action"), statement.get("NotAction")) {
+        (Some(actions), _) => patterns_match(actions, action, "s3:*") == Some(true),
+        (None, Some(not_actions)) => patterns_match(not_actions, action, "s3:*") == Some(false),
+        (None, None) => false,
+    };
+
+    // Check Resource, or NotResource which applies to every resource it does not list
+    let resource_match = match (statement.get("Resource"), statement.get("NotResource")) {
+        (Some(resources), _) => patterns_match(resources, resource, "*") == Some(true),
+        (None, Some(not_resources)) => patterns_match(not_resources, resource, "*") == Some(false),
+        (None, None) => false,
+    };
+
+    // Check Conditions (including IP address)
+    let condition_match = if let Some(conditions) = statement.get("Condition") {
+        let mut all_conditions_met = true;
+
+        // Check IpAddress condition
+        if let Some(ip_condition) = conditions.get("IpAddress") {
+            if let Some(source_ip_condition) = ip_condition.get("aws:SourceIp") {
+                if let Some(client_ip_str) = client_ip {
+                    let ip_allowed = if let Some(arr) = source_ip_condition.as_array() {
+                        arr.iter().any(|allowed_ip| {
+                            if let Some(ip_str) = allowed_ip.as_str() {
+                                is_ip_in_range(client_ip_str, ip_str)
                             } else {
                                 false
                             }
                         })
-                    } else if let Some(act) = actions.as_str() {
-                        act == action || act == "s3:*" ||
-                        (act.ends_with("*") && action.starts_with(&act[..act.len()-1]))
+                    } else if let Some(ip_str) = source_ip_condition.as_str() {
+                        is_ip_in_range(client_ip_str, ip_str)
                     } else {
                         false
+                    };
+                    if !ip_allowed {
+                        debug!("IP condition not met: client_ip={} not in allowed range", client_ip_str);
+                        all_conditions_met = false;
                     }
                 } else {
-                    false
-                };
-
-                // Check Resource
-                let resource_match = if let Some(resources) = statement.get("Resource") {
-                    if let Some(arr) = resources.as_array() {
-                        arr.iter().any(|r| {
-                            if let Some(res) = r.as_str() {
-                                res == resource || res == "*" ||
-                                (res.ends_with("*") && resource.starts_with(&res[..res.len()-1]))
+                    // No client IP available, condition fails
+                    debug!("IP condition not met: no client IP available");
+                    all_conditions_met = false;
+                }
+            }
+        }
+
+        // Check NotIpAddress condition
+        if let Some(not_ip_condition) = conditions.get("NotIpAddress") {
+            if let Some(source_ip_condition) = not_ip_condition.get("aws:SourceIp") {
+                if let Some(client_ip_str) = client_ip {
+                    let ip_blocked = if let Some(arr) = source_ip_condition.as_array() {
+                        arr.iter().any(|blocked_ip| {
+                            if let Some(ip_str) = blocked_ip.as_str() {
+                                is_ip_in_range(client_ip_str, ip_str)
                             } else {
                                 false
                             }
                         })
-                    } else if let Some(res) = resources.as_str() {
-                        res == resource || res == "*" ||
-                        (res.ends_with("*") && resource.starts_with(&res[..res.len()-1]))
+                    } else if let Some(ip_str) = source_ip_condition.as_str() {
+                        is_ip_in_range(client_ip_str, ip_str)
                     } else {
                         false
+                    };
+                    if ip_blocked {
+                        debug!("NotIpAddress condition not met: client_ip={} is in blocked range", client_ip_str);
+                        all_conditions_met = false;
                     }
-                } else {
-                    false
-                };
-
-                // Check Conditions (including IP address)
-                let condition_match = if let Some(conditions) = statement.get("Condition") {
-                    let mut all_conditions_met = true;
-
-                    // Check IpAddress condition
-                    if let Some(ip_condition) = conditions.get("IpAddress") {
-                        if let Some(source_ip_condition) = ip_condition.get("aws:SourceIp") {
-                            if let Some(client_ip_str) = client_ip {
-                                let ip_allowed = if let Some(arr) = source_ip_condition.as_array() {
-                                    arr.iter().any(|allowed_ip| {
-                                        if let Some(ip_str) = allowed_ip.as_str() {
-                                            is_ip_in_range(client_ip_str, ip_str)
-                                        } else {
-                                            false
-                                        }
-                                    })
-                                } else if let Some(ip_str) = source_ip_condition.as_str() {
-                                    is_ip_in_range(client_ip_str, ip_str)
-                                } else {
-                                    false
-                                };
-                                if !ip_allowed {
-                                    debug!("IP condition not met: client_ip={} not in allowed range", client_ip_str);
-                                    all_conditions_met = false;
-                                }
-                            } else {
-                                // No client IP available, condition fails
-                                debug!("IP condition not met: no client IP available");
-                                all_conditions_met = false;
-                            }
-                        }
-                    }
+                }
+            }
+        }
 
-                    // Check NotIpAddress condition
-                    if let Some(not_ip_condition) = conditions.get("NotIpAddress") {
-                        if let Some(source_ip_condition) = not_ip_condition.get("aws:SourceIp") {
-                            if let Some(client_ip_str) = client_ip {
-                                let ip_blocked = if let Some(arr) = source_ip_condition.as_array() {
-                                    arr.iter().any(|blocked_ip| {
-                                        if let Some(ip_str) = blocked_ip.as_str() {
-                                            is_ip_in_range(client_ip_str, ip_str)
-                                        } else {
-                                            false
-                                        }
-                                    })
-                                } else if let Some(ip_str) = source_ip_condition.as_str() {
-                                    is_ip_in_range(client_ip_str, ip_str)
-                                } else {
-                                    false
-                                };
-                                if ip_blocked {
-                                    debug!("NotIpAddress condition not met: client_ip={} is in blocked range", client_ip_str);
-                                    all_conditions_met = false;
-                                }
-                            }
-                        }
-                    }
+        // Check Bool aws:SecureTransport, typically in a statement denying requests made without TLS
+        if let Some(transport_condition) = conditions.get("Bool").and_then(|c| c.get("aws:SecureTransport")) {
+            let values: Vec<&serde_json::Value> = match transport_condition.as_array() {
+                Some(arr) => arr.iter().collect(),
+                None => vec![transport_condition],
+            };
+            // Policies write the value as a string ("false") or as a JSON boolean
+            let transport_matches = values.iter().any(|value| {
+                value.as_bool()
+                    .or_else(|| value.as_str().and_then(|s| s.trim().to_ascii_lowercase().parse::<bool>().ok()))
+                    == Some(secure_transport)
+            });
+            if !transport_matches {
+                debug!("Bool condition not met: aws:SecureTransport is {}", secure_transport);
+                all_conditions_met = false;
+            }
+        }
 
-                    all_conditions_met
-                } else {
-                    // No conditions, always match
-                    true
-                };
-
-                // If all conditions match (including IP conditions)
-                if principal_match && action_match && resource_match && condition_match {
-                    debug!("Statement matched with effect: {}", effect);
-                    if effect == "Allow" {
-                        return true;
-                    } else if effect == "Deny" {
-                        return false;
+        // Check s3:prefix conditions (only evaluated for ListBucket requests)
+        if let Some(prefix) = s3_prefix {
+            for (operator, wildcard) in [("StringEquals", false), ("StringLike", true)] {
+                if let Some(prefix_condition) = conditions.get(operator).and_then(|c| c.get("s3:prefix")) {
+                    let prefix_allowed = if let Some(arr) = prefix_condition.as_array() {
+                        arr.iter().any(|p| p.as_str().is_some_and(|p| prefix_matches(p, prefix, wildcard)))
+                    } else if let Some(p) = prefix_condition.as_str() {
+                        prefix_matches(p, prefix, wildcard)
+                    } else {
+                        false
+                    };
+                    if !prefix_allowed {
+                        debug!("{} condition not met: s3:prefix={} not allowed", operator, prefix);
+                        all_conditions_met = false;
                     }
                 }
             }
         }
+
+        all_conditions_met
+    } else {
+        // No conditions, always match
+        true
+    };
+
+    principal_match && action_match && resource_match && condition_match
+}
+
+// Compare a requested prefix against a condition value, optionally using * and ? wildcards
+fn prefix_matches(pattern: &str, prefix: &str, wildcard: bool) -> bool {
+    if !wildcard {
+        return pattern == prefix;
     }
 
-    // Default deny if no matching statement
-    debug!("No matching statement found, denying access");
-    false
+    let pattern: Vec<char> = pattern.chars().collect();
+    let value: Vec<char> = prefix.chars().collect();
+    let (mut p, mut v) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+
+    while v < value.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == value[v]) {
+            p += 1;
+            v += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, v));
+            p += 1;
+        } else if let Some((star_p, star_v)) = star {
+            // Let the last * absorb one more character
+            p = star_p + 1;
+            v = star_v + 1;
+            star = Some((star_p, star_v + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+fn principal_value_matches(value: &serde_json::Value, principal: Principal) -> bool {
+    let Some(value) = value.as_str() else {
+        return false;
+    };
+    match principal {
+        _ if value == "*" => true,
+        Principal::Anonymous => false,
+        Principal::AccessKey { access_key, account_id } => {
+            value == access_key || value == account_id || value == format!("arn:aws:iam::{}:root", account_id)
+        }
+    }
+}
+
+// Whether a string or array of Action/Resource patterns matches the value.
+// Patterns may end in `*`; `match_all` matches everything. None when the patterns are neither.
+fn patterns_match(patterns: &serde_json::Value, value: &str, match_all: &str) -> Option<bool> {
+    let pattern_matches = |pattern: &serde_json::Value| {
+        pattern.as_str().is_some_and(|pattern| {
+            pattern == value || pattern == match_all ||
+            (pattern.ends_with('*') && value.starts_with(&pattern[..pattern.len()-1]))
+        })
+    };
+    if let Some(arr) = patterns.as_array() {
+        Some(arr.iter().any(pattern_matches))
+    } else if patterns.is_string() {
+        Some(pattern_matches(patterns))
+    } else {
+        None
+    }
 }
 
 // Helper function to check if an IP is in a CIDR range
@@ -0,0 +1,192 @@
+use axum::http::StatusCode;
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use hmac::Mac;
+use std::collections::HashMap;
+use tracing::debug;
+
+use crate::utils::{HmacSha256, find_sequence};
+
+// Form fields a POST policy never has to mention, as in S3
+const UNCHECKED_FIELDS: [&str; 3] = ["file", "policy", "x-amz-signature"];
+
+// A parsed multipart/form-data upload. S3 ignores fields after the file, so parsing stops there.
+pub struct PostForm {
+    // Form fields before the file, keyed by lowercased name
+    pub fields: HashMap<String, String>,
+    pub file_name: Option<String>,
+    pub file_content_type: Option<String>,
+    pub file: Option<Bytes>,
+}
+
+// Why an upload does not satisfy its policy, as the S3 error it is reported with
+#[derive(Debug)]
+pub struct PolicyViolation {
+    pub status: StatusCode,
+    pub code: &'static str,
+    pub message: String,
+}
+
+impl PolicyViolation {
+    fn new(status: StatusCode, code: &'static str, message: impl Into<String>) -> Self {
+        PolicyViolation { status, code, message: message.into() }
+    }
+
+    fn condition_failed(condition: &serde_json::Value) -> Self {
+        Self::new(StatusCode::FORBIDDEN, "AccessDenied", format!("Invalid according to Policy: Policy Condition failed: {}", condition))
+    }
+}
+
+// The boundary parameter of a multipart/form-data Content-Type
+pub fn form_boundary(content_type: &str) -> Option<&str> {
+    let mut params = content_type.split(';');
+    if !params.next()?.trim().eq_ignore_ascii_case("multipart/form-data") {
+        return None;
+    }
+    params
+        .filter_map(|param| param.trim().split_once('='))
+        .find(|(name, _)| name.eq_ignore_ascii_case("boundary"))
+        .map(|(_, value)| value.trim_matches('"'))
+        .filter(|boundary| !boundary.is_empty())
+}
+
+// Split a multipart/form-data body into its fields and the uploaded file
+pub fn parse_form(body: &Bytes, boundary: &str) -> Result<PostForm, String> {
+    let delimiter = format!("--{}", boundary).into_bytes();
+    let mut form = PostForm { fields: HashMap::new(), file_name: None, file_content_type: None, file: None };
+
+    let mut position = find(body, &delimiter, 0).ok_or("missing opening boundary")? + delimiter.len();
+    loop {
+        // The final boundary is followed by "--"
+        if body[position..].starts_with(b"--") {
+            return Ok(form);
+        }
+        if !body[position..].starts_with(b"\r\n") {
+            return Err("boundary not followed by CRLF".to_string());
+        }
+        let headers_start = position + 2;
+        let headers_end = find(body, b"\r\n\r\n", headers_start).ok_or("unterminated part headers")?;
+        let headers = std::str::from_utf8(&body[headers_start..headers_end]).map_err(|_| "part headers are not UTF-8")?;
+
+        let content_start = headers_end + 4;
+        let mut closing = b"\r\n".to_vec();
+        closing.extend_from_slice(&delimiter);
+        let content_end = find(body, &closing, content_start).ok_or("unterminated part")?;
+
+        let mut name = None;
+        let mut file_name = None;
+        let mut content_type = None;
+        for line in headers.split("\r\n") {
+            let Some((header, value)) = line.split_once(':') else { continue };
+            if header.trim().eq_ignore_ascii_case("content-disposition") {
+                for param in value.split(';').skip(1) {
+                    match param.trim().split_once('=') {
+                        Some((key, value)) if key.eq_ignore_ascii_case("name") => name = Some(value.trim_matches('"').to_string()),
+                        Some((key, value)) if key.eq_ignore_ascii_case("filename") => file_name = Some(value.trim_matches('"').to_string()),
+                        _ => {}
+                    }
+                }
+            } else if header.trim().eq_ignore_ascii_case("content-type") {
+                content_type = Some(value.trim().to_string());
+            }
+        }
+
+        let name = name.ok_or("part without a name")?.to_ascii_lowercase();
+        if name == "file" {
+            form.file = Some(body.slice(content_start..content_end));
+            form.file_name = file_name;
+            form.file_content_type = content_type;
+            return Ok(form);
+        }
+        let value = String::from_utf8(body[content_start..content_end].to_vec()).map_err(|_| format!("field {} is not UTF-8", name))?;
+        form.fields.insert(name, value);
+
+        position = content_end + closing.len();
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8], from: usize) -> Option<usize> {
+    find_sequence(haystack.get(from..)?, needle).map(|offset| from + offset)
+}
+
+// SigV4 signature of the base64 policy document: the policy itself is the string to sign
+pub fn policy_signature(secret_key: &str, date: &str, region: &str, service: &str, policy: &str) -> String {
+    let mut key = format!("AWS4{}", secret_key).into_bytes();
+    for part in [date, region, service, "aws4_request", policy] {
+        let mut mac = HmacSha256::new_from_slice(&key).expect("HMAC accepts any key length");
+        mac.update(part.as_bytes());
+        key = mac.finalize().into_bytes().to_vec();
+    }
+    key.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// Check the policy's expiration and every condition against the form fields (which include "bucket") and the
+// file size. Every field except the file, the policy and its signature must be covered by some condition.
+pub fn check_policy(policy: &serde_json::Value, fields: &HashMap<String, String>, file_size: u64, now: DateTime<Utc>) -> Result<(), PolicyViolation> {
+    let expiration = policy.get("expiration")
+        .and_then(|value| value.as_str())
+        .ok_or_else(|| PolicyViolation::new(StatusCode::BAD_REQUEST, "InvalidPolicyDocument", "Invalid Policy: Policy missing expiration."))?;
+    let expiration = DateTime::parse_from_rfc3339(expiration)
+        .map_err(|_| PolicyViolation::new(StatusCode::BAD_REQUEST, "InvalidPolicyDocument", "Invalid Policy: Invalid 'expiration' value."))?;
+    if expiration < now {
+        return Err(PolicyViolation::new(StatusCode::FORBIDDEN, "AccessDenied", "Invalid according to Policy: Policy expired."));
+    }
+
+    let conditions = policy.get("conditions")
+        .and_then(|value| value.as_array())
+        .ok_or_else(|| PolicyViolation::new(StatusCode::BAD_REQUEST, "InvalidPolicyDocument", "Invalid Policy: Policy missing conditions."))?;
+
+    let field = |name: &str| fields.get(&name.to_ascii_lowercase()).map(String::as_str).unwrap_or("");
+    let mut covered: Vec<String> = Vec::new();
+    for condition in conditions {
+        // {"field": "value"} is shorthand for ["eq", "$field", "value"]
+        if let Some(object) = condition.as_object() {
+            for (name, expected) in object {
+                if Some(field(name)) != expected.as_str() {
+                    return Err(PolicyViolation::condition_failed(condition));
+                }
+                covered.push(name.to_ascii_lowercase());
+            }
+            continue;
+        }
+
+        let invalid = || PolicyViolation::new(StatusCode::BAD_REQUEST, "InvalidPolicyDocument", format!("Invalid Policy: Invalid condition {}", condition));
+        let parts = condition.as_array().filter(|parts| parts.len() == 3).ok_or_else(invalid)?;
+        let operator = parts[0].as_str().ok_or_else(invalid)?.to_ascii_lowercase();
+
+        if operator == "content-length-range" {
+            let bound = |value: &serde_json::Value| value.as_u64().or_else(|| value.as_str().and_then(|s| s.parse().ok()));
+            let (min, max) = bound(&parts[1]).zip(bound(&parts[2])).ok_or_else(invalid)?;
+            debug!("POST upload of {} bytes against content-length-range [{}, {}]", file_size, min, max);
+            if file_size < min {
+                return Err(PolicyViolation::new(StatusCode::BAD_REQUEST, "EntityTooSmall", "Your proposed upload is smaller than the minimum allowed size"));
+            }
+            if file_size > max {
+                return Err(PolicyViolation::new(StatusCode::BAD_REQUEST, "EntityTooLarge", "Your proposed upload exceeds the maximum allowed size"));
+            }
+            continue;
+        }
+
+        let name = parts[1].as_str().and_then(|name| name.strip_prefix('$')).ok_or_else(invalid)?;
+        let expected = parts[2].as_str().ok_or_else(invalid)?;
+        let matched = match operator.as_str() {
+            "eq" => field(name) == expected,
+            "starts-with" => field(name).starts_with(expected),
+            _ => return Err(invalid()),
+        };
+        if !matched {
+            return Err(PolicyViolation::condition_failed(condition));
+        }
+        covered.push(name.to_ascii_lowercase());
+    }
+
+    let extra = fields.keys()
+        .filter(|name| name.as_str() != "bucket" && !name.starts_with("x-ignore-"))
+        .filter(|name| !UNCHECKED_FIELDS.contains(&name.as_str()) && !covered.contains(name))
+        .min();
+    if let Some(name) = extra {
+        return Err(PolicyViolation::new(StatusCode::FORBIDDEN, "AccessDenied",
+            format!("Invalid according to Policy: Extra input fields: {}", name)));
+    }
+    Ok(())
+}
@@ -1,3 +1,4 @@
+use crate::filesystem::{create_storage_dir, write_storage_file};
 use crate::models::{BucketQuota, BucketQuotaCache, BucketStats, Operation};
 use chrono::{Datelike, Utc};
 use serde_json;
@@ -6,21 +7,31 @@ use std::env;
 use std::fs;
 use std::io;
 use std::path::{Path, PathBuf};
-use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
 use std::time::{Duration, Instant};
-use tokio::sync::RwLock;
-use tokio::time::interval;
+use tokio::sync::{Mutex as AsyncMutex, OwnedMutexGuard};
+use tokio::time::sleep;
 use tracing::{debug, error, info, warn};
 use walkdir::WalkDir;
 
 const DEFAULT_QUOTA_BYTES: u64 = 5 * 1024 * 1024 * 1024; // 5GB
 const FLUSH_INTERVAL_SECS: u64 = 1;
+const FLUSH_MAX_BACKOFF_SECS: u64 = 60;
 
+// Per-bucket cache entry; None until the quota has been loaded from disk
+type QuotaSlot = Arc<AsyncMutex<Option<BucketQuotaCache>>>;
+type StatsSlot = Arc<Mutex<BucketStats>>;
+
+// The outer maps are only write-locked when a bucket is seen for the first time;
+// updates lock just their own bucket's slot, so buckets never contend with each other
 pub struct QuotaManager {
     storage_path: PathBuf,
-    quota_cache: Arc<RwLock<HashMap<String, BucketQuotaCache>>>,
-    stats_cache: Arc<RwLock<HashMap<String, BucketStats>>>,
+    quota_cache: Arc<RwLock<HashMap<String, QuotaSlot>>>,
+    stats_cache: Arc<RwLock<HashMap<String, StatsSlot>>>,
     flush_interval: Duration,
+    flush_max_backoff: Duration,
+    flush_failures: AtomicU64, // quota and stats files that could not be written since startup
     enabled: bool,
 }
 
@@ -36,11 +47,20 @@ impl QuotaManager {
             .and_then(|v| v.parse().ok())
             .unwrap_or(FLUSH_INTERVAL_SECS * 1000);
 
+        // Failed flushes double the wait up to this, so an unhealthy disk is not hammered every interval
+        let flush_max_backoff_ms = env::var("QUOTA_FLUSH_MAX_BACKOFF_MS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(FLUSH_MAX_BACKOFF_SECS * 1000)
+            .max(flush_interval_ms);
+
         QuotaManager {
             storage_path,
             quota_cache: Arc::new(RwLock::new(HashMap::new())),
             stats_cache: Arc::new(RwLock::new(HashMap::new())),
             flush_interval: Duration::from_millis(flush_interval_ms),
+            flush_max_backoff: Duration::from_millis(flush_max_backoff_ms),
+            flush_failures: AtomicU64::new(0),
             enabled,
         }
     }
@@ -50,6 +70,19 @@ impl QuotaManager {
         self.enabled
     }
 
+    // Number of quota and stats files the flush task failed to write since startup
+    pub fn flush_failures(&self) -> u64 {
+        self.flush_failures.load(Ordering::Relaxed)
+    }
+
+    // Drop the cached quota and stats of a deleted bucket, so they are neither flushed into a directory
+    // that no longer exists nor carried over to a new bucket of the same name
+    pub fn forget_bucket(&self, bucket: &str) {
+        self.quota_cache.write().unwrap().remove(bucket);
+        let stats_prefix = format!("{}:", bucket);
+        self.stats_cache.write().unwrap().retain(|cache_key, _| !cache_key.starts_with(&stats_prefix));
+    }
+
     // Load quota from disk or generate from filesystem scan
     pub async fn load_or_generate_quota(&self, bucket: &str) -> io::Result<BucketQuota> {
         // If quota and stats are disabled, return unlimited quota without any I/O
@@ -62,11 +95,24 @@ impl QuotaManager {
             });
         }
 
-        let mut cache = self.quota_cache.write().await;
+        let cached = self.lock_quota(bucket).await?;
+        Ok(cached.as_ref().map(|c| c.quota.clone()).unwrap())
+    }
+
+    // Lock a bucket's cache slot, loading the quota from disk or a filesystem scan on first use
+    async fn lock_quota(&self, bucket: &str) -> io::Result<OwnedMutexGuard<Option<BucketQuotaCache>>> {
+        let slot = {
+            let existing = self.quota_cache.read().unwrap().get(bucket).cloned();
+            match existing {
+                Some(slot) => slot,
+                None => self.quota_cache.write().unwrap().entry(bucket.to_string()).or_default().clone(),
+            }
+        };
+        let mut cached = slot.lock_owned().await;
 
         // Check if already in cache
-        if let Some(cached) = cache.get(bucket) {
-            return Ok(cached.quota.clone());
+        if cached.is_some() {
+            return Ok(cached);
         }
 
         let bucket_path = self.storage_path.join(bucket);
@@ -88,16 +134,25 @@ impl QuotaManager {
         };
 
         // Add to cache
-        cache.insert(
-            bucket.to_string(),
-            BucketQuotaCache {
-                quota: quota.clone(),
-                dirty: false,
-                last_flush: Instant::now(),
-            },
-        );
+        *cached = Some(BucketQuotaCache {
+            quota,
+            dirty: false,
+            last_flush: Instant::now(),
+        });
 
-        Ok(quota)
+        Ok(cached)
+    }
+
+    // Apply a change to a bucket's cached quota, loading it first if needed
+    async fn modify_quota<F: FnOnce(&mut BucketQuota)>(&self, bucket: &str, modify: F) -> io::Result<()> {
+        let mut cached = self.lock_quota(bucket).await?;
+        if let Some(cached) = cached.as_mut() {
+            modify(&mut cached.quota);
+            cached.quota.last_updated = Utc::now();
+            cached.dirty = true;
+        }
+
+        Ok(())
     }
 
     // Load quota from .quota file
@@ -145,7 +200,7 @@ impl QuotaManager {
     fn save_quota_to_file(&self, quota_file: &Path, quota: &BucketQuota) -> io::Result<()> {
         let temp_file = quota_file.with_extension("tmp");
         let content = serde_json::to_string_pretty(quota)?;
-        fs::write(&temp_file, content)?;
+        write_storage_file(&temp_file, content)?;
         fs::rename(temp_file, quota_file)?;
         Ok(())
     }
@@ -158,58 +213,34 @@ impl QuotaManager {
         }
 
         let quota = self.load_or_generate_quota(bucket).await?;
-        Ok(quota.current_usage_bytes + new_size <= quota.max_size_bytes)
+        Ok(quota.current_usage_bytes.saturating_add(new_size) <= quota.max_size_bytes)
     }
 
     // Update quota after adding an object
-    pub async fn update_quota_add(&self, bucket: &str, size: u64) -> io::Result<()> {
+    pub async fn update_quota_add(&self, bucket: &str, key: &str, size: u64) -> io::Result<()> {
         // If quota and stats are disabled, do nothing
-        if !self.enabled {
+        if !self.enabled || !is_counted_key(key) {
             return Ok(());
         }
-        let mut cache = self.quota_cache.write().await;
 
-        // Ensure quota is loaded
-        if !cache.contains_key(bucket) {
-            drop(cache);
-            self.load_or_generate_quota(bucket).await?;
-            cache = self.quota_cache.write().await;
-        }
-
-        if let Some(cached) = cache.get_mut(bucket) {
-            cached.quota.current_usage_bytes += size;
-            cached.quota.object_count += 1;
-            cached.quota.last_updated = Utc::now();
-            cached.dirty = true;
-        }
-
-        Ok(())
+        self.modify_quota(bucket, |quota| {
+            quota.current_usage_bytes = quota.current_usage_bytes.saturating_add(size);
+            quota.object_count += 1;
+        }).await
     }
 
-    // Update quota after removing an object
-    pub async fn update_quota_remove(&self, bucket: &str, size: u64) -> io::Result<()> {
+    // Update quota after removing an object. Callers only report objects whose file they actually removed,
+    // so a key deleted twice (or never stored) is not subtracted again.
+    pub async fn update_quota_remove(&self, bucket: &str, key: &str, size: u64) -> io::Result<()> {
         // If quota and stats are disabled, do nothing
-        if !self.enabled {
+        if !self.enabled || !is_counted_key(key) {
             return Ok(());
         }
 
-        let mut cache = self.quota_cache.write().await;
-
-        // Ensure quota is loaded
-        if !cache.contains_key(bucket) {
-            drop(cache);
-            self.load_or_generate_quota(bucket).await?;
-            cache = self.quota_cache.write().await;
-        }
-
-        if let Some(cached) = cache.get_mut(bucket) {
-            cached.quota.current_usage_bytes = cached.quota.current_usage_bytes.saturating_sub(size);
-            cached.quota.object_count = cached.quota.object_count.saturating_sub(1);
-            cached.quota.last_updated = Utc::now();
-            cached.dirty = true;
-        }
-
-        Ok(())
+        self.modify_quota(bucket, |quota| {
+            quota.current_usage_bytes = quota.current_usage_bytes.saturating_sub(size);
+            quota.object_count = quota.object_count.saturating_sub(1);
+        }).await
     }
 
     // Get quota information for a bucket
@@ -242,12 +273,12 @@ impl QuotaManager {
     fn save_stats_to_file(&self, stats_file: &Path, stats: &BucketStats) -> io::Result<()> {
         // Create stats directory if it doesn't exist
         if let Some(parent) = stats_file.parent() {
-            fs::create_dir_all(parent)?;
+            create_storage_dir(parent)?;
         }
 
         let temp_file = stats_file.with_extension("tmp");
         let content = serde_json::to_string_pretty(stats)?;
-        fs::write(&temp_file, content)?;
+        write_storage_file(&temp_file, content)?;
         fs::rename(temp_file, stats_file)?;
         Ok(())
     }
@@ -278,12 +309,16 @@ impl QuotaManager {
         }
 
         let stats_file = self.get_current_stats_file(bucket);
-        let mut cache = self.stats_cache.write().await;
-
         let cache_key = format!("{}:{}", bucket, stats_file.display());
-        let stats = cache.entry(cache_key.clone()).or_insert_with(|| {
-            self.load_stats_from_file(&stats_file).unwrap_or_default()
-        });
+
+        let existing = self.stats_cache.read().unwrap().get(&cache_key).cloned();
+        let slot = match existing {
+            Some(slot) => slot,
+            None => self.stats_cache.write().unwrap().entry(cache_key).or_insert_with(|| {
+                Arc::new(Mutex::new(self.load_stats_from_file(&stats_file).unwrap_or_default()))
+            }).clone(),
+        };
+        let mut stats = slot.lock().unwrap();
 
         match operation {
             Operation::Get => stats.get_count += 1,
@@ -313,54 +348,79 @@ impl QuotaManager {
         self.load_stats_from_file(&stats_file)
     }
 
-    // Flush all dirty quotas and stats to disk
-    pub async fn flush_all(&self) -> io::Result<()> {
+    // Flush all dirty quotas and stats to disk, returning how many quota entries were written; fails when any
+    // file could not be written
+    pub async fn flush_all(&self) -> io::Result<usize> {
         // If quota and stats are disabled, do nothing
         if !self.enabled {
-            return Ok(());
+            return Ok(0);
         }
+        let mut failed = 0;
+        let mut flushed = 0;
+
+        // Flush quotas; snapshot the slots so updates are not blocked while writing
+        let quota_slots: Vec<(String, QuotaSlot)> = self.quota_cache.read().unwrap()
+            .iter()
+            .map(|(bucket, slot)| (bucket.clone(), slot.clone()))
+            .collect();
+        for (bucket, slot) in quota_slots {
+            let quota = {
+                let mut cached = slot.lock().await;
+                match cached.as_mut() {
+                    Some(quota_cache) if quota_cache.dirty => {
+                        quota_cache.dirty = false;
+                        quota_cache.last_flush = Instant::now();
+                        quota_cache.quota.clone()
+                    }
+                    _ => continue,
+                }
+            };
 
-        // Flush quotas
-        {
-            let mut cache = self.quota_cache.write().await;
-            for (bucket, quota_cache) in cache.iter_mut() {
-                if quota_cache.dirty {
-                    let quota_file = self.storage_path.join(bucket).join(".quota");
-                    match self.save_quota_to_file(&quota_file, &quota_cache.quota) {
-                        Ok(_) => {
-                            quota_cache.dirty = false;
-                            quota_cache.last_flush = Instant::now();
-                            debug!("Flushed quota for bucket: {}", bucket);
-                        }
-                        Err(e) => {
-                            error!("Failed to flush quota for bucket {}: {}", bucket, e);
-                        }
+            let quota_file = self.storage_path.join(&bucket).join(".quota");
+            match self.save_quota_to_file(&quota_file, &quota) {
+                Ok(_) => {
+                    debug!("Flushed quota for bucket: {}", bucket);
+                    flushed += 1;
+                }
+                Err(e) => {
+                    error!("Failed to flush quota for bucket {}: {}", bucket, e);
+                    failed += 1;
+                    // Keep it dirty so the next flush retries
+                    if let Some(quota_cache) = slot.lock().await.as_mut() {
+                        quota_cache.dirty = true;
                     }
                 }
             }
         }
 
         // Flush stats
-        {
-            let cache = self.stats_cache.read().await;
-            for (cache_key, stats) in cache.iter() {
-                // Extract bucket name and stats file from cache key
-                if let Some(colon_pos) = cache_key.find(':') {
-                    let bucket = &cache_key[..colon_pos];
-                    let stats_file = self.get_current_stats_file(bucket);
-                    match self.save_stats_to_file(&stats_file, stats) {
-                        Ok(_) => {
-                            debug!("Flushed stats for bucket: {}", bucket);
-                        }
-                        Err(e) => {
-                            error!("Failed to flush stats for bucket {}: {}", bucket, e);
-                        }
+        let stats_slots: Vec<(String, StatsSlot)> = self.stats_cache.read().unwrap()
+            .iter()
+            .map(|(cache_key, slot)| (cache_key.clone(), slot.clone()))
+            .collect();
+        for (cache_key, slot) in stats_slots {
+            // Extract bucket name and stats file from cache key
+            if let Some(colon_pos) = cache_key.find(':') {
+                let bucket = &cache_key[..colon_pos];
+                let stats_file = self.get_current_stats_file(bucket);
+                let stats = slot.lock().unwrap().clone();
+                match self.save_stats_to_file(&stats_file, &stats) {
+                    Ok(_) => {
+                        debug!("Flushed stats for bucket: {}", bucket);
+                    }
+                    Err(e) => {
+                        error!("Failed to flush stats for bucket {}: {}", bucket, e);
+                        failed += 1;
                     }
                 }
             }
         }
 
-        Ok(())
+        if failed > 0 {
+            self.flush_failures.fetch_add(failed, Ordering::Relaxed);
+            return Err(io::Error::other(format!("{} quota or stats files could not be written", failed)));
+        }
+        Ok(flushed)
     }
 
     // Background task to periodically flush quotas and stats
@@ -371,13 +431,27 @@ impl QuotaManager {
             return;
         }
 
-        let mut interval = interval(self.flush_interval);
+        let mut delay = self.flush_interval;
 
         loop {
-            interval.tick().await;
-            if let Err(e) = self.flush_all().await {
-                error!("Error during periodic flush: {}", e);
+            sleep(delay).await;
+            match self.flush_all().await {
+                Ok(_) => {
+                    if delay != self.flush_interval {
+                        info!("Periodic flush succeeded again, back to every {:?}", self.flush_interval);
+                    }
+                    delay = self.flush_interval;
+                }
+                Err(e) => {
+                    delay = (delay * 2).min(self.flush_max_backoff);
+                    error!("Error during periodic flush, retrying in {:?}: {}", delay, e);
+                }
             }
         }
     }
-}
\ No newline at end of file
+}
+
+// Folder markers ("photos/") are stored as hidden files, which the filesystem scan does not count either
+fn is_counted_key(key: &str) -> bool {
+    !key.ends_with('/')
+}
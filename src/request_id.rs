@@ -0,0 +1,32 @@
+use axum::{
+    extract::{Request, State},
+    http::HeaderValue,
+    middleware::Next,
+    response::Response,
+};
+use tracing::debug;
+use uuid::Uuid;
+
+use crate::models::AppState;
+
+// ID of the request being served, stored in the request extensions for handlers and the access log
+#[derive(Clone, Debug)]
+pub struct RequestId(pub String);
+
+// Give every request an x-amz-request-id of the form <prefix>-<16 hex digits>. The prefix (REQUEST_ID_PREFIX,
+// NODE_ID by default) tells which deployment served a request when logs of several nodes are combined.
+pub async fn request_id_middleware(State(state): State<AppState>, mut request: Request, next: Next) -> Response {
+    let random = Uuid::new_v4().simple().to_string()[..16].to_uppercase();
+    let id = match state.settings.request_id_prefix.as_str() {
+        "" => random,
+        prefix => format!("{}-{}", prefix, random),
+    };
+    debug!("Request {} {} has id {}", request.method(), request.uri(), id);
+    request.extensions_mut().insert(RequestId(id.clone()));
+
+    let mut response = next.run(request).await;
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response.headers_mut().insert("x-amz-request-id", value);
+    }
+    response
+}
@@ -0,0 +1,277 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use regex::Regex;
+
+use crate::utils::CANNED_ACLS;
+
+const MAX_MULTIPART_PARTS: i32 = 10_000;
+// Keys are at most 1024 bytes, so real keys never nest deeper than this
+const DEFAULT_LIST_MAX_DEPTH: usize = 128;
+// Fits a 1000-key DeleteObjects request with maximum-length keys; configuration documents are far smaller
+const DEFAULT_CONFIG_BODY_MAX_BYTES: usize = 2 * 1024 * 1024;
+// SigV4 allows a signed request to be 15 minutes off the server's clock
+const DEFAULT_MAX_CLOCK_SKEW_SECS: u64 = 15 * 60;
+const DEFAULT_ACCOUNT_ID: &str = "000000000000";
+
+// Runtime settings for request handlers, read once from the environment at startup
+#[derive(Clone, Debug)]
+pub struct Settings {
+    // Multipart parts larger than this many bytes are kept on disk only
+    pub multipart_memory_threshold: usize,
+    // Highest part number accepted for a multipart upload, and the most parts one may be completed from
+    pub multipart_max_parts: i32,
+    // Refuse requests that did not arrive over HTTPS, as reported by the proxy in X-Forwarded-Proto
+    pub require_tls: bool,
+    // Largest body accepted by bucket and root requests and by object subresources such as ?tagging.
+    // Object data, multipart parts and POST form uploads are not limited.
+    pub config_body_max_bytes: usize,
+    // Read .metadata sidecars for the returned page of object listings (real ETag, storage class)
+    pub list_fetch_metadata: bool,
+    // Directories nested deeper than this below the bucket are not listed
+    pub list_max_depth: usize,
+    // List files and directories that symlinks under the storage path point to; off by default
+    pub list_follow_symlinks: bool,
+    // Serve listings from an in-memory sorted key index instead of scanning the bucket for every page
+    pub list_index: bool,
+    // Unix permission bits for files and directories created under the storage path
+    pub storage_file_mode: u32,
+    pub storage_dir_mode: u32,
+    // Serve unencrypted objects of at least get_mmap_threshold bytes from a memory mapping
+    pub get_mmap: bool,
+    pub get_mmap_threshold: u64,
+    // Encrypt objects larger than this many bytes as independently decryptable chunks; None keeps one ciphertext
+    pub encryption_chunk_size: Option<u64>,
+    // Recompute the MD5 of every object read by GET and refuse to serve it if it differs from the stored ETag
+    pub verify_on_read: bool,
+    // Non-standard: accept PUTs carrying Content-Range as pieces of a resumable upload
+    pub partial_uploads: bool,
+    // With versioning enabled, a PUT of the current content keeps the current version instead of adding one
+    pub dedup_identical_versions: bool,
+    // Writing "a/b/c.txt" also creates the folder marker objects "a/" and "a/b/" when they do not exist
+    pub create_folder_markers: bool,
+    // Canned ACL of objects uploaded without x-amz-acl to buckets without a default ACL; None is private
+    pub default_object_acl: Option<String>,
+    // Maximum number of buckets create_bucket allows; None means unlimited
+    pub max_buckets: Option<usize>,
+    // Most multipart uploads that may be in progress in one bucket; None means unlimited
+    pub multipart_max_uploads_per_bucket: Option<usize>,
+    // State file the replicator writes, read to report replication lag
+    pub replicator_state_file: PathBuf,
+    // Access-Control-Allow-Origin sent on OPTIONS /; None leaves CORS headers off that response
+    pub root_cors_allow_origin: Option<String>,
+    // Reject uploads with 503 when the quota cannot be checked instead of letting them through
+    pub quota_strict: bool,
+    // Prefix of generated x-amz-request-id values; empty for bare IDs
+    pub request_id_prefix: String,
+    // Content types by lowercase file extension, given to objects uploaded without a specific type
+    pub content_type_overrides: HashMap<String, String>,
+    // Header-signed requests dated further than this from now are rejected; None disables the check
+    pub max_clock_skew_secs: Option<u64>,
+    // New object keys must match this pattern as a whole; None accepts every key
+    pub key_allow_pattern: Option<Regex>,
+    // Account the configured access keys belong to, so policies may name it as arn:aws:iam::<id>:root
+    pub account_id: String,
+}
+
+impl Settings {
+    pub fn from_env() -> Self {
+        let multipart_memory_threshold = env::var("MULTIPART_MEMORY_THRESHOLD")
+            .unwrap_or_else(|_| "5242880".to_string())
+            .parse::<usize>()
+            .unwrap_or(5 * 1024 * 1024);
+
+        // S3 allows at most 10,000 parts, so the limit can only be lowered
+        let multipart_max_parts = env::var("MULTIPART_MAX_PARTS")
+            .ok()
+            .and_then(|v| v.parse::<i32>().ok())
+            .unwrap_or(MAX_MULTIPART_PARTS)
+            .clamp(1, MAX_MULTIPART_PARTS);
+
+        let require_tls = env::var("REQUIRE_TLS")
+            .unwrap_or_else(|_| "false".to_string()) == "true";
+
+        let config_body_max_bytes = env::var("CONFIG_BODY_MAX_BYTES")
+            .ok()
+            .and_then(|v| v.parse::<usize>().ok())
+            .unwrap_or(DEFAULT_CONFIG_BODY_MAX_BYTES);
+
+        let list_fetch_metadata = env::var("LIST_FETCH_METADATA")
+            .unwrap_or_else(|_| "true".to_string()) == "true";
+
+        let list_max_depth = env::var("LIST_MAX_DEPTH")
+            .ok()
+            .and_then(|v| v.parse::<usize>().ok())
+            .unwrap_or(DEFAULT_LIST_MAX_DEPTH);
+
+        let list_follow_symlinks = env::var("LIST_FOLLOW_SYMLINKS")
+            .unwrap_or_else(|_| "false".to_string()) == "true";
+
+        let list_index = env::var("LIST_INDEX")
+            .unwrap_or_else(|_| "false".to_string()) == "true";
+
+        let storage_file_mode = parse_mode("STORAGE_FILE_MODE", 0o600);
+        let storage_dir_mode = parse_mode("STORAGE_DIR_MODE", 0o700);
+
+        let get_mmap = env::var("GET_MMAP")
+            .unwrap_or_else(|_| "false".to_string()) == "true";
+
+        let get_mmap_threshold = env::var("GET_MMAP_THRESHOLD")
+            .unwrap_or_else(|_| "8388608".to_string())
+            .parse::<u64>()
+            .unwrap_or(8 * 1024 * 1024);
+
+        let encryption_chunk_size = env::var("ENCRYPTION_CHUNK_SIZE")
+            .ok()
+            .and_then(|value| value.trim().parse::<u64>().ok())
+            .filter(|&size| size > 0);
+
+        let verify_on_read = env::var("VERIFY_ON_READ")
+            .unwrap_or_else(|_| "false".to_string()) == "true";
+
+        let partial_uploads = env::var("PARTIAL_UPLOADS")
+            .unwrap_or_else(|_| "false".to_string()) == "true";
+
+        let dedup_identical_versions = env::var("DEDUP_IDENTICAL_VERSIONS")
+            .unwrap_or_else(|_| "false".to_string()) == "true";
+
+        let create_folder_markers = env::var("CREATE_FOLDER_MARKERS")
+            .unwrap_or_else(|_| "false".to_string()) == "true";
+
+        let default_object_acl = env::var("DEFAULT_OBJECT_ACL")
+            .ok()
+            .map(|acl| acl.trim().to_string())
+            .filter(|acl| CANNED_ACLS.contains(&acl.as_str()) && acl != "private");
+
+        let max_buckets = env::var("MAX_BUCKETS")
+            .ok()
+            .and_then(|v| v.parse::<usize>().ok())
+            .filter(|max| *max > 0);
+
+        let multipart_max_uploads_per_bucket = env::var("MULTIPART_MAX_UPLOADS_PER_BUCKET")
+            .ok()
+            .and_then(|v| v.parse::<usize>().ok())
+            .filter(|max| *max > 0);
+
+        let replicator_state_file = PathBuf::from(
+            env::var("REPLICATOR_STATE_PATH").unwrap_or_else(|_| "/state".to_string())
+        ).join("replicator.state");
+
+        let root_cors_allow_origin = Some(env::var("ROOT_CORS_ALLOW_ORIGIN").unwrap_or_else(|_| "*".to_string()))
+            .map(|origin| origin.trim().to_string())
+            .filter(|origin| !origin.is_empty());
+
+        let quota_strict = env::var("QUOTA_STRICT")
+            .unwrap_or_else(|_| "false".to_string()) == "true";
+
+        // Only characters that are safe in a header value and in log lines are kept
+        let request_id_prefix: String = env::var("REQUEST_ID_PREFIX")
+            .or_else(|_| env::var("NODE_ID"))
+            .unwrap_or_else(|_| "node-1".to_string())
+            .chars()
+            .filter(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+            .collect();
+
+        // env_check refuses to start with an unreadable file, so errors cannot be ignored silently here
+        let content_type_overrides = env::var("CONTENT_TYPE_OVERRIDES_FILE")
+            .ok()
+            .and_then(|path| load_content_type_overrides(Path::new(&path)).ok())
+            .unwrap_or_default();
+
+        let max_clock_skew_secs = Some(
+            env::var("MAX_CLOCK_SKEW_SECS")
+                .ok()
+                .and_then(|v| v.parse::<u64>().ok())
+                .unwrap_or(DEFAULT_MAX_CLOCK_SKEW_SECS)
+        ).filter(|secs| *secs > 0);
+
+        // env_check refuses to start with a pattern that does not compile
+        let key_allow_pattern = env::var("KEY_ALLOW_PATTERN")
+            .ok()
+            .filter(|pattern| !pattern.is_empty())
+            .and_then(|pattern| compile_key_pattern(&pattern).ok());
+
+        let account_id = env::var("ACCOUNT_ID")
+            .map(|id| id.trim().to_string())
+            .unwrap_or_else(|_| DEFAULT_ACCOUNT_ID.to_string());
+
+        Settings {
+            multipart_memory_threshold,
+            multipart_max_parts,
+            list_fetch_metadata,
+            list_max_depth,
+            list_follow_symlinks,
+            list_index,
+            config_body_max_bytes,
+            require_tls,
+            storage_file_mode,
+            storage_dir_mode,
+            get_mmap,
+            get_mmap_threshold,
+            encryption_chunk_size,
+            verify_on_read,
+            partial_uploads,
+            dedup_identical_versions,
+            create_folder_markers,
+            default_object_acl,
+            max_buckets,
+            multipart_max_uploads_per_bucket,
+            replicator_state_file,
+            root_cors_allow_origin,
+            quota_strict,
+            request_id_prefix,
+            content_type_overrides,
+            max_clock_skew_secs,
+            key_allow_pattern,
+            account_id,
+        }
+    }
+
+    // Whether a new object may be stored under this key
+    pub fn is_key_allowed(&self, key: &str) -> bool {
+        self.key_allow_pattern.as_ref().is_none_or(|pattern| pattern.is_match(key))
+    }
+
+    // Content type configured for the extension of the key's last segment, if any
+    pub fn content_type_override(&self, key: &str) -> Option<&str> {
+        let name = key.rsplit('/').next().unwrap_or(key);
+        let (_, extension) = name.rsplit_once('.')?;
+        self.content_type_overrides.get(&extension.to_ascii_lowercase()).map(String::as_str)
+    }
+}
+
+// Anchor a KEY_ALLOW_PATTERN so it has to match the whole key, not just part of it
+pub fn compile_key_pattern(pattern: &str) -> Result<Regex, regex::Error> {
+    Regex::new(&format!("^(?:{})$", pattern))
+}
+
+// Read a JSON object mapping file extensions to content types, e.g. {"js": "text/javascript", ".wasm": "application/wasm"}
+pub fn load_content_type_overrides(path: &Path) -> Result<HashMap<String, String>, String> {
+    let json = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let entries: HashMap<String, String> = serde_json::from_str(&json)
+        .map_err(|e| format!("not a JSON object of extensions to content types: {}", e))?;
+
+    let mut overrides = HashMap::with_capacity(entries.len());
+    for (extension, content_type) in entries {
+        let extension = extension.trim().trim_start_matches('.').to_ascii_lowercase();
+        let content_type = content_type.trim().to_string();
+        if extension.is_empty() || extension.contains('/') {
+            return Err(format!("{:?} is not a file extension", extension));
+        }
+        if content_type.is_empty() || axum::http::HeaderValue::from_str(&content_type).is_err() {
+            return Err(format!("{:?} is not a valid content type for .{}", content_type, extension));
+        }
+        overrides.insert(extension, content_type);
+    }
+    Ok(overrides)
+}
+
+// Read an octal permission mode such as "0640" from the environment
+fn parse_mode(var: &str, default: u32) -> u32 {
+    env::var(var)
+        .ok()
+        .and_then(|v| u32::from_str_radix(v.trim().trim_start_matches("0o"), 8).ok())
+        .filter(|mode| *mode <= 0o7777)
+        .unwrap_or(default)
+}
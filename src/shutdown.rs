@@ -0,0 +1,75 @@
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tracing::{error, info};
+
+use crate::{models::AppState, wal::WALDrain};
+
+// Requests being handled, so shutdown can report how many it waited for
+#[derive(Default)]
+pub struct InFlightRequests {
+    active: AtomicUsize,
+    // Requests that were in flight when the shutdown signal arrived; graceful shutdown lets them finish
+    at_signal: AtomicUsize,
+}
+
+impl InFlightRequests {
+    pub fn signal_received(&self) {
+        let active = self.active.load(Ordering::Acquire);
+        self.at_signal.store(active, Ordering::Release);
+        info!("Shutdown signal received, finishing {} in-flight requests", active);
+    }
+}
+
+// Decrements the count when the handler finishes, including when its future is dropped
+struct InFlightGuard<'a>(&'a AtomicUsize);
+
+impl Drop for InFlightGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+pub async fn in_flight_middleware(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    state.in_flight.active.fetch_add(1, Ordering::AcqRel);
+    let _guard = InFlightGuard(&state.in_flight.active);
+    next.run(request).await
+}
+
+// Log one structured line saying what was persisted on the way out, at error level if anything was not
+pub fn log_summary(in_flight: &InFlightRequests, quota_flush: std::io::Result<usize>, wal: Option<WALDrain>, pending_uploads: usize) {
+    let requests_completed = in_flight.at_signal.load(Ordering::Acquire);
+    let (quota_entries_flushed, quota_flush_error) = match quota_flush {
+        Ok(flushed) => (flushed, None),
+        Err(e) => (0, Some(e.to_string())),
+    };
+    let wal_enabled = wal.is_some();
+    let wal = wal.unwrap_or_default();
+    let wal_failed = wal_enabled && !(wal.written && wal.synced);
+
+    if quota_flush_error.is_some() || wal_failed {
+        error!(
+            requests_completed,
+            quota_entries_flushed,
+            quota_flush_error = quota_flush_error.as_deref().unwrap_or(""),
+            wal_entries_drained = wal.entries,
+            wal_written = wal.written,
+            wal_fsynced = wal.synced,
+            multipart_uploads_pending = pending_uploads,
+            "Shutdown summary: some state may not have been persisted"
+        );
+    } else {
+        info!(
+            requests_completed,
+            quota_entries_flushed,
+            wal_enabled,
+            wal_entries_drained = wal.entries,
+            wal_fsynced = wal.synced,
+            multipart_uploads_pending = pending_uploads,
+            "Shutdown summary: all state persisted"
+        );
+    }
+}
@@ -4,6 +4,118 @@ use sha2::Sha256;
 
 pub type HmacSha256 = Hmac<Sha256>;
 
+// Names IronBucket uses for its own files and directories at the root of a bucket
+const RESERVED_KEY_NAMES: [&str; 16] = [
+    ".versions", ".multipart", ".stats", ".quota", ".policy", ".bucket_metadata",
+    ".versioning", ".encryption", ".cors", ".lifecycle", ".ttl", ".logging", ".partial", ".website",
+    ".storage_class", ".default_acl",
+];
+
+// Storage classes objects can be uploaded with or transitioned to
+pub const STORAGE_CLASSES: [&str; 7] = [
+    "STANDARD", "INTELLIGENT_TIERING", "STANDARD_IA", "ONEZONE_IA", "GLACIER_IR", "GLACIER", "DEEP_ARCHIVE",
+];
+
+// Canned ACLs accepted in x-amz-acl; only public-read and public-read-write open an object to anonymous reads
+pub const CANNED_ACLS: [&str; 7] = [
+    "private", "public-read", "public-read-write", "authenticated-read",
+    "aws-exec-read", "bucket-owner-read", "bucket-owner-full-control",
+];
+
+// Storage classes that must be restored before the object data can be read
+pub const ARCHIVE_STORAGE_CLASSES: [&str; 2] = ["GLACIER", "DEEP_ARCHIVE"];
+
+// A key ending in '/' (a "folder") is stored as a zero-byte file of this name inside the directory it names
+pub const FOLDER_MARKER_NAME: &str = ".folder";
+
+// Whether a key would land on an internal file or directory, or on another object's .metadata sidecar
+pub fn is_reserved_key(key: &str) -> bool {
+    let first_segment = key.split('/').next().unwrap_or("");
+    RESERVED_KEY_NAMES.contains(&first_segment)
+        || key.split('/').any(|segment| segment.ends_with(".metadata") || segment == FOLDER_MARKER_NAME)
+}
+
+// Path of an object's data file relative to the bucket directory: the key itself, or the marker file for folders
+pub fn object_storage_key(key: &str) -> String {
+    if key.ends_with('/') {
+        format!("{}{}", key, FOLDER_MARKER_NAME)
+    } else {
+        key.to_string()
+    }
+}
+
+// Components of an "AWS4-HMAC-SHA256 Credential=..., SignedHeaders=..., Signature=..." Authorization header
+pub struct SigV4Authorization<'a> {
+    pub credential: &'a str,
+    pub signed_headers: &'a str,
+    pub signature: &'a str,
+}
+
+impl<'a> SigV4Authorization<'a> {
+    // The access key is the first of the slash-separated credential scope fields
+    pub fn access_key(&self) -> &'a str {
+        self.credential.split('/').next().unwrap_or("")
+    }
+
+    // A SigV4 signature is always 64 hex digits; anything else cannot come from a real client
+    pub fn has_well_formed_signature(&self) -> bool {
+        self.signature.len() == 64 && self.signature.bytes().all(|b| b.is_ascii_hexdigit())
+    }
+}
+
+// Parse a SigV4 Authorization header. Clients differ in the whitespace around the commas and the '=' signs
+// (or put none at all), so components are found by name rather than by position; all three must be present.
+pub fn parse_sigv4_authorization(header: &str) -> Option<SigV4Authorization<'_>> {
+    let components = header.trim().strip_prefix("AWS4-HMAC-SHA256")?;
+    if !components.starts_with(char::is_whitespace) {
+        return None;
+    }
+
+    let (mut credential, mut signed_headers, mut signature) = (None, None, None);
+    for component in components.split(',') {
+        let (name, value) = component.split_once('=')?;
+        let value = value.trim();
+        match name.trim() {
+            "Credential" => credential = Some(value),
+            "SignedHeaders" => signed_headers = Some(value),
+            "Signature" => signature = Some(value),
+            _ => {}
+        }
+    }
+
+    Some(SigV4Authorization {
+        credential: credential.filter(|v| !v.is_empty())?,
+        signed_headers: signed_headers.filter(|v| !v.is_empty())?,
+        signature: signature.filter(|v| !v.is_empty())?,
+    })
+}
+
+// Escape text for inclusion in an XML element
+pub fn xml_escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    push_xml_escaped(&mut escaped, value);
+    escaped
+}
+
+// Append value to out with XML special characters escaped, without intermediate allocations
+pub fn push_xml_escaped(out: &mut String, value: &str) {
+    for c in value.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            c => out.push(c),
+        }
+    }
+}
+
+// ETags are stored as bare hex; responses carry exactly one pair of quotes, whatever was stored
+pub fn quoted_etag(etag: &str) -> String {
+    format!("\"{}\"", etag.trim_matches('"'))
+}
+
 // Helper function to format date for HTTP Last-Modified header (RFC2822 with GMT)
 pub fn format_http_date(dt: &DateTime<Utc>) -> String {
     dt.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
@@ -71,4 +183,94 @@ pub fn parse_chunked_data(input: &[u8]) -> Vec<u8> {
 pub fn find_sequence(haystack: &[u8], needle: &[u8]) -> Option<usize> {
     haystack.windows(needle.len())
         .position(|window| window == needle)
-}
\ No newline at end of file
+}
+// Collect x-amz-meta-* headers into a metadata map, decoding RFC 2047 encoded values
+pub fn extract_custom_metadata(headers: &axum::http::HeaderMap) -> std::collections::HashMap<String, String> {
+    let mut custom_metadata = std::collections::HashMap::new();
+    for (name, value) in headers {
+        if let Some(meta_key) = name.as_str().strip_prefix("x-amz-meta-") {
+            custom_metadata.insert(meta_key.to_string(), decode_metadata_value(value.as_bytes()));
+        }
+    }
+    custom_metadata
+}
+
+// Whether the client reached the server over TLS. IronBucket itself serves plain HTTP, so this is what the
+// TLS-terminating proxy reports in X-Forwarded-Proto; the header must come from a proxy that sets it.
+pub fn is_secure_transport(headers: &axum::http::HeaderMap) -> bool {
+    headers.get("x-forwarded-proto")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split(',').next())
+        .is_some_and(|proto| proto.trim().eq_ignore_ascii_case("https"))
+}
+
+// Decode a metadata header value; raw non-ASCII bytes are taken as UTF-8
+pub fn decode_metadata_value(raw: &[u8]) -> String {
+    let value = String::from_utf8_lossy(raw);
+
+    // Whitespace between adjacent encoded words is not part of the value
+    let words: Vec<&str> = value.split_whitespace().collect();
+    if words.is_empty() || !words.iter().all(|w| w.starts_with("=?") && w.ends_with("?=")) {
+        return value.to_string();
+    }
+
+    let mut decoded = Vec::new();
+    for word in words {
+        match decode_encoded_word(word) {
+            Some(bytes) => decoded.extend(bytes),
+            None => return value.to_string(),
+        }
+    }
+    String::from_utf8(decoded).unwrap_or_else(|_| value.to_string())
+}
+
+// Encode a metadata value for use as a header; values that are not plain printable ASCII use RFC 2047
+pub fn encode_metadata_value(value: &str) -> String {
+    let is_plain = value.bytes().all(|b| (0x20..0x7f).contains(&b))
+        && value.trim() == value
+        && !value.starts_with("=?");
+
+    if is_plain {
+        value.to_string()
+    } else {
+        use base64::Engine as _;
+        format!("=?UTF-8?B?{}?=", base64::engine::general_purpose::STANDARD.encode(value.as_bytes()))
+    }
+}
+
+// Decode a single "=?charset?encoding?text?=" word (B or Q encoding)
+fn decode_encoded_word(word: &str) -> Option<Vec<u8>> {
+    let inner = word.strip_prefix("=?")?.strip_suffix("?=")?;
+    let mut parts = inner.splitn(3, '?');
+    let charset = parts.next()?;
+    let encoding = parts.next()?;
+    let text = parts.next()?;
+
+    if !charset.eq_ignore_ascii_case("UTF-8") && !charset.eq_ignore_ascii_case("US-ASCII") {
+        return None;
+    }
+
+    if encoding.eq_ignore_ascii_case("B") {
+        use base64::Engine as _;
+        base64::engine::general_purpose::STANDARD.decode(text).ok()
+    } else if encoding.eq_ignore_ascii_case("Q") {
+        let bytes = text.as_bytes();
+        let mut decoded = Vec::with_capacity(bytes.len());
+        let mut i = 0;
+        while i < bytes.len() {
+            match bytes[i] {
+                b'_' => decoded.push(b' '),
+                b'=' if i + 2 < bytes.len() => {
+                    let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok()?;
+                    decoded.push(u8::from_str_radix(hex, 16).ok()?);
+                    i += 2;
+                }
+                b => decoded.push(b),
+            }
+            i += 1;
+        }
+        Some(decoded)
+    } else {
+        None
+    }
+}
@@ -1,12 +1,12 @@
-use crossbeam::channel::{bounded, Sender, TryRecvError};
-use std::fs::{self, OpenOptions};
+use crossbeam::channel::{bounded, Sender, TryRecvError, TrySendError};
+use std::fs::{self, File, OpenOptions};
 use std::io::{BufReader, BufRead, BufWriter, Write, Seek, SeekFrom};
-use std::path::PathBuf;
-use std::sync::Arc;
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
-use std::thread;
-use tracing::{error, info, debug};
+use std::thread::{self, JoinHandle};
+use tracing::{error, info, debug, warn};
 
 #[derive(Debug)]
 pub enum WALOp {
@@ -35,28 +35,56 @@ pub enum WALOp {
         bucket: String,
         metadata_type: String,  // "versioning", "encryption", "policy", "cors", "lifecycle"
     },
+    // The object's .metadata sidecar changed (tags, ACL) without new content
+    UpdateObjectMetadata {
+        bucket: String,
+        key: String,
+    },
+}
+
+const WAL_QUEUE_CAPACITY: usize = 10000;
+
+// What the writer thread did with the queue when it stopped, for the shutdown summary
+#[derive(Debug, Default)]
+pub struct WALDrain {
+    // Entries still queued or batched when shutdown began
+    pub entries: usize,
+    // Every entry was written and the buffer flushed
+    pub written: bool,
+    // The WAL file was fsynced afterwards
+    pub synced: bool,
 }
 
 pub struct WALWriter {
     sender: Sender<WALOp>,
+    // How long a log call waits for room when the queue is full before the entry is dropped
+    send_timeout: Duration,
+    // Entries that never reached the WAL file because the queue stayed full; each one is a replication gap
+    dropped: AtomicU64,
     sequence: Arc<AtomicU64>,
     node_id: String,
     enabled: bool,
+    stopping: Arc<AtomicBool>,
+    thread: Mutex<Option<JoinHandle<WALDrain>>>,
 }
 
 impl WALWriter {
-    pub fn new(path: PathBuf, node_id: String, enabled: bool) -> Self {
+    pub fn new(path: PathBuf, node_id: String, enabled: bool, send_timeout: Duration) -> Self {
         if !enabled {
             let (sender, _) = bounded(1);
             return WALWriter {
                 sender,
+                send_timeout,
+                dropped: AtomicU64::new(0),
                 sequence: Arc::new(AtomicU64::new(0)),
                 node_id,
                 enabled: false,
+                stopping: Arc::new(AtomicBool::new(false)),
+                thread: Mutex::new(None),
             };
         }
 
-        let (sender, receiver) = bounded(10000);
+        let (sender, receiver) = bounded(WAL_QUEUE_CAPACITY);
 
         let writer_node_id = node_id.clone();
 
@@ -68,7 +96,10 @@ impl WALWriter {
         let thread_counter = sequence_counter.clone();
         let wal_path = path.clone();
 
-        thread::spawn(move || {
+        let stopping = Arc::new(AtomicBool::new(false));
+        let thread_stopping = stopping.clone();
+
+        let handle = thread::spawn(move || {
             let mut file = match OpenOptions::new()
                 .create(true)
                 .append(true)
@@ -77,7 +108,7 @@ impl WALWriter {
                 Ok(f) => BufWriter::with_capacity(1024 * 1024, f),
                 Err(e) => {
                     error!("Failed to open WAL file: {}", e);
-                    return;
+                    return WALDrain::default();
                 }
             };
 
@@ -97,77 +128,37 @@ impl WALWriter {
                 }
 
                 // Drain any additional messages that are ready
+                let mut disconnected = false;
                 while batch.len() < 1000 {
                     match receiver.try_recv() {
                         Ok(op) => batch.push(op),
                         Err(TryRecvError::Empty) => break,
                         Err(TryRecvError::Disconnected) => {
-                            info!("WAL writer shutting down");
-                            return;
+                            disconnected = true;
+                            break;
                         }
                     }
                 }
 
-                // Flush every 5 seconds OR if batch is large (increased for better performance)
-                if !batch.is_empty() && (last_flush.elapsed() >= Duration::from_secs(5) || batch.len() >= 1000) {
-                    let timestamp = SystemTime::now()
-                        .duration_since(UNIX_EPOCH)
-                        .unwrap()
-                        .as_millis() as u64;
-
-                    let batch_size = batch.len();  // Save size before draining
-                    for op in batch.drain(..) {
-                        let sequence = thread_counter.fetch_add(1, Ordering::Relaxed);
-
-                        let line = match op {
-                            WALOp::Put { bucket, key, size, etag } => {
-                                format!("PUT\t{}\t{}\t{}\t{}\t{}\t{}\t{}\n",
-                                    writer_node_id, sequence, timestamp, bucket, key, size,
-                                    etag.unwrap_or_default())
-                            }
-                            WALOp::Delete { bucket, key } => {
-                                format!("DELETE\t{}\t{}\t{}\t{}\t{}\n",
-                                    writer_node_id, sequence, timestamp, bucket, key)
-                            }
-                            WALOp::CreateBucket { bucket } => {
-                                format!("CREATE_BUCKET\t{}\t{}\t{}\t{}\n",
-                                    writer_node_id, sequence, timestamp, bucket)
-                            }
-                            WALOp::DeleteBucket { bucket } => {
-                                format!("DELETE_BUCKET\t{}\t{}\t{}\t{}\n",
-                                    writer_node_id, sequence, timestamp, bucket)
-                            }
-                            WALOp::UpdateMetadata { bucket, metadata_type, content } => {
-                                // Escape content to handle newlines and tabs
-                                let escaped_content = content.replace('\n', "\\n").replace('\t', "\\t");
-                                format!("UPDATE_METADATA\t{}\t{}\t{}\t{}\t{}\t{}\n",
-                                    writer_node_id, sequence, timestamp, bucket, metadata_type, escaped_content)
-                            }
-                            WALOp::DeleteMetadata { bucket, metadata_type } => {
-                                format!("DELETE_METADATA\t{}\t{}\t{}\t{}\t{}\n",
-                                    writer_node_id, sequence, timestamp, bucket, metadata_type)
-                            }
-                        };
-
-                        if let Err(e) = file.write_all(line.as_bytes()) {
-                            error!("Failed to write to WAL: {}", e);
-                        }
-                    }
-
-                    // Write sequence state for faster startup
-                    let state_path = wal_path.with_extension("sequence");
-                    let next_seq = thread_counter.load(Ordering::Relaxed);
-                    let _ = fs::write(&state_path, format!("{}", next_seq));
-
-                    // Always flush after writing a batch to ensure data is persisted
-                    // Use the saved batch_size since batch is now empty after drain
-                    if batch_size > 0 {
-                        if let Err(e) = file.flush() {
-                            error!("Failed to flush WAL: {}", e);
+                // On shutdown take everything still queued, write it and sync before the thread exits
+                if disconnected || thread_stopping.load(Ordering::Acquire) {
+                    batch.extend(receiver.try_iter());
+                    let entries = batch.len();
+                    let written = write_batch(&mut file, &mut batch, &thread_counter, &writer_node_id, &wal_path);
+                    let synced = match file.get_ref().sync_all() {
+                        Ok(()) => true,
+                        Err(e) => {
+                            error!("Failed to sync WAL on shutdown: {}", e);
+                            false
                         }
-                        debug!("WAL batch force flushed ({} entries)", batch_size);
-                    }
+                    };
+                    info!("WAL writer shut down at sequence {}", thread_counter.load(Ordering::Relaxed));
+                    return WALDrain { entries, written, synced };
+                }
 
+                // Flush every 5 seconds OR if batch is large (increased for better performance)
+                if !batch.is_empty() && (last_flush.elapsed() >= Duration::from_secs(5) || batch.len() >= 1000) {
+                    write_batch(&mut file, &mut batch, &thread_counter, &writer_node_id, &wal_path);
                     last_flush = Instant::now();
                 }
             }
@@ -175,9 +166,27 @@ impl WALWriter {
 
         WALWriter {
             sender,
+            send_timeout,
+            dropped: AtomicU64::new(0),
             sequence: sequence_counter,
             node_id,
             enabled: true,
+            stopping,
+            thread: Mutex::new(Some(handle)),
+        }
+    }
+
+    /// Write every queued operation, fsync the WAL and persist the sequence, then stop the writer thread.
+    /// Operations logged after this are dropped. Returns None when the WAL is disabled or already stopped.
+    pub fn shutdown(&self) -> Option<WALDrain> {
+        let handle = self.thread.lock().unwrap().take()?;
+        self.stopping.store(true, Ordering::Release);
+        match handle.join() {
+            Ok(drain) => Some(drain),
+            Err(_) => {
+                error!("WAL writer thread panicked before shutdown");
+                None
+            }
         }
     }
 
@@ -187,7 +196,7 @@ impl WALWriter {
             return;
         }
 
-        let _ = self.sender.try_send(WALOp::Put {
+        self.enqueue(WALOp::Put {
             bucket: bucket.to_string(),
             key: key.to_string(),
             size,
@@ -201,7 +210,7 @@ impl WALWriter {
             return;
         }
 
-        let _ = self.sender.try_send(WALOp::Delete {
+        self.enqueue(WALOp::Delete {
             bucket: bucket.to_string(),
             key: key.to_string(),
         });
@@ -213,7 +222,7 @@ impl WALWriter {
             return;
         }
 
-        let _ = self.sender.try_send(WALOp::CreateBucket {
+        self.enqueue(WALOp::CreateBucket {
             bucket: bucket.to_string(),
         });
     }
@@ -224,7 +233,7 @@ impl WALWriter {
             return;
         }
 
-        let _ = self.sender.try_send(WALOp::DeleteBucket {
+        self.enqueue(WALOp::DeleteBucket {
             bucket: bucket.to_string(),
         });
     }
@@ -235,7 +244,7 @@ impl WALWriter {
             return;
         }
 
-        let _ = self.sender.try_send(WALOp::UpdateMetadata {
+        self.enqueue(WALOp::UpdateMetadata {
             bucket: bucket.to_string(),
             metadata_type: metadata_type.to_string(),
             content: content.to_string(),
@@ -248,12 +257,66 @@ impl WALWriter {
             return;
         }
 
-        let _ = self.sender.try_send(WALOp::DeleteMetadata {
+        self.enqueue(WALOp::DeleteMetadata {
             bucket: bucket.to_string(),
             metadata_type: metadata_type.to_string(),
         });
     }
 
+    // Queue an entry for the writer thread. A full queue applies backpressure for up to send_timeout;
+    // entries still not queued after that are counted and logged as dropped.
+    fn enqueue(&self, op: WALOp) {
+        let queued = match self.sender.try_send(op) {
+            Ok(()) => true,
+            Err(TrySendError::Full(op)) if !self.send_timeout.is_zero() => {
+                self.sender.send_timeout(op, self.send_timeout).is_ok()
+            }
+            Err(_) => false,
+        };
+        if !queued {
+            let dropped = self.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+            // The first drop and every thousandth after it, so a burst does not flood the log
+            if dropped % 1000 == 1 {
+                warn!("WAL queue full, {} entries dropped so far; replicas will miss these changes", dropped);
+            }
+        }
+    }
+
+    #[inline(always)]
+    pub fn log_update_object_metadata(&self, bucket: &str, key: &str) {
+        if !self.enabled {
+            return;
+        }
+
+        self.enqueue(WALOp::UpdateObjectMetadata {
+            bucket: bucket.to_string(),
+            key: key.to_string(),
+        });
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn node_id(&self) -> &str {
+        &self.node_id
+    }
+
+    /// Entries waiting for the writer thread
+    pub fn queue_depth(&self) -> usize {
+        self.sender.len()
+    }
+
+    /// Entries dropped since startup because the queue was full
+    pub fn dropped_entries(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Sequence number of the last entry handed to the WAL, if any
+    pub fn last_sequence(&self) -> Option<u64> {
+        self.sequence.load(Ordering::Relaxed).checked_sub(1)
+    }
+
     /// Load the last sequence number from an existing WAL file
     /// Optimized to read only from the end of the file
     fn load_last_sequence(path: &PathBuf, node_id: &str) -> Option<u64> {
@@ -309,4 +372,75 @@ impl WALWriter {
             None
         }
     }
-}
\ No newline at end of file
+}
+
+/// Append a batch to the WAL, then flush it and record the next sequence for faster startup
+// Append the batch to the WAL; false if any entry could not be written or the buffer not flushed
+fn write_batch(file: &mut BufWriter<File>, batch: &mut Vec<WALOp>, counter: &AtomicU64, node_id: &str, wal_path: &Path) -> bool {
+    if batch.is_empty() {
+        return true;
+    }
+    let mut written = true;
+
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64;
+
+    let batch_size = batch.len();  // Save size before draining
+    for op in batch.drain(..) {
+        let sequence = counter.fetch_add(1, Ordering::Relaxed);
+
+        let line = match op {
+            WALOp::Put { bucket, key, size, etag } => {
+                format!("PUT\t{}\t{}\t{}\t{}\t{}\t{}\t{}\n",
+                    node_id, sequence, timestamp, bucket, key, size,
+                    etag.unwrap_or_default())
+            }
+            WALOp::Delete { bucket, key } => {
+                format!("DELETE\t{}\t{}\t{}\t{}\t{}\n",
+                    node_id, sequence, timestamp, bucket, key)
+            }
+            WALOp::CreateBucket { bucket } => {
+                format!("CREATE_BUCKET\t{}\t{}\t{}\t{}\n",
+                    node_id, sequence, timestamp, bucket)
+            }
+            WALOp::DeleteBucket { bucket } => {
+                format!("DELETE_BUCKET\t{}\t{}\t{}\t{}\n",
+                    node_id, sequence, timestamp, bucket)
+            }
+            WALOp::UpdateMetadata { bucket, metadata_type, content } => {
+                // Escape content to handle newlines and tabs
+                let escaped_content = content.replace('\n', "\\n").replace('\t', "\\t");
+                format!("UPDATE_METADATA\t{}\t{}\t{}\t{}\t{}\t{}\n",
+                    node_id, sequence, timestamp, bucket, metadata_type, escaped_content)
+            }
+            WALOp::DeleteMetadata { bucket, metadata_type } => {
+                format!("DELETE_METADATA\t{}\t{}\t{}\t{}\t{}\n",
+                    node_id, sequence, timestamp, bucket, metadata_type)
+            }
+            WALOp::UpdateObjectMetadata { bucket, key } => {
+                format!("UPDATE_OBJECT_METADATA\t{}\t{}\t{}\t{}\t{}\n",
+                    node_id, sequence, timestamp, bucket, key)
+            }
+        };
+
+        if let Err(e) = file.write_all(line.as_bytes()) {
+            error!("Failed to write to WAL: {}", e);
+            written = false;
+        }
+    }
+
+    // Flush before recording the sequence so the state file never runs ahead of the log
+    if let Err(e) = file.flush() {
+        error!("Failed to flush WAL: {}", e);
+        written = false;
+    }
+    debug!("WAL batch force flushed ({} entries)", batch_size);
+
+    // Write sequence state for faster startup
+    let state_path = wal_path.with_extension("sequence");
+    let next_seq = counter.load(Ordering::Relaxed);
+    let _ = fs::write(&state_path, format!("{}", next_seq));
+    written
+}
@@ -54,7 +54,21 @@ test_metadata_persistence() {
         ((failed++))
     fi
 
-    # Test 6: Metadata persistence after restart
+    # Test 6: Unicode metadata round-trip
+    if run_test "Unicode metadata round-trip" test_unicode_metadata "$BUCKET"; then
+        ((passed++))
+    else
+        ((failed++))
+    fi
+
+    # Test 7: Metadata containing newlines round-trip
+    if run_test "Newline metadata round-trip" test_newline_metadata "$BUCKET"; then
+        ((passed++))
+    else
+        ((failed++))
+    fi
+
+    # Test 8: Metadata persistence after restart
     if run_test "Metadata persistence after restart" test_persistence_after_restart "$BUCKET"; then
         ((passed++))
     else
@@ -166,6 +180,78 @@ test_list_objects() {
     fi
 }
 
+# Decode an RFC 2047 "=?UTF-8?B?...?=" header value, pass anything else through
+decode_rfc2047() {
+    local value="$1"
+    if [[ "$value" =~ ^=\?UTF-8\?B\?(.*)\?=$ ]]; then
+        printf '%s' "${BASH_REMATCH[1]}" | base64 -d
+    else
+        printf '%s' "$value"
+    fi
+}
+
+test_unicode_metadata() {
+    local bucket="$1"
+    local expected="Zürich ✓ naïve"
+    local temp_file="/tmp/test-unicode-meta-$$"
+    echo "unicode metadata" > "$temp_file"
+
+    # The AWS CLI refuses non-ASCII metadata, so send the raw UTF-8 header with curl
+    local status=$(curl -s -o /dev/null -w "%{http_code}" -X PUT \
+        "${S3_ENDPOINT}/${bucket}/unicode-meta.txt" \
+        -H "Authorization: AWS4-HMAC-SHA256 Credential=${S3_ACCESS_KEY}/$(date -u +%Y%m%d)/${S3_REGION}/s3/aws4_request, SignedHeaders=host, Signature=unsigned" \
+        -H "x-amz-meta-city: ${expected}" \
+        --data-binary @"$temp_file")
+    rm -f "$temp_file"
+
+    if [ "$status" != "200" ]; then
+        echo -e "  ${RED}Upload failed with status $status${NC}"
+        return 1
+    fi
+
+    local stored=$(get_metadata_content "$bucket" "unicode-meta.txt" | jq -r .metadata.city)
+    local returned=$(aws --endpoint-url="${S3_ENDPOINT}" s3api head-object \
+        --bucket "$bucket" --key "unicode-meta.txt" 2>/dev/null | jq -r .Metadata.city)
+    local decoded=$(decode_rfc2047 "$returned")
+
+    if [ "$stored" = "$expected" ] && [ "$decoded" = "$expected" ]; then
+        echo -e "  ${GREEN}Unicode metadata round-tripped: $decoded${NC}"
+        return 0
+    else
+        echo -e "  ${RED}Unicode metadata mismatch: stored=$stored returned=$returned${NC}"
+        return 1
+    fi
+}
+
+test_newline_metadata() {
+    local bucket="$1"
+    local expected=$'first line\nsecond line'
+    local encoded="=?UTF-8?B?$(printf '%s' "$expected" | base64 -w0)?="
+    local temp_file="/tmp/test-newline-meta-$$"
+    echo "newline metadata" > "$temp_file"
+
+    if ! aws --endpoint-url="${S3_ENDPOINT}" s3api put-object --bucket "$bucket" --key "newline-meta.txt" \
+        --body "$temp_file" --metadata "{\"note\":\"${encoded}\"}" >/dev/null 2>&1; then
+        rm -f "$temp_file"
+        echo -e "  ${RED}Upload with encoded metadata failed${NC}"
+        return 1
+    fi
+    rm -f "$temp_file"
+
+    local stored=$(get_metadata_content "$bucket" "newline-meta.txt" | jq -r .metadata.note)
+    local returned=$(aws --endpoint-url="${S3_ENDPOINT}" s3api head-object \
+        --bucket "$bucket" --key "newline-meta.txt" 2>/dev/null | jq -r .Metadata.note)
+    local decoded=$(decode_rfc2047 "$returned")
+
+    if [ "$stored" = "$expected" ] && [ "$decoded" = "$expected" ]; then
+        echo -e "  ${GREEN}Newline metadata stored decoded and returned encoded${NC}"
+        return 0
+    else
+        echo -e "  ${RED}Newline metadata mismatch: stored=$stored returned=$returned${NC}"
+        return 1
+    fi
+}
+
 test_persistence_after_restart() {
     local bucket="$1"
 